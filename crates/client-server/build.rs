@@ -1,9 +1,11 @@
 fn main(){
     let client_csharp_native = csbindgen::Builder::default()
     .input_extern_file("src/client/ffi.rs")
+    .input_extern_file("src/client/interpolation.rs")
     .csharp_dll_name("client_server")
     .csharp_type_rename(move |x| match x.as_str() {     // optional, default: `|x| x`
         "Client" => "void".into(),
+        "FloatInterpolationBuffer" => "void".into(),
         _ => x,
     })
     .csharp_class_name("OmgppClientNative")
@@ -19,6 +21,7 @@ fn main(){
     .csharp_dll_name("client_server")
     .csharp_type_rename(move |x| match x.as_str() {     // optional, default: `|x| x`
         "Server" => "void".into(),
+        "FloatLagCompensation" => "void".into(),
         _ => x,
     })
     .csharp_class_name("OmgppServerNative")
@@ -0,0 +1,89 @@
+//! Transports used by `Server::with_settings` and `Client::new`.
+//! GNS is used when the `gns` feature is enabled, otherwise QUIC with the `quic` feature.
+
+use std::net::IpAddr;
+
+#[cfg(feature = "gns")]
+use omgpp_core::transport::gns::{GnsConnector, GnsServerTransport};
+#[cfg(all(feature = "quic", not(feature = "gns")))]
+use omgpp_core::transport::quic::{QuicConnector, QuicServerTransport};
+use omgpp_core::connection_config::ConnectionConfig;
+use omgpp_core::transport::{Connector, Transport};
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+use omgpp_core::{transport::{ConnectionHandle, TransportResult}, Endpoint};
+
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+const NO_BACKEND: &str = "No transport backend, build with the `gns` or `quic` feature";
+
+#[cfg(feature = "gns")]
+pub fn listen(ip: IpAddr, port: u16, config: &ConnectionConfig) -> Result<Box<dyn Transport>, String> {
+    Ok(Box::new(GnsServerTransport::listen(ip, port, config)?))
+}
+#[cfg(all(feature = "quic", not(feature = "gns")))]
+pub fn listen(ip: IpAddr, port: u16, config: &ConnectionConfig) -> Result<Box<dyn Transport>, String> {
+    // fails for a `ConnectionConfig` QUIC cannot apply instead of dropping it
+    let transport = QuicServerTransport::listen(ip, port)?;
+    transport.set_accept_config(config)?;
+    Ok(Box::new(transport))
+}
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+pub fn listen(_ip: IpAddr, _port: u16, _config: &ConnectionConfig) -> Result<Box<dyn Transport>, String> {
+    Err(NO_BACKEND.to_string())
+}
+
+#[cfg(feature = "gns")]
+pub fn connector() -> Box<dyn Connector> {
+    Box::new(GnsConnector)
+}
+// the server certificate cannot be verified without a trusted certificate, so connecting fails;
+// use `Client::with_connector` with `QuicConnector::with_trusted_certificate`
+#[cfg(all(feature = "quic", not(feature = "gns")))]
+pub fn connector() -> Box<dyn Connector> {
+    Box::new(QuicConnector::default())
+}
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+pub fn connector() -> Box<dyn Connector> {
+    Box::new(NoConnector)
+}
+
+/// `connector` that trusts the QUIC server presenting `certificate` (DER). GNS ignores it
+#[cfg(feature = "gns")]
+pub fn connector_with_certificate(_certificate: Vec<u8>) -> Box<dyn Connector> {
+    Box::new(GnsConnector)
+}
+#[cfg(all(feature = "quic", not(feature = "gns")))]
+pub fn connector_with_certificate(certificate: Vec<u8>) -> Box<dyn Connector> {
+    Box::new(QuicConnector::with_trusted_certificate(certificate.into()))
+}
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+pub fn connector_with_certificate(_certificate: Vec<u8>) -> Box<dyn Connector> {
+    Box::new(NoConnector)
+}
+
+/// `connector` that accepts any QUIC server certificate, only for local development.
+/// GNS does not verify servers either way
+#[cfg(feature = "gns")]
+pub fn dangerous_insecure_connector() -> Box<dyn Connector> {
+    Box::new(GnsConnector)
+}
+#[cfg(all(feature = "quic", not(feature = "gns")))]
+pub fn dangerous_insecure_connector() -> Box<dyn Connector> {
+    Box::new(QuicConnector::dangerous_skip_verification())
+}
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+pub fn dangerous_insecure_connector() -> Box<dyn Connector> {
+    Box::new(NoConnector)
+}
+
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+struct NoConnector;
+#[cfg(not(any(feature = "gns", feature = "quic")))]
+impl Connector for NoConnector {
+    fn connect(
+        &self,
+        _endpoint: &Endpoint,
+        _config: &ConnectionConfig,
+    ) -> TransportResult<(Box<dyn Transport>, ConnectionHandle)> {
+        Err(NO_BACKEND.to_string())
+    }
+}
@@ -1,37 +1,63 @@
 pub mod ffi;
+pub mod interpolation;
+pub mod lockstep;
+pub mod prediction;
+pub mod replication;
+pub mod time_sync;
 
 use std::{
-    cell::{Ref, RefCell},
+    cell::{Cell, RefCell, RefMut},
+    collections::{HashSet, VecDeque},
     net::IpAddr,
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
-use gns::{GnsSocket, IsClient, IsCreated};
-use gns_sys::{
-    k_nSteamNetworkingSend_Reliable, k_nSteamNetworkingSend_Unreliable,
-    ESteamNetworkingConnectionState,
-};
 use omgpp_core::{
-    cmd_handler::{CmdHandler, CmdHandlerContainer}, messages::general_message::{
+    clock::{Clock, SystemClock}, cmd_handler::{CmdHandler, CmdHandlerContainer, CmdPermissions}, connection_config::ConnectionConfig, interceptor::{Direction, Intercept, InterceptContext, InterceptorChain}, lanes::LaneMap, network_simulation::NetworkSimulation, messages::general_message::{
         general_omgpp_message::{self, CmdRequest, Data},
         GeneralOmgppMessage,
-    }, ConnectionState, Endpoint, OmgppPredefinedCmd, ToEndpoint, TransmitterHelper, GNS
+    }, transport::{ConnectionHandle, Connector, SendMode, Transport, TransportEvent}, typed_message::TypedMessageHandlers, ConnectionState, Endpoint, OmgppPredefinedCmd
 };
-use protobuf::Message;
+#[cfg(feature = "gns")]
+use omgpp_core::GNS;
+
+use crate::backend;
+use crate::lockstep::{LockstepTurn, SessionId};
+use lockstep::LockstepState;
+use protobuf::{Message, MessageFull};
+use replication::{EntityId, ReplicatedEntities, ReplicatedEntity, ReplicationEvent};
+use time_sync::TimeSync;
 use uuid::Uuid;
 
 type OnConnectionChangedCallback = Box<dyn Fn(&Client, &Endpoint, ConnectionState) + 'static>;
 type OnMessageCallback = Box<dyn Fn(&Client, &Endpoint, i64, Vec<u8>) + 'static>;
 type OnRpcCallback = Box<dyn Fn(&Client, &Endpoint, bool, i64, u64, i64, Vec<u8>) + 'static>;
 type OnAuthCallback = Box<dyn Fn(&Client, &Endpoint) -> Vec<String> + 'static>;
+type OnAnnouncementCallback = Box<dyn Fn(&Client, &Endpoint, &str) + 'static>;
+type OnMessageDecodeErrorCallback = Box<dyn Fn(&Client, &Endpoint, i64, &str) + 'static>;
+type OnEntitySpawnedCallback = Box<dyn Fn(&Client, &ReplicatedEntity) + 'static>;
+type OnEntityUpdatedCallback = Box<dyn Fn(&Client, EntityId, i64, Option<&[u8]>) + 'static>;
+type OnEntityDespawnedCallback = Box<dyn Fn(&Client, &ReplicatedEntity) + 'static>;
 
 type ClientResult<T> = Result<T, String>; // TODO replace error with enum
+
+const DEFAULT_TIME_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_INPUT_REDUNDANCY: usize = 3;
+// unacknowledged inputs kept, the oldest are dropped beyond it
+const MAX_PENDING_INPUTS: usize = 256;
 struct ClientCallbacks {
     on_connection_changed_callback: Option<OnConnectionChangedCallback>,
     on_message_callback: Option<OnMessageCallback>,
     on_rpc_callback: Option<OnRpcCallback>,
     on_authenticate_callback: Option<OnAuthCallback>,
+    on_announcement_callback: Option<OnAnnouncementCallback>,
+    on_message_decode_error_callback: Option<OnMessageDecodeErrorCallback>,
+    on_entity_spawned_callback: Option<OnEntitySpawnedCallback>,
+    on_entity_updated_callback: Option<OnEntityUpdatedCallback>,
+    on_entity_despawned_callback: Option<OnEntityDespawnedCallback>,
 }
-//TODO In order to support multiple servers, track multiple GnsSockets
+//TODO In order to support multiple servers, track multiple transports
 struct ConnectionTracker {
     server_endpoint: Endpoint,
     state: ConnectionState,
@@ -44,22 +70,60 @@ impl ConnectionTracker {
         self.state.clone()
     }
 }
-// TODO In order to support multiple servers, move `socket` in ConnectionTracker
+// TODO In order to support multiple servers, move `transport` in ConnectionTracker
 pub struct Client {
-    socket: Option<GnsSocket<'static, 'static, IsClient>>,
+    connector: Box<dyn Connector>,
+    transport: Option<Box<dyn Transport>>,
+    connection: Option<ConnectionHandle>,
     callbacks: RefCell<ClientCallbacks>,
     connection_tracker: RefCell<ConnectionTracker>,
     cmd_handlers: RefCell<CmdHandlerContainer<Client>>,
+    interceptors: RefCell<InterceptorChain<Client>>,
+    typed_messages: RefCell<TypedMessageHandlers<Client>>,
+    replicated: RefCell<ReplicatedEntities>,
+    clock: Rc<dyn Clock>,
+    // start of the client clock sent with time sync requests
+    epoch: Instant,
+    time_sync: RefCell<TimeSync>,
+    time_sync_interval: Duration,
+    next_time_sync: Cell<Option<Instant>>,
+    // reported to the server for lag compensation
+    interpolation_delay: Duration,
+    // server time stamped on the message being dispatched
+    message_server_time: Cell<Option<Duration>>,
+    message_last_input: Cell<Option<u64>>,
+    pending_inputs: RefCell<VecDeque<(u64, Vec<u8>)>>,
+    last_input: Cell<u64>,
+    input_redundancy: usize,
+    lockstep: RefCell<LockstepState>,
+    lanes: RefCell<LaneMap>,
+    connection_config: ConnectionConfig,
+    // the connection has not seen the current `connection_config` yet
+    connection_config_changed: Cell<bool>,
+    network_simulation: NetworkSimulation,
 }
 impl Client {
     pub fn new(server_ip: IpAddr, server_port: u16) -> Client {
+        Client::with_connector(server_ip, server_port, backend::connector())
+    }
+    /// Create a client which opens its transport through `connector` on `connect`
+    pub fn with_connector(server_ip: IpAddr, server_port: u16, connector: Box<dyn Connector>) -> Client {
+        let clock: Rc<dyn Clock> = Rc::new(SystemClock);
+        let epoch = clock.now();
         let client = Client {
-            socket: None,
+            connector,
+            transport: None,
+            connection: None,
             callbacks: RefCell::new(ClientCallbacks {
                 on_connection_changed_callback: None,
                 on_message_callback: None,
                 on_rpc_callback: None,
                 on_authenticate_callback:None,
+                on_announcement_callback: None,
+                on_message_decode_error_callback: None,
+                on_entity_spawned_callback: None,
+                on_entity_updated_callback: None,
+                on_entity_despawned_callback: None,
             }),
             connection_tracker: RefCell::new(ConnectionTracker {
                 state: ConnectionState::None,
@@ -69,6 +133,25 @@ impl Client {
                 },
             }),
             cmd_handlers: RefCell::new(CmdHandlerContainer::new()),
+            interceptors: Default::default(),
+            typed_messages: Default::default(),
+            replicated: Default::default(),
+            clock,
+            epoch,
+            time_sync: Default::default(),
+            time_sync_interval: DEFAULT_TIME_SYNC_INTERVAL,
+            next_time_sync: Cell::new(None),
+            interpolation_delay: Duration::ZERO,
+            message_server_time: Cell::new(None),
+            message_last_input: Cell::new(None),
+            pending_inputs: Default::default(),
+            last_input: Cell::new(0),
+            input_redundancy: DEFAULT_INPUT_REDUNDANCY,
+            lockstep: Default::default(),
+            lanes: Default::default(),
+            connection_config: Default::default(),
+            connection_config_changed: Cell::new(false),
+            network_simulation: Default::default(),
         };
         client.init_default_cmd_handlers();
         client
@@ -80,6 +163,11 @@ impl Client {
             false,
             Box::new(Client::cmd_auth_handle),
         ));
+        _ = cmd_handlers.register_handler(CmdHandler::new(
+            OmgppPredefinedCmd::ANNOUNCEMENT,
+            false,
+            Box::new(Client::cmd_announcement_handle),
+        ));
     }
     fn cmd_auth_handle(
         &self,
@@ -99,9 +187,15 @@ impl Client {
                 if let Some(cb) = &callbacks.on_connection_changed_callback {
                     cb(self, endpoint, new_state);
                 }
+                self.next_time_sync.set(Some(self.clock.now()));
             }
         }
     }
+    fn cmd_announcement_handle(&self, _: &Uuid, endpoint: &Endpoint, _: &CmdHandler<Client>, request: &CmdRequest) {
+        if let (Some(text), Some(cb)) = (request.args.first(), &self.callbacks.borrow().on_announcement_callback) {
+            cb(self, endpoint, text);
+        }
+    }
     pub fn register_on_connection_state_changed(
         &self,
         callback: impl Fn(&Client, &Endpoint, ConnectionState) + 'static,
@@ -123,66 +217,178 @@ impl Client {
     pub fn register_on_auth(&self,callback: impl Fn(&Client, &Endpoint)->Vec<String> + 'static){
         self.callbacks.borrow_mut().on_authenticate_callback = Some(Box::from(callback));
     }
+    /// Texts sent by server admins through `omgpp_announce`
+    pub fn register_on_announcement(&self, callback: impl Fn(&Client, &Endpoint, &str) + 'static) {
+        self.callbacks.borrow_mut().on_announcement_callback = Some(Box::from(callback));
+    }
+    /// Connection options applied on `connect` and, from the next send or `process`,
+    /// to the current connection
+    pub fn set_connection_config(&mut self, config: ConnectionConfig) {
+        self.connection_config = config;
+        self.connection_config_changed.set(true);
+    }
+    pub fn connection_config(&self) -> &ConnectionConfig {
+        &self.connection_config
+    }
+    pub fn connection_config_mut(&mut self) -> &mut ConnectionConfig {
+        self.connection_config_changed.set(true);
+        &mut self.connection_config
+    }
+    /// Send messages of `msg_type` on `lane` of `ConnectionConfig::lanes`, ordered apart from
+    /// the other lanes. Lane 0 restores the default
+    pub fn set_message_lane(&self, msg_type: i64, lane: u16) -> ClientResult<()> {
+        self.check_lane(lane)?;
+        self.lanes.borrow_mut().set_message_lane(msg_type, lane);
+        Ok(())
+    }
+    /// Send calls of `method_id` on `lane`
+    pub fn set_rpc_lane(&self, method_id: i64, lane: u16) -> ClientResult<()> {
+        self.check_lane(lane)?;
+        self.lanes.borrow_mut().set_rpc_lane(method_id, lane);
+        Ok(())
+    }
+    /// Send requests of `cmd` and the replies to it on `lane`
+    pub fn set_cmd_lane(&self, cmd: &str, lane: u16) -> ClientResult<()> {
+        self.check_lane(lane)?;
+        self.lanes.borrow_mut().set_cmd_lane(cmd, lane);
+        Ok(())
+    }
+    /// Send on `lane` instead of the lane `msg_type` is mapped to
+    pub fn send_on_lane(&self, lane: u16, msg_type: i64, data: &[u8]) -> ClientResult<()> {
+        self.check_lane(lane)?;
+        self.send_message_on_lane(SendMode::Unreliable, lane, create_general_message(msg_type, data))
+    }
+    pub fn send_reliable_on_lane(&self, lane: u16, msg_type: i64, data: &[u8]) -> ClientResult<()> {
+        self.check_lane(lane)?;
+        self.send_message_on_lane(SendMode::Reliable, lane, create_general_message(msg_type, data))
+    }
+    /// `call_rpc` on `lane` instead of the lane `method_id` is mapped to
+    pub fn call_rpc_on_lane(
+        &self,
+        lane: u16,
+        reliable: bool,
+        method_id: i64,
+        request_id: u64,
+        arg_type: i64,
+        arg_data: Option<&[u8]>,
+    ) -> ClientResult<()> {
+        self.check_lane(lane)?;
+        let message = create_rpc_message(reliable, method_id, request_id, arg_type, arg_data);
+        self.send_message_on_lane(SendMode::from_reliable(reliable), lane, message)
+    }
+    fn check_lane(&self, lane: u16) -> ClientResult<()> {
+        // lanes of the connection have to match the ones checked here
+        self.apply_connection_config()?;
+        let lane_count = self.connection_config.lane_count();
+        match (lane as usize) < lane_count {
+            true => Ok(()),
+            false => Err(format!("Lane {} is not configured, there are {} lanes", lane, lane_count)),
+        }
+    }
+    /// Enable fake packet loss, lag, jitter, reorder and duplication.
+    /// GNS applies it process wide; pass `NetworkSimulation::default()` to turn it off
+    pub fn set_network_simulation(&mut self, simulation: NetworkSimulation) -> ClientResult<()> {
+        #[cfg(feature = "gns")]
+        GNS.as_ref()?;
+        simulation.apply()?;
+        self.network_simulation = simulation;
+        Ok(())
+    }
+    pub fn network_simulation(&self) -> &NetworkSimulation {
+        &self.network_simulation
+    }
+    /// Open a new connection to the server. Fails until the previous one is closed by
+    /// `disconnect` or by the server
     pub fn connect(&mut self) -> ClientResult<()> {
-        let old_socket = &self.socket;
-        let tracker = &self.connection_tracker.borrow();
-        let current_connection_state = &tracker.state;
-
-        match (old_socket, current_connection_state) {
-            (Some(_), ConnectionState::Connecting | ConnectionState::Connected) => {
+        let server_endpoint = {
+            let tracker = self.connection_tracker.borrow();
+            // the previous transport has to be closed first, e.g. by `disconnect`
+            let closed = matches!(tracker.state, ConnectionState::None | ConnectionState::Disconnected);
+            if self.transport.is_some() && !closed {
                 Err("Already connected to server")?
             }
-            _ => (),
-        }
-        let gns = GNS.as_ref()?;
-        let gns_socket = GnsSocket::<IsCreated>::new(&gns.global, &gns.utils).unwrap();
-
-        let address_to_connect = match tracker.server_endpoint.ip {
-            IpAddr::V4(v4) => v4.to_ipv6_mapped(),
-            IpAddr::V6(v6) => v6,
+            tracker.server_endpoint
         };
-        let port = tracker.server_endpoint.port;
-        let client_socket = gns_socket
-            .connect(address_to_connect, port)
-            .or(Err("Cannot create socket to connect to server".to_string()))?;
+        let (transport, connection) = self.connector.connect(&server_endpoint, &self.connection_config)?;
+        self.connection_config_changed.set(false);
+        // the server starts over with a full snapshot
+        self.replicated.get_mut().clear();
+        *self.time_sync.get_mut() = Default::default();
+        self.next_time_sync.set(None);
+        // sequence numbers start over with the new connection
+        self.pending_inputs.get_mut().clear();
+        self.last_input.set(0);
+        // the server forgets the sessions of disconnected players
+        self.lockstep.get_mut().clear();
 
-        self.socket = Some(client_socket);
+        self.transport = Some(transport);
+        self.connection = Some(connection);
+        // connecting until the transport reports otherwise
+        self.connection_tracker
+            .get_mut()
+            .track_connection_state(ConnectionState::Connecting);
         Ok(())
     }
 
+    /// Close the connection. `connect` can be called again afterwards
     pub fn disconnect(&self) {
-        if let Some(socket) = &self.socket {
-            socket.close_connection(socket.connection(), 0, "", false);
+        if let (Some(transport), Some(connection)) = (&self.transport, self.connection) {
+            transport.close(connection, "");
+            self.connection_tracker
+                .borrow_mut()
+                .track_connection_state(ConnectionState::Disconnected);
         }
     }
+    pub fn transport(&self) -> Option<&dyn Transport> {
+        self.transport.as_deref()
+    }
     pub fn send_cmd(
         &self,
         cmd: &str,
         request_id: u64,
         args: Option<Vec<String>>,
     ) -> ClientResult<()> {
-        if let Some(socket) = &self.socket {
-            let cmd_bytes = create_cmd_message(String::from(cmd), request_id, args.unwrap_or_else(|| Vec::new()))
-                .or_else(|_or| Err("Cannot create cmd message".to_string()))?;
-            let _send_results = TransmitterHelper::send(
-                socket,
-                &[socket.connection()],
-                k_nSteamNetworkingSend_Reliable,
-                &cmd_bytes,
-            );
-            Ok(())
-        } else {
-            Err("Socket not connected; Make sure to call `connect`".to_string())
+        if self.transport.is_none() {
+            return Err("Socket not connected; Make sure to call `connect`".to_string());
+        }
+        let message = create_cmd_message(String::from(cmd), request_id, args.unwrap_or_else(|| Vec::new()));
+        self.send_message(SendMode::Reliable, message)
+    }
+    /// Handle `handler.cmd` sent by the server. Names starting with `omgpp_` are reserved.
+    /// Handlers cannot be changed from inside of a command handler
+    pub fn register_cmd_handler(&self, handler: CmdHandler<Client>) -> ClientResult<()> {
+        if OmgppPredefinedCmd::is_reserved(&handler.cmd) {
+            return Err(format!("Command {:?} uses reserved prefix", handler.cmd));
+        }
+        self.cmd_handlers_mut()?.register_handler(handler)
+    }
+    pub fn unregister_cmd_handler(&self, cmd: &str) -> ClientResult<()> {
+        if OmgppPredefinedCmd::is_reserved(cmd) {
+            return Err(format!("Command {:?} uses reserved prefix", cmd));
         }
+        self.cmd_handlers_mut()?
+            .unregister_handler(cmd)
+            .map(|_| ())
+            .ok_or_else(|| format!("Command {:?} is not registered", cmd))
+    }
+    /// Handle every command without its own handler
+    pub fn register_cmd_fallback(&self, handler: Option<CmdHandler<Client>>) -> ClientResult<()> {
+        self.cmd_handlers_mut()?.set_fallback(handler);
+        Ok(())
+    }
+    fn cmd_handlers_mut(&self) -> ClientResult<RefMut<'_, CmdHandlerContainer<Client>>> {
+        self.cmd_handlers
+            .try_borrow_mut()
+            .map_err(|_| "Command handlers are in use".to_string())
     }
     pub fn process<const N: usize>(&self) -> ClientResult<()> {
-        if self.socket.is_none() {
+        let Some(transport) = &self.transport else {
             return Err("Socket not initialized".to_string());
-        }
-        let socket = self.socket.as_ref().unwrap();
-        socket.poll_callbacks();
+        };
+        let config_result = self.apply_connection_config();
+        transport.poll();
         let mut socket_op_is_success = ClientResult::Ok(());
-        let _processed_event_count = socket.poll_event::<N>(|event| {
+        let _processed_event_count = transport.poll_events(N, &mut |event| {
             Client::process_connection_events(
                 &self,
                 event,
@@ -190,18 +396,293 @@ impl Client {
                 &self.connection_tracker,
             );
         });
-        let _processed_msg_count = socket.poll_messages::<N>(|msg| {
+        let _processed_msg_count = transport.poll_messages(N, &mut |_connection, data| {
             socket_op_is_success =
-                Client::process_messages(self, msg, &self.connection_tracker, &self.callbacks);
+                Client::process_messages(self, data, &self.connection_tracker, &self.callbacks);
         });
-        socket_op_is_success
+        self.sync_time_if_due();
+        config_result.and(socket_op_is_success)
+    }
+    /// Clock used for time synchronization, e.g. a `ManualClock` in tests. Resets the estimate
+    pub fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        self.epoch = clock.now();
+        self.clock = clock;
+        *self.time_sync.get_mut() = Default::default();
+    }
+    /// How often the server clock is sampled once the first samples are in
+    pub fn set_time_sync_interval(&mut self, interval: Duration) {
+        self.time_sync_interval = interval;
+    }
+    pub fn time_sync_interval(&self) -> Duration {
+        self.time_sync_interval
+    }
+    /// How far behind the server time remote states are rendered, e.g. the delay of the
+    /// `InterpolationBuffer`s. Sent with time sync requests so the server can compensate for it
+    pub fn set_interpolation_delay(&mut self, delay: Duration) {
+        self.interpolation_delay = delay;
+    }
+    pub fn interpolation_delay(&self) -> Duration {
+        self.interpolation_delay
+    }
+    /// Time since the server started, estimated from the synchronized clock offset.
+    /// `None` until the first time sync answer arrived
+    pub fn server_time(&self) -> Option<Duration> {
+        self.time_sync.borrow().server_time(self.local_time())
+    }
+    /// Smoothed round trip time of time sync requests
+    pub fn rtt(&self) -> Option<Duration> {
+        self.time_sync.borrow().rtt()
+    }
+    /// Microseconds between the client clock and the server clock
+    pub fn clock_offset_micros(&self) -> Option<i64> {
+        self.time_sync.borrow().offset_micros()
+    }
+    /// Latest replication tick stamped on a server message
+    pub fn server_tick(&self) -> Option<u64> {
+        self.time_sync.borrow().server_tick()
+    }
+    /// Server time the message being handled was sent at, to push it into an `InterpolationBuffer`.
+    /// Only set inside of message, rpc, command and entity callbacks
+    pub fn message_server_time(&self) -> Option<Duration> {
+        self.message_server_time.get()
+    }
+    /// Last input the server processed when it sent the message being handled, see
+    /// `Server::take_input`. `None` on broadcasts and before the first processed input
+    pub fn message_last_input(&self) -> Option<u64> {
+        self.message_last_input.get()
+    }
+    pub fn time_sync(&self) -> TimeSync {
+        self.time_sync.borrow().clone()
+    }
+    /// Send the input of this tick and return its sequence number. Inputs go unreliably, each
+    /// packet repeats the last `input_redundancy` inputs the server did not acknowledge
+    pub fn send_input(&self, data: &[u8]) -> ClientResult<u64> {
+        if self.transport.is_none() {
+            return Err("Socket not connected; Make sure to call `connect`".to_string());
+        }
+        let sequence = self.last_input.get() + 1;
+        self.last_input.set(sequence);
+        let mut pending = self.pending_inputs.borrow_mut();
+        pending.push_back((sequence, data.to_vec()));
+        while pending.len() > MAX_PENDING_INPUTS {
+            pending.pop_front();
+        }
+        let mut batch = general_omgpp_message::InputBatch::new();
+        let resent = pending.len().min(self.input_redundancy.max(1));
+        for (sequence, data) in pending.iter().skip(pending.len() - resent) {
+            let mut input = general_omgpp_message::InputCommand::new();
+            input.sequence = *sequence;
+            input.data = data.clone();
+            batch.inputs.push(input);
+        }
+        drop(pending);
+        let mut message = GeneralOmgppMessage::new();
+        message.data = Some(Data::Inputs(batch));
+        self.send_message(SendMode::Unreliable, message)?;
+        Ok(sequence)
+    }
+    pub fn send_input_typed<M: MessageFull>(&self, input: &M) -> ClientResult<u64> {
+        let data = input
+            .write_to_bytes()
+            .map_err(|err| format!("Cannot encode {}: {}", M::descriptor().full_name(), err))?;
+        self.send_input(&data)
+    }
+    /// Number of inputs repeated in every input packet
+    pub fn set_input_redundancy(&mut self, redundancy: usize) {
+        self.input_redundancy = redundancy;
+    }
+    pub fn input_redundancy(&self) -> usize {
+        self.input_redundancy
+    }
+    /// Inputs sent but not processed by the server yet
+    pub fn unacknowledged_inputs(&self) -> usize {
+        self.pending_inputs.borrow().len()
+    }
+    /// Lockstep session the server put this client in with `Server::create_lockstep_session`, until it ends
+    pub fn lockstep_session(&self) -> Option<SessionId> {
+        self.lockstep.borrow().session()
+    }
+    /// Player number of this client in the lockstep session
+    pub fn lockstep_player(&self) -> Option<usize> {
+        self.lockstep.borrow().player()
+    }
+    pub fn lockstep_players(&self) -> usize {
+        self.lockstep.borrow().players()
+    }
+    /// Send the input of this client for `turn`. Turns start at 1; sending a few turns ahead
+    /// hides the round trip to the server
+    pub fn send_lockstep_input(&self, turn: u64, data: &[u8]) -> ClientResult<()> {
+        if self.lockstep_session().is_none() {
+            return Err("Not in a lockstep session".to_string());
+        }
+        let mut input = general_omgpp_message::LockstepInput::new();
+        input.turn = turn;
+        input.data = data.to_vec();
+        let mut message = GeneralOmgppMessage::new();
+        message.data = Some(Data::LockstepInput(input));
+        self.send_message(SendMode::Reliable, message)
+    }
+    /// Send the checksum of the simulation state after applying `turn`, the server compares
+    /// it with the other players to detect desyncs
+    pub fn send_lockstep_checksum(&self, turn: u64, checksum: u64) -> ClientResult<()> {
+        if self.lockstep_session().is_none() {
+            return Err("Not in a lockstep session".to_string());
+        }
+        let mut request = general_omgpp_message::LockstepChecksum::new();
+        request.turn = turn;
+        request.checksum = checksum;
+        let mut message = GeneralOmgppMessage::new();
+        message.data = Some(Data::LockstepChecksum(request));
+        self.send_message(SendMode::Reliable, message)
+    }
+    /// Next completed turn to simulate, `None` until the server completed it
+    pub fn take_lockstep_turn(&self) -> Option<LockstepTurn> {
+        self.lockstep.borrow_mut().take()
+    }
+    /// Turn `take_lockstep_turn` returns next, 0 outside a session
+    pub fn next_lockstep_turn(&self) -> u64 {
+        self.lockstep.borrow().next_turn()
+    }
+    fn acknowledge_inputs(&self, last_input: u64) {
+        let mut pending = self.pending_inputs.borrow_mut();
+        while pending.front().is_some_and(|(sequence, _)| *sequence <= last_input) {
+            pending.pop_front();
+        }
+    }
+    fn local_time(&self) -> Duration {
+        self.clock.now() - self.epoch
+    }
+    fn sync_time_if_due(&self) {
+        let now = self.clock.now();
+        let Some(next_time_sync) = self.next_time_sync.get() else {
+            return;
+        };
+        if now < next_time_sync || self.connection_tracker.borrow().state() != ConnectionState::Connected {
+            return;
+        }
+        // sample quickly until the estimate settles
+        let interval = match self.time_sync.borrow().is_warmed_up() {
+            true => self.time_sync_interval,
+            false => self.time_sync_interval / 8,
+        };
+        self.next_time_sync.set(Some(now + interval));
+        let mut request = general_omgpp_message::TimeSync::new();
+        request.client_time = self.local_time().as_micros() as u64;
+        request.rtt = self.rtt().map_or(0, |rtt| rtt.as_micros() as u64);
+        request.interpolation_delay = self.interpolation_delay.as_micros() as u64;
+        let mut message = GeneralOmgppMessage::new();
+        message.data = Some(Data::TimeSync(request));
+        _ = self.send_message(SendMode::Unreliable, message);
     }
 
     pub fn send(&self, msg_type: i64, data: &[u8]) -> ClientResult<()> {
-        self.send_with_flags(k_nSteamNetworkingSend_Unreliable, msg_type, data)
+        self.send_with_mode(SendMode::Unreliable, msg_type, data)
     }
     pub fn send_reliable(&self, msg_type: i64, data: &[u8]) -> ClientResult<()> {
-        self.send_with_flags(k_nSteamNetworkingSend_Reliable, msg_type, data)
+        self.send_with_mode(SendMode::Reliable, msg_type, data)
+    }
+
+    /// Send `message` with its id from the message registry, see `register_message`
+    pub fn send_typed<M: MessageFull>(&self, message: &M) -> ClientResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.send(msg_type, &data)
+    }
+    pub fn send_typed_reliable<M: MessageFull>(&self, message: &M) -> ClientResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.send_reliable(msg_type, &data)
+    }
+    /// Send and receive `M` with its `stable_type_id`. Only needed to detect id collisions early,
+    /// unregistered types use the same id
+    pub fn register_message<M: MessageFull>(&self) -> ClientResult<i64> {
+        self.typed_messages_mut()?.registry_mut().register::<M>()
+    }
+    /// Send and receive `M` with a hand picked id instead of its `stable_type_id`
+    pub fn register_message_with_id<M: MessageFull>(&self, msg_type: i64) -> ClientResult<i64> {
+        self.typed_messages_mut()?.registry_mut().register_with_id::<M>(msg_type)
+    }
+    pub fn message_type_id<M: MessageFull>(&self) -> i64 {
+        self.typed_messages.borrow().registry().type_id::<M>()
+    }
+    /// Handle messages of type `M`. They are decoded before `handler` runs and no longer reach
+    /// `register_on_message`; payloads that fail to decode go to `register_on_message_decode_error`
+    pub fn on_message<M: MessageFull>(&self, handler: impl Fn(&Client, M) + 'static) -> ClientResult<()> {
+        self.typed_messages_mut()?
+            .set_handler(move |client, _peer, _endpoint, message| handler(client, message))
+    }
+    pub fn remove_message_handler<M: MessageFull>(&self) -> ClientResult<()> {
+        match self.typed_messages_mut()?.remove_handler::<M>() {
+            true => Ok(()),
+            false => Err(format!("No handler for {}", M::descriptor().full_name())),
+        }
+    }
+    pub fn register_on_message_decode_error(&self, callback: impl Fn(&Client, &Endpoint, i64, &str) + 'static) {
+        self.callbacks.borrow_mut().on_message_decode_error_callback = Some(Box::from(callback));
+    }
+    fn typed_messages_mut(&self) -> ClientResult<RefMut<'_, TypedMessageHandlers<Client>>> {
+        self.typed_messages
+            .try_borrow_mut()
+            .map_err(|_| "Message handlers are in use".to_string())
+    }
+
+    /// Entities replicated from the server, see `Server::spawn_entity`
+    pub fn entities(&self) -> Vec<EntityId> {
+        self.replicated.borrow().ids()
+    }
+    pub fn entity(&self, entity: EntityId) -> Option<ReplicatedEntity> {
+        self.replicated.borrow().get(entity).cloned()
+    }
+    /// Decode the `M` component of `entity`, set with `Server::set_component_typed`
+    pub fn entity_component_typed<M: MessageFull>(&self, entity: EntityId) -> Option<Result<M, String>> {
+        let component_type = self.message_type_id::<M>();
+        self.replicated.borrow().get(entity)?.component_typed(component_type)
+    }
+    /// Tick of the last snapshot applied
+    pub fn replication_tick(&self) -> Option<u64> {
+        self.replicated.borrow().last_tick()
+    }
+    /// Called when an entity appears, with the components it has
+    pub fn register_on_entity_spawned(&self, callback: impl Fn(&Client, &ReplicatedEntity) + 'static) {
+        self.callbacks.borrow_mut().on_entity_spawned_callback = Some(Box::from(callback));
+    }
+    /// Called for every changed component of an entity, with `None` if the component was removed
+    pub fn register_on_entity_updated(&self, callback: impl Fn(&Client, EntityId, i64, Option<&[u8]>) + 'static) {
+        self.callbacks.borrow_mut().on_entity_updated_callback = Some(Box::from(callback));
+    }
+    /// Called with the last state of a removed entity
+    pub fn register_on_entity_despawned(&self, callback: impl Fn(&Client, &ReplicatedEntity) + 'static) {
+        self.callbacks.borrow_mut().on_entity_despawned_callback = Some(Box::from(callback));
+    }
+    fn apply_snapshot(&self, snapshot: general_omgpp_message::Snapshot, callbacks: &RefCell<ClientCallbacks>) {
+        let tick = snapshot.tick;
+        let Some(events) = self.replicated.borrow_mut().apply(snapshot) else {
+            return;
+        };
+        let mut ack = GeneralOmgppMessage::new();
+        let mut snapshot_ack = general_omgpp_message::SnapshotAck::new();
+        snapshot_ack.tick = tick;
+        ack.data = Some(Data::SnapshotAck(snapshot_ack));
+        _ = self.send_message(SendMode::Unreliable, ack);
+
+        let callbacks = callbacks.borrow();
+        for event in events {
+            match event {
+                ReplicationEvent::Spawned(entity) => {
+                    if let Some(cb) = &callbacks.on_entity_spawned_callback {
+                        cb(self, &entity)
+                    }
+                }
+                ReplicationEvent::Updated(entity, component_type, data) => {
+                    if let Some(cb) = &callbacks.on_entity_updated_callback {
+                        cb(self, entity, component_type, data.as_deref())
+                    }
+                }
+                ReplicationEvent::Despawned(entity) => {
+                    if let Some(cb) = &callbacks.on_entity_despawned_callback {
+                        cb(self, &entity)
+                    }
+                }
+            }
+        }
     }
 
     pub fn call_rpc(
@@ -212,46 +693,75 @@ impl Client {
         arg_type: i64,
         arg_data: Option<&[u8]>,
     ) -> ClientResult<()> {
-        if let Some(socket) = &self.socket {
-            let msg_bytes = create_rpc_message(reliable, method_id, request_id, arg_type, arg_data)
-                .or_else(|_or| Err("Cannot create rpc message".to_string()))?;
-
-            let flags = match reliable {
-                true => k_nSteamNetworkingSend_Reliable,
-                false => k_nSteamNetworkingSend_Unreliable,
-            };
+        let message = create_rpc_message(reliable, method_id, request_id, arg_type, arg_data);
+        self.send_message(SendMode::from_reliable(reliable), message)
+    }
+    /// Append an interceptor to the chain. It sees every decoded inbound message before it is
+    /// dispatched and every outbound message before it is encoded, and can modify, handle or reject it
+    pub fn add_interceptor(
+        &self,
+        name: &str,
+        interceptor: impl Fn(&Client, &InterceptContext, &mut GeneralOmgppMessage) -> Intercept + 'static,
+    ) -> ClientResult<()> {
+        self.interceptors
+            .try_borrow_mut()
+            .map_err(|_| "Interceptors are in use".to_string())?
+            .add(name, Box::new(interceptor))
+    }
+    pub fn remove_interceptor(&self, name: &str) -> ClientResult<()> {
+        self.interceptors
+            .try_borrow_mut()
+            .map_err(|_| "Interceptors are in use".to_string())?
+            .remove(name)
+    }
 
-            // TODO check send result
-            let _send_results =
-                TransmitterHelper::send(socket, &[socket.connection()], flags, &msg_bytes);
+    fn send_with_mode(&self, mode: SendMode, msg_type: i64, data: &[u8]) -> ClientResult<()> {
+        let message = create_general_message(msg_type, data);
+        self.send_message(mode, message)
+    }
+    fn apply_connection_config(&self) -> ClientResult<()> {
+        let (Some(transport), Some(connection)) = (&self.transport, self.connection) else {
+            return Ok(());
+        };
+        match self.connection_config_changed.replace(false) {
+            true => transport.configure_connection(connection, &self.connection_config),
+            false => Ok(()),
         }
-        Ok(())
     }
-
-    fn send_with_flags(&self, flags: i32, msg_type: i64, data: &[u8]) -> ClientResult<()> {
-        if let Some(socket) = &self.socket {
-            let msg_bytes = create_general_message(msg_type, data)
-                .or_else(|_err| Err("Cannot create general message"))?;
-
-            // TODO check send result
-            let _send_results =
-                TransmitterHelper::send(socket, &[socket.connection()], flags, &msg_bytes);
+    fn send_message(&self, mode: SendMode, message: GeneralOmgppMessage) -> ClientResult<()> {
+        let lane = self.lanes.borrow().lane_of(&message);
+        self.send_message_on_lane(mode, lane, message)
+    }
+    fn send_message_on_lane(&self, mode: SendMode, lane: u16, mut message: GeneralOmgppMessage) -> ClientResult<()> {
+        let (Some(transport), Some(connection)) = (&self.transport, self.connection) else {
+            return Ok(());
+        };
+        self.apply_connection_config()?;
+        let endpoint = self.connection_tracker.borrow().server_endpoint;
+        let context = InterceptContext {
+            direction: Direction::Outbound,
+            peer: &Uuid::nil(),
+            endpoint: &endpoint,
+        };
+        match self.interceptors.borrow().run(self, &context, &mut message) {
+            Intercept::Continue => (),
+            Intercept::Handled => return Ok(()),
+            Intercept::Reject(reason) => return Err(reason),
         }
-        Ok(())
+        let msg_bytes = message
+            .write_to_bytes()
+            .map_err(|_| "Cannot create general message".to_string())?;
+        transport.send_on_lane(&[connection], mode, lane, &msg_bytes)
     }
     fn process_connection_events(
         &self,
-        event: gns::GnsConnectionEvent,
+        event: TransportEvent,
         callbacks: &RefCell<ClientCallbacks>,
         connection_tracker: &RefCell<ConnectionTracker>,
     ) {
-        let endpoint = event.info().to_endpoint();
-        match (event.old_state(), event.info().state()) {
+        match event {
             // client tries to connect
-            (
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_None,
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting,
-            ) => {
+            TransportEvent::Connecting(_, endpoint) => {
                 connection_tracker.borrow_mut().track_connection_state(ConnectionState::Connecting);
                 let new_state = connection_tracker.borrow().state();
                 if let Some(cb) = &callbacks.borrow().on_connection_changed_callback{
@@ -259,13 +769,7 @@ impl Client {
                 }
             }
             // client disconnected gracefully (? or may be not)
-            (
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting
-                | ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connected,
-                 ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_None
-                |ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_ClosedByPeer
-                |ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_ProblemDetectedLocally,
-            ) => {
+            TransportEvent::Disconnected(_, endpoint) => {
                 connection_tracker.borrow_mut().track_connection_state(ConnectionState::Disconnected);
                 let new_state = connection_tracker.borrow().state();
                 if let Some(cb) = &callbacks.borrow().on_connection_changed_callback {
@@ -273,10 +777,7 @@ impl Client {
                 }
             }
             // client connected but not authenticated
-            (
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting,
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connected,
-            ) => {
+            TransportEvent::Connected(_, endpoint) => {
                 connection_tracker.borrow_mut().track_connection_state(ConnectionState::ConnectedUnverified);
                 let new_state = connection_tracker.borrow().state();
                 if let Some(cb) = &callbacks.borrow().on_connection_changed_callback {
@@ -288,63 +789,124 @@ impl Client {
                 }
                 _ = self.send_cmd(OmgppPredefinedCmd::AUTH, 0, auth_params);
             }
-
-            (_, _) => (),
         }
     }
 
     fn process_messages(
         &self,
-        gns_msg: &gns::GnsNetworkMessage<gns::ToReceive>,
+        data: &[u8],
+        connection_tracker: &RefCell<ConnectionTracker>,
+        callbacks: &RefCell<ClientCallbacks>,
+    ) -> ClientResult<()> {
+        let Ok(decoded) = GeneralOmgppMessage::parse_from_bytes(data) else {
+            // cannot decode message;
+            return Ok(());
+        };
+        let batch = match decoded.data {
+            Some(Data::Batch(batch)) => batch,
+            _ => return self.process_message(decoded, connection_tracker, callbacks),
+        };
+        // batches are unpacked one level deep
+        let mut result = Ok(());
+        for message in &batch.messages {
+            let Ok(decoded) = GeneralOmgppMessage::parse_from_bytes(message) else {
+                continue;
+            };
+            if matches!(decoded.data, Some(Data::Batch(_))) {
+                continue;
+            }
+            result = result.and(self.process_message(decoded, connection_tracker, callbacks));
+        }
+        result
+    }
+    fn process_message(
+        &self,
+        mut decoded: GeneralOmgppMessage,
         connection_tracker: &RefCell<ConnectionTracker>,
         callbacks: &RefCell<ClientCallbacks>,
     ) -> ClientResult<()> {
-        let data = gns_msg.payload();
         let sender = connection_tracker.borrow().server_endpoint.clone();
-        if let Some(decoded) = GeneralOmgppMessage::parse_from_bytes(data).ok() {
-            // we decoded the message
-            match decoded.data {
-                Some(Data::Message(message)) => {
+        let context = InterceptContext {
+            direction: Direction::Inbound,
+            peer: &Uuid::nil(),
+            endpoint: &sender,
+        };
+        if self.interceptors.borrow().run(self, &context, &mut decoded) != Intercept::Continue {
+            return Ok(());
+        }
+        self.time_sync.borrow_mut().observe_tick(decoded.server_tick);
+        self.message_server_time.set(Some(Duration::from_micros(decoded.server_time)));
+        let last_input = (decoded.last_input > 0).then_some(decoded.last_input);
+        self.message_last_input.set(last_input);
+        if let Some(last_input) = last_input {
+            self.acknowledge_inputs(last_input);
+        }
+        match decoded.data {
+            Some(Data::Message(message)) => {
+                let typed_result =
+                    self.typed_messages
+                        .borrow()
+                        .dispatch(self, &Uuid::nil(), &sender, message.type_, &message.data);
+                match typed_result {
+                    Some(Ok(())) => (),
+                    Some(Err(reason)) => {
+                        if let Some(cb) = &callbacks.borrow().on_message_decode_error_callback {
+                            cb(self, &sender, message.type_, &reason)
+                        }
+                    }
                     // cb stands for callback
-                    if let Some(cb) = &callbacks.borrow().on_message_callback {
-                        cb(self, &sender, message.type_, message.data)
+                    None => {
+                        if let Some(cb) = &callbacks.borrow().on_message_callback {
+                            cb(self, &sender, message.type_, message.data)
+                        }
                     }
                 }
-                Some(Data::Rpc(rpc_call)) => {
-                    if let Some(rpc_callback) = &callbacks.borrow().on_rpc_callback {
-                        rpc_callback(
-                            self,
-                            &sender,
-                            rpc_call.reliable,
-                            rpc_call.method_id,
-                            rpc_call.request_id,
-                            rpc_call.arg_type,
-                            rpc_call.arg_data,
-                        );
-                    };
-                }
-                Some(Data::Cmd(cmd)) =>{
-                    self.cmd_handlers
-                    .borrow()
-                    .handle(self, &Uuid::nil(), &sender, &cmd);
-                }
-                _ => (),
             }
-        } else {
-            // cannot decode message;
+            Some(Data::Rpc(rpc_call)) => {
+                if let Some(rpc_callback) = &callbacks.borrow().on_rpc_callback {
+                    rpc_callback(
+                        self,
+                        &sender,
+                        rpc_call.reliable,
+                        rpc_call.method_id,
+                        rpc_call.request_id,
+                        rpc_call.arg_type,
+                        rpc_call.arg_data,
+                    );
+                };
+            }
+            Some(Data::Snapshot(snapshot)) => self.apply_snapshot(snapshot, callbacks),
+            Some(Data::LockstepStart(start)) => self.lockstep.borrow_mut().start(start),
+            Some(Data::LockstepTurn(turn)) => self.lockstep.borrow_mut().push(turn),
+            Some(Data::LockstepEnd(end)) => self.lockstep.borrow_mut().end(end),
+            Some(Data::TimeSync(sync)) => self.time_sync.borrow_mut().add_sample(
+                Duration::from_micros(sync.client_time),
+                self.local_time(),
+                Duration::from_micros(sync.server_time),
+            ),
+            Some(Data::Cmd(cmd)) =>{
+                // commands come from the server, which is trusted
+                let roles = HashSet::new();
+                let permissions = CmdPermissions { authenticated: true, roles: &roles };
+                _ = self.cmd_handlers
+                .borrow()
+                .handle(self, &Uuid::nil(), &sender, &cmd, &permissions);
+            }
+            _ => (),
         }
+        self.message_server_time.set(None);
+        self.message_last_input.set(None);
         Ok(())
     }
 }
 
-fn create_general_message(msg_type: i64, data: &[u8]) -> protobuf::Result<Vec<u8>> {
+fn create_general_message(msg_type: i64, data: &[u8]) -> GeneralOmgppMessage {
     let mut payload = GeneralOmgppMessage::new();
     let mut message = general_omgpp_message::Message::new();
     message.type_ = msg_type;
     message.data = Vec::from(data); // somehow get rid of unessesary array copying
     payload.data = Some(Data::Message(message));
-    let bytes = payload.write_to_bytes()?;
-    return Ok(bytes);
+    payload
 }
 fn create_rpc_message(
     reliable: bool,
@@ -352,7 +914,7 @@ fn create_rpc_message(
     request_id: u64,
     arg_type: i64,
     data: Option<&[u8]>,
-) -> protobuf::Result<Vec<u8>> {
+) -> GeneralOmgppMessage {
     let mut payload = GeneralOmgppMessage::new();
     let mut rpc = general_omgpp_message::RpcCall::new();
     rpc.reliable = reliable;
@@ -364,21 +926,19 @@ fn create_rpc_message(
         None => Vec::new(),
     };
     payload.data = Some(Data::Rpc(rpc));
-    let bytes = payload.write_to_bytes()?;
-    return Ok(bytes);
+    payload
 }
 
 fn create_cmd_message(
     cmd: String,
     request_id: u64,
     args: Vec<String>,
-) -> protobuf::Result<Vec<u8>> {
+) -> GeneralOmgppMessage {
     let mut payload = GeneralOmgppMessage::new();
     let mut request = general_omgpp_message::CmdRequest::new();
     request.cmd = cmd;
     request.request_id = request_id;
     request.args = args;
     payload.data = Some(Data::Cmd(request));
-    let bytes = payload.write_to_bytes()?;
-    return Ok(bytes);
+    payload
 }
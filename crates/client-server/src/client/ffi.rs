@@ -1,19 +1,37 @@
-use crate::client::Client;
+use crate::client::{
+    interpolation::{InterpolationBuffer, SampleKind},
+    Client,
+};
 use omgpp_core::{
-    ffi::{EndpointFFI, ToFfi},
-    ConnectionState,
+    cmd_handler::CmdHandler,
+    ffi::{lanes_from_ffi, strings_from_ffi, CStringArray, EndpointFFI, NetworkSimulationFFI, ToFfi},
+    messages::general_message::general_omgpp_message::CmdRequest,
+    network_simulation::NetworkSimulation,
+    ConnectionState, Endpoint,
 };
+use uuid::Uuid;
 use std::{
     ffi::{c_char, c_uchar, CStr},
     net::IpAddr,
-    ptr::null_mut,
+    ptr::{null, null_mut},
     str::FromStr,
+    time::Duration,
 };
 
 // FFI
 type ClientOnConnectionChanged = extern "C" fn(EndpointFFI, ConnectionState);
 type ClientOnMessage = extern "C" fn(EndpointFFI, i64, *const c_uchar, usize);
 type ClientOnRpc = extern "C" fn(EndpointFFI, bool, i64, u64, i64, *const c_uchar, usize);
+// (endpoint, cmd, request_id, args, args_count)
+type ClientOnCmd = extern "C" fn(EndpointFFI, *const c_char, u64, *const *const c_char, usize);
+type ClientOnAnnouncement = extern "C" fn(EndpointFFI, *const c_char);
+// (entity, entity_type)
+type ClientOnEntitySpawned = extern "C" fn(u64, i64);
+// (entity, component_type, data, size); data is null if the component was removed
+type ClientOnEntityUpdated = extern "C" fn(u64, i64, *const c_uchar, usize);
+type ClientOnEntityDespawned = extern "C" fn(u64, i64);
+// (player, data, size, missing)
+type ClientOnLockstepInput = extern "C" fn(u32, *const c_uchar, usize, bool);
 
 #[no_mangle]
 pub unsafe extern "C" fn client_create(ip: *const c_char, port: u16) -> *mut Client {
@@ -128,6 +146,163 @@ pub unsafe extern "C" fn client_call_rpc(
         .call_rpc(reliable, method_id, request_id, arg_type, msg_data);
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn client_set_timeout_initial(client: *mut Client, timeout_ms: u32) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .timeout_initial = Some(Duration::from_millis(timeout_ms as u64));
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_timeout_connected(client: *mut Client, timeout_ms: u32) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .timeout_connected = Some(Duration::from_millis(timeout_ms as u64));
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_send_buffer_size(client: *mut Client, size: i32) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .send_buffer_size = Some(size);
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_send_rate_min(client: *mut Client, rate: i32) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .send_rate_min = Some(rate);
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_send_rate_max(client: *mut Client, rate: i32) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .send_rate_max = Some(rate);
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_nagle_time(client: *mut Client, nagle_time_us: u32) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .nagle_time = Some(Duration::from_micros(nagle_time_us as u64));
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_mtu_packet_size(client: *mut Client, size: i32) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .mtu_packet_size = Some(size);
+}
+// Independently ordered lanes, `priorities` and `weights` hold `count` values; 0 lanes restores the single default lane
+#[no_mangle]
+pub unsafe extern "C" fn client_set_lanes(
+    client: *mut Client,
+    priorities: *const i32,
+    weights: *const u16,
+    count: usize,
+) -> bool {
+    let Some(lanes) = lanes_from_ffi(priorities, weights, count) else {
+        return false;
+    };
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .connection_config_mut()
+        .lanes = lanes;
+    true
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_message_lane(client: *mut Client, msg_type: i64, lane: u16) -> bool {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .set_message_lane(msg_type, lane)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_rpc_lane(client: *mut Client, method_id: i64, lane: u16) -> bool {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .set_rpc_lane(method_id, lane)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_set_cmd_lane(client: *mut Client, cmd: *const c_char, lane: u16) -> bool {
+    let Ok(cmd) = CStr::from_ptr(cmd).to_str() else {
+        return false;
+    };
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .set_cmd_lane(cmd, lane)
+        .is_ok()
+}
+// Send on `lane` instead of the lane `msg_type` is mapped to
+#[no_mangle]
+pub unsafe extern "C" fn client_send_on_lane(
+    client: *mut Client,
+    lane: u16,
+    reliable: bool,
+    msg_type: i64,
+    data: *const c_uchar,
+    offset: isize,
+    size: usize,
+) -> bool {
+    let msg_data = core::slice::from_raw_parts(data.offset(offset), size);
+    let client = client.as_ref().expect("Client cannot be null");
+    match reliable {
+        true => client.send_reliable_on_lane(lane, msg_type, msg_data),
+        false => client.send_on_lane(lane, msg_type, msg_data),
+    }
+    .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_call_rpc_on_lane(
+    client: *mut Client,
+    lane: u16,
+    reliable: bool,
+    method_id: i64,
+    request_id: u64,
+    arg_type: i64,
+    arg_data: *const c_uchar,
+    arg_data_offset: isize,
+    arg_data_size: usize,
+) -> bool {
+    let msg_data = match arg_data_size {
+        0 => None,
+        _ => Some(core::slice::from_raw_parts(arg_data.offset(arg_data_offset), arg_data_size)),
+    };
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .call_rpc_on_lane(lane, reliable, method_id, request_id, arg_type, msg_data)
+        .is_ok()
+}
+// Fake network conditions of outgoing (`send`) and incoming (`recv`) packets; null or all zeros
+// turns the simulation off. Reordered packets are held back `reorder_time_ms` and duplicates up to
+// `duplicate_time_max_ms`, with 0 both arrive in order right away, e.g. 10ms makes them visible
+#[no_mangle]
+pub unsafe extern "C" fn client_set_network_simulation(
+    client: *mut Client,
+    simulation: *const NetworkSimulationFFI,
+) -> bool {
+    let simulation = simulation.as_ref().map(NetworkSimulation::from).unwrap_or_default();
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .set_network_simulation(simulation)
+        .is_ok()
+}
 #[no_mangle]
 #[allow(unreachable_patterns)]
 pub unsafe extern "C" fn client_destroy(client: *mut Client) {
@@ -138,3 +313,288 @@ pub unsafe extern "C" fn client_destroy(client: *mut Client) {
         _ => (),
     }
 }
+// Handle `cmd` sent by the server; a null `cmd` handles every command without its own handler.
+// Returns false if `cmd` is reserved or already registered
+#[no_mangle]
+pub unsafe extern "C" fn client_register_cmd(
+    client: *mut Client,
+    cmd: *const c_char,
+    callback: ClientOnCmd,
+) -> bool {
+    let client = client.as_mut().expect("Client cannot be null");
+    let handler = move |_client: &Client,
+                        _uuid: &Uuid,
+                        endpoint: &Endpoint,
+                        _handler: &CmdHandler<Client>,
+                        request: &CmdRequest| {
+        let cmd = CStringArray::new(std::slice::from_ref(&request.cmd));
+        let args = CStringArray::new(&request.args);
+        callback(
+            endpoint.to_ffi(),
+            *cmd.as_ptr(),
+            request.request_id,
+            args.as_ptr(),
+            args.len(),
+        )
+    };
+    if cmd.is_null() {
+        let handler = CmdHandler::new("", false, Box::new(handler));
+        return client.register_cmd_fallback(Some(handler)).is_ok();
+    }
+    let Ok(cmd) = CStr::from_ptr(cmd).to_str() else {
+        return false;
+    };
+    client
+        .register_cmd_handler(CmdHandler::new(cmd, false, Box::new(handler)))
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_unregister_cmd(client: *mut Client, cmd: *const c_char) -> bool {
+    let Ok(cmd) = CStr::from_ptr(cmd).to_str() else {
+        return false;
+    };
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .unregister_cmd_handler(cmd)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_send_cmd(
+    client: *mut Client,
+    cmd: *const c_char,
+    request_id: u64,
+    args: *const *const c_char,
+    args_count: usize,
+) -> bool {
+    let (Ok(cmd), Some(args)) = (CStr::from_ptr(cmd).to_str(), strings_from_ffi(args, args_count)) else {
+        return false;
+    };
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .send_cmd(cmd, request_id, Some(args))
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_register_on_announcement(
+    client: *mut Client,
+    callback: ClientOnAnnouncement,
+) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .register_on_announcement(move |_client, endpoint, text| {
+            let text = CStringArray::new(&[text.to_string()]);
+            callback(endpoint.to_ffi(), *text.as_ptr())
+        });
+}
+// A spawned entity is followed by one update per component it has
+#[no_mangle]
+pub unsafe extern "C" fn client_register_on_entity_replication(
+    client: *mut Client,
+    on_spawned: ClientOnEntitySpawned,
+    on_updated: ClientOnEntityUpdated,
+    on_despawned: ClientOnEntityDespawned,
+) {
+    let client = client.as_mut().expect("Client cannot be null");
+    client.register_on_entity_spawned(move |_client, entity| {
+        on_spawned(entity.id, entity.entity_type);
+        for (component_type, data) in &entity.components {
+            on_updated(entity.id, *component_type, data.as_ptr(), data.len());
+        }
+    });
+    client.register_on_entity_updated(move |_client, entity, component_type, data| match data {
+        Some(data) => on_updated(entity, component_type, data.as_ptr(), data.len()),
+        None => on_updated(entity, component_type, null(), 0),
+    });
+    client.register_on_entity_despawned(move |_client, entity| on_despawned(entity.id, entity.entity_type));
+}
+// Estimated server time in microseconds, -1 until the clock is synchronized
+#[no_mangle]
+pub unsafe extern "C" fn client_server_time_micros(client: *mut Client) -> i64 {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .server_time()
+        .map_or(-1, |time| time.as_micros() as i64)
+}
+// Smoothed round trip time in microseconds, -1 until the clock is synchronized
+#[no_mangle]
+pub unsafe extern "C" fn client_rtt_micros(client: *mut Client) -> i64 {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .rtt()
+        .map_or(-1, |rtt| rtt.as_micros() as i64)
+}
+// Latest tick stamped on a server message, -1 before the first message
+#[no_mangle]
+pub unsafe extern "C" fn client_server_tick(client: *mut Client) -> i64 {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .server_tick()
+        .map_or(-1, |tick| tick as i64)
+}
+// Server time of the message being handled in a callback, -1 outside of callbacks
+#[no_mangle]
+pub unsafe extern "C" fn client_message_server_time_micros(client: *mut Client) -> i64 {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .message_server_time()
+        .map_or(-1, |time| time.as_micros() as i64)
+}
+// Send the input of this tick, returns its sequence number or 0 if it was not sent
+#[no_mangle]
+pub unsafe extern "C" fn client_send_input(client: *mut Client, data: *const c_uchar, offset: isize, size: usize) -> u64 {
+    let input = match size {
+        0 => &[],
+        _ => core::slice::from_raw_parts(data.offset(offset), size),
+    };
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .send_input(input)
+        .unwrap_or(0)
+}
+// Last input the server processed when it sent the message being handled, 0 if unknown
+#[no_mangle]
+pub unsafe extern "C" fn client_message_last_input(client: *mut Client) -> u64 {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .message_last_input()
+        .unwrap_or(0)
+}
+// Player number of the client in its lockstep session, -1 outside a session
+#[no_mangle]
+pub unsafe extern "C" fn client_lockstep_player(client: *mut Client) -> i64 {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .lockstep_player()
+        .map_or(-1, |player| player as i64)
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_send_lockstep_input(
+    client: *mut Client,
+    turn: u64,
+    data: *const c_uchar,
+    offset: isize,
+    size: usize,
+) -> bool {
+    let input = match size {
+        0 => &[],
+        _ => core::slice::from_raw_parts(data.offset(offset), size),
+    };
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .send_lockstep_input(turn, input)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn client_send_lockstep_checksum(client: *mut Client, turn: u64, checksum: u64) -> bool {
+    client
+        .as_ref()
+        .expect("Client cannot be null")
+        .send_lockstep_checksum(turn, checksum)
+        .is_ok()
+}
+// Calls `callback` with the input of every player of the next completed turn and returns the turn,
+// 0 if it is not complete yet
+#[no_mangle]
+pub unsafe extern "C" fn client_take_lockstep_turn(client: *mut Client, callback: ClientOnLockstepInput) -> u64 {
+    let Some(turn) = client.as_ref().expect("Client cannot be null").take_lockstep_turn() else {
+        return 0;
+    };
+    for (player, input) in turn.inputs.iter().enumerate() {
+        match input {
+            Some(data) => callback(player as u32, data.as_ptr(), data.len(), false),
+            None => callback(player as u32, null(), 0, true),
+        }
+    }
+    turn.turn
+}
+// How far behind the server time remote states are rendered, reported for lag compensation
+#[no_mangle]
+pub unsafe extern "C" fn client_set_interpolation_delay_micros(client: *mut Client, delay_micros: u64) {
+    client
+        .as_mut()
+        .expect("Client cannot be null")
+        .set_interpolation_delay(Duration::from_micros(delay_micros));
+}
+
+// Interpolation of float arrays, e.g. positions of a remote player
+type FloatInterpolationBuffer = InterpolationBuffer<Vec<f32>>;
+
+#[no_mangle]
+pub extern "C" fn interpolation_buffer_create(delay_micros: u64, max_extrapolation_micros: u64) -> *mut FloatInterpolationBuffer {
+    let buffer = InterpolationBuffer::new(Duration::from_micros(delay_micros))
+        .with_max_extrapolation(Duration::from_micros(max_extrapolation_micros));
+    Box::into_raw(Box::new(buffer))
+}
+#[no_mangle]
+pub unsafe extern "C" fn interpolation_buffer_set_delay(buffer: *mut FloatInterpolationBuffer, delay_micros: u64) {
+    buffer
+        .as_mut()
+        .expect("Buffer cannot be null")
+        .set_delay(Duration::from_micros(delay_micros));
+}
+#[no_mangle]
+pub unsafe extern "C" fn interpolation_buffer_push(
+    buffer: *mut FloatInterpolationBuffer,
+    server_time_micros: u64,
+    values: *const f32,
+    count: usize,
+) {
+    let values = match count {
+        0 => Vec::new(),
+        _ => core::slice::from_raw_parts(values, count).to_vec(),
+    };
+    buffer
+        .as_mut()
+        .expect("Buffer cannot be null")
+        .push(Duration::from_micros(server_time_micros), values);
+}
+// Writes up to `count` values of the state at `server_time - delay` to `out_values`.
+// Returns false if the buffer is empty
+#[no_mangle]
+pub unsafe extern "C" fn interpolation_buffer_sample(
+    buffer: *mut FloatInterpolationBuffer,
+    server_time_micros: u64,
+    out_values: *mut f32,
+    count: usize,
+    out_kind: *mut SampleKind,
+) -> bool {
+    let buffer = buffer.as_mut().expect("Buffer cannot be null");
+    let Some(sample) = buffer.sample(Duration::from_micros(server_time_micros)) else {
+        return false;
+    };
+    if count > 0 {
+        let out = core::slice::from_raw_parts_mut(out_values, count);
+        for (out, value) in out.iter_mut().zip(&sample.state) {
+            *out = *value;
+        }
+    }
+    if let Some(kind) = out_kind.as_mut() {
+        *kind = sample.kind;
+    }
+    true
+}
+#[no_mangle]
+pub unsafe extern "C" fn interpolation_buffer_is_starved(buffer: *mut FloatInterpolationBuffer) -> bool {
+    buffer.as_ref().expect("Buffer cannot be null").is_starved()
+}
+#[no_mangle]
+pub unsafe extern "C" fn interpolation_buffer_starvation_count(buffer: *mut FloatInterpolationBuffer) -> u64 {
+    buffer.as_ref().expect("Buffer cannot be null").starvation_count()
+}
+#[no_mangle]
+pub unsafe extern "C" fn interpolation_buffer_destroy(buffer: *mut FloatInterpolationBuffer) {
+    if !buffer.is_null() {
+        drop(Box::from_raw(buffer));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{collections::VecDeque, time::Duration};
+
+pub use crate::interpolate::Interpolate;
+
+const DEFAULT_CAPACITY: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum SampleKind {
+    /// Between two buffered states
+    Interpolated,
+    /// Past the newest state, continuing the last movement
+    Extrapolated,
+    /// A buffered state as is: before the oldest one, after extrapolation ran out or with a single state
+    Held,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample<S> {
+    pub state: S,
+    pub kind: SampleKind,
+}
+
+/// Server states ordered by server time, rendered `delay` behind the estimated server time so
+/// there is usually a later state to interpolate towards
+pub struct InterpolationBuffer<S: Interpolate> {
+    states: VecDeque<(Duration, S)>,
+    delay: Duration,
+    max_extrapolation: Duration,
+    capacity: usize,
+    starved: bool,
+    starvation_count: u64,
+}
+impl<S: Interpolate> InterpolationBuffer<S> {
+    pub fn new(delay: Duration) -> InterpolationBuffer<S> {
+        InterpolationBuffer {
+            states: VecDeque::new(),
+            delay,
+            max_extrapolation: Duration::ZERO,
+            capacity: DEFAULT_CAPACITY,
+            starved: false,
+            starvation_count: 0,
+        }
+    }
+    /// How far past the newest state to extrapolate before holding it. No extrapolation by default
+    pub fn with_max_extrapolation(mut self, max_extrapolation: Duration) -> InterpolationBuffer<S> {
+        self.max_extrapolation = max_extrapolation;
+        self
+    }
+    /// Number of states kept, the oldest are dropped first
+    pub fn with_capacity(mut self, capacity: usize) -> InterpolationBuffer<S> {
+        self.capacity = capacity.max(2);
+        self
+    }
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.starved = false;
+    }
+    /// Add the state the server had at `server_time`. States may arrive out of order; a second
+    /// state for the same time replaces the first
+    pub fn push(&mut self, server_time: Duration, state: S) {
+        let index = self.states.partition_point(|(time, _)| *time < server_time);
+        match self.states.get_mut(index) {
+            Some((time, existing)) if *time == server_time => *existing = state,
+            _ => self.states.insert(index, (server_time, state)),
+        }
+        while self.states.len() > self.capacity {
+            self.states.pop_front();
+        }
+    }
+    /// Server time of the newest state
+    pub fn newest_time(&self) -> Option<Duration> {
+        self.states.back().map(|(time, _)| *time)
+    }
+    /// Whether the last `sample` ran past the newest state
+    pub fn is_starved(&self) -> bool {
+        self.starved
+    }
+    /// How many times sampling ran out of states. Raise the delay if it keeps growing
+    pub fn starvation_count(&self) -> u64 {
+        self.starvation_count
+    }
+    /// How much buffered time is left at `server_now`, zero when starved
+    pub fn buffered_ahead(&self, server_now: Duration) -> Duration {
+        let render_time = server_now.saturating_sub(self.delay);
+        self.newest_time()
+            .map_or(Duration::ZERO, |newest| newest.saturating_sub(render_time))
+    }
+
+    /// State at `server_now - delay`, e.g. with `Client::server_time()`. States no longer needed are dropped
+    pub fn sample(&mut self, server_now: Duration) -> Option<Sample<S>> {
+        let render_time = server_now.saturating_sub(self.delay);
+        // keep the last state before the render time, it is the start of the interpolation
+        while self.states.len() > 2 && self.states[1].0 <= render_time {
+            self.states.pop_front();
+        }
+        let (newest_time, newest) = self.states.back()?;
+        let starved = render_time > *newest_time;
+        if starved && !self.starved {
+            self.starvation_count += 1;
+        }
+        self.starved = starved;
+
+        let (first_time, first) = &self.states[0];
+        if render_time <= *first_time || self.states.len() < 2 {
+            let state = match render_time <= *first_time {
+                true => first.clone(),
+                false => newest.clone(),
+            };
+            let kind = SampleKind::Held;
+            return Some(Sample { state, kind });
+        }
+        let (previous_time, previous) = &self.states[self.states.len() - 2];
+        let (from_time, from, to_time, to) = match starved {
+            true => (*previous_time, previous, *newest_time, newest),
+            false => (*first_time, first, self.states[1].0, &self.states[1].1),
+        };
+        let span = (to_time - from_time).as_secs_f32();
+        if !starved {
+            let t = (render_time - from_time).as_secs_f32() / span;
+            let kind = SampleKind::Interpolated;
+            return Some(Sample { state: from.interpolate(to, t), kind });
+        }
+        let overshoot = render_time - to_time;
+        let kind = match overshoot <= self.max_extrapolation {
+            true => SampleKind::Extrapolated,
+            false => SampleKind::Held,
+        };
+        let t = 1.0 + overshoot.min(self.max_extrapolation).as_secs_f32() / span;
+        Some(Sample { state: from.interpolate(to, t), kind })
+    }
+}
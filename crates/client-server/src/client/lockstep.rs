@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use omgpp_core::messages::general_message::general_omgpp_message;
+
+use crate::lockstep::{LockstepTurn, SessionId};
+
+/// Completed turns of the lockstep session the client plays in, handed out strictly in order
+#[derive(Default)]
+pub struct LockstepState {
+    session: Option<SessionId>,
+    player: usize,
+    players: usize,
+    next_turn: u64,
+    turns: BTreeMap<u64, LockstepTurn>,
+}
+impl LockstepState {
+    pub fn session(&self) -> Option<SessionId> {
+        self.session
+    }
+    /// Player number of this client, the index of its inputs in every turn
+    pub fn player(&self) -> Option<usize> {
+        self.session.map(|_| self.player)
+    }
+    pub fn players(&self) -> usize {
+        self.players
+    }
+    /// Turn `take` returns next, 0 outside a session
+    pub fn next_turn(&self) -> u64 {
+        self.next_turn
+    }
+    /// Turns received and not taken yet; only the consecutive ones starting at `next_turn` can be taken
+    pub fn buffered(&self) -> usize {
+        self.turns.len()
+    }
+    pub fn take(&mut self) -> Option<LockstepTurn> {
+        let turn = self.turns.remove(&self.next_turn)?;
+        self.next_turn += 1;
+        Some(turn)
+    }
+    pub(crate) fn start(&mut self, start: general_omgpp_message::LockstepStart) {
+        *self = LockstepState {
+            session: Some(start.session),
+            player: start.player as usize,
+            players: start.players as usize,
+            next_turn: 1,
+            turns: Default::default(),
+        };
+    }
+    pub(crate) fn push(&mut self, turn: general_omgpp_message::LockstepTurn) {
+        if self.session != Some(turn.session) || turn.turn < self.next_turn {
+            return;
+        }
+        self.turns
+            .insert(turn.turn, LockstepTurn::from_message(turn, self.players));
+    }
+    // turns received before the end can still be taken
+    pub(crate) fn end(&mut self, end: general_omgpp_message::LockstepEnd) {
+        if self.session == Some(end.session) {
+            self.session = None;
+        }
+    }
+    pub(crate) fn clear(&mut self) {
+        *self = Default::default();
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::VecDeque;
+
+type Simulate<S, I> = Box<dyn Fn(&S, &I) -> S>;
+
+/// Predicted local state: inputs are applied right away with `simulate` and replayed on top of
+/// every authoritative state until the server processed them
+pub struct Predictor<S: Clone, I> {
+    state: S,
+    pending: VecDeque<(u64, I)>,
+    last_reconciled: u64,
+    simulate: Simulate<S, I>,
+}
+impl<S: Clone, I> Predictor<S, I> {
+    pub fn new(state: S, simulate: impl Fn(&S, &I) -> S + 'static) -> Predictor<S, I> {
+        Predictor {
+            state,
+            pending: VecDeque::new(),
+            last_reconciled: 0,
+            simulate: Box::new(simulate),
+        }
+    }
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+    /// Inputs the server did not process yet
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+    /// Apply `input` sent with `sequence`, see `Client::send_input`
+    pub fn predict(&mut self, sequence: u64, input: I) -> &S {
+        self.state = (self.simulate)(&self.state, &input);
+        self.pending.push_back((sequence, input));
+        &self.state
+    }
+    /// Roll back to `authoritative`, the state after the server processed input `last_processed`,
+    /// and replay the inputs sent since. States older than the last reconciled one are ignored
+    /// and `false` is returned
+    pub fn reconcile(&mut self, authoritative: S, last_processed: u64) -> bool {
+        if last_processed < self.last_reconciled {
+            return false;
+        }
+        self.last_reconciled = last_processed;
+        while self.pending.front().is_some_and(|(sequence, _)| *sequence <= last_processed) {
+            self.pending.pop_front();
+        }
+        self.state = authoritative;
+        for (_, input) in &self.pending {
+            self.state = (self.simulate)(&self.state, input);
+        }
+        true
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+
+use omgpp_core::messages::general_message::general_omgpp_message::Snapshot;
+use protobuf::MessageFull;
+
+pub type EntityId = u64;
+
+/// An entity replicated from the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicatedEntity {
+    pub id: EntityId,
+    pub entity_type: i64,
+    pub components: HashMap<i64, Vec<u8>>,
+}
+impl ReplicatedEntity {
+    pub fn component(&self, component_type: i64) -> Option<&[u8]> {
+        self.components.get(&component_type).map(Vec::as_slice)
+    }
+    /// Decode the component sent with `set_component_typed`; `component_type` is the id `M` is sent with
+    pub fn component_typed<M: MessageFull>(&self, component_type: i64) -> Option<Result<M, String>> {
+        let data = self.component(component_type)?;
+        Some(M::parse_from_bytes(data).map_err(|err| format!("Cannot decode {}: {}", M::descriptor().full_name(), err)))
+    }
+}
+
+pub(crate) enum ReplicationEvent {
+    Spawned(ReplicatedEntity),
+    // `None` if the component was removed
+    Updated(EntityId, i64, Option<Vec<u8>>),
+    Despawned(ReplicatedEntity),
+}
+
+/// Entities reconstructed from the snapshots of the server
+#[derive(Default)]
+pub struct ReplicatedEntities {
+    last_tick: Option<u64>,
+    entities: HashMap<EntityId, ReplicatedEntity>,
+}
+impl ReplicatedEntities {
+    pub fn get(&self, id: EntityId) -> Option<&ReplicatedEntity> {
+        self.entities.get(&id)
+    }
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.entities.keys().copied().collect()
+    }
+    /// Tick of the last applied snapshot
+    pub fn last_tick(&self) -> Option<u64> {
+        self.last_tick
+    }
+    pub(crate) fn clear(&mut self) {
+        self.last_tick = None;
+        self.entities.clear();
+    }
+    /// Apply `snapshot` and return what changed. Snapshots older than the last applied one are
+    /// dropped with `None`, they arrived out of order
+    pub(crate) fn apply(&mut self, snapshot: Snapshot) -> Option<Vec<ReplicationEvent>> {
+        if self.last_tick.is_some_and(|last_tick| snapshot.tick <= last_tick) {
+            return None;
+        }
+        self.last_tick = Some(snapshot.tick);
+        let mut events = Vec::new();
+        if snapshot.full {
+            let kept = snapshot.entities.iter().map(|delta| delta.id).collect::<HashSet<_>>();
+            let dropped = self
+                .entities
+                .keys()
+                .filter(|id| !kept.contains(id))
+                .copied()
+                .collect::<Vec<_>>();
+            for id in dropped {
+                events.extend(self.entities.remove(&id).map(ReplicationEvent::Despawned));
+            }
+        }
+        for delta in snapshot.entities {
+            let components = delta
+                .components
+                .into_iter()
+                .map(|component| (component.type_, component.data));
+            let Some(entity) = self.entities.get_mut(&delta.id) else {
+                let entity = ReplicatedEntity {
+                    id: delta.id,
+                    entity_type: delta.entity_type,
+                    components: components.collect(),
+                };
+                self.entities.insert(delta.id, entity.clone());
+                events.push(ReplicationEvent::Spawned(entity));
+                continue;
+            };
+            let mut removed = delta.removed_components;
+            if snapshot.full {
+                // a full snapshot has every component of the entity
+                let sent = components.collect::<HashMap<_, _>>();
+                removed.extend(entity.components.keys().filter(|key| !sent.contains_key(key)));
+                update_components(entity, sent, &mut events);
+            } else {
+                update_components(entity, components, &mut events);
+            }
+            for component_type in removed {
+                if entity.components.remove(&component_type).is_some() {
+                    events.push(ReplicationEvent::Updated(delta.id, component_type, None));
+                }
+            }
+        }
+        for id in snapshot.despawned {
+            events.extend(self.entities.remove(&id).map(ReplicationEvent::Despawned));
+        }
+        Some(events)
+    }
+}
+
+// snapshots resend components until they are acknowledged, only report actual changes
+fn update_components(
+    entity: &mut ReplicatedEntity,
+    components: impl IntoIterator<Item = (i64, Vec<u8>)>,
+    events: &mut Vec<ReplicationEvent>,
+) {
+    for (component_type, data) in components {
+        if entity.components.get(&component_type) != Some(&data) {
+            entity.components.insert(component_type, data.clone());
+            events.push(ReplicationEvent::Updated(entity.id, component_type, Some(data)));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+// samples averaged evenly before switching to exponential smoothing
+const WARMUP_SAMPLES: u32 = 4;
+// weight of a new sample once warmed up
+const SMOOTHING: f64 = 0.125;
+// round trips this many times slower than the average say more about queuing than about the clock
+const OUTLIER_FACTOR: f64 = 2.0;
+
+/// Estimate of the server clock from `TimeSync` round trips. Times are durations since the start of
+/// the respective clock
+#[derive(Debug, Default, Clone)]
+pub struct TimeSync {
+    samples: u32,
+    // microseconds
+    rtt: f64,
+    // server time minus client time in microseconds
+    offset: f64,
+    server_tick: Option<u64>,
+}
+impl TimeSync {
+    pub fn is_warmed_up(&self) -> bool {
+        self.samples >= WARMUP_SAMPLES
+    }
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+    /// Smoothed round trip time
+    pub fn rtt(&self) -> Option<Duration> {
+        (self.samples > 0).then(|| Duration::from_micros(self.rtt.round() as u64))
+    }
+    /// Microseconds to add to the client clock to get the server clock
+    pub fn offset_micros(&self) -> Option<i64> {
+        (self.samples > 0).then(|| self.offset.round() as i64)
+    }
+    pub fn server_time(&self, client_time: Duration) -> Option<Duration> {
+        let micros = client_time.as_micros() as i64 + self.offset_micros()?;
+        Some(Duration::from_micros(micros.max(0) as u64))
+    }
+    /// Last tick the server stamped on a message
+    pub fn server_tick(&self) -> Option<u64> {
+        self.server_tick
+    }
+    pub(crate) fn observe_tick(&mut self, tick: u64) {
+        self.server_tick = Some(self.server_tick.map_or(tick, |last| last.max(tick)));
+    }
+    /// Add a round trip of a request sent at `sent` and answered at `received`, both on the client
+    /// clock, with `server_time` when the server answered
+    pub fn add_sample(&mut self, sent: Duration, received: Duration, server_time: Duration) {
+        let Some(rtt) = received.checked_sub(sent) else {
+            return;
+        };
+        let rtt = rtt.as_micros() as f64;
+        // the answer left the server about half a round trip ago
+        let offset = server_time.as_micros() as f64 + rtt / 2.0 - received.as_micros() as f64;
+        if !self.is_warmed_up() {
+            self.samples += 1;
+            let weight = 1.0 / self.samples as f64;
+            self.rtt += (rtt - self.rtt) * weight;
+            self.offset += (offset - self.offset) * weight;
+            return;
+        }
+        let is_outlier = rtt > self.rtt * OUTLIER_FACTOR;
+        self.samples += 1;
+        self.rtt += (rtt - self.rtt) * SMOOTHING;
+        if !is_outlier {
+            self.offset += (offset - self.offset) * SMOOTHING;
+        }
+    }
+}
@@ -0,0 +1,33 @@
+/// A state that can be blended with a later one. `t` is 0 at `self` and 1 at `next`, above 1
+/// when extrapolating
+pub trait Interpolate: Clone {
+    fn interpolate(&self, next: &Self, t: f32) -> Self;
+}
+impl Interpolate for f32 {
+    fn interpolate(&self, next: &Self, t: f32) -> Self {
+        self + (next - self) * t
+    }
+}
+impl Interpolate for f64 {
+    fn interpolate(&self, next: &Self, t: f32) -> Self {
+        self + (next - self) * t as f64
+    }
+}
+impl<const N: usize> Interpolate for [f32; N] {
+    fn interpolate(&self, next: &Self, t: f32) -> Self {
+        let mut result = *self;
+        for (value, next) in result.iter_mut().zip(next) {
+            *value = value.interpolate(next, t);
+        }
+        result
+    }
+}
+// states of different length are not blended, the later one wins
+impl Interpolate for Vec<f32> {
+    fn interpolate(&self, next: &Self, t: f32) -> Self {
+        if self.len() != next.len() {
+            return next.clone();
+        }
+        self.iter().zip(next).map(|(value, next)| value.interpolate(next, t)).collect()
+    }
+}
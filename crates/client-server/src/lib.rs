@@ -1,2 +1,7 @@
+pub mod backend;
 pub mod client;
-pub mod server;
\ No newline at end of file
+pub mod interpolate;
+pub mod lockstep;
+pub mod server;
+#[cfg(feature = "testing")]
+pub mod testing;
@@ -0,0 +1,47 @@
+use omgpp_core::messages::general_message::general_omgpp_message::{self, LockstepPlayerInput};
+
+pub type SessionId = u64;
+
+/// Inputs of every player for one turn, indexed by player. `None` if the input of the player
+/// did not arrive before the turn timed out
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockstepTurn {
+    pub session: SessionId,
+    pub turn: u64,
+    pub inputs: Vec<Option<Vec<u8>>>,
+}
+impl LockstepTurn {
+    pub(crate) fn from_message(message: general_omgpp_message::LockstepTurn, players: usize) -> LockstepTurn {
+        let mut inputs = vec![None; players];
+        for input in message.inputs {
+            if let Some(slot) = inputs.get_mut(input.player as usize) {
+                *slot = (!input.missing).then_some(input.data);
+            }
+        }
+        LockstepTurn {
+            session: message.session,
+            turn: message.turn,
+            inputs,
+        }
+    }
+    pub(crate) fn to_message(&self) -> general_omgpp_message::LockstepTurn {
+        let mut message = general_omgpp_message::LockstepTurn::new();
+        message.session = self.session;
+        message.turn = self.turn;
+        message.inputs = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(player, data)| {
+                let mut input = LockstepPlayerInput::new();
+                input.player = player as u32;
+                match data {
+                    Some(data) => input.data = data.clone(),
+                    None => input.missing = true,
+                }
+                input
+            })
+            .collect();
+        message
+    }
+}
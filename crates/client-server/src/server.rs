@@ -1,28 +1,51 @@
+pub mod batching;
 pub mod connection_tracker;
 pub mod server_settings;
+pub mod stats;
 pub mod ffi;
+pub mod replication;
+pub mod interest;
+pub mod input;
+pub mod lag_compensation;
+pub mod lockstep;
+pub mod scheduler;
+mod admin;
 
-use std::cell::RefCell;
-use std::time::Duration;
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use std::{fmt::Debug, marker::PhantomData, net::IpAddr};
+use std::time::Duration;
 
+use batching::Batches;
 use connection_tracker::ConnectionTracker;
 
-use gns::ToReceive;
-use gns::{GnsConnectionEvent, GnsNetworkMessage, GnsSocket, IsCreated, IsServer};
-use gns_sys::{
-    k_nSteamNetworkingSend_Reliable, k_nSteamNetworkingSend_Unreliable,
-    ESteamNetworkingConnectionState,
-};
-use omgpp_core::cmd_handler::{CmdHandler, CmdHandlerContainer};
+use crate::backend;
+
+use omgpp_core::clock::{Clock, SystemClock};
+use omgpp_core::cmd_args::{ArgSpec, ArgType, CmdArgs, CmdSchema};
+use omgpp_core::cmd_handler::{CmdError, CmdHandler, CmdHandlerContainer, CmdPermissions};
+use omgpp_core::connection_config::ConnectionConfig;
+use omgpp_core::interceptor::{Direction, Intercept, InterceptContext, InterceptorChain};
+use omgpp_core::lanes::LaneMap;
+use omgpp_core::network_simulation::NetworkSimulation;
 use omgpp_core::messages::general_message::general_omgpp_message::{self, *};
+use omgpp_core::transport::{ConnectionHandle, ConnectionStatus, SendMode, Transport, TransportEvent};
+use omgpp_core::typed_message::TypedMessageHandlers;
+#[cfg(feature = "websocket")]
+use omgpp_core::transport::{multi::MultiTransport, websocket::WebSocketServerTransport};
 use omgpp_core::{
-    messages::general_message::GeneralOmgppMessage, ConnectionState, Endpoint, TransmitterHelper,
-    GNS,
+    messages::general_message::GeneralOmgppMessage, ConnectionState, Endpoint,
 };
-use omgpp_core::{OmgppPredefinedCmd, ToEndpoint};
-use protobuf::Message;
+use omgpp_core::OmgppPredefinedCmd;
+use protobuf::{Message, MessageFull};
+use input::InputBuffers;
+use interest::{InterestFilter, InterestManager, Position, Relevance};
+use lockstep::{LockstepSessions, LockstepTurn, SessionId};
+use replication::{EntityId, Replication};
+use scheduler::{Scheduler, SendBudget};
 use server_settings::ServerSettings;
+use stats::{ClientStats, ServerStats};
 use uuid::Uuid;
 
 type OnConnectRequestCallback = Box<dyn Fn(&Server, &Uuid, &Endpoint) -> bool + 'static>;
@@ -30,6 +53,14 @@ type OnConnectionChangedCallback =
 Box<dyn Fn(&Server, &Uuid, &Endpoint, ConnectionState) + 'static>;
 type OnMessageCallback = Box<dyn Fn(&Server, &Uuid, &Endpoint, i64, Vec<u8>) + 'static>;
 type OnRpcCallback = Box<dyn Fn(&Server, &Uuid, &Endpoint, bool, i64, u64, i64, Vec<u8>) + 'static>;
+type OnAuthenticateCallback = Box<dyn Fn(&Server, &Uuid, &Endpoint, &[String]) -> AuthResult + 'static>;
+type OnMessageDecodeErrorCallback = Box<dyn Fn(&Server, &Uuid, &Endpoint, i64, &str) + 'static>;
+type OnLockstepTurnCallback = Box<dyn Fn(&Server, &LockstepTurn) + 'static>;
+// (server, session, turn, divergent client)
+type OnDesyncCallback = Box<dyn Fn(&Server, SessionId, u64, Option<&Uuid>) + 'static>;
+
+/// Roles granted to an authenticated client, or the reason it was rejected
+pub type AuthResult = Result<Vec<String>, String>;
 
 type ServerResult<T> = Result<T, String>; // TODO replace error with enum
 
@@ -39,45 +70,104 @@ struct ServerCallbacks {
     on_connection_changed_callback: Option<OnConnectionChangedCallback>,
     on_message_callback: Option<OnMessageCallback>,
     on_rpc_callback: Option<OnRpcCallback>,
+    on_authenticate_callback: Option<OnAuthenticateCallback>,
+    on_message_decode_error_callback: Option<OnMessageDecodeErrorCallback>,
+    on_lockstep_turn_callback: Option<OnLockstepTurnCallback>,
+    on_desync_callback: Option<OnDesyncCallback>,
 }
 pub struct Server<'a> {
     ip: IpAddr,
     port: u16,
     connection_tracker: RefCell<ConnectionTracker>,
-    settings:ServerSettings,
-    socket: GnsSocket<'static, 'static, IsServer>,
+    settings: RefCell<ServerSettings>,
+    // the transport has not seen the current `ServerSettings::connection_config` yet
+    connection_config_changed: Cell<bool>,
+    stats: RefCell<ServerStats>,
+    banned: RefCell<HashSet<IpAddr>>,
+    transport: Box<dyn Transport>,
+    clock: Rc<dyn Clock>,
     callbacks: RefCell<ServerCallbacks>,
     cmd_handlers: RefCell<CmdHandlerContainer<Server<'a>>>,
+    interceptors: RefCell<InterceptorChain<Server<'a>>>,
+    typed_messages: RefCell<TypedMessageHandlers<Server<'a>>>,
+    replication: RefCell<Replication>,
+    interest: RefCell<InterestManager>,
+    inputs: RefCell<InputBuffers>,
+    client_stats: RefCell<HashMap<Uuid, ClientStats>>,
+    lockstep: RefCell<LockstepSessions>,
+    batches: RefCell<Batches>,
+    scheduler: RefCell<Scheduler>,
+    lanes: RefCell<LaneMap>,
     phantom: PhantomData<&'a bool>,
 }
 
 impl<'a> Server<'a> {
     pub fn new(ip: IpAddr, port: u16) -> ServerResult<Server<'a>> {
-        let gns = GNS.as_ref()?;
-        let gns_socket = GnsSocket::<IsCreated>::new(&gns.global, &gns.utils).unwrap();
-        let address_to_bind = match ip {
-            IpAddr::V4(v4) => v4.to_ipv6_mapped(),
-            IpAddr::V6(v6) => v6,
+        Server::with_settings(ip, port, Default::default())
+    }
+    pub fn with_settings(ip: IpAddr, port: u16, settings: ServerSettings) -> ServerResult<Server<'a>> {
+        let transport = backend::listen(ip, port, &settings.connection_config)?;
+        #[cfg(feature = "websocket")]
+        let transport = match settings.websocket {
+            Some(websocket) => {
+                let websocket = WebSocketServerTransport::listen(ip, websocket)?;
+                Box::new(MultiTransport::new(vec![transport, Box::new(websocket)]))
+            }
+            None => transport,
         };
-        let server_socket = gns_socket
-            .listen(address_to_bind, port)
-            .or(ServerResult::Err("Cannot create server socket".to_string()))?;
+        if settings.network_simulation.is_enabled() {
+            settings.network_simulation.apply()?;
+        }
+        Server::with_transport(ip, port, transport, Rc::new(SystemClock), settings)
+    }
+    /// Create a server on top of an already listening transport.
+    /// `clock` drives time based logic such as unverified connection expiry,
+    /// `settings.connection_config` applies to connections asking to connect from now on
+    pub fn with_transport(
+        ip: IpAddr,
+        port: u16,
+        transport: Box<dyn Transport>,
+        clock: Rc<dyn Clock>,
+        settings: ServerSettings,
+    ) -> ServerResult<Server<'a>> {
         let server = Server {
             ip,
             port,
-            socket: server_socket,
-            connection_tracker: RefCell::new(ConnectionTracker::new(Duration::from_secs(3))),
-            settings:Default::default(),
+            transport,
+            stats: RefCell::new(ServerStats::new(clock.now())),
+            banned: Default::default(),
+            clock,
+            connection_tracker: RefCell::new(ConnectionTracker::new(settings.unverified_connection_expire_period)),
+            settings: RefCell::new(settings),
+            connection_config_changed: Cell::new(false),
             callbacks: RefCell::new(ServerCallbacks {
                 on_connect_requested_callback: Box::new(|_server, _id, _endpoint| true),
                 on_connection_changed_callback: None,
                 on_message_callback: None,
                 on_rpc_callback: None,
+                on_authenticate_callback: None,
+                on_message_decode_error_callback: None,
+                on_lockstep_turn_callback: None,
+                on_desync_callback: None,
             }),
             cmd_handlers: RefCell::new(CmdHandlerContainer::new()),
+            interceptors: Default::default(),
+            typed_messages: Default::default(),
+            replication: Default::default(),
+            interest: Default::default(),
+            inputs: Default::default(),
+            client_stats: Default::default(),
+            lockstep: Default::default(),
+            batches: Default::default(),
+            scheduler: Default::default(),
+            lanes: Default::default(),
             phantom: Default::default(),
         };
         server.init_default_cmd_handlers();
+        server.init_admin_cmd_handlers();
+        server
+            .transport
+            .set_accept_config(&server.settings.borrow().connection_config)?;
         Ok(server)
     }
     fn init_default_cmd_handlers(&self) {
@@ -88,6 +178,15 @@ impl<'a> Server<'a> {
             Box::new(Server::cmd_auth_handle),
         ));
         _ = cmd_handlers.register_handler(CmdHandler::new(OmgppPredefinedCmd::RESOURCES, false, Box::new(Server::cmd_resources_handle)));
+        let help_schema = CmdSchema::new("List available commands or describe one of them").arg(
+            ArgSpec::new("command", ArgType::String).optional(),
+        );
+        _ = cmd_handlers.register_handler(CmdHandler::typed(
+            OmgppPredefinedCmd::HELP,
+            false,
+            help_schema,
+            Box::new(Server::cmd_help_handle),
+        ));
     }
     fn cmd_auth_handle(
         &self,
@@ -96,15 +195,45 @@ impl<'a> Server<'a> {
         _handler: &CmdHandler<Server>,
         request: &CmdRequest,
     ) {
-        let is_authenticated = true;
+        let is_admin_login = request.args.first().map(String::as_str) == Some(OmgppPredefinedCmd::ADMIN_AUTH);
+        let auth_result = if is_admin_login {
+            self.authenticate_admin(request.args.get(1))
+        } else {
+            // without an authenticator every client is accepted without roles
+            match &self.callbacks.borrow().on_authenticate_callback {
+                Some(authenticate) => authenticate(self, uuid, endpoint, &request.args),
+                None => Ok(Vec::new()),
+            }
+        };
         let connection = self.connection_tracker.borrow().client_connection(uuid);
-        if is_authenticated {
-            if let Some(gns_connection) = connection {
-                self.connection_tracker.borrow_mut().track_client_connected(
+        // a verified client authenticating again keeps its connection and state
+        let is_verified = self.connection_tracker.borrow().state(uuid) == ConnectionState::Connected;
+        match auth_result {
+            Ok(roles) if is_verified => {
+                let mut tracker = self.connection_tracker.borrow_mut();
+                // an admin login elevates the player, other logins replace its roles
+                match (is_admin_login, tracker.roles_mut(uuid)) {
+                    (true, Some(current)) => current.extend(roles),
+                    _ => tracker.set_roles(*uuid, roles.into_iter().collect()),
+                }
+                drop(tracker);
+                _ = self.send_command(
+                    uuid,
+                    request.cmd.clone(),
+                    request.request_id,
+                    Some(vec!["ok".to_string()]),
+                );
+            }
+            Ok(roles) => if let Some(connection) = connection {
+                let mut tracker = self.connection_tracker.borrow_mut();
+                tracker.track_client_connected(
                     uuid.clone(),
                     endpoint.clone(),
-                    gns_connection,
+                    connection,
                 );
+                tracker.set_roles(*uuid, roles.into_iter().collect());
+                drop(tracker);
+                self.forget_client_state(uuid);
                 let new_state = self.connection_tracker.borrow().state(uuid);
                 let callbacks = self.callbacks.borrow();
                 if let Some(cb) = &callbacks.on_connection_changed_callback {
@@ -117,12 +246,35 @@ impl<'a> Server<'a> {
                     Some(vec!["ok".to_string()]),
                 );
             }
-        } else {
-            if let Some(gns_connection) = connection {
-                self.socket.close_connection(gns_connection, 0, "", false);
+            Err(reason) => {
+                self.send_cmd_error(uuid, request, &reason);
+                if !is_verified {
+                    self.close_client(uuid, &reason);
+                }
             }
         }
     }
+    // a reconnecting client starts over everywhere
+    fn forget_client_state(&self, uuid: &Uuid) {
+        self.replication.borrow_mut().forget_client(uuid);
+        self.interest.borrow_mut().forget_client(uuid);
+        self.inputs.borrow_mut().forget_client(uuid);
+        self.client_stats.borrow_mut().remove(uuid);
+        self.scheduler.borrow_mut().forget_client(uuid);
+    }
+    // a disconnected client also leaves its lockstep session, an authenticating one keeps playing
+    fn forget_disconnected_client(&self, uuid: &Uuid) {
+        self.forget_client_state(uuid);
+        self.lockstep.borrow_mut().forget_client(uuid);
+    }
+    fn send_cmd_error(&self, uuid: &Uuid, request: &CmdRequest, reason: &str) {
+        _ = self.send_command(
+            uuid,
+            request.cmd.clone(),
+            request.request_id,
+            Some(vec!["error".to_string(), reason.to_string()]),
+        );
+    }
     fn cmd_resources_handle(
         &self,
         uuid: &Uuid,
@@ -130,58 +282,370 @@ impl<'a> Server<'a> {
         _handler: &CmdHandler<Server>,
         request: &CmdRequest,
     ) {
-        let resource_location = self.settings.resource_location.clone();
+        let resource_location = self.settings.borrow().resource_location.clone();
         _ = self.send_command(uuid, request.cmd.clone(), request.request_id, Some(vec![resource_location]))
     }
+    fn cmd_help_handle(&self, uuid: &Uuid, _endpoint: &Endpoint, request: &CmdRequest, args: &CmdArgs) {
+        let roles = self.client_roles(uuid);
+        let permissions = CmdPermissions {
+            authenticated: self.connection_tracker.borrow().state(uuid) == ConnectionState::Connected,
+            roles: &roles,
+        };
+        let cmd_handlers = self.cmd_handlers.borrow();
+        let lines = match args.str("command") {
+            Some(command) => cmd_handlers.help_for(command, &permissions),
+            None => Some(cmd_handlers.help(&permissions)),
+        };
+        match lines {
+            Some(lines) => {
+                _ = self.send_command(uuid, request.cmd.clone(), request.request_id, Some(lines));
+            }
+            None => self.send_cmd_error(uuid, request, &CmdError::NotFound.to_string()),
+        }
+    }
     // TODO Maybe it worth to return a Iterator instead of cloning
     pub fn active_clients(&self) -> Vec<(Uuid, Endpoint)> {
         self.connection_tracker.borrow().active_clients()
     }
-    pub fn socket(&self) -> &GnsSocket<'static, 'static, IsServer> {
-        &self.socket
+    pub fn transport(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+    /// GNS listen socket of the server
+    ///
+    /// # Panics
+    /// If the server does not run on the GNS transport
+    #[cfg(feature = "gns")]
+    #[deprecated(note = "use `Server::transport`")]
+    pub fn socket(&self) -> &omgpp_core::transport::gns::GnsServerSocket {
+        self.transport
+            .gns_server_socket()
+            .expect("Server does not use the GNS transport")
+    }
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+    pub fn settings(&self) -> Ref<'_, ServerSettings> {
+        self.settings.borrow()
+    }
+    /// Time since the server started, the clock clients synchronize to
+    pub fn server_time(&self) -> Duration {
+        self.stats.borrow().uptime(self.clock.now())
+    }
+    /// Round trip time and interpolation delay `client` reported, `None` before its first time sync
+    pub fn client_stats(&self, client: &Uuid) -> Option<ClientStats> {
+        self.client_stats.borrow().get(client).cloned()
+    }
+    /// Server time `client` was looking at when it sent what arrives now, for lag compensation.
+    /// The round trip is the ping the transport measured, the reported one only if there is none,
+    /// and the interpolation delay is capped to `ServerSettings::max_interpolation_delay`.
+    /// `None` if the client is not authenticated
+    pub fn client_view_time(&self, client: &Uuid) -> Option<Duration> {
+        if self.connection_tracker.borrow().state(client) != ConnectionState::Connected {
+            return None;
+        }
+        let mut stats = self.client_stats(client).unwrap_or_default();
+        if let Some(ping) = self.connection_status(client).and_then(|status| status.ping) {
+            stats.rtt = Some(ping);
+        }
+        let max_interpolation_delay = self.settings.borrow().max_interpolation_delay;
+        stats.interpolation_delay = stats.interpolation_delay.min(max_interpolation_delay);
+        Some(stats.view_time(self.server_time()))
+    }
+    pub fn stats(&self) -> ServerStats {
+        self.stats.borrow().clone()
+    }
+    /// Connection options for new and, from the next send or `process`, existing connections.
+    /// Replaces options set with `set_client_connection_config`
+    pub fn set_connection_config(&mut self, config: ConnectionConfig) {
+        self.settings.get_mut().connection_config = config;
+        self.connection_config_changed.set(true);
+    }
+    pub fn connection_config_mut(&mut self) -> &mut ConnectionConfig {
+        self.connection_config_changed.set(true);
+        &mut self.settings.get_mut().connection_config
+    }
+    /// Turn `ServerSettings::batch_messages` on or off, turning it off sends what is queued
+    pub fn set_batch_messages(&mut self, batch_messages: bool) -> ServerResult<()> {
+        self.settings.get_mut().batch_messages = batch_messages;
+        match batch_messages {
+            true => Ok(()),
+            false => self.flush(),
+        }
+    }
+    /// Enable fake packet loss, lag, jitter, reorder and duplication.
+    /// Can be changed at any time; pass `NetworkSimulation::default()` to turn it off
+    pub fn set_network_simulation(&mut self, simulation: NetworkSimulation) -> ServerResult<()> {
+        simulation.apply()?;
+        self.settings.get_mut().network_simulation = simulation;
+        Ok(())
+    }
+    pub fn network_simulation(&self) -> Ref<'_, NetworkSimulation> {
+        Ref::map(self.settings.borrow(), |settings| &settings.network_simulation)
+    }
+    /// Override connection options of an already accepted client
+    pub fn set_client_connection_config(
+        &self,
+        client: &Uuid,
+        config: &ConnectionConfig,
+    ) -> ServerResult<()> {
+        let connection = self
+            .connection_tracker
+            .borrow()
+            .client_connection(client)
+            .ok_or("There is not such client")?;
+        self.transport.configure_connection(connection, config)
+    }
+    /// Send messages of `msg_type` on `lane` of `ConnectionConfig::lanes`, ordered apart from
+    /// the other lanes. Lane 0 restores the default
+    pub fn set_message_lane(&self, msg_type: i64, lane: u16) -> ServerResult<()> {
+        self.check_lane(lane)?;
+        self.lanes.borrow_mut().set_message_lane(msg_type, lane);
+        Ok(())
+    }
+    /// Send calls of `method_id` on `lane`
+    pub fn set_rpc_lane(&self, method_id: i64, lane: u16) -> ServerResult<()> {
+        self.check_lane(lane)?;
+        self.lanes.borrow_mut().set_rpc_lane(method_id, lane);
+        Ok(())
+    }
+    /// Send requests of `cmd` and the replies to it on `lane`
+    pub fn set_cmd_lane(&self, cmd: &str, lane: u16) -> ServerResult<()> {
+        self.check_lane(lane)?;
+        self.lanes.borrow_mut().set_cmd_lane(cmd, lane);
+        Ok(())
+    }
+    /// Send to `client` on `lane` instead of the lane `msg_type` is mapped to
+    pub fn send_on_lane(&self, client: &Uuid, lane: u16, msg_type: i64, data: &[u8]) -> ServerResult<()> {
+        self.check_lane(lane)?;
+        let message = Server::create_regular_message(msg_type, data);
+        self.send_message_on_lane(client, SendMode::Unreliable, lane, message)
+    }
+    pub fn send_reliable_on_lane(&self, client: &Uuid, lane: u16, msg_type: i64, data: &[u8]) -> ServerResult<()> {
+        self.check_lane(lane)?;
+        let message = Server::create_regular_message(msg_type, data);
+        self.send_message_on_lane(client, SendMode::Reliable, lane, message)
+    }
+    /// `call_rpc` on `lane` instead of the lane `method_id` is mapped to
+    #[allow(clippy::too_many_arguments)]
+    pub fn call_rpc_on_lane(
+        &self,
+        client: &Uuid,
+        lane: u16,
+        reliable: bool,
+        method_id: i64,
+        request_id: u64,
+        arg_type: i64,
+        arg_data: Option<&[u8]>,
+    ) -> ServerResult<()> {
+        self.check_lane(lane)?;
+        let message = Server::create_rpc_message(reliable, method_id, request_id, arg_type, arg_data);
+        self.send_message_on_lane(client, SendMode::from_reliable(reliable), lane, message)
+    }
+    fn check_lane(&self, lane: u16) -> ServerResult<()> {
+        // lanes of existing connections have to match the ones checked here
+        self.apply_connection_config()?;
+        let lane_count = self.settings.borrow().connection_config.lane_count();
+        match (lane as usize) < lane_count {
+            true => Ok(()),
+            false => Err(format!("Lane {} is not configured, there are {} lanes", lane, lane_count)),
+        }
     }
     /// Make 1 server cycle.
     /// Generic paramter N specfies maximum number of events and messages to process per a call
     pub fn process<const N: usize>(&self) -> ServerResult<()> {
-        let socket = &self.socket;
-        socket.poll_callbacks();
-        let mut socket_op_result = ServerResult::Ok(());
-        let _processed_event_count = socket.poll_event::<N>(|event| {
+        let transport = &self.transport;
+        // messages scheduled and batched since the last cycle
+        let mut socket_op_result = self
+            .apply_connection_config()
+            .and(self.send_scheduled())
+            .and(self.flush());
+        transport.poll();
+        let _processed_event_count = transport.poll_events(N, &mut |event| {
             socket_op_result = Server::process_connection_events(
                 self,
                 event,
-                &self.socket,
                 &self.callbacks.borrow(),
                 &self.connection_tracker,
             )
         });
 
-        let _processed_msg_count = socket.poll_messages::<N>(|msg| {
+        let _processed_msg_count = transport.poll_messages(N, &mut |connection, data| {
+            self.stats.borrow_mut().track_received(data.len());
             socket_op_result = Server::process_messages(
                 self,
-                msg,
+                connection,
+                data,
                 &self.connection_tracker,
                 &self.callbacks.borrow(),
             )
         });
 
-        let connection_tracker = self.connection_tracker.borrow();
-        let expired_unverified_connections = connection_tracker
-            .expired_unverified_connections()
-            .enumerate();
-        for (_i, connection) in expired_unverified_connections {
-            println!("{:?}", connection);
-            socket.close_connection(connection, 0, "Unverified", false);
+        let expired_unverified_connections = self
+            .connection_tracker
+            .borrow()
+            .expired_unverified_connections(self.clock.now())
+            .collect::<Vec<_>>();
+        for connection in expired_unverified_connections {
+            let client = self
+                .connection_tracker
+                .borrow()
+                .client_by_connection(&connection)
+                .cloned();
+            match client {
+                Some(uuid) => self.close_client(&uuid, "Unverified"),
+                None => transport.close(connection, "Unverified"),
+            }
         }
+        self.process_lockstep();
 
         socket_op_result
     }
+    /// Send the messages batched with `ServerSettings::batch_messages`, one packet per client,
+    /// send mode and lane. `process` flushes what was sent since the previous cycle
+    pub fn flush(&self) -> ServerResult<()> {
+        let batches = self.batches.borrow_mut().take_all();
+        let mut result = Ok(());
+        for (connection, mode, lane, messages) in batches {
+            result = result.and(self.send_batch(connection, mode, lane, messages));
+        }
+        result
+    }
+    /// Queue an unreliable message for `client` that goes out when its send budget allows.
+    /// Messages with a higher `priority` go first, the priority of a held back message grows by
+    /// `priority` every cycle; after `SchedulerSettings::max_defer` it is dropped
+    pub fn schedule(&self, client: &Uuid, priority: f32, msg_type: i64, data: &[u8]) -> ServerResult<()> {
+        self.ensure_client(client)?;
+        let now = self.clock.now();
+        self.scheduler
+            .borrow_mut()
+            .push_unreliable(client, priority, msg_type, data.to_vec(), now);
+        Ok(())
+    }
+    /// Queue a reliable message for `client`. Reliable messages go out in order on the next
+    /// scheduler run before any unreliable one, even if the budget is exhausted
+    pub fn schedule_reliable(&self, client: &Uuid, msg_type: i64, data: &[u8]) -> ServerResult<()> {
+        self.ensure_client(client)?;
+        self.scheduler.borrow_mut().push_reliable(client, msg_type, data.to_vec());
+        Ok(())
+    }
+    pub fn schedule_typed<M: MessageFull>(&self, client: &Uuid, priority: f32, message: &M) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.schedule(client, priority, msg_type, &data)
+    }
+    pub fn schedule_typed_reliable<M: MessageFull>(&self, client: &Uuid, message: &M) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.schedule_reliable(client, msg_type, &data)
+    }
+    /// Send what the budget of every client allows. `process` runs the scheduler as well, call
+    /// this at the end of a tick to send without waiting for the next cycle
+    pub fn send_scheduled(&self) -> ServerResult<()> {
+        let now = self.clock.now();
+        let settings = self.settings.borrow().scheduler.clone();
+        let sends = self
+            .scheduler
+            .borrow_mut()
+            .run(now, &settings, |client| self.connection_status(client));
+        let mut result = Ok(());
+        for send in sends {
+            result = result.and(self.send_with_mode(&send.client, send.msg_type, &send.data, send.mode));
+        }
+        result
+    }
+    /// Budget of `client` as of the last scheduler run, `None` before anything was scheduled for it
+    pub fn send_budget(&self, client: &Uuid) -> Option<SendBudget> {
+        self.scheduler.borrow().budget(client)
+    }
+    /// Ping, estimated send rate and queued bytes of the connection of `client`, if the transport tracks them
+    pub fn connection_status(&self, client: &Uuid) -> Option<ConnectionStatus> {
+        let connection = self.connection_tracker.borrow().client_connection(client)?;
+        self.transport.connection_status(connection)
+    }
+    fn ensure_client(&self, client: &Uuid) -> ServerResult<()> {
+        match self.connection_tracker.borrow().client_connection(client) {
+            Some(_) => Ok(()),
+            None => Err("There is not such client to send".to_string()),
+        }
+    }
+    /// Messages waiting for `flush`
+    pub fn batched_messages(&self) -> usize {
+        self.batches.borrow().queued()
+    }
+    /// Close the connection of `client`. `reason` is passed to the transport
+    pub fn disconnect(&self, client: &Uuid, reason: &str) -> ServerResult<()> {
+        if self.connection_tracker.borrow().client_connection(client).is_none() {
+            return Err("There is not such client to disconnect".to_string());
+        }
+        self.close_client(client, reason);
+        Ok(())
+    }
+    /// Refuse new connections from `ip` and disconnect the clients connected from it
+    pub fn ban(&self, ip: IpAddr, reason: &str) {
+        self.banned.borrow_mut().insert(ip);
+        let clients = self.connection_tracker.borrow().clients_from(&ip);
+        for client in clients {
+            self.close_client(&client, reason);
+        }
+    }
+    /// Returns `false` if `ip` was not banned
+    pub fn unban(&self, ip: &IpAddr) -> bool {
+        self.banned.borrow_mut().remove(ip)
+    }
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.banned.borrow().contains(ip)
+    }
+    pub fn banned(&self) -> Vec<IpAddr> {
+        self.banned.borrow().iter().cloned().collect()
+    }
+    /// Send `text` to every authenticated client as an `omgpp_announcement` command
+    pub fn announce(&self, text: &str) -> ServerResult<()> {
+        let message = Self::create_cmd_message(OmgppPredefinedCmd::ANNOUNCEMENT.to_string(), 0, vec![text.to_string()]);
+        self.broadcast_message(SendMode::Reliable, message)
+    }
+    // closing the connection locally does not produce a connection event, track it here
+    fn close_client(&self, uuid: &Uuid, reason: &str) {
+        let connection = self.connection_tracker.borrow().client_connection(uuid);
+        let Some(connection) = connection else {
+            return;
+        };
+        // e.g. the reason of a rejected authentication
+        let batches = self.batches.borrow_mut().take_connection(connection);
+        for (mode, lane, messages) in batches {
+            _ = self.send_batch(connection, mode, lane, messages);
+        }
+        self.transport.close(connection, reason);
+        let endpoint = self.connection_tracker.borrow().client_endpoint(uuid).cloned();
+        self.connection_tracker.borrow_mut().track_client_disconnected(uuid);
+        self.forget_disconnected_client(uuid);
+        let state = self.connection_tracker.borrow().state(uuid);
+        if let (Some(endpoint), Some(cb)) =
+            (endpoint, &self.callbacks.borrow().on_connection_changed_callback)
+        {
+            cb(self, uuid, &endpoint, state);
+        }
+    }
     pub fn send(&self, client: &Uuid, msg_type: i64, data: &[u8]) -> ServerResult<()> {
-        self.send_with_flags(client, msg_type, data, k_nSteamNetworkingSend_Unreliable)
+        self.send_with_mode(client, msg_type, data, SendMode::Unreliable)
     }
 
     pub fn send_reliable(&self, client: &Uuid, msg_type: i64, data: &[u8]) -> ServerResult<()> {
-        self.send_with_flags(client, msg_type, data, k_nSteamNetworkingSend_Reliable)
+        self.send_with_mode(client, msg_type, data, SendMode::Reliable)
+    }
+    /// Send `message` with its id from the message registry, see `register_message`
+    pub fn send_typed<M: MessageFull>(&self, client: &Uuid, message: &M) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.send(client, msg_type, &data)
+    }
+    pub fn send_typed_reliable<M: MessageFull>(&self, client: &Uuid, message: &M) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.send_reliable(client, msg_type, &data)
+    }
+    pub fn broadcast_typed<M: MessageFull>(&self, message: &M) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.broadcast(msg_type, &data)
+    }
+    pub fn broadcast_typed_reliable<M: MessageFull>(&self, message: &M) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.broadcast_reliable(msg_type, &data)
     }
     pub fn send_command(
         &self,
@@ -190,35 +654,43 @@ impl<'a> Server<'a> {
         request_id: u64,
         args: Option<Vec<String>>,
     ) -> ServerResult<()> {
-        let connection = self
-            .connection_tracker
-            .borrow()
-            .client_connection(client)
-            .ok_or_else(|| "There is not such client to send")?;
-
-        let cmd_bytes =
-            Self::create_cmd_message(cmd, request_id, args.unwrap_or_else(|| Vec::new()))
-                .or_else(|_or| Err("Cannot create cmd message".to_string()))?;
-
-        // TODO check send result
-        let _send_result = TransmitterHelper::send(
-            &self.socket,
-            &[connection],
-            k_nSteamNetworkingSend_Reliable,
-            cmd_bytes.as_slice(),
-        );
+        let message = Self::create_cmd_message(cmd, request_id, args.unwrap_or_else(|| Vec::new()));
+        self.send_message(client, SendMode::Reliable, message)
+    }
+    /// Handle `handler.cmd` sent by clients. Names starting with `omgpp_` are reserved.
+    /// Handlers cannot be changed from inside of a command handler
+    pub fn register_cmd_handler(&self, handler: CmdHandler<Server<'a>>) -> ServerResult<()> {
+        if OmgppPredefinedCmd::is_reserved(&handler.cmd) {
+            return Err(format!("Command {:?} uses reserved prefix", handler.cmd));
+        }
+        self.cmd_handlers_mut()?.register_handler(handler)
+    }
+    pub fn unregister_cmd_handler(&self, cmd: &str) -> ServerResult<()> {
+        if OmgppPredefinedCmd::is_reserved(cmd) {
+            return Err(format!("Command {:?} uses reserved prefix", cmd));
+        }
+        self.cmd_handlers_mut()?
+            .unregister_handler(cmd)
+            .map(|_| ())
+            .ok_or_else(|| format!("Command {:?} is not registered", cmd))
+    }
+    /// Handle every command without its own handler. `None` restores replying "Unknown command"
+    pub fn register_cmd_fallback(&self, handler: Option<CmdHandler<Server<'a>>>) -> ServerResult<()> {
+        self.cmd_handlers_mut()?.set_fallback(handler);
         Ok(())
     }
+    fn cmd_handlers_mut(&self) -> ServerResult<RefMut<'_, CmdHandlerContainer<Server<'a>>>> {
+        self.cmd_handlers
+            .try_borrow_mut()
+            .map_err(|_| "Command handlers are in use".to_string())
+    }
     pub fn broadcast(&self, msg_type: i64, data: &[u8]) -> ServerResult<()> {
-        let msg_bytes = Server::create_regular_message(msg_type, data)
-            .or_else(|_or| Err("Cannot create general message".to_string()))?;
-
-        self.broadcast_with_flags(k_nSteamNetworkingSend_Unreliable, msg_bytes.as_slice())
+        let message = Server::create_regular_message(msg_type, data);
+        self.broadcast_message(SendMode::Unreliable, message)
     }
     pub fn broadcast_reliable(&self, msg_type: i64, data: &[u8]) -> ServerResult<()> {
-        let msg_bytes = Server::create_regular_message(msg_type, data)
-            .or_else(|_or| Err("Cannot create general message".to_string()))?;
-        self.broadcast_with_flags(k_nSteamNetworkingSend_Reliable, msg_bytes.as_slice())
+        let message = Server::create_regular_message(msg_type, data);
+        self.broadcast_message(SendMode::Reliable, message)
     }
     pub fn call_rpc(
         &self,
@@ -229,24 +701,8 @@ impl<'a> Server<'a> {
         arg_type: i64,
         arg_data: Option<&[u8]>,
     ) -> ServerResult<()> {
-        let connection = self
-            .connection_tracker
-            .borrow()
-            .client_connection(client)
-            .ok_or_else(|| "There is not such client to send")?;
-
-        let msg_bytes =
-            Server::create_rpc_message(reliable, method_id, request_id, arg_type, arg_data)
-                .or_else(|_or| Err("Cannot create rpc message".to_string()))?;
-
-        let flags = match reliable {
-            true => k_nSteamNetworkingSend_Reliable,
-            false => k_nSteamNetworkingSend_Unreliable,
-        };
-        // TODO check send result
-        let _send_result =
-            TransmitterHelper::send(&self.socket, &[connection], flags, msg_bytes.as_slice());
-        Ok(())
+        let message = Server::create_rpc_message(reliable, method_id, request_id, arg_type, arg_data);
+        self.send_message(client, SendMode::from_reliable(reliable), message)
     }
     pub fn call_rpc_broadcast(
         &self,
@@ -256,16 +712,57 @@ impl<'a> Server<'a> {
         arg_type: i64,
         arg_data: Option<&[u8]>,
     ) -> ServerResult<()> {
-        let msg_bytes =
-            Server::create_rpc_message(reliable, method_id, request_id, arg_type, arg_data)
-                .or_else(|_or| Err("Cannot create rpc message".to_string()))?;
-        let flags = match reliable {
-            true => k_nSteamNetworkingSend_Reliable,
-            false => k_nSteamNetworkingSend_Unreliable,
-        };
-        let tracker = self.connection_tracker.borrow();
-        let connections = tracker.active_connections();
-        let _res = TransmitterHelper::send_with_iter(&self.socket, connections, flags, &msg_bytes);
+        let message = Server::create_rpc_message(reliable, method_id, request_id, arg_type, arg_data);
+        self.broadcast_message(SendMode::from_reliable(reliable), message)
+    }
+    /// Append an interceptor to the chain. It sees every decoded inbound message before it is
+    /// dispatched and every outbound message before it is encoded, and can modify, handle or reject it
+    pub fn add_interceptor(
+        &self,
+        name: &str,
+        interceptor: impl Fn(&Server, &InterceptContext, &mut GeneralOmgppMessage) -> Intercept + 'static,
+    ) -> ServerResult<()> {
+        self.interceptors
+            .try_borrow_mut()
+            .map_err(|_| "Interceptors are in use".to_string())?
+            .add(name, Box::new(interceptor))
+    }
+    pub fn remove_interceptor(&self, name: &str) -> ServerResult<()> {
+        self.interceptors
+            .try_borrow_mut()
+            .map_err(|_| "Interceptors are in use".to_string())?
+            .remove(name)
+    }
+    /// Decide whether a client passes `omgpp_auth`. `args` are the arguments the client sent.
+    /// Return the roles of the client or the reason it is rejected
+    pub fn register_on_authenticate(
+        &self,
+        callback: impl Fn(&Server, &Uuid, &Endpoint, &[String]) -> AuthResult + 'static,
+    ) {
+        self.callbacks.borrow_mut().on_authenticate_callback = Some(Box::from(callback));
+    }
+    pub fn client_roles(&self, client: &Uuid) -> HashSet<String> {
+        self.connection_tracker
+            .borrow()
+            .roles(client)
+            .cloned()
+            .unwrap_or_default()
+    }
+    /// Grant a role to an authenticated client
+    pub fn grant_role(&self, client: &Uuid, role: &str) -> ServerResult<()> {
+        let mut tracker = self.connection_tracker.borrow_mut();
+        let roles = tracker
+            .roles_mut(client)
+            .ok_or("There is not such authenticated client")?;
+        roles.insert(role.to_string());
+        Ok(())
+    }
+    pub fn revoke_role(&self, client: &Uuid, role: &str) -> ServerResult<()> {
+        let mut tracker = self.connection_tracker.borrow_mut();
+        let roles = tracker
+            .roles_mut(client)
+            .ok_or("There is not such authenticated client")?;
+        roles.remove(role);
         Ok(())
     }
     pub fn register_on_connect_requested(
@@ -286,81 +783,383 @@ impl<'a> Server<'a> {
     ) {
         self.callbacks.borrow_mut().on_message_callback = Some(Box::from(callback));
     }
+    /// Send and receive `M` with its `stable_type_id`. Only needed to detect id collisions early,
+    /// unregistered types use the same id
+    pub fn register_message<M: MessageFull>(&self) -> ServerResult<i64> {
+        self.typed_messages_mut()?.registry_mut().register::<M>()
+    }
+    /// Send and receive `M` with a hand picked id instead of its `stable_type_id`
+    pub fn register_message_with_id<M: MessageFull>(&self, msg_type: i64) -> ServerResult<i64> {
+        self.typed_messages_mut()?.registry_mut().register_with_id::<M>(msg_type)
+    }
+    pub fn message_type_id<M: MessageFull>(&self) -> i64 {
+        self.typed_messages.borrow().registry().type_id::<M>()
+    }
+    /// Handle messages of type `M` from authenticated clients. They are decoded before `handler` runs and
+    /// no longer reach `register_on_message`; payloads that fail to decode go to `register_on_message_decode_error`.
+    /// Handlers cannot be changed from inside of a message handler
+    pub fn on_message<M: MessageFull>(&self, handler: impl Fn(&Server<'a>, &Uuid, M) + 'static) -> ServerResult<()> {
+        self.typed_messages_mut()?
+            .set_handler(move |server, uuid, _endpoint, message| handler(server, uuid, message))
+    }
+    pub fn remove_message_handler<M: MessageFull>(&self) -> ServerResult<()> {
+        match self.typed_messages_mut()?.remove_handler::<M>() {
+            true => Ok(()),
+            false => Err(format!("No handler for {}", M::descriptor().full_name())),
+        }
+    }
+    pub fn register_on_message_decode_error(
+        &self,
+        callback: impl Fn(&Server, &Uuid, &Endpoint, i64, &str) + 'static,
+    ) {
+        self.callbacks.borrow_mut().on_message_decode_error_callback = Some(Box::from(callback));
+    }
+    fn typed_messages_mut(&self) -> ServerResult<RefMut<'_, TypedMessageHandlers<Server<'a>>>> {
+        self.typed_messages
+            .try_borrow_mut()
+            .map_err(|_| "Message handlers are in use".to_string())
+    }
+    /// Create an entity replicated to every authenticated client on `replicate`
+    pub fn spawn_entity(&self, entity_type: i64) -> EntityId {
+        self.replication.borrow_mut().spawn(entity_type)
+    }
+    pub fn despawn_entity(&self, entity: EntityId) -> ServerResult<()> {
+        self.replication.borrow_mut().despawn(entity)?;
+        self.interest.borrow_mut().forget_entity(entity);
+        Ok(())
+    }
+    pub fn entities(&self) -> Vec<EntityId> {
+        self.replication.borrow().entity_ids()
+    }
+    pub fn entity_type(&self, entity: EntityId) -> Option<i64> {
+        self.replication.borrow().entity_type(entity)
+    }
+    /// Set a component of `entity`. It is only sent again if `data` changed
+    pub fn set_component(&self, entity: EntityId, component_type: i64, data: &[u8]) -> ServerResult<()> {
+        self.replication.borrow_mut().set_component(entity, component_type, data)
+    }
+    /// Set the `M` component of `entity`, identified by the id `M` is sent with, see `register_message`
+    pub fn set_component_typed<M: MessageFull>(&self, entity: EntityId, component: &M) -> ServerResult<()> {
+        let (component_type, data) = self.typed_messages.borrow().registry().encode(component)?;
+        self.set_component(entity, component_type, &data)
+    }
+    pub fn remove_component(&self, entity: EntityId, component_type: i64) -> ServerResult<()> {
+        self.replication.borrow_mut().remove_component(entity, component_type)
+    }
+    pub fn component(&self, entity: EntityId, component_type: i64) -> Option<Vec<u8>> {
+        self.replication.borrow().component(entity, component_type).map(Vec::from)
+    }
+    /// End the replication tick: send every authenticated client an unreliable snapshot of the
+    /// entities and components that changed since the last snapshot it acknowledged. Returns the tick
+    pub fn replicate(&self) -> ServerResult<u64> {
+        let tick = self.replication.borrow_mut().advance();
+        let mut result = Ok(());
+        for (client, _endpoint) in self.active_clients() {
+            let mut interest = self.interest.borrow_mut();
+            let snapshot = self
+                .replication
+                .borrow_mut()
+                .snapshot_for(&client, |entity| interest.update_visibility(&client, entity));
+            drop(interest);
+            if let Some(snapshot) = snapshot {
+                let mut message = GeneralOmgppMessage::new();
+                message.data = Some(Data::Snapshot(snapshot));
+                result = result.and(self.send_message(&client, SendMode::Unreliable, message));
+            }
+        }
+        result.map(|_| tick)
+    }
+    pub fn replication_tick(&self) -> u64 {
+        self.replication.borrow().tick()
+    }
+    /// Next input command of `client` with its sequence number. Inputs are buffered until
+    /// `ServerSettings::input_jitter_window` of them arrived, then handed out one per call in order
+    pub fn take_input(&self, client: &Uuid) -> Option<(u64, Vec<u8>)> {
+        let jitter_window = self.settings.borrow().input_jitter_window;
+        self.inputs.borrow_mut().take(client, jitter_window)
+    }
+    pub fn take_input_typed<M: MessageFull>(&self, client: &Uuid) -> Option<(u64, ServerResult<M>)> {
+        let (sequence, data) = self.take_input(client)?;
+        let input = M::parse_from_bytes(&data)
+            .map_err(|err| format!("Cannot decode {}: {}", M::descriptor().full_name(), err));
+        Some((sequence, input))
+    }
+    /// One input of every authenticated client that has one, e.g. once per simulation tick
+    pub fn take_inputs(&self) -> Vec<(Uuid, u64, Vec<u8>)> {
+        self.active_clients()
+            .into_iter()
+            .filter_map(|(client, _endpoint)| {
+                let (sequence, data) = self.take_input(&client)?;
+                Some((client, sequence, data))
+            })
+            .collect()
+    }
+    /// Sequence number of the last input taken for `client`. Messages sent to the client carry it,
+    /// so it can replay the inputs the state does not include yet
+    pub fn last_processed_input(&self, client: &Uuid) -> u64 {
+        self.inputs.borrow().last_processed(client)
+    }
+    pub fn buffered_inputs(&self, client: &Uuid) -> usize {
+        self.inputs.borrow().buffered(client)
+    }
+    /// Limit replication and `broadcast_relevant` by position with `filter`, e.g. a `RadiusFilter`
+    /// or `GridFilter`. `None` ignores positions again
+    pub fn set_interest_filter(&self, filter: Option<Box<dyn InterestFilter>>) {
+        self.interest.borrow_mut().set_filter(filter);
+    }
+    /// Position `client` sees the world from, without one it only gets entities and messages
+    /// that have no position
+    pub fn set_client_position(&self, client: &Uuid, position: Option<Position>) {
+        self.interest.borrow_mut().set_client_position(client, position);
+    }
+    pub fn client_position(&self, client: &Uuid) -> Option<Position> {
+        self.interest.borrow().client(client)?.position
+    }
+    /// Let `client` receive entities and messages with `tag`
+    pub fn subscribe_tag(&self, client: &Uuid, tag: &str) {
+        self.interest.borrow_mut().subscribe(client, tag);
+    }
+    pub fn unsubscribe_tag(&self, client: &Uuid, tag: &str) -> bool {
+        self.interest.borrow_mut().unsubscribe(client, tag)
+    }
+    /// Replicate `entity` only to clients it is relevant for
+    pub fn set_entity_relevance(&self, entity: EntityId, relevance: Relevance) -> ServerResult<()> {
+        if !self.replication.borrow().contains(entity) {
+            return Err(format!("There is not such entity {}", entity));
+        }
+        self.interest.borrow_mut().set_entity(entity, relevance);
+        Ok(())
+    }
+    /// Move `entity`, keeping its tag
+    pub fn set_entity_position(&self, entity: EntityId, position: Position) -> ServerResult<()> {
+        let tag = self.interest.borrow().entity(entity).and_then(|relevance| relevance.tag.clone());
+        self.set_entity_relevance(entity, Relevance { position: Some(position), tag })
+    }
+    /// Whether `entity` was replicated to `client` by the last `replicate`
+    pub fn is_entity_visible(&self, client: &Uuid, entity: EntityId) -> bool {
+        self.interest.borrow().is_visible(client, entity)
+    }
+    /// Authenticated clients a message with `relevance` is sent to by `broadcast_relevant`
+    pub fn relevant_clients(&self, relevance: &Relevance) -> Vec<Uuid> {
+        let interest = self.interest.borrow();
+        self.active_clients()
+            .into_iter()
+            .map(|(client, _endpoint)| client)
+            .filter(|client| interest.is_relevant(client, relevance))
+            .collect()
+    }
+    /// Send to every authenticated client `relevance` applies to
+    pub fn broadcast_relevant(&self, relevance: &Relevance, msg_type: i64, data: &[u8]) -> ServerResult<()> {
+        let message = Server::create_regular_message(msg_type, data);
+        self.multicast_message(&self.relevant_clients(relevance), SendMode::Unreliable, message)
+    }
+    pub fn broadcast_relevant_reliable(&self, relevance: &Relevance, msg_type: i64, data: &[u8]) -> ServerResult<()> {
+        let message = Server::create_regular_message(msg_type, data);
+        self.multicast_message(&self.relevant_clients(relevance), SendMode::Reliable, message)
+    }
+    pub fn broadcast_relevant_typed<M: MessageFull>(&self, relevance: &Relevance, message: &M) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.broadcast_relevant(relevance, msg_type, &data)
+    }
+    pub fn broadcast_relevant_typed_reliable<M: MessageFull>(
+        &self,
+        relevance: &Relevance,
+        message: &M,
+    ) -> ServerResult<()> {
+        let (msg_type, data) = self.typed_messages.borrow().registry().encode(message)?;
+        self.broadcast_relevant_reliable(relevance, msg_type, &data)
+    }
     pub fn register_on_rpc(
         &mut self,
         callback: impl Fn(&Server, &Uuid, &Endpoint, bool, i64, u64, i64, Vec<u8>) + 'static,
     ) {
         self.callbacks.borrow_mut().on_rpc_callback = Some(Box::from(callback));
     }
+    /// Start a lockstep session of `clients`, numbered by their order. Each gets a
+    /// `LockstepStart` and the session waits at most `turn_timeout` for inputs of a turn,
+    /// `Duration::ZERO` waits for every input
+    pub fn create_lockstep_session(&self, clients: &[Uuid], turn_timeout: Duration) -> ServerResult<SessionId> {
+        if clients.is_empty() {
+            return Err("A lockstep session needs players".to_string());
+        }
+        for (index, client) in clients.iter().enumerate() {
+            if clients[..index].contains(client) {
+                return Err(format!("Client {} is listed twice", client));
+            }
+            if self.connection_tracker.borrow().state(client) != ConnectionState::Connected {
+                return Err(format!("Client {} is not authenticated", client));
+            }
+            if self.lockstep.borrow().session_of(client).is_some() {
+                return Err(format!("Client {} already plays in a lockstep session", client));
+            }
+        }
+        let now = self.server_time();
+        let session = self.lockstep.borrow_mut().create(clients.to_vec(), turn_timeout, now);
+        let mut result = Ok(());
+        for (player, client) in clients.iter().enumerate() {
+            let mut start = LockstepStart::new();
+            start.session = session;
+            start.player = player as u32;
+            start.players = clients.len() as u32;
+            let mut message = GeneralOmgppMessage::new();
+            message.data = Some(Data::LockstepStart(start));
+            result = result.and(self.send_message(client, SendMode::Reliable, message));
+        }
+        result.map(|_| session)
+    }
+    /// Stop collecting turns of `session`, its clients get a `LockstepEnd` and may join other sessions
+    pub fn end_lockstep_session(&self, session: SessionId) -> ServerResult<()> {
+        let ended = self
+            .lockstep
+            .borrow_mut()
+            .end(session)
+            .ok_or_else(|| format!("There is no lockstep session {}", session))?;
+        let mut end = LockstepEnd::new();
+        end.session = session;
+        let mut message = GeneralOmgppMessage::new();
+        message.data = Some(Data::LockstepEnd(end));
+        self.multicast_message(&ended.present_players(), SendMode::Reliable, message)
+    }
+    pub fn lockstep_sessions(&self) -> Vec<SessionId> {
+        self.lockstep.borrow().ids()
+    }
+    /// Session `client` plays in, unless it disconnected from it
+    pub fn lockstep_session_of(&self, client: &Uuid) -> Option<SessionId> {
+        self.lockstep.borrow().session_of(client).map(|session| session.id())
+    }
+    pub fn lockstep_players(&self, session: SessionId) -> Vec<Uuid> {
+        self.lockstep
+            .borrow()
+            .get(session)
+            .map_or_else(Vec::new, |session| session.players().to_vec())
+    }
+    /// Turn `session` collects inputs for
+    pub fn lockstep_turn(&self, session: SessionId) -> Option<u64> {
+        self.lockstep.borrow().get(session).map(|session| session.turn())
+    }
+    /// Called with every completed turn after it was sent to the players
+    pub fn register_on_lockstep_turn(&self, callback: impl Fn(&Server, &LockstepTurn) + 'static) {
+        self.callbacks.borrow_mut().on_lockstep_turn_callback = Some(Box::new(callback));
+    }
+    /// Called for every client whose state checksum of a turn differs from the one most players agree on,
+    /// without a client if no checksum has more votes than every other one
+    pub fn register_on_desync(&self, callback: impl Fn(&Server, SessionId, u64, Option<&Uuid>) + 'static) {
+        self.callbacks.borrow_mut().on_desync_callback = Some(Box::new(callback));
+    }
+    fn process_lockstep(&self) {
+        let (turns, desyncs) = self.lockstep.borrow_mut().update(self.server_time());
+        let callbacks = self.callbacks.borrow();
+        for turn in turns {
+            let players = self
+                .lockstep
+                .borrow()
+                .get(turn.session)
+                .map_or_else(Vec::new, |session| session.present_players());
+            let mut message = GeneralOmgppMessage::new();
+            message.data = Some(Data::LockstepTurn(turn.to_message()));
+            _ = self.multicast_message(&players, SendMode::Reliable, message);
+            if let Some(cb) = &callbacks.on_lockstep_turn_callback {
+                cb(self, &turn);
+            }
+        }
+        if let Some(cb) = &callbacks.on_desync_callback {
+            for desync in desyncs {
+                cb(self, desync.session, desync.turn, desync.client.as_ref());
+            }
+        }
+    }
+    fn generate_client_uuid(&self, connection: ConnectionHandle, endpoint: &Endpoint) -> Uuid {
+        ConnectionTracker::generate_endpoint_uuid(endpoint, self.transport.transport_index(connection))
+    }
     fn process_connection_events(
         &self,
-        event: GnsConnectionEvent,
-        socket: &GnsSocket<IsServer>,
+        event: TransportEvent,
         callbacks: &ServerCallbacks,
         connection_tracker: &RefCell<ConnectionTracker>,
     ) -> ServerResult<()> {
-        let endpoint = event.info().to_endpoint();
-        let client_uuid = ConnectionTracker::generate_endpoint_uuid(&endpoint);
-        match (event.old_state(), event.info().state()) {
+        match event {
             // client tries to connect
-            (
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_None,
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting,
-            ) => {
+            TransportEvent::Connecting(connection, endpoint) => {
+                let client_uuid = self.generate_client_uuid(connection, &endpoint);
                 if let Some(cb) = &callbacks.on_connection_changed_callback{
                     cb(self,&client_uuid, &endpoint, ConnectionState::Connecting);      // TODO add host and port as parameters
                 }
-                let should_accept = (callbacks.on_connect_requested_callback)(self,&client_uuid,&endpoint);
+                let should_accept = !self.is_banned(&endpoint.ip)
+                    && (callbacks.on_connect_requested_callback)(self, &client_uuid, &endpoint);
                 if should_accept {
-                    socket.accept(event.connection()).or_else(|_err| {
-                        ServerResult::Err("Cannot accept the connection".to_string())
-                    })?;
+                    self.transport.accept(connection)?;
                 } else {
-                    // watch all possible reasons in ESteamNetConnectionEnd at steamworks_sdk_160\sdk\public\steam\steamnetworkingtypes.h (SteamworksSDK)
-                    socket.close_connection(
-                        event.connection(),
-                        0,      // k_ESteamNetConnectionEnd_Invalid 
-                        "You are not allowed to connect",
-                        false,
-                    );
+                    self.transport.close(connection, "You are not allowed to connect");
                 }
             }
             // client disconnected gracefully (? or may be not)
-            (
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting | ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connected,
-                 ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_ClosedByPeer | ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_None |ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_ProblemDetectedLocally,
-            ) => {
+            TransportEvent::Disconnected(connection, endpoint) => {
+                self.batches.borrow_mut().forget_connection(connection);
+                let client_uuid = self.generate_client_uuid(connection, &endpoint);
                 connection_tracker.borrow_mut().track_client_disconnected(&client_uuid);
+                self.forget_disconnected_client(&client_uuid);
                 let state = connection_tracker.borrow().state(&client_uuid);
                 if let Some(cb) = &callbacks.on_connection_changed_callback {
                     cb(self,&client_uuid, &endpoint, state);
                 }
             }
             // client connected but auth required
-            (
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting,
-                ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connected,
-            ) => {
-                connection_tracker.borrow_mut().track_client_connected_unverified(client_uuid.clone(),endpoint, event.connection());
+            TransportEvent::Connected(connection, endpoint) => {
+                let client_uuid = self.generate_client_uuid(connection, &endpoint);
+                connection_tracker.borrow_mut().track_client_connected_unverified(client_uuid.clone(),endpoint, connection, self.clock.now());
                 let state = connection_tracker.borrow().state(&client_uuid);
                 if let Some(cb) = &callbacks.on_connection_changed_callback {
                     cb(self,&client_uuid, &endpoint, state);
                 }
             }
-
-            (_, _) => (),
         }
         Ok(())
     }
 
     fn process_messages(
         &self,
-        event: &GnsNetworkMessage<ToReceive>,
+        connection: ConnectionHandle,
+        data: &[u8],
+        connection_tracker: &RefCell<ConnectionTracker>,
+        callbacks: &ServerCallbacks,
+    ) -> ServerResult<()> {
+        let Ok(decoded) = GeneralOmgppMessage::parse_from_bytes(data) else {
+            // cannot decode message;
+            return Ok(());
+        };
+        let batch = match decoded.data {
+            Some(Data::Batch(batch)) => batch,
+            _ => return self.process_message(connection, decoded, connection_tracker, callbacks),
+        };
+        // batches are unpacked one level deep and only from verified clients
+        let is_sender_verified = {
+            let tracker = connection_tracker.borrow();
+            tracker
+                .client_by_connection(&connection)
+                .is_some_and(|sender| tracker.state(sender) == ConnectionState::Connected)
+        };
+        if !is_sender_verified {
+            return Ok(());
+        }
+        let mut result = Ok(());
+        for message in &batch.messages {
+            let Ok(decoded) = GeneralOmgppMessage::parse_from_bytes(message) else {
+                continue;
+            };
+            if matches!(decoded.data, Some(Data::Batch(_))) {
+                continue;
+            }
+            result = result.and(self.process_message(connection, decoded, connection_tracker, callbacks));
+        }
+        result
+    }
+    fn process_message(
+        &self,
+        connection: ConnectionHandle,
+        mut decoded: GeneralOmgppMessage,
         connection_tracker: &RefCell<ConnectionTracker>,
         callbacks: &ServerCallbacks,
     ) -> ServerResult<()> {
-        let data = event.payload();
-        let connection = event.connection();
         let sender = connection_tracker
             .borrow()
             .client_by_connection(&connection)
@@ -375,82 +1174,287 @@ impl<'a> Server<'a> {
             .cloned()
             .ok_or_else(|| "Unknown endpoint".to_string())?;
 
-        if let Some(decoded) = GeneralOmgppMessage::parse_from_bytes(data).ok() {
-            // we decoded the message
-            match decoded.data {
-                Some(Data::Message(message)) => {
+        let context = InterceptContext {
+            direction: Direction::Inbound,
+            peer: &sender,
+            endpoint: &endpoint,
+        };
+        match self.interceptors.borrow().run(self, &context, &mut decoded) {
+            Intercept::Continue => (),
+            Intercept::Handled => return Ok(()),
+            Intercept::Reject(reason) => {
+                if let Some(Data::Cmd(cmd)) = &decoded.data {
+                    self.send_cmd_error(&sender, cmd, &reason);
+                }
+                return Ok(());
+            }
+        }
+        match decoded.data {
+            Some(Data::Message(message)) if is_sender_verified => {
+                let typed_result =
+                    self.typed_messages
+                        .borrow()
+                        .dispatch(self, &sender, &endpoint, message.type_, &message.data);
+                match typed_result {
+                    Some(Ok(())) => (),
+                    Some(Err(reason)) => {
+                        if let Some(cb) = &callbacks.on_message_decode_error_callback {
+                            cb(self, &sender, &endpoint, message.type_, &reason)
+                        }
+                    }
                     // cb stands for callback
-                    if let Some(cb) = &callbacks.on_message_callback {
-                        if is_sender_verified {
+                    None => {
+                        if let Some(cb) = &callbacks.on_message_callback {
                             cb(self, &sender, &endpoint, message.type_, message.data)
                         }
                     }
                 }
-                Some(Data::Rpc(rpc_call)) => {
-                    if let Some(rpc_callback) = &callbacks.on_rpc_callback {
-                        if is_sender_verified {
-                            rpc_callback(
-                                self,
-                                &sender,
-                                &endpoint,
-                                rpc_call.reliable,
-                                rpc_call.method_id,
-                                rpc_call.request_id,
-                                rpc_call.arg_type,
-                                rpc_call.arg_data,
-                            );
-                        }
-                    };
+            }
+            Some(Data::Rpc(rpc_call)) => {
+                if let Some(rpc_callback) = &callbacks.on_rpc_callback {
+                    if is_sender_verified {
+                        rpc_callback(
+                            self,
+                            &sender,
+                            &endpoint,
+                            rpc_call.reliable,
+                            rpc_call.method_id,
+                            rpc_call.request_id,
+                            rpc_call.arg_type,
+                            rpc_call.arg_data,
+                        );
+                    }
+                };
+            }
+            Some(Data::SnapshotAck(ack)) if is_sender_verified => {
+                self.replication.borrow_mut().acknowledge(&sender, ack.tick);
+            }
+            Some(Data::Inputs(batch)) if is_sender_verified => {
+                let mut inputs = self.inputs.borrow_mut();
+                for input in batch.inputs {
+                    inputs.push(&sender, input.sequence, input.data);
                 }
-                Some(Data::Cmd(cmd)) => {
-                    self.cmd_handlers
-                        .borrow()
-                        .handle(self, &sender, &endpoint, &cmd);
+            }
+            Some(Data::LockstepInput(input)) if is_sender_verified => {
+                self.lockstep.borrow_mut().submit_input(&sender, input.turn, input.data);
+            }
+            Some(Data::LockstepChecksum(checksum)) if is_sender_verified => {
+                self.lockstep
+                    .borrow_mut()
+                    .submit_checksum(&sender, checksum.turn, checksum.checksum);
+            }
+            Some(Data::TimeSync(mut sync)) if is_sender_verified => {
+                // clients cannot widen the lag compensation window with made up values
+                let stats = ClientStats::from_report(
+                    sync.rtt,
+                    sync.interpolation_delay,
+                    self.connection_status(&sender).and_then(|status| status.ping),
+                    self.settings.borrow().max_interpolation_delay,
+                );
+                self.client_stats.borrow_mut().insert(sender, stats);
+                sync.server_time = self.server_time().as_micros() as u64;
+                let mut reply = GeneralOmgppMessage::new();
+                reply.data = Some(Data::TimeSync(sync));
+                _ = self.send_message(&sender, SendMode::Unreliable, reply);
+            }
+            Some(Data::Cmd(cmd)) => {
+                let roles = self.client_roles(&sender);
+                let permissions = CmdPermissions {
+                    authenticated: is_sender_verified,
+                    roles: &roles,
+                };
+                let result = self
+                    .cmd_handlers
+                    .borrow()
+                    .handle(self, &sender, &endpoint, &cmd, &permissions);
+                match result {
+                    Ok(()) => (),
+                    // don't answer unverified connections probing for commands
+                    Err(CmdError::NotFound) if !is_sender_verified => (),
+                    Err(error) => self.send_cmd_error(&sender, &cmd, &error.to_string()),
                 }
-                _ => (),
             }
-        } else {
-            // cannot decode message;
+            _ => (),
         }
         Ok(())
     }
 
-    fn send_with_flags(
+    fn send_with_mode(
         &self,
         client: &Uuid,
         msg_type: i64,
         data: &[u8],
-        flags: i32,
+        mode: SendMode,
     ) -> ServerResult<()> {
-        let connection = self
-            .connection_tracker
-            .borrow()
-            .client_connection(client)
-            .ok_or_else(|| "There is not such client to send")?;
-
-        let msg_bytes = Server::create_regular_message(msg_type, data)
-            .or_else(|_or| Err("Cannot create general message".to_string()))?;
-
-        // TODO check send result
-        let _send_result =
-            TransmitterHelper::send(&self.socket, &[connection], flags, msg_bytes.as_slice());
-        Ok(())
+        let message = Server::create_regular_message(msg_type, data);
+        self.send_message(client, mode, message)
     }
-    fn broadcast_with_flags(&self, flags: i32, data: &[u8]) -> ServerResult<()> {
-        let tracker = self.connection_tracker.borrow();
-        let connections = tracker.active_connections();
-        let _res = TransmitterHelper::send_with_iter(&self.socket, connections, flags, data);
+    // the transport configures connections before they are reported, existing ones are reconfigured
+    fn apply_connection_config(&self) -> ServerResult<()> {
+        if !self.connection_config_changed.replace(false) {
+            return Ok(());
+        }
+        let settings = self.settings.borrow();
+        let config = &settings.connection_config;
+        let mut result = self.transport.set_accept_config(config);
+        let connections = self.connection_tracker.borrow().connections().collect::<Vec<_>>();
+        for connection in connections {
+            result = result.and(self.transport.configure_connection(connection, config));
+        }
+        result
+    }
+    fn send_message(&self, client: &Uuid, mode: SendMode, message: GeneralOmgppMessage) -> ServerResult<()> {
+        let lane = self.lanes.borrow().lane_of(&message);
+        self.send_message_on_lane(client, mode, lane, message)
+    }
+    fn send_message_on_lane(
+        &self,
+        client: &Uuid,
+        mode: SendMode,
+        lane: u16,
+        mut message: GeneralOmgppMessage,
+    ) -> ServerResult<()> {
+        self.stamp(&mut message);
+        message.last_input = self.inputs.borrow().last_processed(client);
+        let (connection, endpoint) = {
+            let tracker = self.connection_tracker.borrow();
+            let connection = tracker
+                .client_connection(client)
+                .ok_or_else(|| "There is not such client to send")?;
+            (connection, tracker.client_endpoint(client).cloned())
+        };
+        let bytes = match endpoint {
+            Some(endpoint) => self.intercept_outbound(client, &endpoint, message)?,
+            None => Some(Self::encode(&message)?),
+        };
+        match bytes {
+            Some(bytes) => self.transport_send(&[connection], mode, lane, &bytes),
+            None => Ok(()),
+        }
+    }
+    fn broadcast_message(&self, mode: SendMode, mut message: GeneralOmgppMessage) -> ServerResult<()> {
+        self.stamp(&mut message);
+        if self.interceptors.borrow().is_empty() {
+            let connections = self
+                .connection_tracker
+                .borrow()
+                .active_connections()
+                .collect::<Vec<_>>();
+            if connections.is_empty() {
+                return Ok(());
+            }
+            let lane = self.lanes.borrow().lane_of(&message);
+            return self.transport_send(&connections, mode, lane, &Self::encode(&message)?);
+        }
+        // interceptors may change or stop the message per client
+        let mut result = Ok(());
+        for (client, _endpoint) in self.active_clients() {
+            let sent = self.send_message(&client, mode, message.clone());
+            result = result.and(sent);
+        }
+        result
+    }
+    // tick and time the message is sent at, for clients to order and interpolate states
+    fn stamp(&self, message: &mut GeneralOmgppMessage) {
+        message.server_tick = self.replication_tick();
+        message.server_time = self.server_time().as_micros() as u64;
+    }
+    fn multicast_message(&self, clients: &[Uuid], mode: SendMode, mut message: GeneralOmgppMessage) -> ServerResult<()> {
+        self.stamp(&mut message);
+        if self.interceptors.borrow().is_empty() {
+            let connections = {
+                let tracker = self.connection_tracker.borrow();
+                clients
+                    .iter()
+                    .filter_map(|client| tracker.client_connection(client))
+                    .collect::<Vec<_>>()
+            };
+            if connections.is_empty() {
+                return Ok(());
+            }
+            let lane = self.lanes.borrow().lane_of(&message);
+            return self.transport_send(&connections, mode, lane, &Self::encode(&message)?);
+        }
+        let mut result = Ok(());
+        for client in clients {
+            let sent = self.send_message(client, mode, message.clone());
+            result = result.and(sent);
+        }
+        result
+    }
+    // encoded message, or `None` if an interceptor handled it
+    fn intercept_outbound(
+        &self,
+        client: &Uuid,
+        endpoint: &Endpoint,
+        mut message: GeneralOmgppMessage,
+    ) -> ServerResult<Option<Vec<u8>>> {
+        let context = InterceptContext {
+            direction: Direction::Outbound,
+            peer: client,
+            endpoint,
+        };
+        match self.interceptors.borrow().run(self, &context, &mut message) {
+            Intercept::Continue => Ok(Some(Self::encode(&message)?)),
+            Intercept::Handled => Ok(None),
+            Intercept::Reject(reason) => Err(reason),
+        }
+    }
+    fn transport_send(&self, connections: &[ConnectionHandle], mode: SendMode, lane: u16, bytes: &[u8]) -> ServerResult<()> {
+        self.apply_connection_config()?;
+        let (batch_messages, max_batch_size) = {
+            let settings = self.settings.borrow();
+            (settings.batch_messages, settings.max_batch_size)
+        };
+        if !batch_messages {
+            self.transport.send_on_lane(connections, mode, lane, bytes)?;
+            self.stats.borrow_mut().track_sent(connections.len(), bytes.len());
+            return Ok(());
+        }
+        let mut result = Ok(());
+        for connection in connections {
+            let full = self.batches.borrow_mut().push(*connection, mode, lane, bytes, max_batch_size);
+            if let Some(messages) = full {
+                result = result.and(self.send_batch(*connection, mode, lane, messages));
+            }
+        }
+        result
+    }
+    // a single message goes without the batch envelope
+    fn send_batch(
+        &self,
+        connection: ConnectionHandle,
+        mode: SendMode,
+        lane: u16,
+        mut messages: Vec<Vec<u8>>,
+    ) -> ServerResult<()> {
+        let bytes = match messages.len() {
+            1 => messages.pop().unwrap_or_default(),
+            _ => {
+                let mut batch = general_omgpp_message::Batch::new();
+                batch.messages = messages;
+                let mut message = GeneralOmgppMessage::new();
+                message.data = Some(Data::Batch(batch));
+                Self::encode(&message)?
+            }
+        };
+        self.transport.send_on_lane(&[connection], mode, lane, &bytes)?;
+        self.stats.borrow_mut().track_sent(1, bytes.len());
         Ok(())
     }
+    fn encode(message: &GeneralOmgppMessage) -> ServerResult<Vec<u8>> {
+        message
+            .write_to_bytes()
+            .map_err(|_| "Cannot create general message".to_string())
+    }
 
-    fn create_regular_message(msg_type: i64, data: &[u8]) -> protobuf::Result<Vec<u8>> {
+    fn create_regular_message(msg_type: i64, data: &[u8]) -> GeneralOmgppMessage {
         let mut payload = GeneralOmgppMessage::new();
         let mut message = general_omgpp_message::Message::new();
         message.type_ = msg_type;
         message.data = Vec::from(data); // somehow get rid of unessesary array copying
         payload.data = Some(Data::Message(message));
-        let bytes = payload.write_to_bytes()?;
-        return Ok(bytes);
+        payload
     }
     fn create_rpc_message(
         reliable: bool,
@@ -458,7 +1462,7 @@ impl<'a> Server<'a> {
         request_id: u64,
         arg_type: i64,
         data: Option<&[u8]>,
-    ) -> protobuf::Result<Vec<u8>> {
+    ) -> GeneralOmgppMessage {
         let mut payload = GeneralOmgppMessage::new();
         let mut rpc = general_omgpp_message::RpcCall::new();
         rpc.reliable = reliable;
@@ -470,23 +1474,21 @@ impl<'a> Server<'a> {
             None => Vec::new(),
         };
         payload.data = Some(Data::Rpc(rpc));
-        let bytes = payload.write_to_bytes()?;
-        return Ok(bytes);
+        payload
     }
 
     fn create_cmd_message(
         cmd: String,
         request_id: u64,
         args: Vec<String>,
-    ) -> protobuf::Result<Vec<u8>> {
+    ) -> GeneralOmgppMessage {
         let mut payload = GeneralOmgppMessage::new();
         let mut request = general_omgpp_message::CmdRequest::new();
         request.cmd = cmd;
         request.request_id = request_id;
         request.args = args;
         payload.data = Some(Data::Cmd(request));
-        let bytes = payload.write_to_bytes()?;
-        return Ok(bytes);
+        payload
     }
 }
 
@@ -0,0 +1,207 @@
+use std::{net::IpAddr, str::FromStr, time::Duration};
+
+use omgpp_core::{
+    cmd_args::{ArgSpec, ArgType, ArgValue, CmdArgs, CmdSchema},
+    cmd_handler::CmdHandler,
+    messages::general_message::general_omgpp_message::CmdRequest,
+    Endpoint, OmgppPredefinedCmd,
+};
+use uuid::Uuid;
+
+use super::{AuthResult, Server};
+
+const RESOURCE_LOCATION: &str = "resource_location";
+const UNVERIFIED_EXPIRE_MS: &str = "unverified_expire_ms";
+
+// reply lines, `None` if the command already replied
+type AdminResult = Result<Option<Vec<String>>, String>;
+
+// Built-in commands to operate a live server. They require `OmgppPredefinedCmd::ADMIN_ROLE`
+impl<'a> Server<'a> {
+    pub(super) fn init_admin_cmd_handlers(&self) {
+        let admin_commands = [
+            Server::admin_cmd(
+                OmgppPredefinedCmd::PLAYERS,
+                CmdSchema::new("List authenticated clients"),
+                Server::cmd_players_handle,
+            ),
+            Server::admin_cmd(
+                OmgppPredefinedCmd::KICK,
+                CmdSchema::new("Disconnect a client")
+                    .arg(ArgSpec::new("player", ArgType::Uuid))
+                    .arg(ArgSpec::new("reason", ArgType::String).with_default(ArgValue::String("Kicked".to_string()))),
+                Server::cmd_kick_handle,
+            ),
+            Server::admin_cmd(
+                OmgppPredefinedCmd::BAN,
+                CmdSchema::new("Disconnect and refuse every connection from the ip of a client")
+                    .arg(ArgSpec::new("target", ArgType::String).with_description("Uuid of a client or an ip"))
+                    .arg(ArgSpec::new("reason", ArgType::String).with_default(ArgValue::String("Banned".to_string()))),
+                Server::cmd_ban_handle,
+            ),
+            Server::admin_cmd(
+                OmgppPredefinedCmd::UNBAN,
+                CmdSchema::new("Accept connections from a banned ip again")
+                    .arg(ArgSpec::new("ip", ArgType::String)),
+                Server::cmd_unban_handle,
+            ),
+            Server::admin_cmd(
+                OmgppPredefinedCmd::ANNOUNCE,
+                CmdSchema::new("Send a text to every client").arg(ArgSpec::new("text", ArgType::String)),
+                Server::cmd_announce_handle,
+            ),
+            Server::admin_cmd(
+                OmgppPredefinedCmd::SET,
+                CmdSchema::new("Show or change runtime settings")
+                    .arg(
+                        ArgSpec::new(
+                            "setting",
+                            ArgType::Enum(vec![RESOURCE_LOCATION.to_string(), UNVERIFIED_EXPIRE_MS.to_string()]),
+                        )
+                        .optional(),
+                    )
+                    .arg(ArgSpec::new("value", ArgType::String).optional()),
+                Server::cmd_set_handle,
+            ),
+            Server::admin_cmd(
+                OmgppPredefinedCmd::STATS,
+                CmdSchema::new("Show uptime, clients and traffic"),
+                Server::cmd_stats_handle,
+            ),
+        ];
+        let mut cmd_handlers = self.cmd_handlers.borrow_mut();
+        for handler in admin_commands {
+            _ = cmd_handlers.register_handler(handler);
+        }
+    }
+    fn admin_cmd(
+        cmd: &str,
+        schema: CmdSchema,
+        callback: impl Fn(&Server<'a>, &Uuid, &CmdRequest, &CmdArgs) -> AdminResult + 'static,
+    ) -> CmdHandler<Server<'a>> {
+        let handler = move |server: &Server<'a>, uuid: &Uuid, _endpoint: &Endpoint, request: &CmdRequest, args: &CmdArgs| {
+            match callback(server, uuid, request, args) {
+                Ok(Some(lines)) => {
+                    _ = server.send_command(uuid, request.cmd.clone(), request.request_id, Some(lines));
+                }
+                Ok(None) => (),
+                Err(reason) => server.send_cmd_error(uuid, request, &reason),
+            }
+        };
+        CmdHandler::typed(cmd, true, schema, Box::new(handler)).with_roles(&[OmgppPredefinedCmd::ADMIN_ROLE])
+    }
+    pub(super) fn authenticate_admin(&self, password: Option<&String>) -> AuthResult {
+        let settings = self.settings.borrow();
+        let Some(expected) = &settings.admin_password else {
+            return Err("Admin access is disabled".to_string());
+        };
+        match password {
+            Some(password) if constant_time_eq(password.as_bytes(), expected.as_bytes()) => {
+                Ok(vec![OmgppPredefinedCmd::ADMIN_ROLE.to_string()])
+            }
+            _ => Err("Wrong admin password".to_string()),
+        }
+    }
+    fn cmd_players_handle(&self, _uuid: &Uuid, _request: &CmdRequest, _args: &CmdArgs) -> AdminResult {
+        let players = self
+            .active_clients()
+            .into_iter()
+            .map(|(client, endpoint)| {
+                let mut roles = self.client_roles(&client).into_iter().collect::<Vec<_>>();
+                roles.sort();
+                format!("{} {}:{} [{}]", client, endpoint.ip, endpoint.port, roles.join(","))
+            })
+            .collect();
+        Ok(Some(players))
+    }
+    fn cmd_kick_handle(&self, uuid: &Uuid, request: &CmdRequest, args: &CmdArgs) -> AdminResult {
+        let player = args.uuid("player").unwrap_or_default();
+        if self.connection_tracker.borrow().client_connection(&player).is_none() {
+            return Err("There is not such client".to_string());
+        }
+        // answer first, the admin may kick itself
+        _ = self.send_command(uuid, request.cmd.clone(), request.request_id, Some(vec!["ok".to_string()]));
+        self.disconnect(&player, args.str("reason").unwrap_or_default())?;
+        Ok(None)
+    }
+    fn cmd_ban_handle(&self, uuid: &Uuid, request: &CmdRequest, args: &CmdArgs) -> AdminResult {
+        let target = args.str("target").unwrap_or_default();
+        let ip = match Uuid::parse_str(target) {
+            Ok(player) => self
+                .connection_tracker
+                .borrow()
+                .client_endpoint(&player)
+                .map(|endpoint| endpoint.ip)
+                .ok_or("There is not such client")?,
+            Err(_) => IpAddr::from_str(target).map_err(|_| format!("{:?} is neither a uuid nor an ip", target))?,
+        };
+        // answer first, the admin may share the ip
+        _ = self.send_command(uuid, request.cmd.clone(), request.request_id, Some(vec!["ok".to_string(), ip.to_string()]));
+        self.ban(ip, args.str("reason").unwrap_or_default());
+        Ok(None)
+    }
+    fn cmd_unban_handle(&self, _uuid: &Uuid, _request: &CmdRequest, args: &CmdArgs) -> AdminResult {
+        let ip = args.str("ip").unwrap_or_default();
+        let ip = IpAddr::from_str(ip).map_err(|_| format!("{:?} is not an ip", ip))?;
+        match self.unban(&ip) {
+            true => Ok(Some(vec!["ok".to_string()])),
+            false => Err(format!("{} is not banned", ip)),
+        }
+    }
+    fn cmd_announce_handle(&self, _uuid: &Uuid, _request: &CmdRequest, args: &CmdArgs) -> AdminResult {
+        self.announce(args.str("text").unwrap_or_default())?;
+        Ok(Some(vec!["ok".to_string()]))
+    }
+    fn cmd_set_handle(&self, _uuid: &Uuid, _request: &CmdRequest, args: &CmdArgs) -> AdminResult {
+        if let (Some(setting), Some(value)) = (args.str("setting"), args.str("value")) {
+            match setting {
+                RESOURCE_LOCATION => self.settings.borrow_mut().resource_location = value.to_string(),
+                _ => {
+                    let millis = value
+                        .parse()
+                        .map_err(|_| format!("{:?} is not a number of milliseconds", value))?;
+                    let period = Duration::from_millis(millis);
+                    self.settings.borrow_mut().unverified_connection_expire_period = period;
+                    self.connection_tracker
+                        .borrow_mut()
+                        .set_unverified_connection_expire_period(period);
+                }
+            }
+        }
+        let settings = self.settings.borrow();
+        let values = [
+            (RESOURCE_LOCATION, settings.resource_location.clone()),
+            (
+                UNVERIFIED_EXPIRE_MS,
+                settings.unverified_connection_expire_period.as_millis().to_string(),
+            ),
+        ];
+        let lines = values
+            .into_iter()
+            .filter(|(name, _)| args.str("setting").is_none_or(|setting| setting == *name))
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        Ok(Some(lines))
+    }
+    fn cmd_stats_handle(&self, _uuid: &Uuid, _request: &CmdRequest, _args: &CmdArgs) -> AdminResult {
+        let stats = self.stats();
+        let tracker = self.connection_tracker.borrow();
+        Ok(Some(vec![
+            format!("uptime_s={}", stats.uptime(self.clock.now()).as_secs()),
+            format!("clients={}", tracker.active_clients().len()),
+            format!("unverified={}", tracker.unverified_count()),
+            format!("banned={}", self.banned.borrow().len()),
+            format!("messages_received={}", stats.messages_received),
+            format!("bytes_received={}", stats.bytes_received),
+            format!("messages_sent={}", stats.messages_sent),
+            format!("bytes_sent={}", stats.bytes_sent),
+        ]))
+    }
+}
+
+// compares fixed length digests, so neither the position of the first mismatch
+// nor the length of the password leak through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (md5::compute(a), md5::compute(b));
+    a.iter().zip(b.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
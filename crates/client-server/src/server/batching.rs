@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use omgpp_core::transport::{ConnectionHandle, SendMode};
+
+// length prefix and field tag of an encoded message inside a batch
+const FRAMING_OVERHEAD: usize = 4;
+
+#[derive(Default)]
+struct Batch {
+    messages: Vec<Vec<u8>>,
+    size: usize,
+}
+
+type BatchKey = (ConnectionHandle, SendMode, u16);
+
+/// Encoded messages queued per connection, send mode and lane until the tick is flushed
+#[derive(Default)]
+pub struct Batches {
+    batches: HashMap<BatchKey, Batch>,
+    // flush order, so batches leave in the order they were started
+    order: Vec<BatchKey>,
+}
+impl Batches {
+    /// Queue `bytes` for `connection`. If the batch would grow beyond `max_size` its messages are
+    /// returned to be sent right away, and `bytes` starts a new batch; a larger message goes alone
+    pub fn push(
+        &mut self,
+        connection: ConnectionHandle,
+        mode: SendMode,
+        lane: u16,
+        bytes: &[u8],
+        max_size: usize,
+    ) -> Option<Vec<Vec<u8>>> {
+        let key = (connection, mode, lane);
+        let batch = self.batches.entry(key).or_insert_with(|| {
+            self.order.push(key);
+            Batch::default()
+        });
+        let size = bytes.len() + FRAMING_OVERHEAD;
+        let full = match !batch.messages.is_empty() && batch.size + size > max_size {
+            true => Some(std::mem::take(batch)),
+            false => None,
+        };
+        batch.messages.push(bytes.to_vec());
+        batch.size += size;
+        full.map(|batch| batch.messages)
+    }
+    /// Every queued batch, oldest first
+    pub fn take_all(&mut self) -> Vec<(ConnectionHandle, SendMode, u16, Vec<Vec<u8>>)> {
+        let mut batches = std::mem::take(&mut self.batches);
+        std::mem::take(&mut self.order)
+            .into_iter()
+            .filter_map(|key| {
+                let batch = batches.remove(&key)?;
+                Some((key.0, key.1, key.2, batch.messages))
+            })
+            .collect()
+    }
+    /// Queued batches of `connection`, e.g. to send them before it is closed
+    pub fn take_connection(&mut self, connection: ConnectionHandle) -> Vec<(SendMode, u16, Vec<Vec<u8>>)> {
+        let mut taken = Vec::new();
+        self.order.retain(|key| {
+            if key.0 != connection {
+                return true;
+            }
+            if let Some(batch) = self.batches.remove(key) {
+                taken.push((key.1, key.2, batch.messages));
+            }
+            false
+        });
+        taken
+    }
+    pub fn queued(&self) -> usize {
+        self.batches.values().map(|batch| batch.messages.len()).sum()
+    }
+    pub fn forget_connection(&mut self, connection: ConnectionHandle) {
+        self.take_connection(connection);
+    }
+}
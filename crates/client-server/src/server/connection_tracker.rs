@@ -1,18 +1,19 @@
-use std::{collections::HashMap, net::IpAddr, time::Instant};
+use std::{collections::{HashMap, HashSet}, net::IpAddr, time::Instant};
 
 use bimap::BiHashMap;
-use gns::{GnsConnection};
-use omgpp_core::{ConnectionState, Endpoint};
+use omgpp_core::{transport::ConnectionHandle, ConnectionState, Endpoint};
 use std::time::Duration;
 use uuid::Uuid;
 
 
 #[derive(Default, Debug)]
 pub struct ConnectionTracker {
-    connections: BiHashMap<Uuid, GnsConnection>,
+    connections: BiHashMap<Uuid, ConnectionHandle>,
     unverified_connections: HashMap<Uuid, Instant>,
-    endpoints: BiHashMap<Uuid, Endpoint>,
+    // clients of different transports can share an endpoint
+    endpoints: HashMap<Uuid, Endpoint>,
     states: HashMap<Uuid,ConnectionState>,
+    roles: HashMap<Uuid, HashSet<String>>,
     unverified_connection_expire_period: Duration
 }
 
@@ -33,7 +34,7 @@ impl ConnectionTracker {
         active_endpoints
     }
 
-    pub fn client_connection(&self, client: &Uuid) -> Option<GnsConnection> {
+    pub fn client_connection(&self, client: &Uuid) -> Option<ConnectionHandle> {
         self.connections
             .get_by_left(client)
             .map(|conn| conn.clone())
@@ -45,36 +46,31 @@ impl ConnectionTracker {
             .unwrap_or(ConnectionState::None)
     }
     pub fn client_endpoint(&self, client: &Uuid) -> Option<&Endpoint> {
-        self.endpoints
-            .get_by_left(client)
-            .map(|conn| conn)
+        self.endpoints.get(client)
     }
     pub fn track_client_disconnected(&mut self, uuid: &Uuid) {
         if self.connections.contains_left(uuid) {
             self.connections.remove_by_left(uuid);
         }
-        if self.endpoints.contains_left(uuid){
-            self.endpoints.remove_by_left(uuid);
-        }
+        self.endpoints.remove(uuid);
         if self.unverified_connections.contains_key(uuid){
             self.unverified_connections.remove(uuid);
         }
+        self.roles.remove(uuid);
         //TODO remove disconnected entries after some period; Prevent infinite collection growing
         self.states.insert(uuid.clone(), ConnectionState::Disconnected);
     }
 
-    pub fn track_client_connected_unverified(&mut self, uuid: Uuid, endpoint:Endpoint,connection: GnsConnection) {
+    pub fn track_client_connected_unverified(&mut self, uuid: Uuid, endpoint:Endpoint,connection: ConnectionHandle, now: Instant) {
         if !self.connections.contains_left(&uuid){
             self.connections.insert(uuid,connection);
         }
-        let now = Instant::now();
-        println!("{:?} - {:?}",uuid,now);
         self.unverified_connections.insert(uuid, now);
         // TODO decide what todo when we have already associated endpoint
         let _old_endpoint = self.endpoints.insert(uuid, endpoint);   
         self.states.insert(uuid.clone(), ConnectionState::ConnectedUnverified);
     }
-    pub fn track_client_connected(&mut self, uuid: Uuid, endpoint:Endpoint,connection: GnsConnection) {
+    pub fn track_client_connected(&mut self, uuid: Uuid, endpoint:Endpoint,connection: ConnectionHandle) {
         if self.unverified_connections.contains_key(&uuid){
             self.unverified_connections.remove(&uuid);
         }
@@ -85,10 +81,31 @@ impl ConnectionTracker {
         let _old_endpoint = self.endpoints.insert(uuid, endpoint);   
         self.states.insert(uuid.clone(), ConnectionState::Connected);
     }
-    pub fn client_by_connection(&self, connection: &GnsConnection) -> Option<&Uuid> {
+    pub fn roles(&self, client: &Uuid) -> Option<&HashSet<String>> {
+        self.roles.get(client)
+    }
+    pub fn set_roles(&mut self, client: Uuid, roles: HashSet<String>) {
+        self.roles.insert(client, roles);
+    }
+    pub fn roles_mut(&mut self, client: &Uuid) -> Option<&mut HashSet<String>> {
+        self.roles.get_mut(client)
+    }
+    /// Clients connected from `ip`, verified or not
+    pub fn clients_from(&self, ip: &IpAddr) -> Vec<Uuid> {
+        self.endpoints
+            .iter()
+            .filter(|(_, endpoint)| endpoint.ip == *ip)
+            .map(|(uuid, _)| *uuid)
+            .collect()
+    }
+    pub fn client_by_connection(&self, connection: &ConnectionHandle) -> Option<&Uuid> {
         self.connections.get_by_right(connection)
     }
-    pub fn active_connections(&self) -> impl Iterator<Item = GnsConnection> + '_ {
+    /// Connections of every tracked client, verified or not
+    pub fn connections(&self) -> impl Iterator<Item = ConnectionHandle> + '_ {
+        self.connections.right_values().cloned()
+    }
+    pub fn active_connections(&self) -> impl Iterator<Item = ConnectionHandle> + '_ {
         let connections = &self.connections;
         connections
             .into_iter()
@@ -96,8 +113,7 @@ impl ConnectionTracker {
             .map(|item| item.1.clone())
             .into_iter()
     }
-    pub fn expired_unverified_connections(&self) ->impl Iterator<Item = GnsConnection> + '_ {
-        let now = Instant::now();
+    pub fn expired_unverified_connections(&self, now: Instant) ->impl Iterator<Item = ConnectionHandle> + '_ {
         let expiring_period =self.unverified_connection_expire_period.clone();
 
         let unverified_connections = &self.unverified_connections;
@@ -110,16 +126,27 @@ impl ConnectionTracker {
             .filter(|item| item.is_some())
             .map(|item| item.unwrap())
     }
-    pub fn generate_endpoint_uuid(endpoint: &Endpoint) -> Uuid {
-        ConnectionTracker::generate_uuid(endpoint.ip, endpoint.port)
+    pub fn unverified_connection_expire_period(&self) -> Duration {
+        self.unverified_connection_expire_period
+    }
+    pub fn set_unverified_connection_expire_period(&mut self, period: Duration) {
+        self.unverified_connection_expire_period = period;
+    }
+    pub fn unverified_count(&self) -> usize {
+        self.unverified_connections.len()
+    }
+    /// `transport` is `Transport::transport_index` of the connection, so clients of a TCP and
+    /// an UDP transport on the same ip and port get different uuids
+    pub fn generate_endpoint_uuid(endpoint: &Endpoint, transport: usize) -> Uuid {
+        ConnectionTracker::generate_uuid(endpoint.ip, endpoint.port, transport)
     }
-    pub fn generate_uuid(ip: IpAddr, port: u16) -> Uuid {
+    pub fn generate_uuid(ip: IpAddr, port: u16, transport: usize) -> Uuid {
         let ip = match ip {
             IpAddr::V4(v4) => v4.to_ipv6_mapped(),
             IpAddr::V6(v6) => v6,
         };
 
-        let hash_str = format!("{}:{}", ip.to_string(), port.to_string());
+        let hash_str = format!("{}:{}/{}", ip.to_string(), port.to_string(), transport);
         let hash_digest = md5::compute(hash_str);
 
         Uuid::from_bytes(hash_digest.0)
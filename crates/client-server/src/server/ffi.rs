@@ -1,15 +1,23 @@
 use omgpp_core::{
-    ffi::{EndpointFFI, ToFfi, UuidFFI},
-    ConnectionState,
+    cmd_handler::CmdHandler,
+    ffi::{lanes_from_ffi, strings_from_ffi, CStringArray, EndpointFFI, NetworkSimulationFFI, ToFfi, UuidFFI},
+    network_simulation::NetworkSimulation,
+    messages::general_message::general_omgpp_message::CmdRequest,
+    ConnectionState, Endpoint,
 };
 use std::{
     ffi::{c_char, c_uchar, CStr},
     net::IpAddr,
     ptr::null_mut,
     str::FromStr,
+    time::Duration,
 };
 use uuid::Uuid;
-use crate::server::Server;
+use crate::server::{
+    interest::{GridFilter, Position, RadiusFilter, Relevance},
+    lag_compensation::LagCompensation,
+    Server,
+};
 
 
 // FFI
@@ -17,6 +25,12 @@ type ServerOnConnectRequested = extern "C" fn(UuidFFI, EndpointFFI) -> bool;
 type ServerOnConnectionChanged = extern "C" fn(UuidFFI, EndpointFFI, ConnectionState);
 type ServerOnMessage = extern "C" fn(UuidFFI, EndpointFFI, i64, *const c_uchar, usize);
 type ServerOnRpc = extern "C" fn(UuidFFI, EndpointFFI,bool, i64, u64, i64, *const c_uchar,usize);
+// (client, endpoint, cmd, request_id, args, args_count)
+type ServerOnCmd = extern "C" fn(UuidFFI, EndpointFFI, *const c_char, u64, *const *const c_char, usize);
+// (client, sequence, data, size)
+type ServerOnInput = extern "C" fn(UuidFFI, u64, *const c_uchar, usize);
+// (session, turn, client)
+type ServerOnDesync = extern "C" fn(u64, u64, UuidFFI);
 
 #[no_mangle]
 pub unsafe extern "C" fn server_create(ip: *const c_char, port: u16) -> *mut Server<'static> {
@@ -219,13 +233,621 @@ pub unsafe extern "C" fn server_call_rpc_broadcast(
         msg_data,
     );
 }
+// Handle `cmd` sent by clients; a null `cmd` handles every command without its own handler.
+// Returns false if `cmd` is reserved or already registered
+#[no_mangle]
+pub unsafe extern "C" fn server_register_cmd(
+    server: *mut Server,
+    cmd: *const c_char,
+    auth_required: bool,
+    callback: ServerOnCmd,
+) -> bool {
+    let server = server.as_mut().expect("Server cannot be null");
+    let handler = move |_server: &Server,
+                        uuid: &Uuid,
+                        endpoint: &Endpoint,
+                        _handler: &CmdHandler<Server>,
+                        request: &CmdRequest| {
+        let cmd = CStringArray::new(std::slice::from_ref(&request.cmd));
+        let args = CStringArray::new(&request.args);
+        callback(
+            uuid.to_ffi(),
+            endpoint.to_ffi(),
+            *cmd.as_ptr(),
+            request.request_id,
+            args.as_ptr(),
+            args.len(),
+        )
+    };
+    if cmd.is_null() {
+        let handler = CmdHandler::new("", auth_required, Box::new(handler));
+        return server.register_cmd_fallback(Some(handler)).is_ok();
+    }
+    let Ok(cmd) = CStr::from_ptr(cmd).to_str() else {
+        return false;
+    };
+    server
+        .register_cmd_handler(CmdHandler::new(cmd, auth_required, Box::new(handler)))
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_unregister_cmd(server: *mut Server, cmd: *const c_char) -> bool {
+    let Ok(cmd) = CStr::from_ptr(cmd).to_str() else {
+        return false;
+    };
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .unregister_cmd_handler(cmd)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_send_cmd(
+    server: *mut Server,
+    uuid: *const UuidFFI,
+    cmd: *const c_char,
+    request_id: u64,
+    args: *const *const c_char,
+    args_count: usize,
+) -> bool {
+    let client_uuid = uuid_from_ffi_ptr(uuid);
+    let (Ok(cmd), Some(args)) = (CStr::from_ptr(cmd).to_str(), strings_from_ffi(args, args_count)) else {
+        return false;
+    };
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .send_command(&client_uuid, cmd.to_string(), request_id, Some(args))
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_timeout_initial(server: *mut Server, timeout_ms: u32) {
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .timeout_initial = Some(Duration::from_millis(timeout_ms as u64));
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_timeout_connected(server: *mut Server, timeout_ms: u32) {
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .timeout_connected = Some(Duration::from_millis(timeout_ms as u64));
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_send_buffer_size(server: *mut Server, size: i32) {
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .send_buffer_size = Some(size);
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_send_rate_min(server: *mut Server, rate: i32) {
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .send_rate_min = Some(rate);
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_send_rate_max(server: *mut Server, rate: i32) {
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .send_rate_max = Some(rate);
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_nagle_time(server: *mut Server, nagle_time_us: u32) {
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .nagle_time = Some(Duration::from_micros(nagle_time_us as u64));
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_mtu_packet_size(server: *mut Server, size: i32) {
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .mtu_packet_size = Some(size);
+}
+// Independently ordered lanes, `priorities` and `weights` hold `count` values; 0 lanes restores the single default lane
+#[no_mangle]
+pub unsafe extern "C" fn server_set_lanes(
+    server: *mut Server,
+    priorities: *const i32,
+    weights: *const u16,
+    count: usize,
+) -> bool {
+    let Some(lanes) = lanes_from_ffi(priorities, weights, count) else {
+        return false;
+    };
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .connection_config_mut()
+        .lanes = lanes;
+    true
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_message_lane(server: *mut Server, msg_type: i64, lane: u16) -> bool {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_message_lane(msg_type, lane)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_rpc_lane(server: *mut Server, method_id: i64, lane: u16) -> bool {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_rpc_lane(method_id, lane)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_cmd_lane(server: *mut Server, cmd: *const c_char, lane: u16) -> bool {
+    let Ok(cmd) = CStr::from_ptr(cmd).to_str() else {
+        return false;
+    };
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_cmd_lane(cmd, lane)
+        .is_ok()
+}
+// Send on `lane` instead of the lane `msg_type` is mapped to
+#[no_mangle]
+pub unsafe extern "C" fn server_send_on_lane(
+    server: *mut Server,
+    uuid: *const UuidFFI,
+    lane: u16,
+    reliable: bool,
+    msg_type: i64,
+    data: *const c_uchar,
+    offset: isize,
+    size: usize,
+) -> bool {
+    let msg_data = core::slice::from_raw_parts(data.offset(offset), size);
+    let client_uuid = uuid_from_ffi_ptr(uuid);
+    let server = server.as_ref().expect("Server cannot be null");
+    match reliable {
+        true => server.send_reliable_on_lane(&client_uuid, lane, msg_type, msg_data),
+        false => server.send_on_lane(&client_uuid, lane, msg_type, msg_data),
+    }
+    .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_call_rpc_on_lane(
+    server: *mut Server,
+    client: *const UuidFFI,
+    lane: u16,
+    reliable: bool,
+    method_id: i64,
+    request_id: u64,
+    arg_type: i64,
+    arg_data: *const c_uchar,
+    arg_data_offset: isize,
+    arg_data_size: usize,
+) -> bool {
+    let client_uuid = uuid_from_ffi_ptr(client);
+    let msg_data = match arg_data_size {
+        0 => None,
+        _ => Some(core::slice::from_raw_parts(arg_data.offset(arg_data_offset), arg_data_size)),
+    };
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .call_rpc_on_lane(&client_uuid, lane, reliable, method_id, request_id, arg_type, msg_data)
+        .is_ok()
+}
+// Fake network conditions of outgoing (`send`) and incoming (`recv`) packets; null or all zeros
+// turns the simulation off. Reordered packets are held back `reorder_time_ms` and duplicates up to
+// `duplicate_time_max_ms`, with 0 both arrive in order right away, e.g. 10ms makes them visible
+#[no_mangle]
+pub unsafe extern "C" fn server_set_network_simulation(
+    server: *mut Server,
+    simulation: *const NetworkSimulationFFI,
+) -> bool {
+    let simulation = simulation.as_ref().map(NetworkSimulation::from).unwrap_or_default();
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .set_network_simulation(simulation)
+        .is_ok()
+}
+// returns false if the client is not authenticated or `role` is not valid UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn server_grant_role(
+    server: *mut Server,
+    uuid: *const UuidFFI,
+    role: *const c_char,
+) -> bool {
+    let client_uuid = uuid_from_ffi_ptr(uuid);
+    let Ok(role) = CStr::from_ptr(role).to_str() else {
+        return false;
+    };
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .grant_role(&client_uuid, role)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_revoke_role(
+    server: *mut Server,
+    uuid: *const UuidFFI,
+    role: *const c_char,
+) -> bool {
+    let client_uuid = uuid_from_ffi_ptr(uuid);
+    let Ok(role) = CStr::from_ptr(role).to_str() else {
+        return false;
+    };
+    server
+        .as_mut()
+        .expect("Server cannot be null")
+        .revoke_role(&client_uuid, role)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_disconnect(server: *mut Server, uuid: *const UuidFFI) {
+    let client_uuid = uuid_from_ffi_ptr(uuid);
+    // TODO handle result
+    _ = server
+        .as_ref()
+        .expect("Server cannot be null")
+        .disconnect(&client_uuid, "");
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_announce(server: *mut Server, text: *const c_char) -> bool {
+    let Ok(text) = CStr::from_ptr(text).to_str() else {
+        return false;
+    };
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .announce(text)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_spawn_entity(server: *mut Server, entity_type: i64) -> u64 {
+    server.as_ref().expect("Server cannot be null").spawn_entity(entity_type)
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_despawn_entity(server: *mut Server, entity: u64) -> bool {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .despawn_entity(entity)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_component(
+    server: *mut Server,
+    entity: u64,
+    component_type: i64,
+    data: *const c_uchar,
+    offset: isize,
+    size: usize,
+) -> bool {
+    let component_data = match size {
+        0 => &[],
+        _ => core::slice::from_raw_parts(data.offset(offset), size),
+    };
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_component(entity, component_type, component_data)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_remove_component(server: *mut Server, entity: u64, component_type: i64) -> bool {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .remove_component(entity, component_type)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_schedule(
+    server: *mut Server,
+    uuid: *const UuidFFI,
+    priority: f32,
+    msg_type: i64,
+    data: *const c_uchar,
+    offset: isize,
+    size: usize,
+) -> bool {
+    let msg_data = core::slice::from_raw_parts(data.offset(offset), size);
+    let client_uuid = uuid_from_ffi_ptr(uuid);
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .schedule(&client_uuid, priority, msg_type, msg_data)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_schedule_reliable(
+    server: *mut Server,
+    uuid: *const UuidFFI,
+    msg_type: i64,
+    data: *const c_uchar,
+    offset: isize,
+    size: usize,
+) -> bool {
+    let msg_data = core::slice::from_raw_parts(data.offset(offset), size);
+    let client_uuid = uuid_from_ffi_ptr(uuid);
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .schedule_reliable(&client_uuid, msg_type, msg_data)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_send_scheduled(server: *mut Server) -> bool {
+    server.as_ref().expect("Server cannot be null").send_scheduled().is_ok()
+}
+// Bytes per second the scheduler allows for the client, -1 before anything was scheduled for it
+#[no_mangle]
+pub unsafe extern "C" fn server_client_send_rate(server: *mut Server, client: *const UuidFFI) -> i64 {
+    let client_uuid = uuid_from_ffi_ptr(client);
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .send_budget(&client_uuid)
+        .map_or(-1, |budget| budget.send_rate as i64)
+}
+// Coalesce the messages sent between `server_process` calls, see `ServerSettings::batch_messages`
+#[no_mangle]
+pub unsafe extern "C" fn server_set_batch_messages(server: *mut Server, batch_messages: bool) {
+    // TODO handle result
+    _ = server
+        .as_mut()
+        .expect("Server cannot be null")
+        .set_batch_messages(batch_messages);
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_flush(server: *mut Server) -> bool {
+    server.as_ref().expect("Server cannot be null").flush().is_ok()
+}
+// Send the changes of this tick to every client, returns the tick
+#[no_mangle]
+pub unsafe extern "C" fn server_replicate(server: *mut Server) -> u64 {
+    let server = server.as_ref().expect("Server cannot be null");
+    // TODO handle result
+    _ = server.replicate();
+    server.replication_tick()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_interest_radius(server: *mut Server, radius: f32, hysteresis: f32) {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_interest_filter(Some(Box::new(RadiusFilter::new(radius, hysteresis))));
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_interest_grid(
+    server: *mut Server,
+    cell_size: f32,
+    view_cells: u32,
+    hysteresis_cells: u32,
+) {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_interest_filter(Some(Box::new(GridFilter::new(cell_size, view_cells, hysteresis_cells))));
+}
 #[no_mangle]
-pub unsafe extern "C" fn server_disconnect(_server: *mut Server, uuid: *const UuidFFI) {
-    let _client_uuid = uuid_from_ffi_ptr(uuid);
+pub unsafe extern "C" fn server_clear_interest_filter(server: *mut Server) {
+    server.as_ref().expect("Server cannot be null").set_interest_filter(None);
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_set_client_position(server: *mut Server, client: *const UuidFFI, x: f32, y: f32, z: f32) {
+    let client_uuid = uuid_from_ffi_ptr(client);
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_client_position(&client_uuid, Some(Position::new(x, y, z)));
+}
+// subscribed when `subscribe` is true, unsubscribed otherwise
+#[no_mangle]
+pub unsafe extern "C" fn server_set_client_tag(
+    server: *mut Server,
+    client: *const UuidFFI,
+    tag: *const c_char,
+    subscribe: bool,
+) -> bool {
+    let Ok(tag) = CStr::from_ptr(tag).to_str() else {
+        return false;
+    };
+    let client_uuid = uuid_from_ffi_ptr(client);
+    let server = server.as_ref().expect("Server cannot be null");
+    match subscribe {
+        true => {
+            server.subscribe_tag(&client_uuid, tag);
+            true
+        }
+        false => server.unsubscribe_tag(&client_uuid, tag),
+    }
+}
+// `tag` may be null
+#[no_mangle]
+pub unsafe extern "C" fn server_set_entity_relevance(
+    server: *mut Server,
+    entity: u64,
+    has_position: bool,
+    x: f32,
+    y: f32,
+    z: f32,
+    tag: *const c_char,
+) -> bool {
+    let Some(relevance) = relevance_from_ffi(has_position, x, y, z, tag) else {
+        return false;
+    };
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .set_entity_relevance(entity, relevance)
+        .is_ok()
+}
+// `tag` may be null
+#[no_mangle]
+pub unsafe extern "C" fn server_broadcast_relevant(
+    server: *mut Server,
+    reliable: bool,
+    has_position: bool,
+    x: f32,
+    y: f32,
+    z: f32,
+    tag: *const c_char,
+    msg_type: i64,
+    data: *const c_uchar,
+    offset: isize,
+    size: usize,
+) {
+    let Some(relevance) = relevance_from_ffi(has_position, x, y, z, tag) else {
+        return;
+    };
+    let msg_data = core::slice::from_raw_parts(data.offset(offset), size);
+    let server = server.as_ref().expect("Server cannot be null");
+    // TODO handle result
+    _ = match reliable {
+        true => server.broadcast_relevant_reliable(&relevance, msg_type, msg_data),
+        false => server.broadcast_relevant(&relevance, msg_type, msg_data),
+    };
+}
+unsafe fn relevance_from_ffi(has_position: bool, x: f32, y: f32, z: f32, tag: *const c_char) -> Option<Relevance> {
+    let tag = match tag.is_null() {
+        true => None,
+        false => Some(CStr::from_ptr(tag).to_str().ok()?.to_string()),
+    };
+    let position = has_position.then(|| Position::new(x, y, z));
+    Some(Relevance { position, tag })
+}
+// Calls `callback` with one input of every client that has one, returns how many
+#[no_mangle]
+pub unsafe extern "C" fn server_take_inputs(server: *mut Server, callback: ServerOnInput) -> usize {
+    let inputs = server.as_ref().expect("Server cannot be null").take_inputs();
+    for (client, sequence, data) in &inputs {
+        callback(client.to_ffi(), *sequence, data.as_ptr(), data.len());
+    }
+    inputs.len()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_last_processed_input(server: *mut Server, client: *const UuidFFI) -> u64 {
+    let client_uuid = uuid_from_ffi_ptr(client);
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .last_processed_input(&client_uuid)
+}
+// Server time the client saw when it sent what arrives now in microseconds, -1 if it is not authenticated
+#[no_mangle]
+pub unsafe extern "C" fn server_client_view_time_micros(server: *mut Server, client: *const UuidFFI) -> i64 {
+    let client_uuid = uuid_from_ffi_ptr(client);
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .client_view_time(&client_uuid)
+        .map_or(-1, |time| time.as_micros() as i64)
+}
+// Returns the id of the new session, 0 if it cannot be created
+#[no_mangle]
+pub unsafe extern "C" fn server_create_lockstep_session(
+    server: *mut Server,
+    clients: *const UuidFFI,
+    count: usize,
+    turn_timeout_micros: u64,
+) -> u64 {
+    let clients = match count {
+        0 => Vec::new(),
+        _ => (0..count).map(|index| uuid_from_ffi_ptr(clients.add(index))).collect(),
+    };
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .create_lockstep_session(&clients, Duration::from_micros(turn_timeout_micros))
+        .unwrap_or(0)
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_end_lockstep_session(server: *mut Server, session: u64) -> bool {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .end_lockstep_session(session)
+        .is_ok()
+}
+#[no_mangle]
+pub unsafe extern "C" fn server_register_on_desync(server: *mut Server, callback: ServerOnDesync) {
+    server
+        .as_ref()
+        .expect("Server cannot be null")
+        .register_on_desync(move |_server, session, turn, client| {
+            // a nil uuid if the whole session diverged
+            callback(session, turn, client.copied().unwrap_or_default().to_ffi())
+        });
+}
 
-    panic!("server disconnect not implemented")
-    // TODO uncomment when disconnect implemented
-    // server.as_ref().expect("Server cannot be null").disconnect();
+// Lag compensation history of float arrays, e.g. hit box positions
+type FloatLagCompensation = LagCompensation<Vec<f32>>;
+
+#[no_mangle]
+pub extern "C" fn lag_compensation_create(max_rewind_micros: u64) -> *mut FloatLagCompensation {
+    Box::into_raw(Box::new(LagCompensation::new(Duration::from_micros(max_rewind_micros))))
+}
+// Record the state of `entity` at the current tick of `server`
+#[no_mangle]
+pub unsafe extern "C" fn lag_compensation_record(
+    history: *mut FloatLagCompensation,
+    server: *mut Server,
+    entity: u64,
+    values: *const f32,
+    count: usize,
+) {
+    let values = match count {
+        0 => Vec::new(),
+        _ => core::slice::from_raw_parts(values, count).to_vec(),
+    };
+    history.as_mut().expect("History cannot be null").record_now(
+        server.as_ref().expect("Server cannot be null"),
+        entity,
+        values,
+    );
+}
+// Writes up to `count` values of `entity` as `client` saw it to `out_values`.
+// Returns false if the entity was not recorded or the client is not authenticated
+#[no_mangle]
+pub unsafe extern "C" fn lag_compensation_state_for(
+    history: *mut FloatLagCompensation,
+    server: *mut Server,
+    client: *const UuidFFI,
+    entity: u64,
+    out_values: *mut f32,
+    count: usize,
+) -> bool {
+    let client_uuid = uuid_from_ffi_ptr(client);
+    let history = history.as_ref().expect("History cannot be null");
+    let Some(state) = history.state_for(server.as_ref().expect("Server cannot be null"), &client_uuid, entity) else {
+        return false;
+    };
+    if count > 0 {
+        let out = core::slice::from_raw_parts_mut(out_values, count);
+        for (out, value) in out.iter_mut().zip(&state) {
+            *out = *value;
+        }
+    }
+    true
+}
+#[no_mangle]
+pub unsafe extern "C" fn lag_compensation_destroy(history: *mut FloatLagCompensation) {
+    if !history.is_null() {
+        drop(Box::from_raw(history));
+    }
 }
 #[no_mangle]
 #[allow(unreachable_patterns)]
@@ -0,0 +1,55 @@
+use std::collections::{BTreeMap, HashMap};
+
+use uuid::Uuid;
+
+// inputs kept per client, the oldest are dropped beyond it
+const MAX_BUFFERED_INPUTS: usize = 128;
+
+/// Input commands of one client, ordered by sequence number
+#[derive(Default)]
+struct ClientInputs {
+    queue: BTreeMap<u64, Vec<u8>>,
+    last_processed: u64,
+    // handing out inputs starts once `jitter_window` inputs arrived, and starts over when the queue ran dry
+    started: bool,
+}
+
+/// Jitter buffer of the inputs of every client. Inputs arrive unreliably and repeatedly; each is
+/// handed out once, in sequence order
+#[derive(Default)]
+pub struct InputBuffers {
+    clients: HashMap<Uuid, ClientInputs>,
+}
+impl InputBuffers {
+    pub fn push(&mut self, client: &Uuid, sequence: u64, data: Vec<u8>) {
+        let inputs = self.clients.entry(*client).or_default();
+        if sequence <= inputs.last_processed {
+            return;
+        }
+        inputs.queue.entry(sequence).or_insert(data);
+        while inputs.queue.len() > MAX_BUFFERED_INPUTS {
+            inputs.queue.pop_first();
+        }
+    }
+    /// Next input of `client`, skipping inputs that never arrived
+    pub fn take(&mut self, client: &Uuid, jitter_window: usize) -> Option<(u64, Vec<u8>)> {
+        let inputs = self.clients.get_mut(client)?;
+        if !inputs.started && inputs.queue.len() < jitter_window.max(1) {
+            return None;
+        }
+        let (sequence, data) = inputs.queue.pop_first()?;
+        inputs.started = !inputs.queue.is_empty();
+        inputs.last_processed = sequence;
+        Some((sequence, data))
+    }
+    pub fn buffered(&self, client: &Uuid) -> usize {
+        self.clients.get(client).map_or(0, |inputs| inputs.queue.len())
+    }
+    /// Sequence number of the last input handed out for `client`, 0 before the first
+    pub fn last_processed(&self, client: &Uuid) -> u64 {
+        self.clients.get(client).map_or(0, |inputs| inputs.last_processed)
+    }
+    pub fn forget_client(&mut self, client: &Uuid) {
+        self.clients.remove(client);
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use super::replication::EntityId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+impl Position {
+    pub fn new(x: f32, y: f32, z: f32) -> Position {
+        Position { x, y, z }
+    }
+    pub fn distance(&self, other: &Position) -> f32 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+/// Decides if something at a position is relevant for a client at another one.
+/// `was_relevant` is the previous answer for the same entity, so a filter can keep entities
+/// slightly beyond its range instead of making them flicker at the boundary
+pub trait InterestFilter {
+    fn is_relevant(&self, viewer: &Position, subject: &Position, was_relevant: bool) -> bool;
+}
+
+/// Relevant within `radius`, entities already relevant stay so within `radius + hysteresis`
+pub struct RadiusFilter {
+    pub radius: f32,
+    pub hysteresis: f32,
+}
+impl RadiusFilter {
+    pub fn new(radius: f32, hysteresis: f32) -> RadiusFilter {
+        RadiusFilter { radius, hysteresis }
+    }
+}
+impl InterestFilter for RadiusFilter {
+    fn is_relevant(&self, viewer: &Position, subject: &Position, was_relevant: bool) -> bool {
+        let range = match was_relevant {
+            true => self.radius + self.hysteresis,
+            false => self.radius,
+        };
+        viewer.distance(subject) <= range
+    }
+}
+
+/// Splits the world into cubes of `cell_size`. Relevant within `view_cells` cells of the viewer's
+/// cell, entities already relevant stay so within `view_cells + hysteresis_cells`
+pub struct GridFilter {
+    pub cell_size: f32,
+    pub view_cells: u32,
+    pub hysteresis_cells: u32,
+}
+impl GridFilter {
+    pub fn new(cell_size: f32, view_cells: u32, hysteresis_cells: u32) -> GridFilter {
+        GridFilter {
+            cell_size,
+            view_cells,
+            hysteresis_cells,
+        }
+    }
+    pub fn cell(&self, position: &Position) -> (i64, i64, i64) {
+        let coordinate = |value: f32| (value / self.cell_size).floor() as i64;
+        (coordinate(position.x), coordinate(position.y), coordinate(position.z))
+    }
+}
+impl InterestFilter for GridFilter {
+    fn is_relevant(&self, viewer: &Position, subject: &Position, was_relevant: bool) -> bool {
+        let (viewer, subject) = (self.cell(viewer), self.cell(subject));
+        let cells = (viewer.0 - subject.0)
+            .abs()
+            .max((viewer.1 - subject.1).abs())
+            .max((viewer.2 - subject.2).abs());
+        let range = match was_relevant {
+            true => self.view_cells + self.hysteresis_cells,
+            false => self.view_cells,
+        };
+        cells <= range as i64
+    }
+}
+
+/// Where an entity or a broadcast message is relevant. Without position and tag it is
+/// relevant for every client
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Relevance {
+    pub position: Option<Position>,
+    // only clients subscribed to the tag receive it
+    pub tag: Option<String>,
+}
+impl Relevance {
+    pub fn everywhere() -> Relevance {
+        Relevance::default()
+    }
+    pub fn at(position: Position) -> Relevance {
+        Relevance {
+            position: Some(position),
+            tag: None,
+        }
+    }
+    pub fn tagged(tag: &str) -> Relevance {
+        Relevance {
+            position: None,
+            tag: Some(tag.to_string()),
+        }
+    }
+    pub fn with_tag(mut self, tag: &str) -> Relevance {
+        self.tag = Some(tag.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientInterest {
+    pub position: Option<Position>,
+    pub tags: HashSet<String>,
+}
+
+/// Positions and tags of clients and entities, and which entities each client currently sees
+#[derive(Default)]
+pub struct InterestManager {
+    filter: Option<Box<dyn InterestFilter>>,
+    clients: HashMap<Uuid, ClientInterest>,
+    entities: HashMap<EntityId, Relevance>,
+    visible: HashMap<Uuid, HashSet<EntityId>>,
+}
+impl InterestManager {
+    /// Without a filter positions are ignored and only tags limit relevancy
+    pub fn set_filter(&mut self, filter: Option<Box<dyn InterestFilter>>) {
+        self.filter = filter;
+    }
+    pub fn has_filter(&self) -> bool {
+        self.filter.is_some()
+    }
+    pub fn client(&self, client: &Uuid) -> Option<&ClientInterest> {
+        self.clients.get(client)
+    }
+    pub fn set_client_position(&mut self, client: &Uuid, position: Option<Position>) {
+        self.clients.entry(*client).or_default().position = position;
+    }
+    pub fn subscribe(&mut self, client: &Uuid, tag: &str) {
+        self.clients.entry(*client).or_default().tags.insert(tag.to_string());
+    }
+    pub fn unsubscribe(&mut self, client: &Uuid, tag: &str) -> bool {
+        self.clients
+            .get_mut(client)
+            .is_some_and(|interest| interest.tags.remove(tag))
+    }
+    pub fn forget_client(&mut self, client: &Uuid) {
+        self.clients.remove(client);
+        self.visible.remove(client);
+    }
+    pub fn entity(&self, entity: EntityId) -> Option<&Relevance> {
+        self.entities.get(&entity)
+    }
+    pub fn set_entity(&mut self, entity: EntityId, relevance: Relevance) {
+        self.entities.insert(entity, relevance);
+    }
+    pub fn forget_entity(&mut self, entity: EntityId) {
+        self.entities.remove(&entity);
+        for visible in self.visible.values_mut() {
+            visible.remove(&entity);
+        }
+    }
+    pub fn is_visible(&self, client: &Uuid, entity: EntityId) -> bool {
+        self.visible.get(client).is_some_and(|visible| visible.contains(&entity))
+    }
+
+    /// Whether a message with `relevance` should reach `client`
+    pub fn is_relevant(&self, client: &Uuid, relevance: &Relevance) -> bool {
+        self.check(client, relevance, false)
+    }
+    /// Whether `entity` should be replicated to `client`, remembering the answer for hysteresis
+    pub fn update_visibility(&mut self, client: &Uuid, entity: EntityId) -> bool {
+        let relevant = match self.entities.get(&entity) {
+            Some(relevance) => self.check(client, relevance, self.is_visible(client, entity)),
+            None => true,
+        };
+        let visible = self.visible.entry(*client).or_default();
+        if relevant {
+            visible.insert(entity);
+        } else {
+            visible.remove(&entity);
+        }
+        relevant
+    }
+    fn check(&self, client: &Uuid, relevance: &Relevance, was_relevant: bool) -> bool {
+        let interest = self.clients.get(client);
+        if let Some(tag) = &relevance.tag {
+            if !interest.is_some_and(|interest| interest.tags.contains(tag)) {
+                return false;
+            }
+        }
+        match (&self.filter, &relevance.position) {
+            (Some(filter), Some(position)) => match interest.and_then(|interest| interest.position.as_ref()) {
+                Some(viewer) => filter.is_relevant(viewer, position, was_relevant),
+                // a client without position sees nothing placed in the world
+                None => false,
+            },
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use uuid::Uuid;
+
+use super::{replication::EntityId, Server};
+use crate::interpolate::Interpolate;
+
+/// States of the recorded entities at one server tick
+#[derive(Debug, Clone)]
+pub struct Frame<S> {
+    pub tick: u64,
+    pub time: Duration,
+    pub states: HashMap<EntityId, S>,
+}
+
+/// Past states of entities, e.g. hit boxes, to check what a client saw when it acted.
+/// Frames older than `max_rewind` are dropped and queries never go further back
+pub struct LagCompensation<S: Interpolate> {
+    frames: VecDeque<Frame<S>>,
+    max_rewind: Duration,
+}
+impl<S: Interpolate> LagCompensation<S> {
+    pub fn new(max_rewind: Duration) -> LagCompensation<S> {
+        LagCompensation {
+            frames: VecDeque::new(),
+            max_rewind,
+        }
+    }
+    pub fn max_rewind(&self) -> Duration {
+        self.max_rewind
+    }
+    pub fn frames(&self) -> impl Iterator<Item = &Frame<S>> {
+        self.frames.iter()
+    }
+    /// Record the state of `entity` at `tick`, adding to the frame of the tick if there is one.
+    /// Ticks have to be recorded in order
+    pub fn record(&mut self, tick: u64, time: Duration, entity: EntityId, state: S) {
+        match self.frames.back_mut() {
+            Some(frame) if frame.tick == tick => {
+                frame.states.insert(entity, state);
+            }
+            _ => {
+                let frame = Frame {
+                    tick,
+                    time,
+                    states: HashMap::from([(entity, state)]),
+                };
+                self.frames.push_back(frame);
+                while self
+                    .frames
+                    .front()
+                    .is_some_and(|oldest| oldest.time.saturating_add(self.max_rewind) < time)
+                {
+                    self.frames.pop_front();
+                }
+            }
+        }
+    }
+    /// Record at the current replication tick and server time of `server`
+    pub fn record_now(&mut self, server: &Server, entity: EntityId, state: S) {
+        self.record(server.replication_tick(), server.server_time(), entity, state);
+    }
+    /// `time` moved into the recorded and allowed range
+    pub fn clamp(&self, time: Duration) -> Option<Duration> {
+        let (oldest, newest) = (self.frames.front()?.time, self.frames.back()?.time);
+        let earliest = newest.saturating_sub(self.max_rewind).max(oldest);
+        Some(time.clamp(earliest, newest))
+    }
+    /// State of `entity` at `time`, interpolated between the frames around it
+    pub fn state_at(&self, entity: EntityId, time: Duration) -> Option<S> {
+        let time = self.clamp(time)?;
+        let after = self.frames.partition_point(|frame| frame.time < time);
+        let next = self.frames.get(after).and_then(|frame| Some((frame.time, frame.states.get(&entity)?)));
+        let previous = after
+            .checked_sub(1)
+            .and_then(|index| self.frames.get(index))
+            .and_then(|frame| Some((frame.time, frame.states.get(&entity)?)));
+        match (previous, next) {
+            (Some((previous_time, previous)), Some((next_time, next))) => {
+                let t = (time - previous_time).as_secs_f32() / (next_time - previous_time).as_secs_f32();
+                Some(previous.interpolate(next, t))
+            }
+            (Some((_, state)), None) | (None, Some((_, state))) => Some(state.clone()),
+            (None, None) => None,
+        }
+    }
+    /// States of every entity recorded around `time`
+    pub fn states_at(&self, time: Duration) -> HashMap<EntityId, S> {
+        let Some(time) = self.clamp(time) else {
+            return HashMap::new();
+        };
+        let after = self.frames.partition_point(|frame| frame.time < time);
+        let mut entities = self
+            .frames
+            .range(after.saturating_sub(1)..(after + 1).min(self.frames.len()))
+            .flat_map(|frame| frame.states.keys().copied())
+            .collect::<Vec<_>>();
+        entities.sort_unstable();
+        entities.dedup();
+        entities
+            .into_iter()
+            .filter_map(|entity| Some((entity, self.state_at(entity, time)?)))
+            .collect()
+    }
+    /// State of `entity` as `client` saw it when it sent what arrives now, see `Server::client_view_time`
+    pub fn state_for(&self, server: &Server, client: &Uuid, entity: EntityId) -> Option<S> {
+        self.state_at(entity, server.client_view_time(client)?)
+    }
+    pub fn states_for(&self, server: &Server, client: &Uuid) -> HashMap<EntityId, S> {
+        match server.client_view_time(client) {
+            Some(time) => self.states_at(time),
+            None => HashMap::new(),
+        }
+    }
+}
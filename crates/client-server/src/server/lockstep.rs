@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use uuid::Uuid;
+
+pub use crate::lockstep::{LockstepTurn, SessionId};
+
+// turns ahead of the collected one a player may send input for
+const MAX_TURNS_AHEAD: u64 = 64;
+// completed turns whose checksums are kept while waiting for the slowest player
+const MAX_CHECKSUM_TURNS: u64 = 64;
+
+/// A player whose state checksum of `turn` differs from the one most players agree on.
+/// `player` and `client` are `None` if no checksum has more votes than every other one,
+/// e.g. two players disagreeing, then the whole session diverged
+#[derive(Debug, Clone, PartialEq)]
+pub struct Desync {
+    pub session: SessionId,
+    pub turn: u64,
+    pub player: Option<usize>,
+    pub client: Option<Uuid>,
+}
+
+/// Clients simulating the same deterministic game. Turns are collected in order and completed
+/// once every player sent its input, or `turn_timeout` after the previous turn without the
+/// missing ones
+pub struct LockstepSession {
+    id: SessionId,
+    players: Vec<Uuid>,
+    left: Vec<bool>,
+    turn_timeout: Duration,
+    next_turn: u64,
+    turn_started: Duration,
+    inputs: BTreeMap<u64, HashMap<usize, Vec<u8>>>,
+    checksums: BTreeMap<u64, HashMap<usize, u64>>,
+}
+impl LockstepSession {
+    fn new(id: SessionId, players: Vec<Uuid>, turn_timeout: Duration, now: Duration) -> LockstepSession {
+        LockstepSession {
+            id,
+            left: vec![false; players.len()],
+            players,
+            turn_timeout,
+            next_turn: 1,
+            turn_started: now,
+            inputs: Default::default(),
+            checksums: Default::default(),
+        }
+    }
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+    /// Clients of the session, the index is their player number
+    pub fn players(&self) -> &[Uuid] {
+        &self.players
+    }
+    /// Players that did not disconnect
+    pub fn present_players(&self) -> Vec<Uuid> {
+        self.players
+            .iter()
+            .zip(&self.left)
+            .filter(|(_, left)| !**left)
+            .map(|(client, _)| *client)
+            .collect()
+    }
+    pub fn player(&self, client: &Uuid) -> Option<usize> {
+        self.players
+            .iter()
+            .zip(&self.left)
+            .position(|(player, left)| player == client && !left)
+    }
+    /// Turn the session collects inputs for
+    pub fn turn(&self) -> u64 {
+        self.next_turn
+    }
+    pub fn turn_timeout(&self) -> Duration {
+        self.turn_timeout
+    }
+    fn submit_input(&mut self, player: usize, turn: u64, data: Vec<u8>) {
+        if turn < self.next_turn || turn >= self.next_turn + MAX_TURNS_AHEAD {
+            return;
+        }
+        self.inputs.entry(turn).or_default().entry(player).or_insert(data);
+    }
+    fn submit_checksum(&mut self, player: usize, turn: u64, checksum: u64) {
+        if turn >= self.next_turn || turn + MAX_CHECKSUM_TURNS < self.next_turn {
+            return;
+        }
+        self.checksums.entry(turn).or_default().entry(player).or_insert(checksum);
+    }
+    fn is_present(&self, player: usize) -> bool {
+        !self.left[player]
+    }
+    fn complete_turns(&mut self, now: Duration) -> Vec<LockstepTurn> {
+        let mut turns = Vec::new();
+        while self.left.contains(&false) {
+            let arrived = self.inputs.get(&self.next_turn);
+            let complete = (0..self.players.len())
+                .filter(|player| self.is_present(*player))
+                .all(|player| arrived.is_some_and(|arrived| arrived.contains_key(&player)));
+            let timed_out = !self.turn_timeout.is_zero() && now.saturating_sub(self.turn_started) >= self.turn_timeout;
+            if !complete && !timed_out {
+                break;
+            }
+            let mut arrived = self.inputs.remove(&self.next_turn).unwrap_or_default();
+            turns.push(LockstepTurn {
+                session: self.id,
+                turn: self.next_turn,
+                inputs: (0..self.players.len()).map(|player| arrived.remove(&player)).collect(),
+            });
+            self.next_turn += 1;
+            self.turn_started = now;
+        }
+        while self
+            .checksums
+            .first_key_value()
+            .is_some_and(|(turn, _)| turn + MAX_CHECKSUM_TURNS < self.next_turn)
+        {
+            self.checksums.pop_first();
+        }
+        turns
+    }
+    // compares the checksums of turns every present player submitted
+    fn detect_desyncs(&mut self) -> Vec<Desync> {
+        let checked = self
+            .checksums
+            .iter()
+            .filter(|(_, checksums)| {
+                (0..self.players.len())
+                    .filter(|player| self.is_present(*player))
+                    .all(|player| checksums.contains_key(&player))
+            })
+            .map(|(turn, _)| *turn)
+            .collect::<Vec<_>>();
+        let mut desyncs = Vec::new();
+        for turn in checked {
+            let checksums = self.checksums.remove(&turn).unwrap_or_default();
+            let mut players = checksums.into_iter().collect::<Vec<_>>();
+            players.sort_unstable();
+            // most players agree on the reference, without one nobody can be blamed
+            let mut votes: Vec<(u64, usize)> = Vec::new();
+            for (_, checksum) in &players {
+                match votes.iter_mut().find(|(value, _)| value == checksum) {
+                    Some((_, count)) => *count += 1,
+                    None => votes.push((*checksum, 1)),
+                }
+            }
+            votes.sort_unstable_by_key(|(_, count)| std::cmp::Reverse(*count));
+            let reference = match votes.as_slice() {
+                [] | [_] => continue,
+                [(_, first), (_, second), ..] if first == second => {
+                    desyncs.push(Desync {
+                        session: self.id,
+                        turn,
+                        player: None,
+                        client: None,
+                    });
+                    continue;
+                }
+                [(checksum, _), ..] => *checksum,
+            };
+            desyncs.extend(
+                players
+                    .into_iter()
+                    .filter(|(_, checksum)| *checksum != reference)
+                    .map(|(player, _)| Desync {
+                        session: self.id,
+                        turn,
+                        player: Some(player),
+                        client: Some(self.players[player]),
+                    }),
+            );
+        }
+        desyncs
+    }
+}
+
+/// Every lockstep session of the server. A client plays in at most one session
+#[derive(Default)]
+pub struct LockstepSessions {
+    sessions: HashMap<SessionId, LockstepSession>,
+    last_id: SessionId,
+}
+impl LockstepSessions {
+    pub fn create(&mut self, players: Vec<Uuid>, turn_timeout: Duration, now: Duration) -> SessionId {
+        self.last_id += 1;
+        let session = LockstepSession::new(self.last_id, players, turn_timeout, now);
+        self.sessions.insert(self.last_id, session);
+        self.last_id
+    }
+    pub fn end(&mut self, session: SessionId) -> Option<LockstepSession> {
+        self.sessions.remove(&session)
+    }
+    pub fn get(&self, session: SessionId) -> Option<&LockstepSession> {
+        self.sessions.get(&session)
+    }
+    pub fn ids(&self) -> Vec<SessionId> {
+        self.sessions.keys().copied().collect()
+    }
+    pub fn session_of(&self, client: &Uuid) -> Option<&LockstepSession> {
+        self.sessions.values().find(|session| session.player(client).is_some())
+    }
+    pub fn submit_input(&mut self, client: &Uuid, turn: u64, data: Vec<u8>) {
+        for session in self.sessions.values_mut() {
+            if let Some(player) = session.player(client) {
+                session.submit_input(player, turn, data);
+                return;
+            }
+        }
+    }
+    pub fn submit_checksum(&mut self, client: &Uuid, turn: u64, checksum: u64) {
+        for session in self.sessions.values_mut() {
+            if let Some(player) = session.player(client) {
+                session.submit_checksum(player, turn, checksum);
+                return;
+            }
+        }
+    }
+    /// Turns completed by `now` with the desyncs found since the last call
+    pub fn update(&mut self, now: Duration) -> (Vec<LockstepTurn>, Vec<Desync>) {
+        let mut ids = self.ids();
+        ids.sort_unstable();
+        let mut turns = Vec::new();
+        let mut desyncs = Vec::new();
+        for id in ids {
+            let session = self.sessions.get_mut(&id).expect("listed session");
+            turns.extend(session.complete_turns(now));
+            desyncs.extend(session.detect_desyncs());
+        }
+        (turns, desyncs)
+    }
+    /// A disconnected player sends no inputs anymore, its turns are completed without waiting
+    pub fn forget_client(&mut self, client: &Uuid) {
+        for session in self.sessions.values_mut() {
+            if let Some(player) = session.player(client) {
+                session.left[player] = true;
+            }
+        }
+    }
+}
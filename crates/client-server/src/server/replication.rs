@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use omgpp_core::messages::general_message::general_omgpp_message::{Component, EntityDelta, Snapshot};
+use uuid::Uuid;
+
+pub type EntityId = u64;
+
+// snapshots a client may leave unacknowledged before it gets a full one
+const MAX_PENDING_SNAPSHOTS: usize = 64;
+
+struct ComponentState {
+    data: Vec<u8>,
+    // tick of the last change
+    version: u64,
+}
+struct Entity {
+    entity_type: i64,
+    components: BTreeMap<i64, ComponentState>,
+}
+
+// what a client has of an entity: component versions
+struct KnownEntity {
+    components: HashMap<i64, u64>,
+}
+struct SentSnapshot {
+    tick: u64,
+    full: bool,
+    // resulting state of every entity in the snapshot, `None` if despawned
+    entities: HashMap<EntityId, Option<KnownEntity>>,
+}
+// Quake 3 style baseline: snapshots hold everything that changed since the last acknowledged one,
+// so a lost snapshot is repaired by the next one
+#[derive(Default)]
+struct ClientBaseline {
+    acked: HashMap<EntityId, KnownEntity>,
+    pending: VecDeque<SentSnapshot>,
+}
+impl ClientBaseline {
+    fn acknowledge(&mut self, tick: u64) {
+        let Some(index) = self.pending.iter().position(|sent| sent.tick == tick) else {
+            return;
+        };
+        let sent = self.pending.drain(..=index).next_back().expect("index is in range");
+        if sent.full {
+            self.acked.clear();
+        }
+        for (id, entity) in sent.entities {
+            match entity {
+                Some(entity) => self.acked.insert(id, entity),
+                None => self.acked.remove(&id),
+            };
+        }
+    }
+    // components the client may have of `id`, including those of snapshots it did not acknowledge yet
+    fn maybe_known_components(&self, id: EntityId) -> HashSet<i64> {
+        let acked = self.acked.get(&id).into_iter();
+        let pending = self.pending.iter().filter_map(|sent| sent.entities.get(&id)?.as_ref());
+        acked
+            .chain(pending)
+            .flat_map(|entity| entity.components.keys().copied())
+            .collect()
+    }
+    // what the client acknowledged of `id`, unless a later snapshot may have despawned it
+    fn acked_entity(&self, id: EntityId) -> Option<&KnownEntity> {
+        let despawned = self
+            .pending
+            .iter()
+            .any(|sent| sent.entities.get(&id).is_some_and(Option::is_none));
+        match despawned {
+            true => None,
+            false => self.acked.get(&id),
+        }
+    }
+    fn maybe_known_entities(&self) -> HashSet<EntityId> {
+        let pending = self
+            .pending
+            .iter()
+            .flat_map(|sent| sent.entities.iter().filter(|(_, entity)| entity.is_some()).map(|(id, _)| *id));
+        self.acked.keys().copied().chain(pending).collect()
+    }
+}
+
+/// Replicated entities of a server and what every client acknowledged of them
+#[derive(Default)]
+pub struct Replication {
+    tick: u64,
+    next_entity_id: EntityId,
+    entities: BTreeMap<EntityId, Entity>,
+    clients: HashMap<Uuid, ClientBaseline>,
+}
+impl Replication {
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+    pub fn spawn(&mut self, entity_type: i64) -> EntityId {
+        self.next_entity_id += 1;
+        let entity = Entity {
+            entity_type,
+            components: BTreeMap::new(),
+        };
+        self.entities.insert(self.next_entity_id, entity);
+        self.next_entity_id
+    }
+    pub fn despawn(&mut self, id: EntityId) -> Result<(), String> {
+        self.entities
+            .remove(&id)
+            .map(|_| ())
+            .ok_or_else(|| format!("There is not such entity {}", id))
+    }
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.entities.contains_key(&id)
+    }
+    pub fn entity_ids(&self) -> Vec<EntityId> {
+        self.entities.keys().copied().collect()
+    }
+    pub fn entity_type(&self, id: EntityId) -> Option<i64> {
+        self.entities.get(&id).map(|entity| entity.entity_type)
+    }
+    /// Set a component, marking it dirty if `data` changed
+    pub fn set_component(&mut self, id: EntityId, component_type: i64, data: &[u8]) -> Result<(), String> {
+        let version = self.tick + 1;
+        let entity = self.entity_mut(id)?;
+        match entity.components.get_mut(&component_type) {
+            Some(component) if component.data == data => (),
+            Some(component) => {
+                component.data = data.to_vec();
+                component.version = version;
+            }
+            None => {
+                let component = ComponentState {
+                    data: data.to_vec(),
+                    version,
+                };
+                entity.components.insert(component_type, component);
+            }
+        }
+        Ok(())
+    }
+    pub fn remove_component(&mut self, id: EntityId, component_type: i64) -> Result<(), String> {
+        self.entity_mut(id)?
+            .components
+            .remove(&component_type)
+            .map(|_| ())
+            .ok_or_else(|| format!("Entity {} has no component {}", id, component_type))
+    }
+    pub fn component(&self, id: EntityId, component_type: i64) -> Option<&[u8]> {
+        let component = self.entities.get(&id)?.components.get(&component_type)?;
+        Some(&component.data)
+    }
+    fn entity_mut(&mut self, id: EntityId) -> Result<&mut Entity, String> {
+        self.entities
+            .get_mut(&id)
+            .ok_or_else(|| format!("There is not such entity {}", id))
+    }
+
+    /// Start a new tick. Changes made from now on are sent with the next snapshots
+    pub fn advance(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+    /// Snapshot of everything `client` is missing, `None` if it is up to date. Entities
+    /// `is_relevant` rejects are despawned on the client
+    pub fn snapshot_for(&mut self, client: &Uuid, mut is_relevant: impl FnMut(EntityId) -> bool) -> Option<Snapshot> {
+        let tick = self.tick;
+        let baseline = self.clients.entry(*client).or_default();
+        let full = baseline.pending.len() >= MAX_PENDING_SNAPSHOTS;
+        if full {
+            baseline.acked.clear();
+            baseline.pending.clear();
+        }
+        let mut snapshot = Snapshot::new();
+        snapshot.tick = tick;
+        snapshot.full = full;
+        let mut sent = SentSnapshot {
+            tick,
+            full,
+            entities: HashMap::new(),
+        };
+        let mut relevant = HashSet::new();
+        for (id, entity) in &self.entities {
+            if !is_relevant(*id) {
+                continue;
+            }
+            relevant.insert(*id);
+            // entities leaving and entering relevancy are sent whole again
+            let acked = baseline.acked_entity(*id);
+            let mut delta = EntityDelta::new();
+            for (component_type, component) in &entity.components {
+                let is_acked = acked
+                    .and_then(|acked| acked.components.get(component_type))
+                    .is_some_and(|version| *version == component.version);
+                if !is_acked {
+                    let mut changed = Component::new();
+                    changed.type_ = *component_type;
+                    changed.data = component.data.clone();
+                    delta.components.push(changed);
+                }
+            }
+            delta.removed_components = baseline
+                .maybe_known_components(*id)
+                .into_iter()
+                .filter(|component_type| !entity.components.contains_key(component_type))
+                .collect();
+            if acked.is_some() && delta.components.is_empty() && delta.removed_components.is_empty() {
+                continue;
+            }
+            delta.id = *id;
+            delta.entity_type = entity.entity_type;
+            snapshot.entities.push(delta);
+            let known = KnownEntity {
+                components: entity
+                    .components
+                    .iter()
+                    .map(|(component_type, component)| (*component_type, component.version))
+                    .collect(),
+            };
+            sent.entities.insert(*id, Some(known));
+        }
+        if !full {
+            for id in baseline.maybe_known_entities() {
+                if !relevant.contains(&id) {
+                    snapshot.despawned.push(id);
+                    sent.entities.insert(id, None);
+                }
+            }
+        }
+        if sent.entities.is_empty() && !full {
+            return None;
+        }
+        baseline.pending.push_back(sent);
+        Some(snapshot)
+    }
+    pub fn acknowledge(&mut self, client: &Uuid, tick: u64) {
+        if let Some(baseline) = self.clients.get_mut(client) {
+            baseline.acknowledge(tick);
+        }
+    }
+    /// Start over with a full snapshot, e.g. after `client` reconnected
+    pub fn forget_client(&mut self, client: &Uuid) {
+        self.clients.remove(client);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use omgpp_core::transport::{ConnectionStatus, SendMode};
+use uuid::Uuid;
+
+// envelope fields around the data of a scheduled message, counted against the budget
+const MESSAGE_OVERHEAD: usize = 16;
+
+/// Limits of the per-client send scheduler, see `Server::schedule`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedulerSettings {
+    /// Bytes per second used when the transport does not estimate the send rate
+    pub fallback_send_rate: u32,
+    /// Upper bound of the send rate of a client, whatever the transport estimates
+    pub max_send_rate: Option<u32>,
+    /// Unused budget is kept for at most this long, which bounds bursts
+    pub burst: Duration,
+    /// Unreliable messages waiting longer are dropped
+    pub max_defer: Duration,
+}
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        SchedulerSettings {
+            fallback_send_rate: 64 * 1024,
+            max_send_rate: None,
+            burst: Duration::from_millis(100),
+            max_defer: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Send budget of a client and what the scheduler holds back
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SendBudget {
+    pub send_rate: u32,
+    /// Bytes that can go out right now
+    pub available: u64,
+    /// Messages waiting for budget
+    pub deferred: usize,
+    /// Unreliable messages dropped after waiting `max_defer`
+    pub dropped: u64,
+}
+
+/// A message the scheduler released
+pub struct ScheduledSend {
+    pub client: Uuid,
+    pub mode: SendMode,
+    pub msg_type: i64,
+    pub data: Vec<u8>,
+}
+
+struct Deferred {
+    msg_type: i64,
+    data: Vec<u8>,
+    priority: f32,
+    // grows by `priority` every run the message is held back, so nothing starves
+    accumulated: f32,
+    queued_at: Instant,
+}
+
+#[derive(Default)]
+struct ClientQueue {
+    reliable: VecDeque<(i64, Vec<u8>)>,
+    unreliable: Vec<Deferred>,
+    // `None` until the first run, which starts with a full burst
+    tokens: Option<f64>,
+    send_rate: u32,
+    dropped: u64,
+}
+
+/// Token bucket per client: reliable messages go out first and in order, unreliable ones by
+/// accumulated priority while budget is left
+#[derive(Default)]
+pub struct Scheduler {
+    clients: HashMap<Uuid, ClientQueue>,
+    last_run: Option<Instant>,
+}
+impl Scheduler {
+    pub fn push_reliable(&mut self, client: &Uuid, msg_type: i64, data: Vec<u8>) {
+        self.clients.entry(*client).or_default().reliable.push_back((msg_type, data));
+    }
+    pub fn push_unreliable(&mut self, client: &Uuid, priority: f32, msg_type: i64, data: Vec<u8>, now: Instant) {
+        self.clients.entry(*client).or_default().unreliable.push(Deferred {
+            msg_type,
+            data,
+            priority: priority.max(0.0),
+            accumulated: priority.max(0.0),
+            queued_at: now,
+        });
+    }
+    /// Messages to send now. `status` is what the transport reports about a client's connection
+    pub fn run(
+        &mut self,
+        now: Instant,
+        settings: &SchedulerSettings,
+        status: impl Fn(&Uuid) -> Option<ConnectionStatus>,
+    ) -> Vec<ScheduledSend> {
+        let elapsed = self.last_run.map_or(Duration::ZERO, |last_run| now.saturating_duration_since(last_run));
+        self.last_run = Some(now);
+        let mut sends = Vec::new();
+        for (client, queue) in self.clients.iter_mut() {
+            let status = status(client).unwrap_or_default();
+            let send_rate = status
+                .send_rate
+                .unwrap_or(settings.fallback_send_rate)
+                .min(settings.max_send_rate.unwrap_or(u32::MAX));
+            let burst = send_rate as f64 * settings.burst.as_secs_f64();
+            let tokens = match queue.tokens {
+                Some(tokens) => (tokens + send_rate as f64 * elapsed.as_secs_f64()).min(burst),
+                None => burst,
+            };
+            // the transport is still busy with more than a burst
+            let congested = status.queued_bytes as f64 > burst;
+            queue.send_rate = send_rate;
+            queue.tokens = Some(queue.schedule(client, tokens, congested, now, settings.max_defer, &mut sends));
+        }
+        sends
+    }
+    pub fn budget(&self, client: &Uuid) -> Option<SendBudget> {
+        let queue = self.clients.get(client)?;
+        Some(SendBudget {
+            send_rate: queue.send_rate,
+            available: queue.tokens.unwrap_or_default().max(0.0) as u64,
+            deferred: queue.reliable.len() + queue.unreliable.len(),
+            dropped: queue.dropped,
+        })
+    }
+    pub fn forget_client(&mut self, client: &Uuid) {
+        self.clients.remove(client);
+    }
+}
+impl ClientQueue {
+    // releases what fits into `tokens` and returns what is left of them
+    fn schedule(
+        &mut self,
+        client: &Uuid,
+        mut tokens: f64,
+        congested: bool,
+        now: Instant,
+        max_defer: Duration,
+        sends: &mut Vec<ScheduledSend>,
+    ) -> f64 {
+        // reliable gameplay messages are never held back, they may overdraw the budget
+        for (msg_type, data) in self.reliable.drain(..) {
+            tokens -= (data.len() + MESSAGE_OVERHEAD) as f64;
+            sends.push(ScheduledSend {
+                client: *client,
+                mode: SendMode::Reliable,
+                msg_type,
+                data,
+            });
+        }
+        let before = self.unreliable.len();
+        self.unreliable
+            .retain(|deferred| now.saturating_duration_since(deferred.queued_at) <= max_defer);
+        self.dropped += (before - self.unreliable.len()) as u64;
+        self.unreliable.sort_by(|a, b| b.accumulated.total_cmp(&a.accumulated));
+        let mut kept = Vec::with_capacity(self.unreliable.len());
+        for mut deferred in self.unreliable.drain(..) {
+            let size = (deferred.data.len() + MESSAGE_OVERHEAD) as f64;
+            if !congested && size <= tokens {
+                tokens -= size;
+                sends.push(ScheduledSend {
+                    client: *client,
+                    mode: SendMode::Unreliable,
+                    msg_type: deferred.msg_type,
+                    data: deferred.data,
+                });
+            } else {
+                deferred.accumulated += deferred.priority;
+                kept.push(deferred);
+            }
+        }
+        self.unreliable = kept;
+        tokens
+    }
+}
@@ -1,4 +1,39 @@
-#[derive(Default)]
+use std::time::Duration;
+
+use omgpp_core::{connection_config::ConnectionConfig, network_simulation::NetworkSimulation};
+use super::scheduler::SchedulerSettings;
+#[cfg(feature = "websocket")]
+use omgpp_core::transport::websocket::WebSocketSettings;
+
 pub struct ServerSettings{
-    pub resource_location : String      //url
-}
\ No newline at end of file
+    pub resource_location : String,     //url
+    pub connection_config: ConnectionConfig,    // applied to every accepted connection
+    pub network_simulation: NetworkSimulation,  // process wide, for local testing only
+    pub unverified_connection_expire_period: Duration,  // connections not passed `omgpp_auth` in time are closed
+    pub admin_password: Option<String>,     // enables admin login through `omgpp_auth`; `None` disables it
+    pub max_interpolation_delay: Duration,  // interpolation delay a client reports is capped to this for lag compensation
+    pub input_jitter_window: usize,     // inputs buffered per client before `take_inputs` hands them out
+    pub batch_messages: bool,   // coalesce the messages of a tick per client, send mode and lane, see `Server::flush`
+    pub max_batch_size: usize,  // bytes per batch, fits unreliable batches into one packet
+    pub scheduler: SchedulerSettings,   // per-client budget of `Server::schedule`
+    #[cfg(feature = "websocket")]
+    pub websocket: Option<WebSocketSettings>,   // additional `ws://` listener on the same ip for browser clients
+}
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            resource_location: Default::default(),
+            connection_config: Default::default(),
+            network_simulation: Default::default(),
+            unverified_connection_expire_period: Duration::from_secs(3),
+            admin_password: None,
+            max_interpolation_delay: Duration::from_millis(250),
+            input_jitter_window: 2,
+            batch_messages: false,
+            max_batch_size: 1200,
+            scheduler: Default::default(),
+            #[cfg(feature = "websocket")]
+            websocket: None,
+        }
+    }
+}
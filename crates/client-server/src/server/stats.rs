@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+// a reported round trip may differ this much from the transport ping
+const RTT_TOLERANCE: Duration = Duration::from_millis(50);
+// upper bound of a reported round trip when the transport measures none
+const MAX_REPORTED_RTT: Duration = Duration::from_secs(2);
+
+/// Traffic counters of a server, measured after encoding
+#[derive(Debug, Clone)]
+pub struct ServerStats {
+    pub started_at: Instant,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    // a message sent to several clients is counted once per client, a batch counts as one message
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+}
+impl ServerStats {
+    pub fn new(started_at: Instant) -> ServerStats {
+        ServerStats {
+            started_at,
+            messages_received: 0,
+            bytes_received: 0,
+            messages_sent: 0,
+            bytes_sent: 0,
+        }
+    }
+    pub fn uptime(&self, now: Instant) -> Duration {
+        now - self.started_at
+    }
+    pub(crate) fn track_received(&mut self, bytes: usize) {
+        self.messages_received += 1;
+        self.bytes_received += bytes as u64;
+    }
+    pub(crate) fn track_sent(&mut self, recipients: usize, bytes: usize) {
+        self.messages_sent += recipients as u64;
+        self.bytes_sent += (recipients * bytes) as u64;
+    }
+}
+
+/// What a client reported about its connection with its time sync requests
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientStats {
+    pub rtt: Option<Duration>,
+    pub interpolation_delay: Duration,
+}
+impl ClientStats {
+    /// Values of a time sync request checked against the transport `ping`: the round trip is
+    /// pulled within `RTT_TOLERANCE` of it and the interpolation delay capped to `max_interpolation_delay`
+    pub(crate) fn from_report(
+        rtt_micros: u64,
+        interpolation_delay_micros: u64,
+        ping: Option<Duration>,
+        max_interpolation_delay: Duration,
+    ) -> ClientStats {
+        let rtt = (rtt_micros > 0).then(|| Duration::from_micros(rtt_micros));
+        let rtt = match ping {
+            Some(ping) => rtt.map(|rtt| rtt.clamp(ping.saturating_sub(RTT_TOLERANCE), ping + RTT_TOLERANCE)),
+            None => rtt.map(|rtt| rtt.min(MAX_REPORTED_RTT)),
+        };
+        ClientStats {
+            rtt,
+            interpolation_delay: Duration::from_micros(interpolation_delay_micros).min(max_interpolation_delay),
+        }
+    }
+    /// Server time the client saw when it sent a command that arrives at `now`:
+    /// half a round trip earlier, minus what it renders behind
+    pub fn view_time(&self, now: Duration) -> Duration {
+        let one_way = self.rtt.unwrap_or_default() / 2;
+        now.saturating_sub(one_way + self.interpolation_delay)
+    }
+}
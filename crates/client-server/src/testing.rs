@@ -0,0 +1,513 @@
+//! In-process harness that runs a `Server` and several `Client`s on localhost
+//! and pumps their loops until a condition holds. Enabled by the `testing` feature.
+//!
+//! `TestHarness::start` uses the default backend (GNS, or QUIC without the `gns` feature)
+//! over real UDP, `TestHarness::start_quic` always uses QUIC, `TestHarness::start_loopback` uses the
+//! in-memory loopback transport where time only moves while the harness is pumped.
+//! `TestHarness::start_websocket` (with the `websocket` feature) runs over `ws://` on localhost.
+
+use std::{
+    cell::RefCell,
+    net::{IpAddr, Ipv4Addr, UdpSocket},
+    rc::Rc,
+    sync::{Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use omgpp_core::{
+    clock::{Clock, ManualClock, SystemClock},
+    cmd_handler::CmdHandler,
+    messages::general_message::general_omgpp_message::CmdRequest,
+    transport::{loopback::LoopbackNetwork, Connector, Transport},
+    ConnectionState, Endpoint,
+};
+#[cfg(feature = "quic")]
+use omgpp_core::transport::quic::{QuicConnector, QuicServerTransport};
+#[cfg(feature = "websocket")]
+use omgpp_core::transport::websocket::{WebSocketConnector, WebSocketServerTransport, WebSocketSettings};
+use uuid::Uuid;
+
+use crate::{
+    backend,
+    client::Client,
+    server::{server_settings::ServerSettings, Server},
+};
+
+const MAX_EVENTS_PER_PROCESS: usize = 128;
+// how far the manual clock moves on every loopback pump
+const LOOPBACK_STEP: Duration = Duration::from_millis(1);
+// enough for what is in flight to arrive when no latency is simulated
+const SETTLE_TIME: Duration = Duration::from_millis(20);
+
+// GNS callbacks are dispatched through process wide state, tests must not pump sockets in parallel
+static GNS_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    ConnectionChanged(Uuid, ConnectionState),
+    Message(Uuid, i64, Vec<u8>),
+    Rpc {
+        client: Uuid,
+        reliable: bool,
+        method_id: i64,
+        request_id: u64,
+        arg_type: i64,
+        arg_data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    ConnectionChanged(ConnectionState),
+    Message(i64, Vec<u8>),
+    Rpc {
+        reliable: bool,
+        method_id: i64,
+        request_id: u64,
+        arg_type: i64,
+        arg_data: Vec<u8>,
+    },
+}
+
+/// Command received by a client: name, request id and arguments
+pub type CmdReply = (String, u64, Vec<String>);
+
+/// Shared list of recorded events, cloned into the registered callbacks
+#[derive(Debug)]
+pub struct EventLog<T>(Rc<RefCell<Vec<T>>>);
+impl<T> Clone for EventLog<T> {
+    fn clone(&self) -> Self {
+        EventLog(self.0.clone())
+    }
+}
+impl<T> Default for EventLog<T> {
+    fn default() -> Self {
+        EventLog(Default::default())
+    }
+}
+impl<T: Clone> EventLog<T> {
+    pub fn push(&self, event: T) {
+        self.0.borrow_mut().push(event);
+    }
+    pub fn all(&self) -> Vec<T> {
+        self.0.borrow().clone()
+    }
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+    pub fn any(&self, predicate: impl Fn(&T) -> bool) -> bool {
+        self.0.borrow().iter().any(predicate)
+    }
+    pub fn count(&self, predicate: impl Fn(&T) -> bool) -> usize {
+        self.0.borrow().iter().filter(|event| predicate(event)).count()
+    }
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+pub struct TestServer {
+    pub server: Server<'static>,
+    pub endpoint: Endpoint,
+    pub events: EventLog<ServerEvent>,
+}
+impl TestServer {
+    fn start(settings: ServerSettings) -> Result<TestServer, String> {
+        let port = free_udp_port()?;
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let server = Server::with_settings(ip, port, settings)?;
+        Ok(TestServer::record(server, Endpoint { ip, port }))
+    }
+    fn start_loopback(
+        network: &LoopbackNetwork,
+        settings: ServerSettings,
+    ) -> Result<TestServer, String> {
+        let endpoint = Endpoint {
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 7777,
+        };
+        let transport = network.listen(endpoint)?;
+        let server = Server::with_transport(
+            endpoint.ip,
+            endpoint.port,
+            Box::new(transport),
+            Rc::new(network.clock().clone()),
+            settings,
+        )?;
+        Ok(TestServer::record(server, endpoint))
+    }
+    #[cfg(feature = "quic")]
+    fn start_quic(settings: ServerSettings) -> Result<(TestServer, QuicConnector), String> {
+        let port = free_udp_port()?;
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let transport = QuicServerTransport::listen(ip, port)?;
+        // a test server on localhost, the certificate checks are covered by tests/quic.rs
+        let connector = QuicConnector::dangerous_skip_verification();
+        let server = Server::with_transport(
+            ip,
+            port,
+            Box::new(transport),
+            Rc::new(SystemClock),
+            settings,
+        )?;
+        Ok((TestServer::record(server, Endpoint { ip, port }), connector))
+    }
+    #[cfg(feature = "websocket")]
+    fn start_websocket(settings: ServerSettings) -> Result<TestServer, String> {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let transport = WebSocketServerTransport::listen(ip, WebSocketSettings::new(0))?;
+        let endpoint = transport.local_endpoint()?;
+        let server = Server::with_transport(
+            ip,
+            endpoint.port,
+            Box::new(transport),
+            Rc::new(SystemClock),
+            settings,
+        )?;
+        Ok(TestServer::record(server, endpoint))
+    }
+    fn record(mut server: Server<'static>, endpoint: Endpoint) -> TestServer {
+        let events = EventLog::default();
+
+        let log = events.clone();
+        server.register_on_connection_state_changed(move |_server, uuid, _endpoint, state| {
+            log.push(ServerEvent::ConnectionChanged(*uuid, state))
+        });
+        let log = events.clone();
+        server.register_on_message(move |_server, uuid, _endpoint, msg_type, data| {
+            log.push(ServerEvent::Message(*uuid, msg_type, data))
+        });
+        let log = events.clone();
+        server.register_on_rpc(
+            move |_server, uuid, _endpoint, reliable, method_id, request_id, arg_type, arg_data| {
+                log.push(ServerEvent::Rpc {
+                    client: *uuid,
+                    reliable,
+                    method_id,
+                    request_id,
+                    arg_type,
+                    arg_data,
+                })
+            },
+        );
+        TestServer {
+            server,
+            endpoint,
+            events,
+        }
+    }
+    pub fn client_state(&self, client: &Uuid) -> ConnectionState {
+        self.events
+            .all()
+            .into_iter()
+            .rev()
+            .find_map(|event| match event {
+                ServerEvent::ConnectionChanged(uuid, state) if uuid == *client => Some(state),
+                _ => None,
+            })
+            .unwrap_or(ConnectionState::None)
+    }
+    pub fn connected_clients(&self) -> usize {
+        self.server.active_clients().len()
+    }
+}
+
+pub struct TestClient {
+    pub client: Client,
+    pub events: EventLog<ClientEvent>,
+}
+impl TestClient {
+    fn connect(
+        server_endpoint: &Endpoint,
+        connector: Box<dyn Connector>,
+        clock: Rc<dyn Clock>,
+        auth_args: Vec<String>,
+    ) -> Result<TestClient, String> {
+        let mut client =
+            Client::with_connector(server_endpoint.ip, server_endpoint.port, connector);
+        client.set_clock(clock);
+        let events = EventLog::default();
+
+        let log = events.clone();
+        client.register_on_connection_state_changed(move |_client, _endpoint, state| {
+            log.push(ClientEvent::ConnectionChanged(state))
+        });
+        let log = events.clone();
+        client.register_on_message(move |_client, _endpoint, msg_type, data| {
+            log.push(ClientEvent::Message(msg_type, data))
+        });
+        let log = events.clone();
+        client.register_on_rpc(
+            move |_client, _endpoint, reliable, method_id, request_id, arg_type, arg_data| {
+                log.push(ClientEvent::Rpc {
+                    reliable,
+                    method_id,
+                    request_id,
+                    arg_type,
+                    arg_data,
+                })
+            },
+        );
+        client.register_on_auth(move |_client, _endpoint| auth_args.clone());
+        client.connect()?;
+        Ok(TestClient { client, events })
+    }
+    pub fn state(&self) -> ConnectionState {
+        self.events
+            .all()
+            .into_iter()
+            .rev()
+            .find_map(|event| match event {
+                ClientEvent::ConnectionChanged(state) => Some(state),
+                _ => None,
+            })
+            .unwrap_or(ConnectionState::None)
+    }
+    pub fn is_connected(&self) -> bool {
+        self.state() == ConnectionState::Connected
+    }
+}
+
+enum Backend {
+    Default,
+    Loopback(LoopbackNetwork),
+    #[cfg(feature = "quic")]
+    Quic(QuicConnector),
+    #[cfg(feature = "websocket")]
+    WebSocket,
+}
+
+/// Server, clients and raw connections that are pumped together
+pub struct TestHarness {
+    pub server: TestServer,
+    pub clients: Vec<TestClient>,
+    // connections that never send `omgpp_auth`
+    raw_connections: Vec<Box<dyn Transport>>,
+    backend: Backend,
+    clock: Rc<dyn Clock>,
+    // only harnesses on the default backend (GNS) need to be serialized
+    _lock: Option<MutexGuard<'static, ()>>,
+}
+impl TestHarness {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Start a server on an ephemeral localhost port and connect `clients` clients to it
+    pub fn start(clients: usize) -> Result<TestHarness, String> {
+        TestHarness::start_with_settings(clients, Default::default())
+    }
+    pub fn start_with_settings(
+        clients: usize,
+        settings: ServerSettings,
+    ) -> Result<TestHarness, String> {
+        let lock = GNS_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let server = TestServer::start(settings)?;
+        let harness = TestHarness {
+            server,
+            clients: Vec::new(),
+            raw_connections: Vec::new(),
+            backend: Backend::Default,
+            clock: Rc::new(SystemClock),
+            _lock: Some(lock),
+        };
+        harness.with_clients(clients)
+    }
+    /// Same as `start` but over QUIC, whatever the default backend is
+    #[cfg(feature = "quic")]
+    pub fn start_quic(clients: usize) -> Result<TestHarness, String> {
+        let (server, connector) = TestServer::start_quic(Default::default())?;
+        let harness = TestHarness {
+            server,
+            clients: Vec::new(),
+            raw_connections: Vec::new(),
+            backend: Backend::Quic(connector),
+            clock: Rc::new(SystemClock),
+            _lock: None,
+        };
+        harness.with_clients(clients)
+    }
+    /// Same as `start` but the server only listens on `ws://` and clients connect over WebSocket
+    #[cfg(feature = "websocket")]
+    pub fn start_websocket(clients: usize) -> Result<TestHarness, String> {
+        let server = TestServer::start_websocket(Default::default())?;
+        let harness = TestHarness {
+            server,
+            clients: Vec::new(),
+            raw_connections: Vec::new(),
+            backend: Backend::WebSocket,
+            clock: Rc::new(SystemClock),
+            _lock: None,
+        };
+        harness.with_clients(clients)
+    }
+    /// Same as `start` but over an in-memory `LoopbackNetwork` driven by a manual clock
+    pub fn start_loopback(clients: usize) -> Result<TestHarness, String> {
+        TestHarness::start_loopback_with_settings(clients, Default::default())
+    }
+    pub fn start_loopback_with_settings(
+        clients: usize,
+        settings: ServerSettings,
+    ) -> Result<TestHarness, String> {
+        let clock = ManualClock::new();
+        let network = LoopbackNetwork::new(clock.clone());
+        let server = TestServer::start_loopback(&network, settings)?;
+        let harness = TestHarness {
+            server,
+            clients: Vec::new(),
+            raw_connections: Vec::new(),
+            backend: Backend::Loopback(network),
+            clock: Rc::new(clock),
+            _lock: None,
+        };
+        harness.with_clients(clients)
+    }
+    /// `start_loopback` and wait until every client is authenticated
+    pub fn start_loopback_connected(clients: usize) -> Result<TestHarness, String> {
+        let harness = TestHarness::start_loopback(clients)?;
+        match harness.wait_all_connected() {
+            true => Ok(harness),
+            false => Err(format!("{} clients did not connect in time", clients)),
+        }
+    }
+    fn with_clients(mut self, clients: usize) -> Result<TestHarness, String> {
+        for _ in 0..clients {
+            self.add_client(vec![])?;
+        }
+        Ok(self)
+    }
+    fn connector(&self) -> Box<dyn Connector> {
+        match &self.backend {
+            // QUIC clients of a localhost test server have no certificate to check against
+            Backend::Default => backend::dangerous_insecure_connector(),
+            Backend::Loopback(network) => Box::new(network.connector()),
+            #[cfg(feature = "quic")]
+            Backend::Quic(connector) => Box::new(connector.clone()),
+            #[cfg(feature = "websocket")]
+            Backend::WebSocket => Box::new(WebSocketConnector),
+        }
+    }
+    /// Loopback network of a harness created with `start_loopback`
+    pub fn network(&self) -> Option<&LoopbackNetwork> {
+        match &self.backend {
+            Backend::Loopback(network) => Some(network),
+            _ => None,
+        }
+    }
+    /// Connect one more client, which authenticates with `auth_args`. Returns its index
+    pub fn add_client(&mut self, auth_args: Vec<String>) -> Result<usize, String> {
+        let client = TestClient::connect(&self.server.endpoint, self.connector(), self.clock.clone(), auth_args)?;
+        self.clients.push(client);
+        Ok(self.clients.len() - 1)
+    }
+    /// Open a connection to the server that never runs the `omgpp_auth` handshake
+    pub fn add_unverified_connection(&mut self) -> Result<(), String> {
+        let (transport, _connection) = self.connector().connect(&self.server.endpoint, &Default::default())?;
+        self.raw_connections.push(transport);
+        Ok(())
+    }
+    pub fn client(&self, index: usize) -> &TestClient {
+        &self.clients[index]
+    }
+    /// Uuid of the `index`-th client that is connected to the server, in the order they got connected
+    pub fn client_uuid(&self, index: usize) -> Uuid {
+        let active = self.server.server.active_clients();
+        let mut connected = Vec::new();
+        for event in self.server.events.all() {
+            if let ServerEvent::ConnectionChanged(uuid, ConnectionState::Connected) = event {
+                if !connected.contains(&uuid) && active.iter().any(|(client, _)| *client == uuid) {
+                    connected.push(uuid);
+                }
+            }
+        }
+        connected[index]
+    }
+    /// Record every command client `index` receives, including the server replies to its own commands
+    pub fn record_client_cmds(&self, index: usize) -> Result<EventLog<CmdReply>, String> {
+        let replies = EventLog::default();
+        let log = replies.clone();
+        self.client(index).client.register_cmd_fallback(Some(CmdHandler::new(
+            "",
+            false,
+            Box::new(move |_client: &Client, _uuid, _endpoint, _handler, request: &CmdRequest| {
+                log.push((request.cmd.clone(), request.request_id, request.args.clone()))
+            }),
+        )))?;
+        Ok(replies)
+    }
+    /// Run one cycle of every server and client loop
+    pub fn pump(&self) {
+        _ = self.server.server.process::<MAX_EVENTS_PER_PROCESS>();
+        for test_client in &self.clients {
+            _ = test_client.client.process::<MAX_EVENTS_PER_PROCESS>();
+        }
+        for transport in &self.raw_connections {
+            transport.poll();
+            transport.poll_events(MAX_EVENTS_PER_PROCESS, &mut |_event| {});
+            transport.poll_messages(MAX_EVENTS_PER_PROCESS, &mut |_connection, _data| {});
+        }
+    }
+    /// Pump loops until `predicate` holds. Returns `false` if `timeout` elapsed first.
+    /// Loopback harnesses measure `timeout` on the manual clock
+    pub fn pump_until(&self, timeout: Duration, predicate: impl Fn(&TestHarness) -> bool) -> bool {
+        let started = self.clock.now();
+        loop {
+            self.pump();
+            if predicate(self) {
+                return true;
+            }
+            if self.clock.now() - started > timeout {
+                return false;
+            }
+            self.step();
+        }
+    }
+    pub fn pump_for(&self, duration: Duration) {
+        let started = self.clock.now();
+        while self.clock.now() - started < duration {
+            self.pump();
+            self.step();
+        }
+    }
+    // let time pass between two pumps
+    fn step(&self) {
+        match &self.backend {
+            Backend::Loopback(network) => network.clock().advance(LOOPBACK_STEP),
+            _ => thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    /// Pump until what is in flight arrived, as long as no latency is simulated
+    pub fn settle(&self) {
+        self.pump_for(SETTLE_TIME);
+    }
+    /// End a replication tick on the server and let the snapshots arrive. Returns the tick
+    pub fn replicate(&self) -> Result<u64, String> {
+        let tick = self.server.server.replicate()?;
+        self.settle();
+        Ok(tick)
+    }
+    pub fn wait_all_connected(&self) -> bool {
+        self.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+            harness.clients.iter().all(|client| client.is_connected())
+                && harness.server.connected_clients() == harness.clients.len()
+        })
+    }
+}
+
+/// Shorthand for timestamps and delays in tests
+pub fn millis(value: u64) -> Duration {
+    Duration::from_millis(value)
+}
+pub fn micros(value: u64) -> Duration {
+    Duration::from_micros(value)
+}
+
+fn free_udp_port() -> Result<u16, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|err| err.to_string())?;
+    let port = socket.local_addr().map_err(|err| err.to_string())?.port();
+    Ok(port)
+}
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use client_server::{
+    server::server_settings::ServerSettings,
+    testing::{ClientEvent, CmdReply, EventLog, TestHarness},
+};
+use omgpp_core::{ConnectionState, OmgppPredefinedCmd};
+
+const PASSWORD: &str = "secret";
+
+// harness with one player (client 0) and one admin (client 1)
+fn admin_harness() -> (TestHarness, EventLog<CmdReply>) {
+    let settings = ServerSettings {
+        admin_password: Some(PASSWORD.to_string()),
+        ..Default::default()
+    };
+    let mut harness = TestHarness::start_loopback_with_settings(1, settings).unwrap();
+    harness
+        .add_client(vec![OmgppPredefinedCmd::ADMIN_AUTH.to_string(), PASSWORD.to_string()])
+        .unwrap();
+    assert!(harness.wait_all_connected());
+    let replies = harness.record_client_cmds(1).unwrap();
+    (harness, replies)
+}
+
+fn admin_cmd(harness: &TestHarness, replies: &EventLog<CmdReply>, cmd: &str, args: &[&str]) -> Vec<String> {
+    let request_id = replies.len() as u64 + 1;
+    let args = args.iter().map(|arg| arg.to_string()).collect();
+    harness.client(1).client.send_cmd(cmd, request_id, Some(args)).unwrap();
+    let answered = |_: &TestHarness| replies.any(|reply| reply.1 == request_id);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, answered));
+    replies.all().into_iter().find(|reply| reply.1 == request_id).unwrap().2
+}
+
+fn player_uuid(harness: &TestHarness) -> uuid::Uuid {
+    harness
+        .server
+        .server
+        .active_clients()
+        .into_iter()
+        .find(|(uuid, _)| harness.server.server.client_roles(uuid).is_empty())
+        .unwrap()
+        .0
+}
+
+#[test]
+fn admin_login_requires_password() {
+    let settings = ServerSettings {
+        admin_password: Some(PASSWORD.to_string()),
+        ..Default::default()
+    };
+    let mut harness = TestHarness::start_loopback_with_settings(0, settings).unwrap();
+    harness
+        .add_client(vec![OmgppPredefinedCmd::ADMIN_AUTH.to_string(), "guess".to_string()])
+        .unwrap();
+    let rejected = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).state() == ConnectionState::Disconnected
+    });
+    assert!(rejected);
+    assert_eq!(harness.server.connected_clients(), 0);
+
+    // without a password admin login is disabled
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness
+        .add_client(vec![OmgppPredefinedCmd::ADMIN_AUTH.to_string(), String::new()])
+        .unwrap();
+    harness.pump_for(Duration::from_millis(100));
+    assert_eq!(harness.server.connected_clients(), 0);
+}
+
+#[test]
+fn failed_admin_login_of_a_player_only_refuses_the_role() {
+    let (harness, _replies) = admin_harness();
+    let server = &harness.server.server;
+    let player = player_uuid(&harness);
+    server.grant_role(&player, "moderator").unwrap();
+    let client = &harness.client(0).client;
+    let login = |request_id, password: &str| {
+        let args = vec![OmgppPredefinedCmd::ADMIN_AUTH.to_string(), password.to_string()];
+        client.send_cmd(OmgppPredefinedCmd::AUTH, request_id, Some(args)).unwrap();
+    };
+
+    login(1, "guess");
+    harness.pump_for(Duration::from_millis(100));
+    assert_eq!(harness.server.connected_clients(), 2);
+    assert!(!server.client_roles(&player).contains(OmgppPredefinedCmd::ADMIN_ROLE));
+
+    login(2, PASSWORD);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| {
+        server.client_roles(&player).contains(OmgppPredefinedCmd::ADMIN_ROLE)
+    }));
+    assert!(server.client_roles(&player).contains("moderator"));
+}
+
+#[test]
+fn players_cannot_run_admin_commands() {
+    let (harness, _replies) = admin_harness();
+    let player = player_uuid(&harness);
+    harness
+        .client(0)
+        .client
+        .send_cmd(OmgppPredefinedCmd::KICK, 1, Some(vec![player.to_string()]))
+        .unwrap();
+    harness.pump_for(Duration::from_millis(100));
+    assert_eq!(harness.server.connected_clients(), 2);
+}
+
+#[test]
+fn admin_lists_and_kicks_players() {
+    let (harness, replies) = admin_harness();
+    let player = player_uuid(&harness);
+    let players = admin_cmd(&harness, &replies, OmgppPredefinedCmd::PLAYERS, &[]);
+    assert_eq!(players.len(), 2);
+    assert!(players.iter().any(|line| line.starts_with(&player.to_string()) && line.ends_with("[]")));
+    assert!(players.iter().any(|line| line.ends_with("[omgpp_admin]")));
+
+    let reply = admin_cmd(&harness, &replies, OmgppPredefinedCmd::KICK, &[&player.to_string()]);
+    assert_eq!(reply, vec!["ok"]);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).state() == ConnectionState::Disconnected
+    }));
+    assert_eq!(harness.server.connected_clients(), 1);
+    let reply = admin_cmd(&harness, &replies, OmgppPredefinedCmd::KICK, &[&player.to_string()]);
+    assert_eq!(reply[0], "error");
+}
+
+#[test]
+fn banned_ip_cannot_reconnect_until_unbanned() {
+    let (mut harness, replies) = admin_harness();
+    let player = player_uuid(&harness);
+    let reply = admin_cmd(&harness, &replies, OmgppPredefinedCmd::BAN, &[&player.to_string()]);
+    assert_eq!(reply, vec!["ok", "127.0.0.1"]);
+    // loopback clients share the ip, so the admin is banned as well
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.connected_clients() == 0
+    }));
+    let ip = "127.0.0.1".parse().unwrap();
+    assert!(harness.server.server.is_banned(&ip));
+
+    let index = harness.add_client(vec![]).unwrap();
+    harness.pump_for(Duration::from_millis(100));
+    assert!(!harness.client(index).is_connected());
+
+    assert!(harness.server.server.unban(&ip));
+    let index = harness.add_client(vec![]).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(index).is_connected()
+    }));
+}
+
+#[test]
+fn admin_announces_changes_settings_and_reads_stats() {
+    let (harness, replies) = admin_harness();
+    let announcements = EventLog::default();
+    let log = announcements.clone();
+    harness
+        .client(0)
+        .client
+        .register_on_announcement(move |_client, _endpoint, text| log.push(text.to_string()));
+
+    assert_eq!(admin_cmd(&harness, &replies, OmgppPredefinedCmd::ANNOUNCE, &["Restart in 5 minutes"]), vec!["ok"]);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| !announcements.is_empty()));
+    assert_eq!(announcements.all()[0], "Restart in 5 minutes");
+    assert!(!harness.client(0).events.any(|event| matches!(event, ClientEvent::Message(..))));
+
+    let reply = admin_cmd(&harness, &replies, OmgppPredefinedCmd::SET, &["resource_location", "http://cdn"]);
+    assert_eq!(reply, vec!["resource_location=http://cdn"]);
+    assert_eq!(harness.server.server.settings().resource_location, "http://cdn");
+    let reply = admin_cmd(&harness, &replies, OmgppPredefinedCmd::SET, &["unverified_expire_ms", "500"]);
+    assert_eq!(reply, vec!["unverified_expire_ms=500"]);
+    assert_eq!(admin_cmd(&harness, &replies, OmgppPredefinedCmd::SET, &[]).len(), 2);
+    let reply = admin_cmd(&harness, &replies, OmgppPredefinedCmd::SET, &["unverified_expire_ms", "soon"]);
+    assert_eq!(reply[0], "error");
+
+    let stats = admin_cmd(&harness, &replies, OmgppPredefinedCmd::STATS, &[]);
+    assert!(stats.contains(&"clients=2".to_string()));
+    assert!(harness.server.server.stats().messages_received > 0);
+    assert!(harness.server.server.stats().messages_sent > 0);
+}
@@ -0,0 +1,200 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    rc::Rc,
+    time::Duration,
+};
+
+use client_server::{
+    server::{interest::Position, Server},
+    testing::{ClientEvent, ServerEvent, TestHarness},
+};
+use omgpp_core::{
+    clock::ManualClock,
+    messages::general_message::{
+        general_omgpp_message::{CmdRequest, Data},
+        GeneralOmgppMessage,
+    },
+    transport::{loopback::LoopbackNetwork, ConnectionHandle, Connector, SendMode, Transport},
+    ConnectionState, Endpoint, OmgppPredefinedCmd,
+};
+use protobuf::Message;
+
+fn cmd_bytes(cmd: &str, request_id: u64, args: &[&str]) -> Vec<u8> {
+    let mut payload = GeneralOmgppMessage::new();
+    let request = CmdRequest {
+        cmd: cmd.to_string(),
+        request_id,
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        ..Default::default()
+    };
+    payload.data = Some(Data::Cmd(request));
+    payload.write_to_bytes().unwrap()
+}
+
+fn received_cmds(transport: &dyn Transport) -> Vec<CmdRequest> {
+    let mut cmds = Vec::new();
+    transport.poll_messages(64, &mut |_connection, data| {
+        if let Ok(GeneralOmgppMessage {
+            data: Some(Data::Cmd(cmd)),
+            ..
+        }) = GeneralOmgppMessage::parse_from_bytes(data)
+        {
+            cmds.push(cmd);
+        }
+    });
+    cmds
+}
+
+struct RawSetup {
+    server: Server<'static>,
+    raw: Box<dyn Transport>,
+    connection: ConnectionHandle,
+}
+
+fn raw_setup() -> RawSetup {
+    let clock = ManualClock::new();
+    let network = LoopbackNetwork::new(clock.clone());
+    let endpoint = Endpoint {
+        ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        port: 7777,
+    };
+    let transport = network.listen(endpoint).unwrap();
+    let server = Server::with_transport(
+        endpoint.ip,
+        endpoint.port,
+        Box::new(transport),
+        Rc::new(clock),
+        Default::default(),
+    )
+    .unwrap();
+    let (raw, connection) = network.connector().connect(&endpoint, &Default::default()).unwrap();
+    // connect request and accept
+    for _ in 0..2 {
+        server.process::<64>().unwrap();
+        raw.poll_events(64, &mut |_event| {});
+    }
+    RawSetup {
+        server,
+        raw,
+        connection,
+    }
+}
+
+#[test]
+fn rejected_client_gets_error_reply_and_is_disconnected() {
+    let setup = raw_setup();
+    setup.server.register_on_authenticate(|_server, _uuid, _endpoint, args| {
+        match args.first().map(String::as_str) {
+            Some("secret") => Ok(vec![]),
+            _ => Err("Wrong password".to_string()),
+        }
+    });
+    let bytes = cmd_bytes(OmgppPredefinedCmd::AUTH, 42, &["guess"]);
+    setup.raw.send(&[setup.connection], SendMode::Reliable, &bytes).unwrap();
+    setup.server.process::<64>().unwrap();
+
+    let replies = received_cmds(setup.raw.as_ref());
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].cmd, OmgppPredefinedCmd::AUTH);
+    assert_eq!(replies[0].request_id, 42);
+    assert_eq!(replies[0].args, vec!["error", "Wrong password"]);
+    assert!(setup.server.active_clients().is_empty());
+}
+
+#[test]
+fn successful_authentication_assigns_roles() {
+    let setup = raw_setup();
+    setup
+        .server
+        .register_on_authenticate(|_server, _uuid, _endpoint, _args| Ok(vec!["admin".to_string()]));
+    let bytes = cmd_bytes(OmgppPredefinedCmd::AUTH, 7, &[]);
+    setup.raw.send(&[setup.connection], SendMode::Reliable, &bytes).unwrap();
+    setup.server.process::<64>().unwrap();
+
+    let replies = received_cmds(setup.raw.as_ref());
+    assert_eq!(replies[0].request_id, 7);
+    assert_eq!(replies[0].args, vec!["ok"]);
+    let (uuid, _endpoint) = setup.server.active_clients()[0];
+    assert!(setup.server.client_roles(&uuid).contains("admin"));
+}
+
+#[test]
+fn verified_client_authenticating_again_keeps_connection_and_state() {
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness
+        .server
+        .server
+        .register_on_authenticate(|_server, _uuid, _endpoint, args| match args.first().map(String::as_str) {
+            Some("bad") => Err("Rejected".to_string()),
+            _ => Ok(args.iter().map(|role| format!("role:{}", role)).collect()),
+        });
+    harness.add_client(vec!["player".to_string()]).unwrap();
+    assert!(harness.wait_all_connected());
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    let position = Position { x: 1.0, y: 2.0, z: 3.0 };
+    server.set_client_position(&uuid, Some(position));
+    let client = &harness.client(0).client;
+
+    client.send_cmd(OmgppPredefinedCmd::AUTH, 1, Some(vec!["bad".to_string()])).unwrap();
+    harness.pump_for(Duration::from_millis(50));
+    assert_eq!(harness.server.connected_clients(), 1);
+    assert!(server.client_roles(&uuid).contains("role:player"));
+
+    client.send_cmd(OmgppPredefinedCmd::AUTH, 2, Some(vec!["moderator".to_string()])).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| {
+        server.client_roles(&uuid).contains("role:moderator")
+    }));
+    assert_eq!(server.client_position(&uuid), Some(position));
+    assert_eq!(harness.server.connected_clients(), 1);
+}
+
+#[test]
+fn roles_are_granted_by_authenticator_and_dropped_on_disconnect() {
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness
+        .server
+        .server
+        .register_on_authenticate(|_server, _uuid, _endpoint, args| {
+            Ok(args.iter().map(|role| format!("role:{}", role)).collect())
+        });
+    harness.add_client(vec!["admin".to_string()]).unwrap();
+    assert!(harness.wait_all_connected());
+
+    let uuid = harness.client_uuid(0);
+    let server = &harness.server.server;
+    assert!(server.client_roles(&uuid).contains("role:admin"));
+    server.grant_role(&uuid, "moderator").unwrap();
+    server.revoke_role(&uuid, "role:admin").unwrap();
+    assert_eq!(
+        server.client_roles(&uuid).into_iter().collect::<Vec<_>>(),
+        vec!["moderator".to_string()]
+    );
+
+    harness.client(0).client.disconnect();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| harness
+        .server
+        .events
+        .any(|event| matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::Disconnected)))));
+    assert!(harness.server.server.client_roles(&uuid).is_empty());
+    assert!(harness.server.server.grant_role(&uuid, "admin").is_err());
+}
+
+#[test]
+fn rejected_client_never_becomes_connected() {
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness
+        .server
+        .server
+        .register_on_authenticate(|_server, _uuid, _endpoint, _args| Err("Banned".to_string()));
+    harness.add_client(vec![]).unwrap();
+    let disconnected = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).state() == ConnectionState::Disconnected
+    });
+    assert!(disconnected);
+    assert!(!harness
+        .client(0)
+        .events
+        .any(|event| *event == ClientEvent::ConnectionChanged(ConnectionState::Connected)));
+    assert_eq!(harness.server.connected_clients(), 0);
+}
@@ -0,0 +1,152 @@
+use client_server::{
+    server::server_settings::ServerSettings,
+    testing::{EventLog, ServerEvent, TestHarness},
+};
+use omgpp_core::{
+    interceptor::{Direction, Intercept},
+    messages::general_message::{
+        general_omgpp_message::{Batch, Data},
+        GeneralOmgppMessage,
+    },
+};
+use protobuf::Message;
+
+fn batching(clients: usize, max_batch_size: usize) -> TestHarness {
+    let settings = ServerSettings {
+        batch_messages: true,
+        max_batch_size,
+        ..Default::default()
+    };
+    let harness = TestHarness::start_loopback_with_settings(clients, settings).unwrap();
+    assert!(harness.wait_all_connected());
+    harness.settle();
+    harness
+}
+
+fn log_messages(harness: &TestHarness, index: usize) -> EventLog<(i64, Vec<u8>)> {
+    let messages = EventLog::default();
+    let log = messages.clone();
+    harness
+        .client(index)
+        .client
+        .register_on_message(move |_client, _endpoint, msg_type, data| log.push((msg_type, data)));
+    messages
+}
+
+#[test]
+fn messages_of_a_tick_go_in_one_packet_per_send_mode() {
+    let harness = batching(1, 1200);
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    let messages = log_messages(&harness, 0);
+    server.flush().unwrap();
+
+    for index in 0..30u8 {
+        server.send_reliable(&uuid, 1, &[index]).unwrap();
+    }
+    for index in 0..5u8 {
+        server.send(&uuid, 2, &[index]).unwrap();
+    }
+    assert_eq!(server.batched_messages(), 35);
+    let sent = server.stats().messages_sent;
+    server.flush().unwrap();
+    assert_eq!(server.stats().messages_sent - sent, 2);
+
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| messages.count(|_| true) == 35));
+    let reliable = messages
+        .all()
+        .into_iter()
+        .filter(|(msg_type, _)| *msg_type == 1)
+        .map(|(_, data)| data[0])
+        .collect::<Vec<_>>();
+    assert_eq!(reliable, (0..30).collect::<Vec<_>>());
+}
+
+#[test]
+fn batches_are_split_at_the_size_limit() {
+    let harness = batching(1, 500);
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    let messages = log_messages(&harness, 0);
+    server.flush().unwrap();
+
+    let sent = server.stats().messages_sent;
+    for index in 0..10u8 {
+        server.send_reliable(&uuid, 1, &[index; 200]).unwrap();
+    }
+    // every batch after the first left when the next message did not fit
+    assert!(server.stats().messages_sent - sent >= 4);
+    server.flush().unwrap();
+    assert!(server.stats().messages_sent - sent < 10);
+
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| messages.count(|_| true) == 10));
+    let order = messages.all().into_iter().map(|(_, data)| data[0]).collect::<Vec<_>>();
+    assert_eq!(order, (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn broadcasts_are_batched_per_client_and_intercepted_per_message() {
+    let harness = batching(2, 1200);
+    let server = &harness.server.server;
+    let outbound = EventLog::default();
+    let log = outbound.clone();
+    server
+        .add_interceptor("count", move |_server, context, message| {
+            if context.direction == Direction::Outbound && matches!(message.data, Some(Data::Message(_))) {
+                log.push(*context.peer);
+            }
+            Intercept::Continue
+        })
+        .unwrap();
+    let first = log_messages(&harness, 0);
+    let second = log_messages(&harness, 1);
+
+    for index in 0..3u8 {
+        server.broadcast_reliable(1, &[index]).unwrap();
+    }
+    assert_eq!(outbound.count(|_| true), 6);
+    // `process` sends what was queued since the previous cycle
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| {
+        first.count(|_| true) == 3 && second.count(|_| true) == 3
+    }));
+    assert_eq!(server.batched_messages(), 0);
+}
+
+// `message` inside `depth` batch envelopes
+fn nest(message: &GeneralOmgppMessage, depth: usize) -> GeneralOmgppMessage {
+    let mut nested = message.clone();
+    for _ in 0..depth {
+        let mut batch = Batch::new();
+        batch.messages = vec![nested.write_to_bytes().unwrap()];
+        nested = GeneralOmgppMessage::new();
+        nested.data = Some(Data::Batch(batch));
+    }
+    nested
+}
+
+#[test]
+fn batches_inside_batches_are_dropped() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let client = &harness.client(0).client;
+    // the message type is how deep the client nests the message
+    client
+        .add_interceptor("nest", |_client, context, message| {
+            let depth = match (context.direction, &message.data) {
+                (Direction::Outbound, Some(Data::Message(inner))) => inner.type_ as usize,
+                _ => return Intercept::Continue,
+            };
+            *message = nest(message, depth);
+            Intercept::Continue
+        })
+        .unwrap();
+    client.send_reliable(2, b"nested").unwrap();
+    // would overflow the stack of a server unpacking batches recursively
+    client.send_reliable(10_000, b"deep").unwrap();
+    client.send_reliable(1, b"batched").unwrap();
+
+    let server = &harness.server;
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| {
+        server.events.any(|event| matches!(event, ServerEvent::Message(_, 1, _)))
+    }));
+    assert!(!server.events.any(|event| matches!(event, ServerEvent::Message(_, msg_type, _) if *msg_type != 1)));
+}
@@ -0,0 +1,212 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+};
+
+use client_server::{
+    client::{self, Client},
+    server::{self, Server},
+    testing::TestHarness,
+};
+use omgpp_core::{
+    cmd_args::{ArgSpec, ArgType, CmdSchema},
+    cmd_handler::CmdHandler,
+    ffi::{EndpointFFI, UuidFFI},
+    messages::general_message::general_omgpp_message::CmdRequest,
+    OmgppPredefinedCmd,
+};
+
+fn echo_handler(cmd: &str) -> CmdHandler<Server<'static>> {
+    CmdHandler::new(
+        cmd,
+        true,
+        Box::new(|server: &Server, uuid, _endpoint, _handler, request: &CmdRequest| {
+            _ = server.send_command(uuid, request.cmd.clone(), request.request_id, Some(request.args.clone()));
+        }),
+    )
+}
+
+#[test]
+fn registered_commands_are_dispatched_both_ways() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let replies = harness.record_client_cmds(0).unwrap();
+    harness.server.server.register_cmd_handler(echo_handler("echo")).unwrap();
+
+    harness
+        .client(0)
+        .client
+        .send_cmd("echo", 3, Some(vec!["hello".to_string()]))
+        .unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| !replies.is_empty()));
+    assert_eq!(replies.all()[0], ("echo".to_string(), 3, vec!["hello".to_string()]));
+}
+
+#[test]
+fn reserved_prefix_cannot_be_registered() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    assert!(server.register_cmd_handler(echo_handler(OmgppPredefinedCmd::AUTH)).is_err());
+    assert!(server.register_cmd_handler(echo_handler("omgpp_custom")).is_err());
+    assert!(server.unregister_cmd_handler(OmgppPredefinedCmd::RESOURCES).is_err());
+    let client_handler = CmdHandler::new("omgpp_custom", false, Box::new(|_: &Client, _, _, _, _: &CmdRequest| {}));
+    assert!(harness.client(0).client.register_cmd_handler(client_handler).is_err());
+}
+
+#[test]
+fn unknown_and_unregistered_commands_get_an_error_reply() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let replies = harness.record_client_cmds(0).unwrap();
+    let server = &harness.server.server;
+    server.register_cmd_handler(echo_handler("echo")).unwrap();
+    assert!(server.register_cmd_handler(echo_handler("echo")).is_err());
+    server.unregister_cmd_handler("echo").unwrap();
+    assert!(server.unregister_cmd_handler("echo").is_err());
+
+    harness.client(0).client.send_cmd("echo", 9, None).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| !replies.is_empty()));
+    assert_eq!(
+        replies.all()[0],
+        ("echo".to_string(), 9, vec!["error".to_string(), "Unknown command".to_string()])
+    );
+}
+
+#[test]
+fn fallback_handles_commands_without_handler() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let replies = harness.record_client_cmds(0).unwrap();
+    harness
+        .server
+        .server
+        .register_cmd_fallback(Some(echo_handler("")))
+        .unwrap();
+
+    harness.client(0).client.send_cmd("anything", 1, None).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| !replies.is_empty()));
+    assert_eq!(replies.all()[0], ("anything".to_string(), 1, vec![]));
+}
+
+#[test]
+fn role_restricted_command_requires_granted_role() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let replies = harness.record_client_cmds(0).unwrap();
+    let server = &harness.server.server;
+    server.register_cmd_handler(echo_handler("kick").with_roles(&["admin"])).unwrap();
+
+    harness.client(0).client.send_cmd("kick", 1, None).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| !replies.is_empty()));
+    assert_eq!(replies.all()[0].2[0], "error");
+
+    let uuid = harness.client_uuid(0);
+    server.grant_role(&uuid, "admin").unwrap();
+    harness.client(0).client.send_cmd("kick", 2, None).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| replies.len() == 2));
+    assert_eq!(replies.all()[1], ("kick".to_string(), 2, vec![]));
+}
+
+thread_local! {
+    static FFI_COMMANDS: RefCell<Vec<(String, u64, Vec<String>)>> = Default::default();
+}
+unsafe fn collect_ffi(cmd: *const c_char, request_id: u64, args: *const *const c_char, args_count: usize) {
+    let args = (0..args_count)
+        .map(|index| CStr::from_ptr(*args.add(index)).to_str().unwrap().to_string())
+        .collect();
+    let cmd = CStr::from_ptr(cmd).to_str().unwrap().to_string();
+    FFI_COMMANDS.with(|commands| commands.borrow_mut().push((cmd, request_id, args)));
+}
+extern "C" fn on_server_cmd(
+    _uuid: UuidFFI,
+    _endpoint: EndpointFFI,
+    cmd: *const c_char,
+    request_id: u64,
+    args: *const *const c_char,
+    args_count: usize,
+) {
+    unsafe { collect_ffi(cmd, request_id, args, args_count) }
+}
+extern "C" fn on_client_cmd(
+    _endpoint: EndpointFFI,
+    cmd: *const c_char,
+    request_id: u64,
+    args: *const *const c_char,
+    args_count: usize,
+) {
+    unsafe { collect_ffi(cmd, request_id, args, args_count) }
+}
+
+#[test]
+fn ffi_commands_round_trip() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server as *const Server as *mut Server;
+    let client = &harness.client(0).client as *const Client as *mut Client;
+    let ping = CString::new("ping").unwrap();
+    let pong = CString::new("pong").unwrap();
+    let reserved = CString::new(OmgppPredefinedCmd::AUTH).unwrap();
+    let arg = CString::new("42").unwrap();
+    let args = [arg.as_ptr()];
+    let uuid = harness.client_uuid(0);
+    let uuid = UuidFFI {
+        bytes: uuid.into_bytes(),
+    };
+    unsafe {
+        assert!(server::ffi::server_register_cmd(server, ping.as_ptr(), true, on_server_cmd));
+        assert!(!server::ffi::server_register_cmd(server, reserved.as_ptr(), true, on_server_cmd));
+        assert!(client::ffi::client_register_cmd(client, pong.as_ptr(), on_client_cmd));
+        assert!(client::ffi::client_send_cmd(client, ping.as_ptr(), 5, args.as_ptr(), 1));
+        assert!(server::ffi::server_send_cmd(server, &uuid, pong.as_ptr(), 6, std::ptr::null(), 0));
+    }
+    let received = |count| FFI_COMMANDS.with(|commands| commands.borrow().len() == count);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| received(2)));
+    let mut commands = FFI_COMMANDS.with(|commands| commands.borrow().clone());
+    commands.sort();
+    assert_eq!(
+        commands,
+        vec![
+            ("ping".to_string(), 5, vec!["42".to_string()]),
+            ("pong".to_string(), 6, vec![]),
+        ]
+    );
+    unsafe {
+        assert!(server::ffi::server_unregister_cmd(server, ping.as_ptr()));
+        assert!(client::ffi::client_unregister_cmd(client, pong.as_ptr()));
+    }
+}
+
+#[test]
+fn help_lists_commands_for_the_caller() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let replies = harness.record_client_cmds(0).unwrap();
+    let server = &harness.server.server;
+    server.register_cmd_handler(echo_handler("echo")).unwrap();
+    server.register_cmd_handler(echo_handler("kick").with_roles(&["admin"])).unwrap();
+    let schema = CmdSchema::new("Repeat a text").arg(ArgSpec::new("times", ArgType::Int).with_description("How often"));
+    server
+        .register_cmd_handler(CmdHandler::typed(
+            "repeat",
+            true,
+            schema,
+            Box::new(|server: &Server, uuid, _endpoint, request: &CmdRequest, args| {
+                let times = args.int("times").unwrap().to_string();
+                _ = server.send_command(uuid, request.cmd.clone(), request.request_id, Some(vec![times]));
+            }),
+        ))
+        .unwrap();
+
+    let client = &harness.client(0).client;
+    client.send_cmd(OmgppPredefinedCmd::HELP, 1, None).unwrap();
+    client.send_cmd(OmgppPredefinedCmd::HELP, 2, Some(vec!["repeat".to_string()])).unwrap();
+    client.send_cmd("repeat", 3, Some(vec!["twice".to_string()])).unwrap();
+    client.send_cmd("repeat", 4, Some(vec!["2".to_string()])).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| replies.len() == 4));
+
+    let replies = replies.all();
+    let listed = &replies.iter().find(|reply| reply.1 == 1).unwrap().2;
+    assert!(listed.contains(&"echo".to_string()));
+    assert!(listed.contains(&"repeat <times:int> - Repeat a text".to_string()));
+    assert!(!listed.contains(&"kick".to_string()));
+    let described = &replies.iter().find(|reply| reply.1 == 2).unwrap().2;
+    assert_eq!(described, &vec!["repeat <times:int>", "Repeat a text", "  times: How often"]);
+    let invalid = &replies.iter().find(|reply| reply.1 == 3).unwrap().2;
+    assert_eq!(invalid[0], "error");
+    assert!(invalid[1].ends_with("Usage: repeat <times:int>"));
+    assert_eq!(replies.iter().find(|reply| reply.1 == 4).unwrap().2, vec!["2"]);
+}
@@ -0,0 +1,168 @@
+use std::{cell::RefCell, rc::Rc};
+
+use client_server::testing::{ClientEvent, ServerEvent, TestHarness};
+use omgpp_core::{
+    cmd_handler::CmdHandler,
+    interceptor::{Direction, Intercept},
+    messages::general_message::{
+        general_omgpp_message::{CmdRequest, Data},
+        GeneralOmgppMessage,
+    },
+};
+
+fn message_type(message: &GeneralOmgppMessage) -> Option<i64> {
+    match &message.data {
+        Some(Data::Message(message)) => Some(message.type_),
+        _ => None,
+    }
+}
+
+#[test]
+fn inbound_interceptors_run_in_order_and_can_modify() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let server = &harness.server.server;
+    let log = seen.clone();
+    server
+        .add_interceptor("first", move |_server, context, message| {
+            // clients also send time sync requests, only look at regular messages
+            if context.direction == Direction::Inbound && message_type(message).is_some() {
+                log.borrow_mut().push(("first", message_type(message)));
+                if let Some(Data::Message(message)) = &mut message.data {
+                    message.type_ += 100;
+                }
+            }
+            Intercept::Continue
+        })
+        .unwrap();
+    let log = seen.clone();
+    server
+        .add_interceptor("second", move |_server, context, message| {
+            if context.direction == Direction::Inbound && message_type(message).is_some() {
+                log.borrow_mut().push(("second", message_type(message)));
+            }
+            Intercept::Continue
+        })
+        .unwrap();
+    assert!(server.add_interceptor("first", |_, _, _| Intercept::Continue).is_err());
+
+    harness.client(0).client.send_reliable(1, b"data").unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 101, _)))
+    }));
+    assert_eq!(*seen.borrow(), vec![("first", Some(1)), ("second", Some(101))]);
+
+    server.remove_interceptor("first").unwrap();
+    assert!(server.remove_interceptor("first").is_err());
+}
+
+#[test]
+fn handled_and_rejected_inbound_messages_are_not_dispatched() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    server
+        .register_cmd_handler(CmdHandler::new(
+            "throttled",
+            true,
+            Box::new(|_, _, _, _, _: &CmdRequest| panic!("rejected command was dispatched")),
+        ))
+        .unwrap();
+    server
+        .add_interceptor("filter", |_server, context, message| match &message.data {
+            _ if context.direction == Direction::Outbound => Intercept::Continue,
+            Some(Data::Message(message)) if message.type_ == 2 => Intercept::Handled,
+            Some(Data::Cmd(cmd)) if cmd.cmd == "throttled" => Intercept::Reject("Slow down".to_string()),
+            _ => Intercept::Continue,
+        })
+        .unwrap();
+    let replies = Rc::new(RefCell::new(Vec::new()));
+    let log = replies.clone();
+    harness
+        .client(0)
+        .client
+        .register_cmd_fallback(Some(CmdHandler::new(
+            "",
+            false,
+            Box::new(move |_, _, _, _, request: &CmdRequest| log.borrow_mut().push(request.args.clone())),
+        )))
+        .unwrap();
+
+    let client = &harness.client(0).client;
+    client.send_reliable(2, b"handled").unwrap();
+    client.send_cmd("throttled", 1, None).unwrap();
+    client.send_reliable(3, b"passes").unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        !replies.borrow().is_empty()
+            && harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 3, _)))
+    }));
+    assert_eq!(replies.borrow()[0], vec!["error", "Slow down"]);
+    assert!(!harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 2, _))));
+}
+
+#[test]
+fn outbound_interceptors_filter_per_client() {
+    let harness = TestHarness::start_loopback_connected(2).unwrap();
+    let server = &harness.server.server;
+    let muted = harness.client_uuid(0);
+    server
+        .add_interceptor("mute", move |_server, context, message| {
+            match (context.direction, message_type(message)) {
+                (Direction::Outbound, Some(_)) if *context.peer == muted => Intercept::Handled,
+                (Direction::Outbound, Some(13)) => Intercept::Reject("Unlucky".to_string()),
+                _ => Intercept::Continue,
+            }
+        })
+        .unwrap();
+
+    server.broadcast_reliable(5, b"news").unwrap();
+    assert_eq!(server.send_reliable(&muted, 6, b"direct"), Ok(()));
+    let other = harness.client_uuid(1);
+    assert_eq!(server.send_reliable(&other, 13, b"x"), Err("Unlucky".to_string()));
+
+    let received = |index: usize| {
+        harness
+            .client(index)
+            .events
+            .any(|event| matches!(event, ClientEvent::Message(5, _)))
+    };
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| received(0) || received(1)));
+    harness.pump_for(std::time::Duration::from_millis(10));
+    assert_eq!(harness.server.server.active_clients().len(), 2);
+    assert!(received(0) != received(1));
+    for index in 0..2 {
+        assert!(!harness.client(index).events.any(|event| matches!(event, ClientEvent::Message(6 | 13, _))));
+    }
+}
+
+#[test]
+fn client_interceptors_see_both_directions() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let directions = Rc::new(RefCell::new(Vec::new()));
+    let log = directions.clone();
+    let client = &harness.client(0).client;
+    client
+        .add_interceptor("log", move |_client, context, message| {
+            if message_type(message).is_some() {
+                log.borrow_mut().push(context.direction);
+            }
+            match message_type(message) {
+                Some(9) => Intercept::Reject("Not allowed".to_string()),
+                _ => Intercept::Continue,
+            }
+        })
+        .unwrap();
+
+    assert_eq!(client.send_reliable(9, b""), Err("Not allowed".to_string()));
+    client.send_reliable(1, b"up").unwrap();
+    let uuid = harness.client_uuid(0);
+    harness.server.server.send_reliable(&uuid, 2, b"down").unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).events.any(|event| matches!(event, ClientEvent::Message(2, _)))
+            && harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 1, _)))
+    }));
+    assert!(!harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 9, _))));
+    assert_eq!(
+        *directions.borrow(),
+        vec![Direction::Outbound, Direction::Outbound, Direction::Inbound]
+    );
+}
@@ -0,0 +1,149 @@
+use client_server::{
+    server::interest::{GridFilter, InterestFilter, Position, RadiusFilter, Relevance},
+    testing::{ClientEvent, TestHarness},
+};
+use uuid::Uuid;
+
+fn clients(harness: &TestHarness) -> Vec<Uuid> {
+    let mut clients = harness
+        .server
+        .server
+        .active_clients()
+        .into_iter()
+        .map(|(uuid, _)| uuid)
+        .collect::<Vec<_>>();
+    clients.sort();
+    clients
+}
+
+fn seeing(harness: &TestHarness, clients: usize, entity: u64) -> usize {
+    (0..clients)
+        .filter(|index| harness.client(*index).client.entity(entity).is_some())
+        .count()
+}
+
+#[test]
+fn radius_filter_keeps_relevant_entities_within_hysteresis() {
+    let filter = RadiusFilter::new(10.0, 2.0);
+    let viewer = Position::new(0.0, 0.0, 0.0);
+    assert!(filter.is_relevant(&viewer, &Position::new(6.0, 8.0, 0.0), false));
+    assert!(!filter.is_relevant(&viewer, &Position::new(11.0, 0.0, 0.0), false));
+    assert!(filter.is_relevant(&viewer, &Position::new(11.0, 0.0, 0.0), true));
+    assert!(!filter.is_relevant(&viewer, &Position::new(12.5, 0.0, 0.0), true));
+}
+
+#[test]
+fn grid_filter_compares_cells() {
+    let filter = GridFilter::new(10.0, 1, 1);
+    let viewer = Position::new(5.0, 5.0, 0.0);
+    assert_eq!(filter.cell(&Position::new(-0.5, 19.0, 0.0)), (-1, 1, 0));
+    assert!(filter.is_relevant(&viewer, &Position::new(-5.0, 19.0, 0.0), false));
+    assert!(!filter.is_relevant(&viewer, &Position::new(21.0, 5.0, 0.0), false));
+    assert!(filter.is_relevant(&viewer, &Position::new(21.0, 5.0, 0.0), true));
+    assert!(!filter.is_relevant(&viewer, &Position::new(31.0, 5.0, 0.0), true));
+}
+
+#[test]
+fn entities_are_replicated_to_clients_in_range() {
+    let harness = TestHarness::start_loopback_connected(2).unwrap();
+    let server = &harness.server.server;
+    server.set_interest_filter(Some(Box::new(RadiusFilter::new(10.0, 0.0))));
+    let clients = clients(&harness);
+    server.set_client_position(&clients[0], Some(Position::new(0.0, 0.0, 0.0)));
+    server.set_client_position(&clients[1], Some(Position::new(100.0, 0.0, 0.0)));
+
+    let near = server.spawn_entity(1);
+    server.set_entity_position(near, Position::new(5.0, 0.0, 0.0)).unwrap();
+    let global = server.spawn_entity(1);
+    harness.replicate().unwrap();
+    assert_eq!(seeing(&harness, 2, near), 1);
+    assert_eq!(seeing(&harness, 2, global), 2);
+    assert!(server.is_entity_visible(&clients[0], near));
+    assert!(!server.is_entity_visible(&clients[1], near));
+
+    // moving into the range of the other client despawns it on the first one
+    server.set_entity_position(near, Position::new(95.0, 0.0, 0.0)).unwrap();
+    harness.replicate().unwrap();
+    assert_eq!(seeing(&harness, 2, near), 1);
+    assert!(server.is_entity_visible(&clients[1], near));
+    assert!(!server.is_entity_visible(&clients[0], near));
+}
+
+#[test]
+fn hysteresis_avoids_flicker_at_the_boundary() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    server.set_interest_filter(Some(Box::new(RadiusFilter::new(10.0, 2.0))));
+    server.set_client_position(&clients(&harness)[0], Some(Position::new(0.0, 0.0, 0.0)));
+    let entity = server.spawn_entity(1);
+    server.set_component(entity, 1, b"state").unwrap();
+
+    server.set_entity_position(entity, Position::new(11.0, 0.0, 0.0)).unwrap();
+    harness.replicate().unwrap();
+    assert!(client.entity(entity).is_none());
+    server.set_entity_position(entity, Position::new(9.0, 0.0, 0.0)).unwrap();
+    harness.replicate().unwrap();
+    assert!(client.entity(entity).is_some());
+    server.set_entity_position(entity, Position::new(11.0, 0.0, 0.0)).unwrap();
+    harness.replicate().unwrap();
+    assert!(client.entity(entity).is_some());
+    server.set_entity_position(entity, Position::new(13.0, 0.0, 0.0)).unwrap();
+    harness.replicate().unwrap();
+    assert!(client.entity(entity).is_none());
+
+    // coming back sends the whole entity again
+    server.set_entity_position(entity, Position::new(0.0, 0.0, 0.0)).unwrap();
+    harness.replicate().unwrap();
+    assert_eq!(client.entity(entity).unwrap().component(1), Some(&b"state"[..]));
+}
+
+#[test]
+fn tagged_entities_need_a_subscription() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = clients(&harness)[0];
+    let entity = server.spawn_entity(1);
+    server.set_entity_relevance(entity, Relevance::tagged("team_red")).unwrap();
+    assert!(server.set_entity_relevance(entity + 1, Relevance::everywhere()).is_err());
+    harness.replicate().unwrap();
+    assert!(harness.client(0).client.entity(entity).is_none());
+
+    server.subscribe_tag(&client, "team_red");
+    harness.replicate().unwrap();
+    assert!(harness.client(0).client.entity(entity).is_some());
+    assert!(server.unsubscribe_tag(&client, "team_red"));
+    harness.replicate().unwrap();
+    assert!(harness.client(0).client.entity(entity).is_none());
+}
+
+#[test]
+fn broadcasts_only_reach_relevant_clients() {
+    let harness = TestHarness::start_loopback_connected(3).unwrap();
+    let server = &harness.server.server;
+    server.set_interest_filter(Some(Box::new(GridFilter::new(16.0, 1, 0))));
+    let clients = clients(&harness);
+    server.set_client_position(&clients[0], Some(Position::new(0.0, 0.0, 0.0)));
+    server.set_client_position(&clients[1], Some(Position::new(20.0, 0.0, 0.0)));
+    server.set_client_position(&clients[2], Some(Position::new(200.0, 0.0, 0.0)));
+    server.subscribe_tag(&clients[2], "chat");
+
+    let explosion = Relevance::at(Position::new(10.0, 0.0, 0.0));
+    assert_eq!(server.relevant_clients(&explosion).len(), 2);
+    server.broadcast_relevant_reliable(&explosion, 5, b"boom").unwrap();
+    server.broadcast_relevant_reliable(&Relevance::tagged("chat"), 6, b"hi").unwrap();
+    harness.pump_for(TestHarness::DEFAULT_TIMEOUT / 10);
+
+    let received = |msg_type: i64| {
+        (0..3)
+            .filter(|index| {
+                harness
+                    .client(*index)
+                    .events
+                    .any(|event| matches!(event, ClientEvent::Message(t, _) if *t == msg_type))
+            })
+            .count()
+    };
+    assert_eq!(received(5), 2);
+    assert_eq!(received(6), 1);
+}
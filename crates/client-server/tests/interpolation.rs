@@ -0,0 +1,79 @@
+use std::{cell::RefCell, rc::Rc};
+
+use client_server::{
+    client::interpolation::{InterpolationBuffer, SampleKind},
+    testing::{millis, TestHarness},
+};
+
+fn buffer() -> InterpolationBuffer<[f32; 2]> {
+    let mut buffer = InterpolationBuffer::new(millis(100)).with_max_extrapolation(millis(50));
+    buffer.push(millis(1_000), [0.0, 10.0]);
+    // out of order
+    buffer.push(millis(1_100), [10.0, 10.0]);
+    buffer.push(millis(1_050), [5.0, 10.0]);
+    buffer
+}
+
+#[test]
+fn states_are_interpolated_behind_server_time() {
+    let mut buffer = buffer();
+    assert_eq!(buffer.len(), 3);
+    let sample = buffer.sample(millis(1_125)).unwrap();
+    assert_eq!(sample.kind, SampleKind::Interpolated);
+    assert_eq!(sample.state, [2.5, 10.0]);
+    let sample = buffer.sample(millis(1_175)).unwrap();
+    assert_eq!(sample.state, [7.5, 10.0]);
+    // the first state is no longer needed
+    assert_eq!(buffer.len(), 2);
+    assert!(!buffer.is_starved());
+    assert_eq!(buffer.buffered_ahead(millis(1_175)), millis(25));
+}
+
+#[test]
+fn states_before_the_buffer_are_held() {
+    let mut buffer = buffer();
+    let sample = buffer.sample(millis(900)).unwrap();
+    assert_eq!(sample.kind, SampleKind::Held);
+    assert_eq!(sample.state, [0.0, 10.0]);
+    assert!(InterpolationBuffer::<f32>::new(millis(100)).sample(millis(900)).is_none());
+}
+
+#[test]
+fn starvation_extrapolates_then_holds() {
+    let mut buffer = buffer();
+    let sample = buffer.sample(millis(1_225)).unwrap();
+    assert_eq!(sample.kind, SampleKind::Extrapolated);
+    assert_eq!(sample.state, [12.5, 10.0]);
+    assert!(buffer.is_starved());
+    let sample = buffer.sample(millis(1_400)).unwrap();
+    assert_eq!(sample.kind, SampleKind::Held);
+    assert_eq!(sample.state, [15.0, 10.0]);
+    assert_eq!(buffer.starvation_count(), 1);
+
+    buffer.push(millis(1_400), [20.0, 10.0]);
+    assert_eq!(buffer.sample(millis(1_450)).unwrap().kind, SampleKind::Interpolated);
+    assert!(!buffer.is_starved());
+    buffer.sample(millis(1_550));
+    assert_eq!(buffer.starvation_count(), 2);
+}
+
+#[test]
+fn messages_carry_the_server_time() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let buffer = Rc::new(RefCell::new(InterpolationBuffer::new(millis(100))));
+    let pushed = buffer.clone();
+    harness.client(0).client.register_on_message(move |client, _endpoint, _msg_type, data| {
+        let server_time = client.message_server_time().unwrap();
+        pushed.borrow_mut().push(server_time, data[0] as f32);
+    });
+    assert_eq!(harness.client(0).client.message_server_time(), None);
+
+    let server = &harness.server.server;
+    for value in 0..3 {
+        server.broadcast(1, &[value * 10]).unwrap();
+        harness.pump_for(millis(50));
+    }
+    let buffer = buffer.borrow();
+    assert_eq!(buffer.len(), 3);
+    assert_eq!(buffer.newest_time(), Some(server.server_time() - millis(50)));
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use client_server::{
+    server::{lag_compensation::LagCompensation, stats::ClientStats},
+    testing::{millis, TestHarness},
+};
+use omgpp_core::{
+    interceptor::{Direction, Intercept},
+    messages::general_message::general_omgpp_message::Data,
+};
+
+fn history() -> LagCompensation<[f32; 2]> {
+    let mut history = LagCompensation::new(millis(200));
+    for tick in 0..10u64 {
+        let x = tick as f32 * 10.0;
+        history.record(tick, millis(tick * 50), 1, [x, 0.0]);
+        if tick >= 5 {
+            history.record(tick, millis(tick * 50), 2, [0.0, x]);
+        }
+    }
+    history
+}
+
+#[test]
+fn states_are_interpolated_between_ticks() {
+    let history = history();
+    assert_eq!(history.state_at(1, millis(375)), Some([75.0, 0.0]));
+    assert_eq!(history.state_at(1, millis(400)), Some([80.0, 0.0]));
+    // entity 2 appeared at tick 5
+    assert_eq!(history.state_at(2, millis(260)), Some([0.0, 52.0]));
+    let states = history.states_at(millis(425));
+    assert_eq!(states.len(), 2);
+    assert_eq!(states[&2], [0.0, 85.0]);
+}
+
+#[test]
+fn rewind_is_bounded() {
+    let history = history();
+    // frames older than 200ms before the newest one are dropped
+    assert_eq!(history.frames().next().unwrap().tick, 5);
+    assert_eq!(history.clamp(millis(0)), Some(millis(250)));
+    assert_eq!(history.state_at(1, millis(0)), Some([50.0, 0.0]));
+    assert_eq!(history.state_at(1, millis(1_000)), Some([90.0, 0.0]));
+    assert_eq!(history.state_at(3, millis(400)), None);
+}
+
+#[test]
+fn view_time_accounts_for_latency_and_interpolation() {
+    let stats = ClientStats {
+        rtt: Some(millis(80)),
+        interpolation_delay: millis(100),
+    };
+    assert_eq!(stats.view_time(millis(1_000)), millis(860));
+    assert_eq!(stats.view_time(millis(100)), Duration::ZERO);
+}
+
+#[test]
+fn unbounded_rewind_keeps_every_frame() {
+    let mut history = LagCompensation::new(Duration::MAX);
+    history.record(1, millis(0), 1, 1.0f32);
+    history.record(2, millis(50), 1, 2.0);
+    assert_eq!(history.frames().count(), 2);
+}
+
+#[test]
+fn reported_interpolation_delay_is_capped() {
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness.add_client(vec![]).unwrap();
+    harness.clients[0].client.set_interpolation_delay(Duration::from_secs(10));
+    assert!(harness.wait_all_connected());
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.server.client_stats(&uuid).is_some()
+    }));
+    harness.pump_for(millis(300));
+    let max = server.settings().max_interpolation_delay;
+    assert_eq!(server.client_view_time(&uuid), Some(server.server_time() - max));
+}
+
+#[test]
+fn clients_report_what_they_see() {
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness.network().unwrap().set_latency(millis(25));
+    harness.add_client(vec![]).unwrap();
+    harness.clients[0].client.set_interpolation_delay(millis(100));
+    assert!(harness.wait_all_connected());
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    // the first request goes before any rtt is known
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.server.client_stats(&uuid).is_some_and(|stats| stats.rtt.is_some())
+    }));
+
+    let stats = server.client_stats(&uuid).unwrap();
+    assert_eq!(stats.interpolation_delay, millis(100));
+    let rtt = stats.rtt.unwrap();
+    assert!(rtt >= millis(50) && rtt <= millis(55), "{:?}", rtt);
+    // the transport ping wins over the reported round trip
+    let view_time = server.client_view_time(&uuid).unwrap();
+    assert_eq!(view_time, server.server_time() - millis(25) - millis(100));
+
+    let mut history = LagCompensation::new(Duration::from_secs(1));
+    history.record(1, view_time, 7, 1.0f32);
+    history.record_now(server, 7, 2.0);
+    assert_eq!(history.state_for(server, &uuid, 7), Some(1.0));
+}
+
+#[test]
+fn made_up_reports_are_corrected() {
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness.network().unwrap().set_latency(millis(25));
+    harness.add_client(vec![]).unwrap();
+    harness.clients[0]
+        .client
+        .add_interceptor("lie", |_client, context, message| {
+            if let (Direction::Outbound, Some(Data::TimeSync(sync))) = (context.direction, &mut message.data) {
+                sync.rtt = u64::MAX;
+                sync.interpolation_delay = u64::MAX;
+            }
+            Intercept::Continue
+        })
+        .unwrap();
+    assert!(harness.wait_all_connected());
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.server.client_stats(&uuid).is_some()
+    }));
+
+    // the loopback ping is twice the latency
+    let stats = server.client_stats(&uuid).unwrap();
+    assert_eq!(stats.rtt, Some(millis(100)));
+    assert_eq!(stats.interpolation_delay, server.settings().max_interpolation_delay);
+}
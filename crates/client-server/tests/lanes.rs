@@ -0,0 +1,174 @@
+use client_server::{
+    server::server_settings::ServerSettings,
+    testing::{ClientEvent, ServerEvent, TestHarness},
+};
+use omgpp_core::connection_config::{ConnectionConfig, Lane};
+
+const GAMEPLAY: i64 = 1;
+const CHAT: i64 = 2;
+const TRADE_RPC: i64 = 7;
+
+fn lanes() -> Vec<Lane> {
+    vec![
+        Lane {
+            priority: 0,
+            weight: 1,
+        },
+        Lane {
+            priority: 1,
+            weight: 3,
+        },
+        Lane {
+            priority: 1,
+            weight: 1,
+        },
+    ]
+}
+
+fn with_lanes(batch_messages: bool) -> TestHarness {
+    let settings = ServerSettings {
+        connection_config: ConnectionConfig {
+            lanes: lanes(),
+            ..Default::default()
+        },
+        batch_messages,
+        ..Default::default()
+    };
+    let harness = TestHarness::start_loopback_with_settings(1, settings).unwrap();
+    assert!(harness.wait_all_connected());
+    harness
+}
+
+#[test]
+fn messages_rpcs_and_commands_go_on_their_lanes() {
+    let harness = with_lanes(false);
+    let server = &harness.server.server;
+    let network = harness.network().unwrap();
+    let uuid = harness.client_uuid(0);
+    server.set_message_lane(CHAT, 2).unwrap();
+    server.set_rpc_lane(TRADE_RPC, 1).unwrap();
+    server.set_cmd_lane("inventory", 1).unwrap();
+
+    server.send_reliable(&uuid, CHAT, b"hello").unwrap();
+    server.send_reliable(&uuid, GAMEPLAY, b"move").unwrap();
+    server.call_rpc(&uuid, true, TRADE_RPC, 1, 0, None).unwrap();
+    server.send_command(&uuid, "inventory".to_string(), 2, None).unwrap();
+    assert_eq!(network.sent_on_lane(1), 2);
+    assert_eq!(network.sent_on_lane(2), 1);
+
+    let client = harness.client(0);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| {
+        client.events.count(|event| matches!(event, ClientEvent::Message(..) | ClientEvent::Rpc { .. })) == 3
+    }));
+    // lane 0 restores the default
+    server.set_message_lane(CHAT, 0).unwrap();
+    server.send_reliable(&uuid, CHAT, b"again").unwrap();
+    assert_eq!(network.sent_on_lane(2), 1);
+}
+
+#[test]
+fn lanes_have_to_be_configured() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    assert!(server.set_message_lane(CHAT, 1).is_err());
+    assert!(server.set_cmd_lane("inventory", 1).is_err());
+    assert!(server.set_message_lane(CHAT, 0).is_ok());
+    assert!(harness.client(0).client.set_rpc_lane(TRADE_RPC, 1).is_err());
+    assert!(with_lanes(false).server.server.set_message_lane(CHAT, 3).is_err());
+}
+
+#[test]
+fn client_lanes_are_configured_on_connect() {
+    let mut harness = TestHarness::start_loopback_connected(1).unwrap();
+    let network = harness.network().unwrap().clone();
+    harness.client(0).client.disconnect();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| harness.server.connected_clients() == 0));
+    let client = &mut harness.clients[0].client;
+    client.connection_config_mut().lanes = lanes();
+    client.connect().unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| harness.server.connected_clients() == 1));
+
+    let client = &harness.client(0).client;
+    client.set_message_lane(CHAT, 2).unwrap();
+    client.send_reliable(CHAT, b"hello").unwrap();
+    assert_eq!(network.sent_on_lane(2), 1);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness
+            .server
+            .events
+            .any(|event| matches!(event, ServerEvent::Message(_, CHAT, data) if data == b"hello"))
+    }));
+}
+
+#[test]
+fn lanes_configured_later_apply_to_existing_connections() {
+    let mut harness = TestHarness::start_loopback_connected(1).unwrap();
+    let network = harness.network().unwrap().clone();
+    harness.server.server.connection_config_mut().lanes = lanes();
+    harness.clients[0].client.set_connection_config(ConnectionConfig {
+        lanes: lanes(),
+        ..Default::default()
+    });
+
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    server.set_message_lane(CHAT, 2).unwrap();
+    server.send_reliable(&uuid, CHAT, b"hello").unwrap();
+    let client = &harness.client(0).client;
+    client.set_message_lane(CHAT, 1).unwrap();
+    client.send_reliable(CHAT, b"hi").unwrap();
+    assert_eq!(network.sent_on_lane(1), 1);
+    assert_eq!(network.sent_on_lane(2), 1);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).events.any(|event| matches!(event, ClientEvent::Message(CHAT, data) if data == b"hello"))
+            && harness
+                .server
+                .events
+                .any(|event| matches!(event, ServerEvent::Message(_, CHAT, data) if data == b"hi"))
+    }));
+}
+
+#[test]
+fn single_calls_pick_their_lane() {
+    let harness = with_lanes(false);
+    let server = &harness.server.server;
+    let network = harness.network().unwrap();
+    let uuid = harness.client_uuid(0);
+    server.set_message_lane(CHAT, 2).unwrap();
+
+    server.send_reliable_on_lane(&uuid, 1, CHAT, b"urgent").unwrap();
+    server.send_on_lane(&uuid, 1, GAMEPLAY, b"move").unwrap();
+    server.call_rpc_on_lane(&uuid, 2, true, TRADE_RPC, 1, 0, None).unwrap();
+    assert_eq!(network.sent_on_lane(1), 2);
+    assert_eq!(network.sent_on_lane(2), 1);
+    assert!(server.send_reliable_on_lane(&uuid, 3, CHAT, b"nowhere").is_err());
+    // the mapping is left as it was
+    server.send_reliable(&uuid, CHAT, b"hello").unwrap();
+    assert_eq!(network.sent_on_lane(2), 2);
+
+    // the client has a single lane
+    let client = &harness.client(0).client;
+    assert!(client.send_reliable_on_lane(1, CHAT, b"hi").is_err());
+    assert!(client.call_rpc_on_lane(0, true, TRADE_RPC, 2, 0, None).is_ok());
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).events.count(|event| matches!(event, ClientEvent::Message(..) | ClientEvent::Rpc { .. })) == 4
+    }));
+}
+
+#[test]
+fn batches_do_not_mix_lanes() {
+    let harness = with_lanes(true);
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    server.set_message_lane(CHAT, 1).unwrap();
+    server.flush().unwrap();
+
+    let sent = server.stats().messages_sent;
+    for index in 0..3u8 {
+        server.send_reliable(&uuid, GAMEPLAY, &[index]).unwrap();
+        server.send_reliable(&uuid, CHAT, &[index]).unwrap();
+    }
+    server.flush().unwrap();
+    assert_eq!(server.stats().messages_sent - sent, 2);
+    assert_eq!(harness.network().unwrap().sent_on_lane(1), 1);
+}
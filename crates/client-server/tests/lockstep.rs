@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use client_server::testing::{EventLog, TestHarness};
+use omgpp_core::OmgppPredefinedCmd;
+
+fn start_session(players: usize, turn_timeout: Duration) -> (TestHarness, u64) {
+    let harness = TestHarness::start_loopback_connected(players).unwrap();
+    let clients = (0..players)
+        .map(|index| harness.client_uuid(index))
+        .collect::<Vec<_>>();
+    let session = harness
+        .server
+        .server
+        .create_lockstep_session(&clients, turn_timeout)
+        .unwrap();
+    harness.settle();
+    (harness, session)
+}
+
+// index of the test client that plays as `player`
+fn client_of(harness: &TestHarness, player: usize) -> usize {
+    (0..harness.clients.len())
+        .find(|index| harness.client(*index).client.lockstep_player() == Some(player))
+        .unwrap()
+}
+
+#[test]
+fn turns_complete_once_every_player_sent_input() {
+    let (harness, session) = start_session(2, Duration::ZERO);
+    let server = &harness.server.server;
+    let first = &harness.client(client_of(&harness, 0)).client;
+    let second = &harness.client(client_of(&harness, 1)).client;
+    assert_eq!(first.lockstep_session(), Some(session));
+    assert_eq!(first.lockstep_players(), 2);
+
+    // inputs may be sent ahead of the turn
+    first.send_lockstep_input(1, b"a1").unwrap();
+    first.send_lockstep_input(2, b"a2").unwrap();
+    harness.settle();
+    assert!(first.take_lockstep_turn().is_none());
+    assert_eq!(server.lockstep_turn(session), Some(1));
+
+    second.send_lockstep_input(1, b"b1").unwrap();
+    harness.settle();
+    for client in [first, second] {
+        let turn = client.take_lockstep_turn().unwrap();
+        assert_eq!(turn.turn, 1);
+        assert_eq!(turn.inputs, vec![Some(b"a1".to_vec()), Some(b"b1".to_vec())]);
+        assert!(client.take_lockstep_turn().is_none());
+    }
+    assert_eq!(server.lockstep_turn(session), Some(2));
+}
+
+#[test]
+fn timed_out_turns_complete_without_missing_inputs() {
+    let (harness, session) = start_session(2, Duration::from_millis(100));
+    let turns = EventLog::default();
+    let log = turns.clone();
+    harness
+        .server
+        .server
+        .register_on_lockstep_turn(move |_server, turn| log.push(turn.clone()));
+    let first = &harness.client(client_of(&harness, 0)).client;
+
+    first.send_lockstep_input(1, b"a1").unwrap();
+    harness.settle();
+    assert!(turns.all().is_empty());
+    harness.pump_for(Duration::from_millis(100));
+    let turn = first.take_lockstep_turn().unwrap();
+    assert_eq!(turn.session, session);
+    assert_eq!(turn.inputs, vec![Some(b"a1".to_vec()), None]);
+    assert_eq!(turns.all(), vec![turn]);
+    // input for a completed turn comes too late
+    harness.client(client_of(&harness, 1)).client.send_lockstep_input(1, b"b1").unwrap();
+    harness.settle();
+    assert_eq!(harness.server.server.lockstep_turn(session), Some(2));
+}
+
+#[test]
+fn disconnected_players_are_not_waited_for() {
+    let (harness, session) = start_session(2, Duration::ZERO);
+    let server = &harness.server.server;
+    let second = client_of(&harness, 1);
+    let first = &harness.client(client_of(&harness, 0)).client;
+    harness.client(second).client.disconnect();
+    harness.settle();
+    assert_eq!(server.lockstep_session_of(&server.lockstep_players(session)[1]), None);
+
+    first.send_lockstep_input(1, b"a1").unwrap();
+    harness.settle();
+    let turn = first.take_lockstep_turn().unwrap();
+    assert_eq!(turn.inputs, vec![Some(b"a1".to_vec()), None]);
+}
+
+#[test]
+fn diverging_checksums_name_the_client() {
+    let (harness, session) = start_session(3, Duration::ZERO);
+    let server = &harness.server.server;
+    let desyncs = EventLog::default();
+    let log = desyncs.clone();
+    server.register_on_desync(move |_server, session, turn, client| log.push((session, turn, client.copied())));
+    for player in 0..3 {
+        let client = &harness.client(client_of(&harness, player)).client;
+        client.send_lockstep_input(1, &[]).unwrap();
+        client.send_lockstep_input(2, &[]).unwrap();
+    }
+    harness.settle();
+    assert_eq!(server.lockstep_turn(session), Some(3));
+
+    for player in 0..3 {
+        let client = &harness.client(client_of(&harness, player)).client;
+        client.send_lockstep_checksum(1, 0xfeed).unwrap();
+        let checksum = if player == 1 { 0xbad } else { 0xfeed };
+        client.send_lockstep_checksum(2, checksum).unwrap();
+    }
+    harness.settle();
+    let diverged = server.lockstep_players(session)[1];
+    assert_eq!(desyncs.all(), vec![(session, 2, Some(diverged))]);
+
+    // a client plays in one session at a time
+    assert!(server.create_lockstep_session(&[diverged], Duration::ZERO).is_err());
+    server.end_lockstep_session(session).unwrap();
+    assert!(server.create_lockstep_session(&[diverged], Duration::ZERO).is_ok());
+}
+
+#[test]
+fn two_diverging_players_desync_the_session() {
+    let (harness, session) = start_session(2, Duration::ZERO);
+    let server = &harness.server.server;
+    let desyncs = EventLog::default();
+    let log = desyncs.clone();
+    server.register_on_desync(move |_server, session, turn, client| log.push((session, turn, client.copied())));
+    for player in 0..2 {
+        let client = &harness.client(client_of(&harness, player)).client;
+        client.send_lockstep_input(1, &[]).unwrap();
+    }
+    harness.settle();
+    for player in 0..2 {
+        let client = &harness.client(client_of(&harness, player)).client;
+        client.send_lockstep_checksum(1, 0xfeed + player as u64).unwrap();
+    }
+    harness.settle();
+    assert_eq!(desyncs.all(), vec![(session, 1, None)]);
+}
+
+#[test]
+fn clients_learn_that_the_session_ended() {
+    let (harness, session) = start_session(2, Duration::ZERO);
+    for player in 0..2 {
+        harness.client(client_of(&harness, player)).client.send_lockstep_input(1, &[]).unwrap();
+    }
+    harness.settle();
+    harness.server.server.end_lockstep_session(session).unwrap();
+    harness.settle();
+    for index in 0..2 {
+        let client = &harness.client(index).client;
+        assert_eq!(client.lockstep_session(), None);
+        assert!(client.send_lockstep_input(2, &[]).is_err());
+        // turns completed before the end can still be taken
+        assert_eq!(client.take_lockstep_turn().unwrap().turn, 1);
+    }
+}
+
+#[test]
+fn authenticating_again_keeps_the_player_in_the_session() {
+    let (harness, session) = start_session(2, Duration::ZERO);
+    let server = &harness.server.server;
+    let first = &harness.client(client_of(&harness, 0)).client;
+    let second = &harness.client(client_of(&harness, 1)).client;
+    second.send_cmd(OmgppPredefinedCmd::AUTH, 1, None).unwrap();
+    harness.settle();
+    assert_eq!(server.lockstep_session_of(&server.lockstep_players(session)[1]), Some(session));
+
+    first.send_lockstep_input(1, b"a1").unwrap();
+    second.send_lockstep_input(1, b"b1").unwrap();
+    harness.settle();
+    let turn = first.take_lockstep_turn().unwrap();
+    assert_eq!(turn.inputs, vec![Some(b"a1".to_vec()), Some(b"b1".to_vec())]);
+}
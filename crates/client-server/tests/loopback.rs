@@ -0,0 +1,207 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use client_server::{
+    server::server_settings::ServerSettings,
+    testing::{ClientEvent, ServerEvent, TestHarness},
+};
+use omgpp_core::{
+    clock::ManualClock,
+    interceptor::{Direction, Intercept},
+    transport::{loopback::LoopbackNetwork, ConnectionHandle, Connector, SendMode, Transport},
+    ConnectionState, Endpoint,
+};
+
+fn is_disconnected(event: &ServerEvent) -> bool {
+    matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::Disconnected))
+}
+
+#[test]
+fn handshake_completes_without_latency() {
+    let harness = TestHarness::start_loopback(2).unwrap();
+    // connect request, accept and auth reply, one pump each; the clock never moves
+    for _ in 0..3 {
+        harness.pump();
+    }
+    assert_eq!(harness.server.connected_clients(), 2);
+    for client in &harness.clients {
+        assert_eq!(
+            client.events.all(),
+            vec![
+                ClientEvent::ConnectionChanged(ConnectionState::Connecting),
+                ClientEvent::ConnectionChanged(ConnectionState::ConnectedUnverified),
+                ClientEvent::ConnectionChanged(ConnectionState::Connected),
+            ]
+        );
+    }
+}
+
+#[test]
+fn handshake_waits_for_latency() {
+    let mut harness = TestHarness::start_loopback(0).unwrap();
+    harness.network().unwrap().set_latency(Duration::from_millis(50));
+    harness.add_client(vec![]).unwrap();
+
+    // connect request, accept and auth each take one trip
+    harness.pump_for(Duration::from_millis(120));
+    assert_eq!(harness.client(0).state(), ConnectionState::ConnectedUnverified);
+    assert_eq!(harness.server.connected_clients(), 0);
+
+    assert!(harness.wait_all_connected());
+    let elapsed = harness.network().unwrap().clock().elapsed();
+    assert!(elapsed >= Duration::from_millis(150));
+}
+
+#[test]
+fn messages_are_delivered_both_ways() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+
+    harness.client(0).client.send_reliable(7, b"hello").unwrap();
+    let uuid = harness.client_uuid(0);
+    harness.server.server.send_reliable(&uuid, 3, b"welcome").unwrap();
+    harness.pump();
+
+    assert!(harness
+        .server
+        .events
+        .any(|event| matches!(event, ServerEvent::Message(_, 7, data) if data == b"hello")));
+    assert!(harness
+        .client(0)
+        .events
+        .any(|event| matches!(event, ClientEvent::Message(3, data) if data == b"welcome")));
+}
+
+#[test]
+fn reliable_messages_survive_loss() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let network = harness.network().unwrap();
+    network.set_unreliable_loss(50);
+
+    for i in 0..10u8 {
+        harness.client(0).client.send(1, &[i]).unwrap();
+        harness.client(0).client.send_reliable(2, &[i]).unwrap();
+    }
+    harness.pump();
+
+    let unreliable = harness.server.events.count(|event| matches!(event, ServerEvent::Message(_, 1, _)));
+    let reliable = harness
+        .server
+        .events
+        .all()
+        .into_iter()
+        .filter_map(|event| match event {
+            ServerEvent::Message(_, 2, data) => Some(data[0]),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(unreliable, 5);
+    assert_eq!(network.dropped_unreliable(), 5);
+    assert_eq!(reliable, (0..10u8).collect::<Vec<_>>());
+}
+
+#[test]
+fn unverified_connection_expires_after_period() {
+    let settings = ServerSettings {
+        unverified_connection_expire_period: Duration::from_secs(3),
+        ..Default::default()
+    };
+    let mut harness = TestHarness::start_loopback_with_settings(0, settings).unwrap();
+    harness.add_unverified_connection().unwrap();
+    harness.pump();
+    harness.pump();
+    assert!(harness.server.events.any(|event| {
+        matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::ConnectedUnverified))
+    }));
+
+    let clock = harness.network().unwrap().clock().clone();
+    clock.advance(Duration::from_millis(2999));
+    harness.pump();
+    assert!(!harness.server.events.any(is_disconnected));
+
+    clock.advance(Duration::from_millis(2));
+    harness.pump();
+    assert!(harness.server.events.any(is_disconnected));
+    assert_eq!(harness.server.connected_clients(), 0);
+}
+
+#[test]
+fn disconnect_is_reported_to_server() {
+    let harness = TestHarness::start_loopback_connected(2).unwrap();
+
+    harness.client(0).client.disconnect();
+    // messages the client sent before are handled first
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.count(is_disconnected) == 1
+    }));
+    assert_eq!(harness.server.connected_clients(), 1);
+}
+
+#[test]
+fn messages_sent_before_disconnect_are_delivered_first() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+
+    harness.client(0).client.send_reliable(9, b"bye").unwrap();
+    harness.client(0).client.disconnect();
+    harness.pump();
+    harness.pump();
+    let events = harness.server.events.all();
+    let message = events.iter().position(|event| matches!(event, ServerEvent::Message(_, 9, _)));
+    let disconnect = events.iter().position(is_disconnected);
+    assert!(message.unwrap() < disconnect.unwrap());
+}
+
+#[test]
+fn send_to_an_unknown_target_sends_nothing() {
+    let network = LoopbackNetwork::new(ManualClock::new());
+    let endpoint = Endpoint {
+        ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        port: 4000,
+    };
+    let server = network.listen(endpoint).unwrap();
+    let (client, connection) = network.connector().connect(&endpoint, &Default::default()).unwrap();
+
+    let targets = [connection, ConnectionHandle(connection.0 + 100)];
+    assert!(client.send(&targets, SendMode::Reliable, b"partial").is_err());
+    assert_eq!(server.poll_messages(16, &mut |_, _| ()), 0);
+}
+
+#[test]
+fn client_connects_once_until_disconnected() {
+    let mut harness = TestHarness::start_loopback(1).unwrap();
+    // the auth reply is dropped, so the client stays unverified
+    harness
+        .client(0)
+        .client
+        .add_interceptor("drop_inbound", |_client, context, _message| match context.direction {
+            Direction::Inbound => Intercept::Handled,
+            Direction::Outbound => Intercept::Continue,
+        })
+        .unwrap();
+    assert!(harness.clients[0].client.connect().is_err());
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness
+            .client(0)
+            .events
+            .any(|event| *event == ClientEvent::ConnectionChanged(ConnectionState::ConnectedUnverified))
+    }));
+    assert!(harness.clients[0].client.connect().is_err());
+
+    harness.client(0).client.disconnect();
+    harness.clients[0].client.remove_interceptor("drop_inbound").unwrap();
+    harness.clients[0].client.connect().unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| harness.server.connected_clients() == 1));
+    assert!(harness.clients[0].client.connect().is_err());
+}
+
+#[test]
+fn client_reconnects_after_disconnect() {
+    let mut harness = TestHarness::start_loopback_connected(1).unwrap();
+
+    harness.client(0).client.disconnect();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| harness.server.connected_clients() == 0));
+
+    harness.clients[0].client.connect().unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| harness.server.connected_clients() == 1));
+}
@@ -0,0 +1,45 @@
+use client_server::testing::TestHarness;
+use omgpp_core::network_simulation::{NetworkSimulation, SimulatedConditions};
+#[cfg(feature = "gns")]
+use client_server::testing::{millis, ServerEvent};
+
+fn lossy(loss_percent: f32) -> NetworkSimulation {
+    NetworkSimulation::symmetric(SimulatedConditions {
+        loss_percent,
+        ..Default::default()
+    })
+}
+
+#[cfg(feature = "gns")]
+#[test]
+fn simulation_changes_at_runtime() {
+    let mut harness = TestHarness::start(1).unwrap();
+    assert!(harness.wait_all_connected());
+    let received = |harness: &TestHarness, data: &[u8]| {
+        harness
+            .server
+            .events
+            .any(|event| matches!(event, ServerEvent::Message(_, 1, received) if received == data))
+    };
+
+    harness.server.server.set_network_simulation(lossy(100.0)).unwrap();
+    assert_eq!(*harness.server.server.network_simulation(), lossy(100.0));
+    harness.client(0).client.send(1, b"lost").unwrap();
+    harness.pump_for(millis(200));
+    assert!(!received(&harness, b"lost"));
+
+    harness.server.server.set_network_simulation(Default::default()).unwrap();
+    assert!(!harness.server.server.network_simulation().is_enabled());
+    harness.client(0).client.send(1, b"delivered").unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| received(harness, b"delivered")));
+}
+
+#[cfg(not(feature = "gns"))]
+#[test]
+fn simulation_needs_gns() {
+    let mut harness = TestHarness::start_loopback_connected(1).unwrap();
+    assert!(harness.server.server.set_network_simulation(lossy(10.0)).is_err());
+    assert!(harness.clients[0].client.set_network_simulation(lossy(10.0)).is_err());
+    assert!(!harness.server.server.network_simulation().is_enabled());
+    assert!(!harness.client(0).client.network_simulation().is_enabled());
+}
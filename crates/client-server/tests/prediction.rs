@@ -0,0 +1,110 @@
+use std::{cell::RefCell, rc::Rc};
+
+use client_server::{
+    client::prediction::Predictor,
+    server::server_settings::ServerSettings,
+    testing::TestHarness,
+};
+
+#[test]
+fn reconciliation_replays_pending_inputs() {
+    let mut predictor = Predictor::new(0, |position: &i32, step: &i32| position + step);
+    predictor.predict(1, 1);
+    predictor.predict(2, 2);
+    assert_eq!(*predictor.predict(3, 4), 7);
+
+    // the server only processed the first input and corrected the position
+    assert!(predictor.reconcile(10, 1));
+    assert_eq!(*predictor.state(), 16);
+    assert_eq!(predictor.pending(), 2);
+    // an older state arrived late
+    assert!(!predictor.reconcile(0, 0));
+    assert_eq!(*predictor.state(), 16);
+    assert!(predictor.reconcile(17, 3));
+    assert_eq!(*predictor.state(), 17);
+    assert_eq!(predictor.pending(), 0);
+}
+
+#[test]
+fn server_buffers_inputs_for_the_jitter_window() {
+    let settings = ServerSettings {
+        input_jitter_window: 2,
+        ..Default::default()
+    };
+    let harness = TestHarness::start_loopback_with_settings(1, settings).unwrap();
+    assert!(harness.wait_all_connected());
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    let uuid = harness.client_uuid(0);
+
+    assert_eq!(client.send_input(b"a").unwrap(), 1);
+    harness.settle();
+    assert_eq!(server.take_input(&uuid), None);
+    assert_eq!(client.send_input(b"b").unwrap(), 2);
+    harness.settle();
+    // inputs are resent until processed but handed out once
+    assert_eq!(server.buffered_inputs(&uuid), 2);
+    assert_eq!(server.take_input(&uuid), Some((1, b"a".to_vec())));
+    assert_eq!(server.take_inputs(), vec![(uuid, 2, b"b".to_vec())]);
+    assert_eq!(server.last_processed_input(&uuid), 2);
+
+    // ran dry: wait for the window to fill again
+    client.send_input(b"c").unwrap();
+    harness.settle();
+    assert_eq!(server.take_input(&uuid), None);
+    client.send_input(b"d").unwrap();
+    harness.settle();
+    assert_eq!(server.take_input(&uuid).map(|(sequence, _)| sequence), Some(3));
+}
+
+#[test]
+fn redundancy_covers_lost_packets() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    let uuid = harness.client_uuid(0);
+    let network = harness.network().unwrap();
+
+    network.set_unreliable_loss(50);
+    for input in 1..=6u8 {
+        client.send_input(&[input]).unwrap();
+        harness.settle();
+    }
+    assert!(network.dropped_unreliable() >= 3);
+    network.set_unreliable_loss(0);
+    let taken = (0..6).filter_map(|_| server.take_input(&uuid)).collect::<Vec<_>>();
+    let sequences = taken.iter().map(|(sequence, _)| *sequence).collect::<Vec<_>>();
+    assert_eq!(sequences, vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn states_acknowledge_inputs() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    let uuid = harness.client_uuid(0);
+    let predictor = Rc::new(RefCell::new(Predictor::new(0u8, |position: &u8, step: &u8| position + step)));
+    let reconciled = predictor.clone();
+    client.register_on_message(move |client, _endpoint, _msg_type, data| {
+        if let Some(last_input) = client.message_last_input() {
+            reconciled.borrow_mut().reconcile(data[0], last_input);
+        }
+    });
+
+    for step in [1, 2, 3] {
+        let sequence = client.send_input(&[step]).unwrap();
+        predictor.borrow_mut().predict(sequence, step);
+    }
+    harness.settle();
+    assert_eq!(client.unacknowledged_inputs(), 3);
+    // the server simulates the first two inputs
+    let mut position = 0;
+    for _ in 0..2 {
+        position += server.take_input(&uuid).unwrap().1[0];
+    }
+    server.send_reliable(&uuid, 1, &[position]).unwrap();
+    harness.settle();
+    assert_eq!(client.unacknowledged_inputs(), 1);
+    assert_eq!(predictor.borrow().pending(), 1);
+    assert_eq!(*predictor.borrow().state(), 6);
+}
@@ -0,0 +1,140 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Instant,
+};
+
+use client_server::testing::{ClientEvent, ServerEvent, TestHarness};
+use omgpp_core::{
+    connection_config::ConnectionConfig,
+    transport::{
+        quic::{QuicConnector, QuicServerTransport},
+        Connector, Transport, TransportEvent,
+    },
+    ConnectionState,
+};
+
+// whether a client of `connector` completes the handshake with a fresh server
+fn handshake(connector: impl FnOnce(&QuicServerTransport) -> QuicConnector) -> bool {
+    let server = QuicServerTransport::listen(IpAddr::V4(Ipv4Addr::LOCALHOST), 0).unwrap();
+    let connector = connector(&server);
+    let (client, _handle) = connector
+        .connect(&server.local_endpoint().unwrap(), &Default::default())
+        .unwrap();
+    let started = Instant::now();
+    while started.elapsed() < TestHarness::DEFAULT_TIMEOUT {
+        let mut incoming = Vec::new();
+        server.poll();
+        server.poll_events(64, &mut |event| {
+            if let TransportEvent::Connecting(handle, _) = event {
+                incoming.push(handle);
+            }
+        });
+        incoming.into_iter().for_each(|handle| server.accept(handle).unwrap());
+        let mut connected = None;
+        client.poll();
+        client.poll_events(64, &mut |event| match event {
+            TransportEvent::Connected(..) => connected = Some(true),
+            TransportEvent::Disconnected(..) => connected = Some(false),
+            TransportEvent::Connecting(..) => (),
+        });
+        if let Some(connected) = connected {
+            return connected;
+        }
+    }
+    panic!("handshake neither completed nor failed");
+}
+
+#[test]
+fn quic_client_passes_auth() {
+    let harness = TestHarness::start_quic(2).unwrap();
+    assert!(harness.wait_all_connected());
+    assert_eq!(harness.server.connected_clients(), 2);
+}
+
+#[test]
+fn quic_reliable_and_unreliable_messages_are_delivered() {
+    let harness = TestHarness::start_quic(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness.client(0).client.send(1, b"datagram").unwrap();
+    harness.client(0).client.send_reliable(2, b"stream").unwrap();
+    // bigger than a datagram, goes over the stream
+    harness.client(0).client.send(3, &vec![7u8; 64 * 1024]).unwrap();
+    let uuid = harness.client_uuid(0);
+    harness.server.server.call_rpc(&uuid, true, 11, 5, 0, Some(b"rpc")).unwrap();
+
+    let received = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 1, data) if data == b"datagram"))
+            && harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 2, data) if data == b"stream"))
+            && harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 3, data) if data.len() == 64 * 1024))
+            && harness.client(0).events.any(|event| matches!(event, ClientEvent::Rpc { method_id: 11, request_id: 5, .. }))
+    });
+    assert!(received);
+}
+
+#[test]
+fn quic_reliable_messages_keep_order() {
+    let harness = TestHarness::start_quic(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    for i in 0..100u8 {
+        harness.client(0).client.send_reliable(4, &[i]).unwrap();
+    }
+    let received = |harness: &TestHarness| {
+        harness
+            .server
+            .events
+            .all()
+            .into_iter()
+            .filter_map(|event| match event {
+                ServerEvent::Message(_, 4, data) => Some(data[0]),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| received(harness).len() == 100));
+    assert_eq!(received(&harness), (0..100u8).collect::<Vec<_>>());
+}
+
+#[test]
+fn quic_disconnect_is_reported_to_server() {
+    let harness = TestHarness::start_quic(2).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness.client(0).client.disconnect();
+    let disconnected = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| {
+            matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::Disconnected))
+        })
+    });
+    assert!(disconnected);
+    assert_eq!(harness.server.connected_clients(), 1);
+}
+
+#[test]
+fn quic_client_verifies_the_server_certificate() {
+    assert!(handshake(|server| QuicConnector::with_trusted_certificate(server.certificate().clone())));
+    let other = QuicServerTransport::listen(IpAddr::V4(Ipv4Addr::LOCALHOST), 0).unwrap();
+    assert!(!handshake(|_server| QuicConnector::with_trusted_certificate(other.certificate().clone())));
+    assert!(handshake(|_server| QuicConnector::dangerous_skip_verification()));
+}
+
+#[test]
+fn quic_connector_needs_a_trusted_certificate() {
+    let server = QuicServerTransport::listen(IpAddr::V4(Ipv4Addr::LOCALHOST), 0).unwrap();
+    let endpoint = server.local_endpoint().unwrap();
+    assert!(QuicConnector::default().connect(&endpoint, &Default::default()).is_err());
+}
+
+#[test]
+fn quic_rejects_gns_connection_options() {
+    let server = QuicServerTransport::listen(IpAddr::V4(Ipv4Addr::LOCALHOST), 0).unwrap();
+    let config = ConnectionConfig {
+        send_rate_max: Some(1024),
+        ..Default::default()
+    };
+    assert!(server.set_accept_config(&config).is_err());
+    assert!(server.set_accept_config(&Default::default()).is_ok());
+    let connector = QuicConnector::with_trusted_certificate(server.certificate().clone());
+    assert!(connector.connect(&server.local_endpoint().unwrap(), &config).is_err());
+}
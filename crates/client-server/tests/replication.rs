@@ -0,0 +1,152 @@
+use std::{cell::RefCell, rc::Rc};
+
+use client_server::testing::TestHarness;
+use omgpp_core::messages::general_message::general_omgpp_message::CmdRequest;
+
+#[test]
+fn entities_and_components_reach_clients() {
+    let harness = TestHarness::start_loopback_connected(2).unwrap();
+    let server = &harness.server.server;
+    let spawned = Rc::new(RefCell::new(Vec::new()));
+    let updated = Rc::new(RefCell::new(Vec::new()));
+    let log = spawned.clone();
+    harness
+        .client(0)
+        .client
+        .register_on_entity_spawned(move |_client, entity| log.borrow_mut().push(entity.clone()));
+    let log = updated.clone();
+    harness
+        .client(0)
+        .client
+        .register_on_entity_updated(move |_client, entity, component_type, data| {
+            log.borrow_mut().push((entity, component_type, data.map(Vec::from)))
+        });
+
+    let entity = server.spawn_entity(7);
+    server.set_component(entity, 1, b"position").unwrap();
+    server.set_component(entity, 2, b"health").unwrap();
+    harness.replicate().unwrap();
+    for index in 0..2 {
+        let replicated = harness.client(index).client.entity(entity).unwrap();
+        assert_eq!(replicated.entity_type, 7);
+        assert_eq!(replicated.component(1), Some(&b"position"[..]));
+        assert_eq!(replicated.component(2), Some(&b"health"[..]));
+    }
+    assert_eq!(spawned.borrow().len(), 1);
+    assert!(updated.borrow().is_empty());
+
+    server.set_component(entity, 1, b"moved").unwrap();
+    // unchanged data is not sent again
+    server.set_component(entity, 2, b"health").unwrap();
+    harness.replicate().unwrap();
+    assert_eq!(*updated.borrow(), vec![(entity, 1, Some(b"moved".to_vec()))]);
+    assert_eq!(harness.client(1).client.entity(entity).unwrap().component(1), Some(&b"moved"[..]));
+}
+
+#[test]
+fn removals_and_despawns_are_replicated() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    let updated = Rc::new(RefCell::new(Vec::new()));
+    let despawned = Rc::new(RefCell::new(Vec::new()));
+    let log = updated.clone();
+    client.register_on_entity_updated(move |_client, entity, component_type, data| {
+        log.borrow_mut().push((entity, component_type, data.is_some()))
+    });
+    let log = despawned.clone();
+    client.register_on_entity_despawned(move |_client, entity| log.borrow_mut().push(entity.id));
+
+    let kept = server.spawn_entity(1);
+    let removed = server.spawn_entity(1);
+    server.set_component(kept, 1, b"a").unwrap();
+    server.set_component(kept, 2, b"b").unwrap();
+    harness.replicate().unwrap();
+    assert_eq!(client.entities().len(), 2);
+
+    server.remove_component(kept, 2).unwrap();
+    server.despawn_entity(removed).unwrap();
+    assert!(server.despawn_entity(removed).is_err());
+    harness.replicate().unwrap();
+    assert_eq!(*updated.borrow(), vec![(kept, 2, false)]);
+    assert_eq!(*despawned.borrow(), vec![removed]);
+    assert_eq!(client.entities(), vec![kept]);
+    assert_eq!(client.entity(kept).unwrap().component(2), None);
+}
+
+#[test]
+fn acknowledged_state_is_not_sent_again() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let entity = server.spawn_entity(1);
+    server.set_component(entity, 1, b"state").unwrap();
+    harness.replicate().unwrap();
+    // let the acknowledgement arrive
+    harness.pump_for(TestHarness::DEFAULT_TIMEOUT / 10);
+
+    let sent = server.stats().messages_sent;
+    server.replicate().unwrap();
+    server.replicate().unwrap();
+    assert_eq!(server.stats().messages_sent, sent);
+}
+
+#[test]
+fn late_clients_get_the_full_state() {
+    let mut harness = TestHarness::start_loopback_connected(1).unwrap();
+    let entity = harness.server.server.spawn_entity(3);
+    harness.server.server.set_component(entity, 1, b"early").unwrap();
+    harness.replicate().unwrap();
+
+    let index = harness.add_client(vec![]).unwrap();
+    assert!(harness.wait_all_connected());
+    // the first client is up to date and gets no snapshot
+    let tick = harness.server.server.replicate().unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(index).client.replication_tick() == Some(tick)
+    }));
+    assert_eq!(harness.client(0).client.replication_tick(), Some(tick - 1));
+    let replicated = harness.client(index).client.entity(entity).unwrap();
+    assert_eq!(replicated.entity_type, 3);
+    assert_eq!(replicated.component(1), Some(&b"early"[..]));
+}
+
+#[test]
+fn lost_snapshots_are_repaired() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    let entity = server.spawn_entity(1);
+    server.set_component(entity, 1, b"first").unwrap();
+    harness.replicate().unwrap();
+
+    let network = harness.network().unwrap();
+    network.set_unreliable_loss(100);
+    server.set_component(entity, 1, b"second").unwrap();
+    let other = server.spawn_entity(2);
+    server.replicate().unwrap();
+    server.replicate().unwrap();
+    harness.pump_for(TestHarness::DEFAULT_TIMEOUT / 10);
+    assert!(network.dropped_unreliable() >= 2);
+    assert_eq!(client.entity(entity).unwrap().component(1), Some(&b"first"[..]));
+
+    // nothing changed since, the next snapshot still carries everything unacknowledged
+    network.set_unreliable_loss(0);
+    harness.replicate().unwrap();
+    assert_eq!(client.entity(entity).unwrap().component(1), Some(&b"second"[..]));
+    assert!(client.entity(other).is_some());
+}
+
+#[test]
+fn typed_components() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let entity = server.spawn_entity(1);
+    let mut component = CmdRequest::new();
+    component.cmd = "name".to_string();
+    server.set_component_typed(entity, &component).unwrap();
+    harness.replicate().unwrap();
+
+    let client = &harness.client(0).client;
+    let replicated = client.entity_component_typed::<CmdRequest>(entity).unwrap().unwrap();
+    assert_eq!(replicated.cmd, "name");
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use client_server::testing::{EventLog, TestHarness};
+
+const LOW: i64 = 1;
+const HIGH: i64 = 2;
+const RELIABLE: i64 = 3;
+
+// 184 bytes of data and the estimated envelope make 200 bytes of budget
+const PAYLOAD: [u8; 184] = [0; 184];
+
+// one client on a connection reported to carry `send_rate` bytes per second
+fn throttled(send_rate: u32) -> (TestHarness, EventLog<i64>) {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    harness.network().unwrap().set_send_rate(Some(send_rate));
+    let received = EventLog::default();
+    let log = received.clone();
+    harness
+        .client(0)
+        .client
+        .register_on_message(move |_client, _endpoint, msg_type, _data| log.push(msg_type));
+    (harness, received)
+}
+
+#[test]
+fn budget_goes_to_higher_priorities_first() {
+    // a burst of 100ms is 1000 bytes, five messages
+    let (harness, received) = throttled(10_000);
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    for _ in 0..10 {
+        server.schedule(&uuid, 1.0, LOW, &PAYLOAD).unwrap();
+    }
+    server.schedule(&uuid, 10.0, HIGH, &PAYLOAD).unwrap();
+    server.schedule(&uuid, 10.0, HIGH, &PAYLOAD).unwrap();
+    server.send_scheduled().unwrap();
+
+    let budget = server.send_budget(&uuid).unwrap();
+    assert_eq!(budget.send_rate, 10_000);
+    assert_eq!(budget.deferred, 7);
+    assert_eq!(budget.available, 0);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| received.count(|_| true) >= 5));
+    assert_eq!(received.all()[..2], [HIGH, HIGH]);
+}
+
+#[test]
+fn reliable_messages_go_out_even_without_budget() {
+    let (harness, received) = throttled(10_000);
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    server.schedule(&uuid, 100.0, HIGH, &PAYLOAD).unwrap();
+    for _ in 0..8 {
+        server.schedule_reliable(&uuid, RELIABLE, &PAYLOAD).unwrap();
+    }
+    server.send_scheduled().unwrap();
+
+    assert_eq!(server.send_budget(&uuid).unwrap().deferred, 1);
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| received.count(|_| true) == 9));
+    // the unreliable message waited until the overdrawn budget recovered
+    assert_eq!(received.all()[..8], [RELIABLE; 8]);
+    assert_eq!(received.all()[8], HIGH);
+}
+
+#[test]
+fn held_back_messages_do_not_starve() {
+    // one message per 100ms, a new high priority one every 100ms would take all of it
+    let (harness, received) = throttled(2_000);
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    server.schedule(&uuid, 1.0, LOW, &PAYLOAD).unwrap();
+    for _ in 0..5 {
+        server.schedule(&uuid, 2.0, HIGH, &PAYLOAD).unwrap();
+        harness.pump_for(Duration::from_millis(100));
+    }
+    assert!(received.any(|msg_type| *msg_type == LOW));
+    assert_eq!(server.send_budget(&uuid).unwrap().dropped, 0);
+}
+
+#[test]
+fn messages_over_the_budget_are_dropped_after_max_defer() {
+    let (harness, received) = throttled(2_000);
+    let server = &harness.server.server;
+    let uuid = harness.client_uuid(0);
+    // larger than a burst, it never fits
+    server.schedule(&uuid, 1.0, LOW, &[0; 500]).unwrap();
+    harness.pump_for(Duration::from_millis(300));
+
+    let budget = server.send_budget(&uuid).unwrap();
+    assert_eq!(budget.dropped, 1);
+    assert_eq!(budget.deferred, 0);
+    assert!(received.all().is_empty());
+    // the budget follows the estimate of the transport
+    assert_eq!(server.connection_status(&uuid).unwrap().send_rate, Some(2_000));
+}
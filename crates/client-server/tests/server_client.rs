@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use client_server::{
+    server::server_settings::ServerSettings,
+    testing::{ClientEvent, ServerEvent, TestHarness},
+};
+use omgpp_core::ConnectionState;
+
+#[test]
+fn client_connects_and_passes_auth() {
+    let harness = TestHarness::start(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    let client_states = harness.client(0).events.all();
+    assert!(client_states.contains(&ClientEvent::ConnectionChanged(ConnectionState::ConnectedUnverified)));
+    assert_eq!(harness.client(0).state(), ConnectionState::Connected);
+
+    let uuid = harness.client_uuid(0);
+    let server_states = harness.server.events.all();
+    assert!(server_states.contains(&ServerEvent::ConnectionChanged(uuid, ConnectionState::Connecting)));
+    assert!(server_states.contains(&ServerEvent::ConnectionChanged(uuid, ConnectionState::ConnectedUnverified)));
+    assert_eq!(harness.server.client_state(&uuid), ConnectionState::Connected);
+}
+
+#[test]
+fn client_message_reaches_server() {
+    let harness = TestHarness::start(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness.client(0).client.send_reliable(7, b"hello").unwrap();
+    let received = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| {
+            matches!(event, ServerEvent::Message(_, 7, data) if data == b"hello")
+        })
+    });
+    assert!(received);
+}
+
+#[test]
+fn server_message_reaches_client() {
+    let harness = TestHarness::start(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    let uuid = harness.client_uuid(0);
+    harness.server.server.send_reliable(&uuid, 3, b"welcome").unwrap();
+    let received = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).events.any(|event| {
+            matches!(event, ClientEvent::Message(3, data) if data == b"welcome")
+        })
+    });
+    assert!(received);
+}
+
+#[test]
+fn rpc_is_delivered_both_ways() {
+    let harness = TestHarness::start(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness
+        .client(0)
+        .client
+        .call_rpc(true, 10, 100, 1, Some(b"ping"))
+        .unwrap();
+    let server_received = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| match event {
+            ServerEvent::Rpc {
+                reliable,
+                method_id,
+                request_id,
+                arg_type,
+                arg_data,
+                ..
+            } => *reliable && *method_id == 10 && *request_id == 100 && *arg_type == 1 && arg_data == b"ping",
+            _ => false,
+        })
+    });
+    assert!(server_received);
+
+    let uuid = harness.client_uuid(0);
+    harness
+        .server
+        .server
+        .call_rpc(&uuid, true, 11, 100, 2, None)
+        .unwrap();
+    let client_received = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).events.any(|event| match event {
+            ClientEvent::Rpc {
+                method_id,
+                request_id,
+                arg_data,
+                ..
+            } => *method_id == 11 && *request_id == 100 && arg_data.is_empty(),
+            _ => false,
+        })
+    });
+    assert!(client_received);
+}
+
+#[test]
+fn broadcast_reaches_every_client() {
+    let harness = TestHarness::start(3).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness.server.server.broadcast_reliable(5, b"news").unwrap();
+    harness
+        .server
+        .server
+        .call_rpc_broadcast(true, 12, 0, 0, None)
+        .unwrap();
+    let received = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.clients.iter().all(|client| {
+            client.events.any(|event| matches!(event, ClientEvent::Message(5, data) if data == b"news"))
+                && client.events.any(|event| matches!(event, ClientEvent::Rpc { method_id: 12, .. }))
+        })
+    });
+    assert!(received);
+}
+
+#[test]
+fn disconnect_is_reported_to_server() {
+    let harness = TestHarness::start(2).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness.client(0).client.disconnect();
+    let disconnected = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| {
+            matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::Disconnected))
+        })
+    });
+    assert!(disconnected);
+    assert_eq!(harness.server.connected_clients(), 1);
+}
+
+#[test]
+fn unverified_connection_expires() {
+    let settings = ServerSettings {
+        unverified_connection_expire_period: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let mut harness = TestHarness::start_with_settings(0, settings).unwrap();
+    harness.add_unverified_connection().unwrap();
+
+    let unverified = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| {
+            matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::ConnectedUnverified))
+        })
+    });
+    assert!(unverified);
+    let expired = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| {
+            matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::Disconnected))
+        })
+    });
+    assert!(expired);
+    assert_eq!(harness.server.connected_clients(), 0);
+}
+
+#[test]
+fn unverified_connection_is_not_listed_as_active() {
+    let settings = ServerSettings {
+        unverified_connection_expire_period: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let mut harness = TestHarness::start_with_settings(1, settings).unwrap();
+    harness.add_unverified_connection().unwrap();
+    assert!(harness.wait_all_connected());
+
+    // the authenticated client passes through `ConnectedUnverified` as well
+    let has_unverified = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.count(|event| {
+            matches!(event, ServerEvent::ConnectionChanged(_, ConnectionState::ConnectedUnverified))
+        }) == 2
+    });
+    assert!(has_unverified);
+    assert_eq!(harness.server.connected_clients(), 1);
+}
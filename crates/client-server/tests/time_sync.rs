@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use client_server::{
+    client::time_sync::TimeSync,
+    testing::{micros, TestHarness},
+};
+
+#[test]
+fn offset_accounts_for_half_the_round_trip() {
+    let mut sync = TimeSync::default();
+    assert_eq!(sync.server_time(micros(0)), None);
+    sync.add_sample(micros(1_000), micros(1_100), micros(5_000));
+    assert_eq!(sync.rtt(), Some(micros(100)));
+    assert_eq!(sync.offset_micros(), Some(3_950));
+    assert_eq!(sync.server_time(micros(2_000)), Some(micros(5_950)));
+}
+
+#[test]
+fn slow_round_trips_do_not_move_the_offset() {
+    let mut sync = TimeSync::default();
+    for index in 0..4 {
+        let sent = index * 10_000;
+        sync.add_sample(micros(sent), micros(sent + 100), micros(sent + 1_050));
+    }
+    assert!(sync.is_warmed_up());
+    assert_eq!(sync.offset_micros(), Some(1_000));
+    // the answer queued somewhere for 10ms
+    sync.add_sample(micros(50_000), micros(60_000), micros(51_050));
+    assert_eq!(sync.offset_micros(), Some(1_000));
+    assert!(sync.rtt().unwrap() > micros(100));
+    // regular samples are smoothed
+    sync.add_sample(micros(70_000), micros(70_100), micros(71_850));
+    assert_eq!(sync.offset_micros(), Some(1_100));
+}
+
+#[test]
+fn client_follows_the_server_clock() {
+    let harness = TestHarness::start_loopback(1).unwrap();
+    harness.network().unwrap().set_latency(Duration::from_millis(20));
+    assert!(harness.wait_all_connected());
+    let client = &harness.client(0).client;
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).client.time_sync().is_warmed_up()
+    }));
+
+    let rtt = client.rtt().unwrap();
+    assert!(rtt >= Duration::from_millis(40) && rtt <= Duration::from_millis(45), "{:?}", rtt);
+    // the client was created after the server, its clock is behind
+    let error = client.server_time().unwrap().abs_diff(harness.server.server.server_time());
+    assert!(error <= Duration::from_millis(3), "{:?}", error);
+    harness.pump_for(Duration::from_secs(3));
+    let error = client.server_time().unwrap().abs_diff(harness.server.server.server_time());
+    assert!(error <= Duration::from_millis(3), "{:?}", error);
+}
+
+#[test]
+fn server_messages_carry_the_tick() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    assert_eq!(client.server_tick(), Some(0));
+
+    server.replicate().unwrap();
+    server.replicate().unwrap();
+    server.send_reliable(&harness.client_uuid(0), 1, b"tick").unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.client(0).client.server_tick() == Some(2)
+    }));
+}
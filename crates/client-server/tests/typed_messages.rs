@@ -0,0 +1,109 @@
+use std::{cell::RefCell, rc::Rc};
+
+use client_server::testing::{ServerEvent, TestHarness};
+use omgpp_core::messages::general_message::general_omgpp_message::{CmdRequest, RpcCall};
+use protobuf::Message;
+use uuid::Uuid;
+
+fn request(cmd: &str) -> CmdRequest {
+    let mut request = CmdRequest::new();
+    request.cmd = cmd.to_string();
+    request
+}
+
+#[test]
+fn typed_messages_are_decoded_on_both_sides() {
+    let harness = TestHarness::start_loopback_connected(2).unwrap();
+    let server_received = Rc::new(RefCell::new(Vec::new()));
+    let log = server_received.clone();
+    harness
+        .server
+        .server
+        .on_message(move |server, uuid, request: CmdRequest| {
+            log.borrow_mut().push((*uuid, request.cmd.clone()));
+            _ = server.broadcast_typed_reliable(&request);
+        })
+        .unwrap();
+    let client_received = Rc::new(RefCell::new(Vec::new()));
+    for index in 0..2 {
+        let log = client_received.clone();
+        harness
+            .client(index)
+            .client
+            .on_message(move |_client, request: CmdRequest| log.borrow_mut().push((index, request.cmd)))
+            .unwrap();
+    }
+
+    harness.client(0).client.send_typed_reliable(&request("jump")).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| client_received.borrow().len() == 2));
+    assert_eq!(server_received.borrow().len(), 1);
+    assert_eq!(server_received.borrow()[0].1, "jump");
+    assert!(client_received.borrow().iter().all(|(_, cmd)| cmd == "jump"));
+    // typed messages don't reach the raw callback
+    assert!(!harness.server.events.any(|event| matches!(event, ServerEvent::Message(..))));
+}
+
+#[test]
+fn explicit_ids_and_raw_fallback() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let client = &harness.client(0).client;
+    server.register_message_with_id::<CmdRequest>(5).unwrap();
+    client.register_message_with_id::<CmdRequest>(5).unwrap();
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let log = received.clone();
+    server
+        .on_message(move |_server, _uuid, request: CmdRequest| log.borrow_mut().push(request.cmd))
+        .unwrap();
+
+    client.send_typed_reliable(&request("typed")).unwrap();
+    // same id, sent as raw bytes
+    client.send_reliable(5, &request("raw").write_to_bytes().unwrap()).unwrap();
+    // a type without handler goes to the raw callback with its stable id
+    client.send_typed_reliable(&RpcCall::new()).unwrap();
+    let rpc_call_id = client.message_type_id::<RpcCall>();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness
+            .server
+            .events
+            .any(|event| matches!(event, ServerEvent::Message(_, msg_type, _) if *msg_type == rpc_call_id))
+    }));
+    assert_eq!(*received.borrow(), vec!["typed".to_string(), "raw".to_string()]);
+}
+
+#[test]
+fn decode_failures_are_reported() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let server = &harness.server.server;
+    let errors = Rc::new(RefCell::new(Vec::new()));
+    let log = errors.clone();
+    server.register_on_message_decode_error(move |_server, uuid, _endpoint, msg_type, reason| {
+        log.borrow_mut().push((*uuid, msg_type, reason.to_string()))
+    });
+    server.on_message(|_server, _uuid, _request: CmdRequest| panic!("must not decode")).unwrap();
+    let id = server.message_type_id::<CmdRequest>();
+
+    harness.client(0).client.send_reliable(id, &[0xff, 0xff, 0xff]).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| !errors.borrow().is_empty()));
+    let (uuid, msg_type, reason) = errors.borrow()[0].clone();
+    assert_ne!(uuid, Uuid::nil());
+    assert_eq!(msg_type, id);
+    assert!(reason.contains("CmdRequest"), "{}", reason);
+}
+
+#[test]
+fn handlers_cannot_be_changed_while_dispatching() {
+    let harness = TestHarness::start_loopback_connected(1).unwrap();
+    let result = Rc::new(RefCell::new(None));
+    let log = result.clone();
+    harness
+        .server
+        .server
+        .on_message(move |server, _uuid, _request: CmdRequest| {
+            *log.borrow_mut() = Some(server.remove_message_handler::<CmdRequest>());
+        })
+        .unwrap();
+    harness.client(0).client.send_typed_reliable(&request("x")).unwrap();
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| result.borrow().is_some()));
+    assert!(result.borrow().clone().unwrap().is_err());
+}
@@ -0,0 +1,202 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use client_server::{
+    client::Client,
+    server::Server,
+    testing::{ClientEvent, EventLog, ServerEvent, TestHarness},
+};
+use omgpp_core::{
+    clock::{ManualClock, SystemClock},
+    transport::{
+        loopback::LoopbackNetwork,
+        multi::MultiTransport,
+        websocket::{WebSocketConnector, WebSocketServerTransport, WebSocketSettings},
+        Connector, SendMode, Transport, TransportEvent,
+    },
+    ConnectionState, Endpoint,
+};
+
+#[test]
+fn websocket_client_passes_auth() {
+    let harness = TestHarness::start_websocket(2).unwrap();
+    assert!(harness.wait_all_connected());
+    assert_eq!(harness.server.connected_clients(), 2);
+}
+
+#[test]
+fn websocket_messages_are_delivered_both_ways() {
+    let harness = TestHarness::start_websocket(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness.client(0).client.send(7, b"unreliable").unwrap();
+    harness.client(0).client.call_rpc(true, 10, 1, 0, Some(b"rpc")).unwrap();
+    let uuid = harness.client_uuid(0);
+    harness.server.server.send_reliable(&uuid, 3, b"welcome").unwrap();
+
+    let received = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.events.any(|event| matches!(event, ServerEvent::Message(_, 7, data) if data == b"unreliable"))
+            && harness.server.events.any(|event| matches!(event, ServerEvent::Rpc { method_id: 10, .. }))
+            && harness.client(0).events.any(|event| matches!(event, ClientEvent::Message(3, data) if data == b"welcome"))
+    });
+    assert!(received);
+}
+
+#[test]
+fn websocket_disconnect_is_reported_to_server() {
+    let harness = TestHarness::start_websocket(1).unwrap();
+    assert!(harness.wait_all_connected());
+
+    harness.client(0).client.disconnect();
+    let disconnected = harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |harness| {
+        harness.server.connected_clients() == 0
+    });
+    assert!(disconnected);
+}
+
+fn connect_client(endpoint: &Endpoint, connector: Box<dyn Connector>) -> (Client, EventLog<ClientEvent>) {
+    let mut client = Client::with_connector(endpoint.ip, endpoint.port, connector);
+    let events = EventLog::default();
+    let log = events.clone();
+    client.register_on_connection_state_changed(move |_client, _endpoint, state| {
+        log.push(ClientEvent::ConnectionChanged(state))
+    });
+    let log = events.clone();
+    client.register_on_message(move |_client, _endpoint, msg_type, data| {
+        log.push(ClientEvent::Message(msg_type, data))
+    });
+    client.connect().unwrap();
+    (client, events)
+}
+
+#[test]
+fn native_and_websocket_clients_share_one_server() {
+    let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let network = LoopbackNetwork::new(ManualClock::new());
+    let native_endpoint = Endpoint { ip, port: 7777 };
+    let native = network.listen(native_endpoint).unwrap();
+    let websocket = WebSocketServerTransport::listen(ip, WebSocketSettings::new(0)).unwrap();
+    let websocket_endpoint = websocket.local_endpoint().unwrap();
+    let transport = MultiTransport::new(vec![Box::new(native), Box::new(websocket)]);
+    let server = Server::with_transport(
+        ip,
+        native_endpoint.port,
+        Box::new(transport),
+        Rc::new(SystemClock),
+        Default::default(),
+    )
+    .unwrap();
+
+    let clients = [
+        connect_client(&native_endpoint, Box::new(network.connector())),
+        connect_client(&websocket_endpoint, Box::new(WebSocketConnector)),
+    ];
+    let pump = || {
+        _ = server.process::<64>();
+        for (client, _) in &clients {
+            _ = client.process::<64>();
+        }
+    };
+    let started = Instant::now();
+    while server.active_clients().len() < 2 && started.elapsed() < TestHarness::DEFAULT_TIMEOUT {
+        pump();
+    }
+    assert_eq!(server.active_clients().len(), 2);
+
+    server.broadcast_reliable(5, b"news").unwrap();
+    let started = Instant::now();
+    let received = |events: &EventLog<ClientEvent>| {
+        events.any(|event| matches!(event, ClientEvent::Message(5, data) if data == b"news"))
+    };
+    while !clients.iter().all(|(_, events)| received(events))
+        && started.elapsed() < TestHarness::DEFAULT_TIMEOUT
+    {
+        pump();
+    }
+    for (_, events) in &clients {
+        assert!(events.any(|event| *event == ClientEvent::ConnectionChanged(ConnectionState::Connected)));
+        assert!(received(events));
+    }
+}
+
+#[test]
+fn clients_of_different_transports_on_one_endpoint_get_their_own_uuid() {
+    let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let endpoint = Endpoint { ip, port: 7777 };
+    // both networks hand out the same client endpoints
+    let networks = [LoopbackNetwork::new(ManualClock::new()), LoopbackNetwork::new(ManualClock::new())];
+    let transport = MultiTransport::new(vec![
+        Box::new(networks[0].listen(endpoint).unwrap()),
+        Box::new(networks[1].listen(endpoint).unwrap()),
+    ]);
+    let server = Server::with_transport(ip, endpoint.port, Box::new(transport), Rc::new(SystemClock), Default::default())
+        .unwrap();
+    let clients = networks.map(|network| connect_client(&endpoint, Box::new(network.connector())));
+    let pump = || {
+        _ = server.process::<64>();
+        for (client, _) in &clients {
+            _ = client.process::<64>();
+        }
+    };
+    let started = Instant::now();
+    while server.active_clients().len() < 2 && started.elapsed() < TestHarness::DEFAULT_TIMEOUT {
+        pump();
+    }
+    let active = server.active_clients();
+    assert_eq!(active.len(), 2);
+    assert_eq!(active[0].1, active[1].1);
+    assert_ne!(active[0].0, active[1].0);
+
+    clients[1].0.disconnect();
+    let started = Instant::now();
+    while server.active_clients().len() > 1 && started.elapsed() < TestHarness::DEFAULT_TIMEOUT {
+        pump();
+    }
+    assert_eq!(server.active_clients().len(), 1);
+    server.broadcast_reliable(5, b"news").unwrap();
+    let started = Instant::now();
+    let received = |events: &EventLog<ClientEvent>| {
+        events.any(|event| matches!(event, ClientEvent::Message(5, data) if data == b"news"))
+    };
+    while !received(&clients[0].1) && started.elapsed() < TestHarness::DEFAULT_TIMEOUT {
+        pump();
+    }
+    assert!(received(&clients[0].1));
+}
+
+#[test]
+fn unreliable_messages_are_dropped_under_congestion() {
+    let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let server = WebSocketServerTransport::listen(ip, WebSocketSettings::new(0)).unwrap();
+    let (client, _client_handle) = WebSocketConnector
+        .connect(&server.local_endpoint().unwrap(), &Default::default())
+        .unwrap();
+
+    let mut connection = None;
+    let started = Instant::now();
+    while connection.is_none() && started.elapsed() < TestHarness::DEFAULT_TIMEOUT {
+        server.poll();
+        client.poll();
+        server.poll_events(16, &mut |event| {
+            if let TransportEvent::Connecting(handle, _) = event {
+                connection = Some(handle);
+            }
+        });
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let connection = connection.unwrap();
+    server.accept(connection).unwrap();
+
+    // the client never reads, so the socket buffers fill up
+    let payload = vec![0u8; 64 * 1024];
+    for _ in 0..256 {
+        server.send(&[connection], SendMode::Unreliable, &payload).unwrap();
+    }
+    assert!(server.dropped_unreliable() > 0);
+    let dropped = server.dropped_unreliable();
+    server.send(&[connection], SendMode::Reliable, &payload).unwrap();
+    assert_eq!(server.dropped_unreliable(), dropped);
+}
@@ -0,0 +1,399 @@
+mod payload;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env, fs,
+    io::{self, Read},
+    net::IpAddr,
+    path::PathBuf,
+    process,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use client_server::{backend, client::Client};
+use omgpp_core::{
+    cmd_handler::CmdHandler,
+    interceptor::{Direction, Intercept},
+    messages::general_message::{general_omgpp_message::Data, GeneralOmgppMessage},
+    ConnectionState,
+};
+use payload::{parse_payload, render, ProtoRegistry};
+
+const USAGE: &str = "Usage: omgpp-cli <ip> <port> [options]
+Options:
+  --auth <arg>              argument of the auth handshake, repeatable
+  --proto <file.proto>      message definitions for `proto:` payloads and --decode, repeatable
+  --decode <kind>:<id>=<Message>
+                            decode payloads of message type (kind `msg`) or rpc method (kind `rpc`) <id>
+  -e <action>               action to run after the handshake, repeatable
+  --script <file>           file with one action per line, `-` reads stdin
+  --connect-timeout <ms>    default 5000
+  --wait <ms>               keep printing received messages after the last action, default 500
+  --trust-cert <file.der>   QUIC builds: only accept the server presenting this certificate
+  --insecure                QUIC builds: accept any server certificate
+Actions:
+  send <type> <payload>             unreliable message
+  send-reliable <type> <payload>    reliable message
+  rpc <method_id> <arg_type> [payload]
+  rpc-unreliable <method_id> <arg_type> [payload]
+  cmd <name> [args...]              waits for the reply and fails on [\"error\", ...]
+  wait <ms>                         print received messages for a while
+Payloads: hex:0a0b0c, utf8:text, proto:<Message> <text format>, or plain text";
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PayloadKind {
+    Message,
+    Rpc,
+}
+
+struct Options {
+    ip: IpAddr,
+    port: u16,
+    auth: Vec<String>,
+    protos: Vec<PathBuf>,
+    decoders: HashMap<(PayloadKind, i64), String>,
+    actions: Vec<String>,
+    connect_timeout: Duration,
+    wait: Duration,
+    certificate: Option<PathBuf>,
+    insecure: bool,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|reason| {
+        eprintln!("{}\n\n{}", reason, USAGE);
+        process::exit(2);
+    });
+    if let Err(reason) = run(options) {
+        eprintln!("error: {}", reason);
+        process::exit(1);
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    if args.len() < 2 {
+        return Err("Missing server address".to_string());
+    }
+    let ip = args[0].parse().map_err(|_| format!("Invalid ip {:?}", args[0]))?;
+    let port = args[1].parse().map_err(|_| format!("Invalid port {:?}", args[1]))?;
+    let mut options = Options {
+        ip,
+        port,
+        auth: Vec::new(),
+        protos: Vec::new(),
+        decoders: HashMap::new(),
+        actions: Vec::new(),
+        connect_timeout: Duration::from_millis(5000),
+        wait: Duration::from_millis(500),
+        certificate: None,
+        insecure: false,
+    };
+    let mut args_iter = args[2..].iter();
+    while let Some(arg) = args_iter.next() {
+        let mut value = || args_iter.next().cloned().ok_or_else(|| format!("Missing value of {}", arg));
+        match &arg[..] {
+            "--auth" => options.auth.push(value()?),
+            "--proto" => options.protos.push(PathBuf::from(value()?)),
+            "--decode" => {
+                let (kind, id, message) = parse_decoder(&value()?)?;
+                options.decoders.insert((kind, id), message);
+            }
+            "-e" => options.actions.push(value()?),
+            "--script" => options.actions.extend(read_script(&value()?)?),
+            "--connect-timeout" => options.connect_timeout = parse_millis(&value()?)?,
+            "--wait" => options.wait = parse_millis(&value()?)?,
+            "--trust-cert" => options.certificate = Some(PathBuf::from(value()?)),
+            "--insecure" => options.insecure = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("Unknown option {:?}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+// `msg:3=chat.Say` or `rpc:7=chat.Request`
+fn parse_decoder(spec: &str) -> Result<(PayloadKind, i64, String), String> {
+    let invalid = || format!("Invalid decoder {:?}, expected <msg|rpc>:<id>=<Message>", spec);
+    let (target, message) = spec.split_once('=').ok_or_else(invalid)?;
+    let (kind, id) = target.split_once(':').ok_or_else(invalid)?;
+    let kind = match kind {
+        "msg" => PayloadKind::Message,
+        "rpc" => PayloadKind::Rpc,
+        _ => return Err(invalid()),
+    };
+    Ok((kind, id.parse().map_err(|_| invalid())?, message.to_string()))
+}
+
+fn parse_millis(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("{:?} is not a number of milliseconds", value))
+}
+
+// Blank lines and lines starting with `#` are skipped
+fn read_script(path: &str) -> Result<Vec<String>, String> {
+    let mut script = String::new();
+    let read = match path {
+        "-" => io::stdin().read_to_string(&mut script).map(|_| ()),
+        _ => fs::read_to_string(path).map(|content| script = content),
+    };
+    read.map_err(|err| format!("Cannot read script {:?}: {}", path, err))?;
+    Ok(script
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let protos = Rc::new(ProtoRegistry::load(&options.protos)?);
+    let decoders = Rc::new(options.decoders);
+    let state = Rc::new(RefCell::new(ConnectionState::None));
+    let replies = Rc::new(RefCell::new(HashMap::<u64, Vec<String>>::new()));
+
+    let connector = match (&options.certificate, options.insecure) {
+        (Some(path), _) => {
+            let certificate = fs::read(path).map_err(|err| format!("Cannot read certificate {:?}: {}", path, err))?;
+            backend::connector_with_certificate(certificate)
+        }
+        (None, true) => backend::dangerous_insecure_connector(),
+        (None, false) => backend::connector(),
+    };
+    let mut client = Client::with_connector(options.ip, options.port, connector);
+    let auth = options.auth.clone();
+    client.register_on_auth(move |_client, _endpoint| auth.clone());
+    let state_cloned = state.clone();
+    client.register_on_connection_state_changed(move |_client, endpoint, new_state| {
+        println!("** {}:{} {:?}", endpoint.ip, endpoint.port, new_state);
+        *state_cloned.borrow_mut() = new_state;
+    });
+    let replies_cloned = replies.clone();
+    let fallback = CmdHandler::new(
+        "",
+        false,
+        Box::new(move |_client: &Client, _uuid, _endpoint, _handler, request| {
+            replies_cloned.borrow_mut().insert(request.request_id, request.args.clone());
+        }),
+    );
+    client.register_cmd_fallback(Some(fallback))?;
+    // prints every message in both directions before the client dispatches or encodes it
+    let protos_cloned = protos.clone();
+    client.add_interceptor("omgpp-cli", move |_client, context, message| {
+        let arrow = match context.direction {
+            Direction::Inbound => "<-",
+            Direction::Outbound => "->",
+        };
+        for line in describe(message, &protos_cloned, &decoders) {
+            println!("{} {}", arrow, line);
+        }
+        Intercept::Continue
+    })?;
+
+    client.connect()?;
+    let started = Instant::now();
+    while *state.borrow() != ConnectionState::Connected {
+        client.process::<128>()?;
+        if *state.borrow() == ConnectionState::Disconnected {
+            return Err("Connection closed during the handshake".to_string());
+        }
+        if started.elapsed() > options.connect_timeout {
+            return Err("Timed out waiting for the handshake".to_string());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let mut next_request_id = 1;
+    for action in &options.actions {
+        run_action(&client, action, &protos, &mut next_request_id, &replies)
+            .map_err(|reason| format!("{:?}: {}", action, reason))?;
+        if *state.borrow() == ConnectionState::Disconnected {
+            return Err("Disconnected by the server".to_string());
+        }
+    }
+    pump(&client, options.wait)?;
+    client.disconnect();
+    Ok(())
+}
+
+fn run_action(
+    client: &Client,
+    action: &str,
+    protos: &ProtoRegistry,
+    next_request_id: &mut u64,
+    replies: &RefCell<HashMap<u64, Vec<String>>>,
+) -> Result<(), String> {
+    let (name, rest) = action.split_once(char::is_whitespace).unwrap_or((action, ""));
+    let rest = rest.trim_start();
+    match name {
+        "send" | "send-reliable" => {
+            let (msg_type, payload) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let msg_type = msg_type.parse().map_err(|_| format!("Invalid message type {:?}", msg_type))?;
+            let data = parse_payload(payload.trim_start(), protos)?;
+            match name {
+                "send" => client.send(msg_type, &data),
+                _ => client.send_reliable(msg_type, &data),
+            }
+        }
+        "rpc" | "rpc-unreliable" => {
+            let mut parts = rest.splitn(3, char::is_whitespace);
+            let method_id = parts.next().unwrap_or_default();
+            let method_id = method_id.parse().map_err(|_| format!("Invalid method id {:?}", method_id))?;
+            let arg_type = parts.next().unwrap_or_default();
+            let arg_type = arg_type.parse().map_err(|_| format!("Invalid arg type {:?}", arg_type))?;
+            let data = parse_payload(parts.next().unwrap_or_default().trim_start(), protos)?;
+            let request_id = take_request_id(next_request_id);
+            client.call_rpc(name == "rpc", method_id, request_id, arg_type, Some(&data))
+        }
+        "cmd" => {
+            let mut args = rest.split_whitespace().map(String::from);
+            let cmd = args.next().ok_or("Missing command name")?;
+            let request_id = take_request_id(next_request_id);
+            client.send_cmd(&cmd, request_id, Some(args.collect()))?;
+            let started = Instant::now();
+            loop {
+                client.process::<128>()?;
+                if let Some(reply) = replies.borrow_mut().remove(&request_id) {
+                    return match reply.first() {
+                        Some(first) if first == "error" => Err(reply[1..].join(" ")),
+                        _ => Ok(()),
+                    };
+                }
+                if started.elapsed() > REPLY_TIMEOUT {
+                    return Err("No reply from the server".to_string());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        "wait" => pump(client, parse_millis(rest)?),
+        _ => Err("Unknown action".to_string()),
+    }
+}
+
+fn take_request_id(next_request_id: &mut u64) -> u64 {
+    let request_id = *next_request_id;
+    *next_request_id += 1;
+    request_id
+}
+
+fn pump(client: &Client, duration: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        client.process::<128>()?;
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+// Envelope fields on the first line, the decoded payload on the next one if a decoder is set
+fn describe(
+    message: &GeneralOmgppMessage,
+    protos: &ProtoRegistry,
+    decoders: &HashMap<(PayloadKind, i64), String>,
+) -> Vec<String> {
+    let (mut lines, decoder) = match &message.data {
+        Some(Data::Message(message)) => (
+            vec![format!("message type={} data={}", message.type_, render(&message.data))],
+            Some((PayloadKind::Message, message.type_, &message.data)),
+        ),
+        Some(Data::Rpc(rpc)) => (
+            vec![format!(
+                "rpc method_id={} request_id={} arg_type={} reliable={} arg_data={}",
+                rpc.method_id,
+                rpc.request_id,
+                rpc.arg_type,
+                rpc.reliable,
+                render(&rpc.arg_data)
+            )],
+            Some((PayloadKind::Rpc, rpc.method_id, &rpc.arg_data)),
+        ),
+        Some(Data::Cmd(cmd)) => (
+            vec![format!("cmd {} request_id={} args={:?}", cmd.cmd, cmd.request_id, cmd.args)],
+            None,
+        ),
+        Some(Data::Snapshot(snapshot)) => (
+            vec![format!(
+                "snapshot tick={} full={} entities={} despawned={:?}",
+                snapshot.tick,
+                snapshot.full,
+                snapshot.entities.len(),
+                snapshot.despawned
+            )],
+            None,
+        ),
+        Some(Data::SnapshotAck(ack)) => (vec![format!("snapshot_ack tick={}", ack.tick)], None),
+        Some(Data::TimeSync(sync)) => (
+            vec![format!(
+                "time_sync client_time={} server_time={} rtt={} interpolation_delay={}",
+                sync.client_time, sync.server_time, sync.rtt, sync.interpolation_delay
+            )],
+            None,
+        ),
+        Some(Data::Inputs(batch)) => (
+            vec![format!(
+                "inputs sequences={:?}",
+                batch.inputs.iter().map(|input| input.sequence).collect::<Vec<_>>()
+            )],
+            None,
+        ),
+        Some(Data::LockstepStart(start)) => (
+            vec![format!(
+                "lockstep_start session={} player={} players={}",
+                start.session, start.player, start.players
+            )],
+            None,
+        ),
+        Some(Data::LockstepInput(input)) => (
+            vec![format!("lockstep_input turn={} data={}", input.turn, render(&input.data))],
+            None,
+        ),
+        Some(Data::LockstepChecksum(checksum)) => (
+            vec![format!("lockstep_checksum turn={} checksum={:#x}", checksum.turn, checksum.checksum)],
+            None,
+        ),
+        Some(Data::LockstepTurn(turn)) => (
+            vec![format!(
+                "lockstep_turn session={} turn={} missing={:?}",
+                turn.session,
+                turn.turn,
+                turn.inputs
+                    .iter()
+                    .filter(|input| input.missing)
+                    .map(|input| input.player)
+                    .collect::<Vec<_>>()
+            )],
+            None,
+        ),
+        Some(Data::LockstepEnd(end)) => (vec![format!("lockstep_end session={}", end.session)], None),
+        _ => (vec!["(empty envelope)".to_string()], None),
+    };
+    if message.server_tick > 0 {
+        lines[0].push_str(&format!(" server_tick={}", message.server_tick));
+    }
+    if message.server_time > 0 {
+        lines[0].push_str(&format!(" server_time={}", message.server_time));
+    }
+    if message.last_input > 0 {
+        lines[0].push_str(&format!(" last_input={}", message.last_input));
+    }
+    if let Some((kind, id, data)) = decoder {
+        if let Some(name) = decoders.get(&(kind, id)) {
+            match protos.decode(name, data) {
+                Ok(text) => lines.push(format!("   {} {{ {} }}", name, text)),
+                Err(reason) => lines.push(format!("   {}", reason)),
+            }
+        }
+    }
+    lines
+}
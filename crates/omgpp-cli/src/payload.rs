@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use protobuf::reflect::{FileDescriptor, MessageDescriptor};
+
+/// Message definitions loaded from `.proto` files, used by `proto:` payloads and `--decode`
+#[derive(Default)]
+pub struct ProtoRegistry {
+    files: Vec<FileDescriptor>,
+}
+impl ProtoRegistry {
+    /// Parse `paths`; imports are resolved relative to the directory of each file
+    pub fn load(paths: &[PathBuf]) -> Result<ProtoRegistry, String> {
+        if paths.is_empty() {
+            return Ok(ProtoRegistry::default());
+        }
+        let mut parser = protobuf_parse::Parser::new();
+        parser.pure();
+        for path in paths {
+            parser.include(path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(".".as_ref()));
+            parser.input(path);
+        }
+        let descriptor_set = parser.file_descriptor_set().map_err(|err| format!("Cannot parse proto: {:#}", err))?;
+        let files = FileDescriptor::new_dynamic_fds(descriptor_set.file, &[])
+            .map_err(|err| format!("Cannot load proto: {}", err))?;
+        Ok(ProtoRegistry { files })
+    }
+    /// Find a message by its full name, e.g. `chat.Say`
+    pub fn message(&self, name: &str) -> Result<MessageDescriptor, String> {
+        let full_name = format!(".{}", name.trim_start_matches('.'));
+        self.files
+            .iter()
+            .find_map(|file| file.message_by_full_name(&full_name))
+            .ok_or_else(|| format!("Message {:?} not found, pass its file with --proto", name))
+    }
+    /// Encode a message written in protobuf text format, e.g. `text: "hi" times: 3`
+    pub fn encode(&self, name: &str, text: &str) -> Result<Vec<u8>, String> {
+        let mut message = self.message(name)?.new_instance();
+        protobuf::text_format::merge_from_str(&mut *message, text)
+            .map_err(|err| format!("Invalid {}: {}", name, err))?;
+        message
+            .write_to_bytes_dyn()
+            .map_err(|err| format!("Cannot encode {}: {}", name, err))
+    }
+    /// Decode `data` and print it in protobuf text format
+    pub fn decode(&self, name: &str, data: &[u8]) -> Result<String, String> {
+        let message = self
+            .message(name)?
+            .parse_from_bytes(data)
+            .map_err(|err| format!("Not a valid {}: {}", name, err))?;
+        Ok(protobuf::text_format::print_to_string(&*message))
+    }
+}
+
+/// Payload given on the command line:
+/// `hex:0a0b0c`, `utf8:some text`, `proto:<Message> <text format>`. Without a prefix the text is sent as UTF-8
+pub fn parse_payload(spec: &str, protos: &ProtoRegistry) -> Result<Vec<u8>, String> {
+    if let Some(hex) = spec.strip_prefix("hex:") {
+        return parse_hex(hex);
+    }
+    if let Some(proto) = spec.strip_prefix("proto:") {
+        let (name, text) = proto.split_once(char::is_whitespace).unwrap_or((proto, ""));
+        return protos.encode(name, text);
+    }
+    Ok(spec.strip_prefix("utf8:").unwrap_or(spec).as_bytes().to_vec())
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits = hex.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+    if digits.len() % 2 != 0 {
+        return Err(format!("Odd number of hex digits in {:?}", hex));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let byte = pair.iter().collect::<String>();
+            u8::from_str_radix(&byte, 16).map_err(|_| format!("Invalid hex byte {:?}", byte))
+        })
+        .collect()
+}
+
+/// Printable UTF-8 is shown as a string, anything else as hex
+pub fn render(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        _ if data.is_empty() => "(empty)".to_string(),
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => format!("utf8 {:?}", text),
+        _ => format!("hex {}", data.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()),
+    }
+}
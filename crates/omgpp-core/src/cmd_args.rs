@@ -0,0 +1,204 @@
+use std::{collections::HashMap, fmt::Display};
+
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgType {
+    Int,
+    Float,
+    Bool,
+    String,
+    Uuid,
+    /// One of the listed values
+    Enum(Vec<String>),
+}
+impl ArgType {
+    pub fn parse(&self, value: &str) -> Result<ArgValue, String> {
+        let parsed = match self {
+            ArgType::Int => value.parse().ok().map(ArgValue::Int),
+            ArgType::Float => value.parse().ok().map(ArgValue::Float),
+            ArgType::Bool => match value.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Some(ArgValue::Bool(true)),
+                "false" | "0" | "no" | "off" => Some(ArgValue::Bool(false)),
+                _ => None,
+            },
+            ArgType::String => Some(ArgValue::String(value.to_string())),
+            ArgType::Uuid => Uuid::parse_str(value).ok().map(ArgValue::Uuid),
+            ArgType::Enum(variants) => variants
+                .iter()
+                .any(|variant| variant == value)
+                .then(|| ArgValue::Enum(value.to_string())),
+        };
+        parsed.ok_or_else(|| format!("expected {}, got {:?}", self, value))
+    }
+}
+impl Display for ArgType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgType::Int => write!(f, "int"),
+            ArgType::Float => write!(f, "float"),
+            ArgType::Bool => write!(f, "bool"),
+            ArgType::String => write!(f, "string"),
+            ArgType::Uuid => write!(f, "uuid"),
+            ArgType::Enum(variants) => write!(f, "{}", variants.join("|")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Uuid(Uuid),
+    Enum(String),
+}
+impl Display for ArgValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgValue::Int(value) => write!(f, "{}", value),
+            ArgValue::Float(value) => write!(f, "{}", value),
+            ArgValue::Bool(value) => write!(f, "{}", value),
+            ArgValue::String(value) | ArgValue::Enum(value) => write!(f, "{}", value),
+            ArgValue::Uuid(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgSpec {
+    pub name: String,
+    pub arg_type: ArgType,
+    pub optional: bool,
+    /// Used when an optional argument is omitted
+    pub default: Option<ArgValue>,
+    pub description: String,
+}
+impl ArgSpec {
+    pub fn new(name: &str, arg_type: ArgType) -> ArgSpec {
+        ArgSpec {
+            name: name.to_string(),
+            arg_type,
+            optional: false,
+            default: None,
+            description: String::new(),
+        }
+    }
+    pub fn optional(mut self) -> ArgSpec {
+        self.optional = true;
+        self
+    }
+    /// Make the argument optional with `default` as its value when omitted
+    pub fn with_default(mut self, default: ArgValue) -> ArgSpec {
+        self.optional = true;
+        self.default = Some(default);
+        self
+    }
+    pub fn with_description(mut self, description: &str) -> ArgSpec {
+        self.description = description.to_string();
+        self
+    }
+    fn usage(&self) -> String {
+        match (&self.default, self.optional) {
+            (Some(default), _) => format!("[{}:{}={}]", self.name, self.arg_type, default),
+            (None, true) => format!("[{}:{}]", self.name, self.arg_type),
+            (None, false) => format!("<{}:{}>", self.name, self.arg_type),
+        }
+    }
+}
+
+/// Positional arguments of a command. Optional arguments have to follow the required ones
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CmdSchema {
+    pub description: String,
+    pub args: Vec<ArgSpec>,
+}
+impl CmdSchema {
+    pub fn new(description: &str) -> CmdSchema {
+        CmdSchema {
+            description: description.to_string(),
+            args: Vec::new(),
+        }
+    }
+    pub fn arg(mut self, arg: ArgSpec) -> CmdSchema {
+        self.args.push(arg);
+        self
+    }
+    /// Validate `args` and convert them to their declared types
+    pub fn parse(&self, args: &[String]) -> Result<CmdArgs, String> {
+        if args.len() > self.args.len() {
+            return Err(format!(
+                "Too many arguments: expected at most {}, got {}",
+                self.args.len(),
+                args.len()
+            ));
+        }
+        let mut values = HashMap::new();
+        for (index, spec) in self.args.iter().enumerate() {
+            let value = match args.get(index) {
+                Some(arg) => spec
+                    .arg_type
+                    .parse(arg)
+                    .map_err(|error| format!("Argument {:?}: {}", spec.name, error))?,
+                None if spec.optional => match &spec.default {
+                    Some(default) => default.clone(),
+                    None => continue,
+                },
+                None => return Err(format!("Missing argument {:?}", spec.name)),
+            };
+            values.insert(spec.name.clone(), value);
+        }
+        Ok(CmdArgs { values })
+    }
+    /// One line usage, e.g. `kick <player:uuid> [reason:string=afk]`
+    pub fn usage(&self, cmd: &str) -> String {
+        std::iter::once(cmd.to_string())
+            .chain(self.args.iter().map(ArgSpec::usage))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Arguments converted according to a `CmdSchema`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CmdArgs {
+    values: HashMap<String, ArgValue>,
+}
+impl CmdArgs {
+    pub fn get(&self, name: &str) -> Option<&ArgValue> {
+        self.values.get(name)
+    }
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            ArgValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn float(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            ArgValue::Float(value) => Some(*value),
+            ArgValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            ArgValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+    /// String or enum value
+    pub fn str(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            ArgValue::String(value) | ArgValue::Enum(value) => Some(value),
+            _ => None,
+        }
+    }
+    pub fn uuid(&self, name: &str) -> Option<Uuid> {
+        match self.get(name)? {
+            ArgValue::Uuid(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
@@ -1,16 +1,33 @@
 use crate::messages::general_message::general_omgpp_message::CmdRequest;
-use std::{collections::HashMap, fmt::Debug};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Debug, Display},
+};
 use uuid::Uuid;
 
-use crate::Endpoint;
+use crate::{
+    cmd_args::{CmdArgs, CmdSchema},
+    Endpoint,
+};
 
 type CmdHandlerCallback<T> =
     Box<dyn Fn(&T, &Uuid, &Endpoint, &CmdHandler<T>, &CmdRequest) + 'static>;
+type TypedCmdHandlerCallback<T> =
+    Box<dyn Fn(&T, &Uuid, &Endpoint, &CmdRequest, &CmdArgs) + 'static>;
+
+enum Callback<T> {
+    Raw(CmdHandlerCallback<T>),
+    Typed(TypedCmdHandlerCallback<T>),
+}
 
 pub struct CmdHandler<T> {
     pub cmd: String,
     pub auth_required: bool,
-    handler: CmdHandlerCallback<T>,
+    /// Sender needs at least one of these roles. Empty means no role is required
+    pub required_roles: Vec<String>,
+    /// Arguments are validated against the schema before the handler runs
+    pub schema: Option<CmdSchema>,
+    handler: Callback<T>,
 }
 impl<T> CmdHandler<T> {
     pub fn new(cmd: &str, auth_required: bool, handler: CmdHandlerCallback<T>) -> CmdHandler<T> {
@@ -20,8 +37,83 @@ impl<T> CmdHandler<T> {
         CmdHandler {
             cmd: cmd,
             auth_required,
-            handler,
+            required_roles: Vec::new(),
+            schema: None,
+            handler: Callback::Raw(handler),
+        }
+    }
+    /// Handler receiving arguments already converted according to `schema`
+    pub fn typed(
+        cmd: &str,
+        auth_required: bool,
+        schema: CmdSchema,
+        handler: TypedCmdHandlerCallback<T>,
+    ) -> CmdHandler<T> {
+        CmdHandler {
+            cmd: cmd.to_string(),
+            auth_required,
+            required_roles: Vec::new(),
+            schema: Some(schema),
+            handler: Callback::Typed(handler),
+        }
+    }
+    /// Validate arguments before calling the handler and describe them in `omgpp_help`
+    pub fn with_schema(mut self, schema: CmdSchema) -> CmdHandler<T> {
+        self.schema = Some(schema);
+        self
+    }
+    pub fn usage(&self) -> String {
+        match &self.schema {
+            Some(schema) => schema.usage(&self.cmd),
+            None => self.cmd.clone(),
+        }
+    }
+    /// Usage followed by the description of the command and of each argument
+    pub fn help(&self) -> Vec<String> {
+        let mut lines = vec![self.usage()];
+        if let Some(schema) = &self.schema {
+            if !schema.description.is_empty() {
+                lines.push(schema.description.clone());
+            }
+            for arg in schema.args.iter().filter(|arg| !arg.description.is_empty()) {
+                lines.push(format!("  {}: {}", arg.name, arg.description));
+            }
+        }
+        lines
+    }
+    fn invoke(&self, item: &T, uuid: &Uuid, endpoint: &Endpoint, request: &CmdRequest) -> Result<(), CmdError> {
+        let args = match &self.schema {
+            Some(schema) => schema.parse(&request.args).map_err(|reason| CmdError::InvalidArgs {
+                reason,
+                usage: self.usage(),
+            })?,
+            None => CmdArgs::default(),
+        };
+        match &self.handler {
+            Callback::Raw(handler) => handler(item, uuid, endpoint, self, request),
+            Callback::Typed(handler) => handler(item, uuid, endpoint, request, &args),
+        }
+        Ok(())
+    }
+    /// Restrict the command to senders having any of `roles`. Implies `auth_required`
+    pub fn with_roles(mut self, roles: &[&str]) -> CmdHandler<T> {
+        self.auth_required = true;
+        self.required_roles = roles.iter().map(|role| role.to_string()).collect();
+        self
+    }
+    pub fn check(&self, permissions: &CmdPermissions) -> Result<(), CmdError> {
+        if self.auth_required && !permissions.authenticated {
+            return Err(CmdError::NotAuthenticated);
         }
+        let has_role = self.required_roles.is_empty()
+            || self
+                .required_roles
+                .iter()
+                .any(|role| permissions.roles.contains(role));
+        if !has_role {
+            return Err(CmdError::MissingRole(self.required_roles.clone()));
+        }
+        Ok(())
     }
 }
 impl<T> Debug for CmdHandler<T> {
@@ -29,17 +121,46 @@ impl<T> Debug for CmdHandler<T> {
         f.debug_struct("CmdHandler")
             .field("cmd", &self.cmd)
             .field("auth_required", &self.auth_required)
+            .field("required_roles", &self.required_roles)
             .finish()
     }
 }
 
+/// What the sender of a command is allowed to run
+#[derive(Debug, Clone, Copy)]
+pub struct CmdPermissions<'a> {
+    pub authenticated: bool,
+    pub roles: &'a HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmdError {
+    NotFound,
+    NotAuthenticated,
+    /// Sender has none of the listed roles
+    MissingRole(Vec<String>),
+    InvalidArgs { reason: String, usage: String },
+}
+impl Display for CmdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CmdError::NotFound => write!(f, "Unknown command"),
+            CmdError::NotAuthenticated => write!(f, "Authentication required"),
+            CmdError::MissingRole(roles) => write!(f, "One of roles {:?} required", roles),
+            CmdError::InvalidArgs { reason, usage } => write!(f, "{}. Usage: {}", reason, usage),
+        }
+    }
+}
+
 pub struct CmdHandlerContainer<T> {
     commands: HashMap<String, CmdHandler<T>>,
+    fallback: Option<CmdHandler<T>>,
 }
 impl<T> CmdHandlerContainer<T> {
     pub fn new() -> CmdHandlerContainer<T> {
         CmdHandlerContainer {
             commands: Default::default(),
+            fallback: None,
         }
     }
     pub fn register_handler(&mut self, cmd_handler: CmdHandler<T>) -> Result<(), String> {
@@ -51,10 +172,60 @@ impl<T> CmdHandlerContainer<T> {
         self.commands.insert(cmd_handler.cmd.clone(), cmd_handler);
         Ok(())
     }
+    pub fn unregister_handler(&mut self, cmd: &str) -> Option<CmdHandler<T>> {
+        self.commands.remove(cmd)
+    }
+    /// Handler for commands that have no handler of their own. Its `cmd` is ignored
+    pub fn set_fallback(&mut self, cmd_handler: Option<CmdHandler<T>>) {
+        self.fallback = cmd_handler;
+    }
+    pub fn contains(&self, cmd: &str) -> bool {
+        self.commands.contains_key(cmd)
+    }
+    pub fn commands(&self) -> impl Iterator<Item = &CmdHandler<T>> {
+        self.commands.values()
+    }
 
-    pub fn handle(&self, item: &T, uuid: &Uuid, endpoint: &Endpoint, cmd: &CmdRequest) {
-        if let Some(cmd_handler) = self.commands.get(&cmd.cmd) {
-            (cmd_handler.handler)(item, uuid, endpoint, cmd_handler, cmd);
-        }
+    /// Run the handler of `cmd` if `permissions` satisfy its requirements
+    pub fn handle(
+        &self,
+        item: &T,
+        uuid: &Uuid,
+        endpoint: &Endpoint,
+        cmd: &CmdRequest,
+        permissions: &CmdPermissions,
+    ) -> Result<(), CmdError> {
+        let cmd_handler = self
+            .commands
+            .get(&cmd.cmd)
+            .or(self.fallback.as_ref())
+            .ok_or(CmdError::NotFound)?;
+        cmd_handler.check(permissions)?;
+        cmd_handler.invoke(item, uuid, endpoint, cmd)
+    }
+    /// Usage of every command the sender is allowed to run, sorted by name
+    pub fn help(&self, permissions: &CmdPermissions) -> Vec<String> {
+        let mut commands = self
+            .commands
+            .values()
+            .filter(|handler| handler.check(permissions).is_ok())
+            .collect::<Vec<_>>();
+        commands.sort_by(|a, b| a.cmd.cmp(&b.cmd));
+        commands
+            .into_iter()
+            .map(|handler| match &handler.schema {
+                Some(schema) if !schema.description.is_empty() => {
+                    format!("{} - {}", handler.usage(), schema.description)
+                }
+                _ => handler.usage(),
+            })
+            .collect()
+    }
+    /// Detailed help of `cmd`, `None` if it does not exist or the sender cannot run it
+    pub fn help_for(&self, cmd: &str, permissions: &CmdPermissions) -> Option<Vec<String>> {
+        self.commands
+            .get(cmd)
+            .filter(|handler| handler.check(permissions).is_ok())
+            .map(CmdHandler::help)
     }
 }
@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+#[cfg(feature = "gns")]
+use gns::GnsConnection;
+#[cfg(feature = "gns")]
+use gns_sys::{
+    ESteamNetworkingConfigValue, HSteamNetConnection,
+    SteamAPI_ISteamNetworkingUtils_SetConnectionConfigValueInt32,
+    SteamAPI_SteamNetworkingUtils_SteamAPI_v004,
+};
+
+/// Typed subset of `ESteamNetworkingConfigValue` options applied to a connection.
+/// Every field left as `None` keeps the GNS default.
+/// Only the GNS transport applies these options, other transports ignore them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionConfig {
+    /// How long to wait for a connection to be established (`TimeoutInitial`)
+    pub timeout_initial: Option<Duration>,
+    /// How long an established connection may stay silent before it is dropped (`TimeoutConnected`)
+    pub timeout_connected: Option<Duration>,
+    /// Upper limit of buffered pending bytes to be sent (`SendBufferSize`)
+    pub send_buffer_size: Option<i32>,
+    /// Minimum send rate in bytes per second (`SendRateMin`)
+    pub send_rate_min: Option<i32>,
+    /// Maximum send rate in bytes per second (`SendRateMax`)
+    pub send_rate_max: Option<i32>,
+    /// Nagle delay applied to unflushed messages (`NagleTime`)
+    pub nagle_time: Option<Duration>,
+    /// Maximum size of a UDP packet (`MTU_PacketSize`)
+    pub mtu_packet_size: Option<i32>,
+    /// Independently ordered lanes (`ConfigureConnectionLanes`), empty keeps the single default lane
+    pub lanes: Vec<Lane>,
+}
+
+/// A lane of a connection. Lanes with a lower `priority` value are sent first,
+/// lanes of the same priority share the bandwidth by `weight`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lane {
+    pub priority: i32,
+    pub weight: u16,
+}
+impl Default for Lane {
+    fn default() -> Self {
+        Lane {
+            priority: 0,
+            weight: 1,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    /// Number of lanes messages can be sent on
+    pub fn lane_count(&self) -> usize {
+        self.lanes.len().max(1)
+    }
+}
+
+#[cfg(feature = "gns")]
+impl ConnectionConfig {
+    /// Options that differ from GNS defaults, in the units GNS expects
+    pub fn config_values(&self) -> Vec<(ESteamNetworkingConfigValue, i32)> {
+        let mut values = Vec::new();
+        if let Some(timeout) = self.timeout_initial {
+            values.push((
+                ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_TimeoutInitial,
+                duration_to_i32(timeout.as_millis()),
+            ));
+        }
+        if let Some(timeout) = self.timeout_connected {
+            values.push((
+                ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_TimeoutConnected,
+                duration_to_i32(timeout.as_millis()),
+            ));
+        }
+        if let Some(size) = self.send_buffer_size {
+            values.push((
+                ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_SendBufferSize,
+                size,
+            ));
+        }
+        if let Some(rate) = self.send_rate_min {
+            values.push((
+                ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_SendRateMin,
+                rate,
+            ));
+        }
+        if let Some(rate) = self.send_rate_max {
+            values.push((
+                ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_SendRateMax,
+                rate,
+            ));
+        }
+        if let Some(nagle) = self.nagle_time {
+            values.push((
+                ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_NagleTime,
+                duration_to_i32(nagle.as_micros()),
+            ));
+        }
+        if let Some(mtu) = self.mtu_packet_size {
+            values.push((
+                ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_MTU_PacketSize,
+                mtu,
+            ));
+        }
+        values
+    }
+    /// Set the options on a connection before it is accepted or while it connects,
+    /// options such as `TimeoutInitial` are only read during the handshake
+    pub fn apply_to_connection(&self, connection: GnsConnection) -> Result<(), String> {
+        let handle = raw_connection_handle(connection);
+        for (value, data) in self.config_values() {
+            let is_set = unsafe {
+                SteamAPI_ISteamNetworkingUtils_SetConnectionConfigValueInt32(
+                    SteamAPI_SteamNetworkingUtils_SteamAPI_v004(),
+                    handle,
+                    value,
+                    data,
+                )
+            };
+            if !is_set {
+                return Err(format!("Cannot set {:?} to {:?}", value, data));
+            }
+        }
+        Ok(())
+    }
+}
+
+// gns-rs (rev a0fc575) declares `GnsConnection` as a `#[repr(transparent)]` newtype over the
+// raw handle but does not expose it. TODO replace with an accessor once gns-rs has one.
+// A size change fails the build, any other layout change fails `raw_handle_matches_gns_layout`
+#[cfg(feature = "gns")]
+const _: () = assert!(
+    std::mem::size_of::<GnsConnection>() == std::mem::size_of::<HSteamNetConnection>()
+        && std::mem::align_of::<GnsConnection>() == std::mem::align_of::<HSteamNetConnection>()
+);
+
+#[cfg(feature = "gns")]
+pub(crate) fn raw_connection_handle(connection: GnsConnection) -> HSteamNetConnection {
+    unsafe { std::mem::transmute::<GnsConnection, HSteamNetConnection>(connection) }
+}
+
+#[cfg(feature = "gns")]
+fn duration_to_i32(value: u128) -> i32 {
+    i32::try_from(value).unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lanes_default_to_one() {
+        assert_eq!(ConnectionConfig::default().lane_count(), 1);
+        let config = ConnectionConfig {
+            lanes: vec![Lane::default(); 3],
+            ..Default::default()
+        };
+        assert_eq!(config.lane_count(), 3);
+    }
+
+    // guards the transmute in `raw_connection_handle` when gns-rs is updated
+    #[cfg(feature = "gns")]
+    #[test]
+    fn raw_handle_matches_gns_layout() {
+        let handle: HSteamNetConnection = 0x1234_5678;
+        let connection = unsafe { std::mem::transmute::<HSteamNetConnection, GnsConnection>(handle) };
+        assert_eq!(format!("{:?}", connection), format!("GnsConnection({})", handle));
+        assert_eq!(raw_connection_handle(connection), handle);
+        assert_eq!(raw_connection_handle(GnsConnection::default()), 0);
+    }
+
+    #[cfg(feature = "gns")]
+    #[test]
+    fn unset_options_keep_gns_defaults() {
+        assert!(ConnectionConfig::default().config_values().is_empty());
+    }
+
+    #[cfg(feature = "gns")]
+    #[test]
+    fn options_map_to_gns_values_and_units() {
+        let config = ConnectionConfig {
+            timeout_initial: Some(Duration::from_secs(2)),
+            timeout_connected: Some(Duration::from_millis(1500)),
+            send_buffer_size: Some(65536),
+            send_rate_min: Some(1000),
+            send_rate_max: Some(2000),
+            nagle_time: Some(Duration::from_millis(3)),
+            mtu_packet_size: Some(1200),
+            lanes: vec![Lane::default(); 2],
+        };
+        assert_eq!(
+            config.config_values(),
+            vec![
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_TimeoutInitial, 2000),
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_TimeoutConnected, 1500),
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_SendBufferSize, 65536),
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_SendRateMin, 1000),
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_SendRateMax, 2000),
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_NagleTime, 3000),
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_MTU_PacketSize, 1200),
+            ]
+        );
+    }
+
+    #[cfg(feature = "gns")]
+    #[test]
+    fn long_durations_saturate() {
+        let config = ConnectionConfig {
+            timeout_connected: Some(Duration::MAX),
+            nagle_time: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.config_values(),
+            vec![
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_TimeoutConnected, i32::MAX),
+                (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_NagleTime, i32::MAX),
+            ]
+        );
+    }
+}
@@ -1,5 +1,13 @@
-use std::net::IpAddr;
-use super::Endpoint;
+use std::{
+    ffi::{c_char, CStr, CString},
+    net::IpAddr,
+    time::Duration,
+};
+use super::{
+    connection_config::Lane,
+    network_simulation::{NetworkSimulation, SimulatedConditions},
+    Endpoint,
+};
 use uuid::Uuid;
 
 pub trait ToFfi<T> {
@@ -33,4 +41,116 @@ pub struct EndpointFFI {
 #[repr(C,packed)]
 pub struct UuidFFI {
     pub bytes:[u8;16]
-}
\ No newline at end of file
+}
+
+/// Fake conditions of one direction; `jitter_ms` is the maximum random delay and applies to every packet
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimulatedConditionsFFI {
+    pub loss_percent: f32,
+    pub lag_ms: u32,
+    pub jitter_ms: u32,
+    pub reorder_percent: f32,
+    pub duplicate_percent: f32,
+}
+impl From<&SimulatedConditionsFFI> for SimulatedConditions {
+    fn from(conditions: &SimulatedConditionsFFI) -> SimulatedConditions {
+        SimulatedConditions {
+            loss_percent: conditions.loss_percent,
+            lag: Duration::from_millis(conditions.lag_ms as u64),
+            jitter_avg: Duration::from_millis(conditions.jitter_ms as u64 / 2),
+            jitter_max: Duration::from_millis(conditions.jitter_ms as u64),
+            jitter_percent: if conditions.jitter_ms > 0 { 100.0 } else { 0.0 },
+            reorder_percent: conditions.reorder_percent,
+            duplicate_percent: conditions.duplicate_percent,
+        }
+    }
+}
+/// `NetworkSimulation` for outgoing (`send`) and incoming (`recv`) packets
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetworkSimulationFFI {
+    pub send: SimulatedConditionsFFI,
+    pub recv: SimulatedConditionsFFI,
+    pub reorder_time_ms: u32,
+    pub duplicate_time_max_ms: u32,
+}
+impl From<&NetworkSimulationFFI> for NetworkSimulation {
+    fn from(simulation: &NetworkSimulationFFI) -> NetworkSimulation {
+        NetworkSimulation {
+            send: (&simulation.send).into(),
+            recv: (&simulation.recv).into(),
+            reorder_time: Duration::from_millis(simulation.reorder_time_ms as u64),
+            duplicate_time_max: Duration::from_millis(simulation.duplicate_time_max_ms as u64),
+        }
+    }
+}
+
+/// C strings handed to a callback. Pointers stay valid while the value lives
+pub struct CStringArray {
+    _strings: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+impl CStringArray {
+    pub fn new(items: &[String]) -> CStringArray {
+        // interior nul bytes cannot be represented in C strings
+        let strings = items
+            .iter()
+            .map(|item| CString::new(item.replace('\0', "")).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let pointers = strings.iter().map(|string| string.as_ptr()).collect();
+        CStringArray {
+            _strings: strings,
+            pointers,
+        }
+    }
+    pub fn as_ptr(&self) -> *const *const c_char {
+        self.pointers.as_ptr()
+    }
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+}
+
+/// Copy `count` C strings. Returns `None` if any of them is null or not valid UTF-8
+///
+/// # Safety
+/// `items` must point to `count` pointers to nul-terminated strings, or be null if `count` is 0
+pub unsafe fn strings_from_ffi(items: *const *const c_char, count: usize) -> Option<Vec<String>> {
+    if count == 0 {
+        return Some(Vec::new());
+    }
+    std::slice::from_raw_parts(items, count)
+        .iter()
+        .map(|item| {
+            item.as_ref()?;
+            CStr::from_ptr(*item).to_str().ok().map(String::from)
+        })
+        .collect()
+}
+/// Lanes from parallel arrays of priorities and weights
+///
+/// # Safety
+/// `priorities` and `weights` must point to `count` values each, or be null if `count` is 0
+pub unsafe fn lanes_from_ffi(priorities: *const i32, weights: *const u16, count: usize) -> Option<Vec<Lane>> {
+    if count == 0 {
+        return Some(Vec::new());
+    }
+    if priorities.is_null() || weights.is_null() {
+        return None;
+    }
+    let priorities = std::slice::from_raw_parts(priorities, count);
+    let weights = std::slice::from_raw_parts(weights, count);
+    let lanes = priorities
+        .iter()
+        .zip(weights)
+        .map(|(priority, weight)| Lane {
+            priority: *priority,
+            weight: *weight,
+        })
+        .collect();
+    Some(lanes)
+}
@@ -0,0 +1,80 @@
+use uuid::Uuid;
+
+use crate::{messages::general_message::GeneralOmgppMessage, Endpoint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// What happens to a message after an interceptor has seen it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Intercept {
+    /// Pass the (possibly modified) message to the next interceptor
+    Continue,
+    /// Stop the chain; the interceptor took care of the message and it is not delivered or sent
+    Handled,
+    /// Drop the message. Inbound commands are answered with the reason, outbound sends fail with it
+    Reject(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterceptContext<'a> {
+    pub direction: Direction,
+    /// Client on the server; nil on the client
+    pub peer: &'a Uuid,
+    pub endpoint: &'a Endpoint,
+}
+
+type InterceptorCallback<T> =
+    Box<dyn Fn(&T, &InterceptContext, &mut GeneralOmgppMessage) -> Intercept + 'static>;
+
+/// Interceptors run in the order they were added, for inbound and outbound messages
+pub struct InterceptorChain<T> {
+    interceptors: Vec<(String, InterceptorCallback<T>)>,
+}
+impl<T> InterceptorChain<T> {
+    pub fn new() -> InterceptorChain<T> {
+        InterceptorChain {
+            interceptors: Vec::new(),
+        }
+    }
+    pub fn add(&mut self, name: &str, interceptor: InterceptorCallback<T>) -> Result<(), String> {
+        if self.interceptors.iter().any(|(existing, _)| existing == name) {
+            return Err(format!("Interceptor {:?} already added", name));
+        }
+        self.interceptors.push((name.to_string(), interceptor));
+        Ok(())
+    }
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let index = self
+            .interceptors
+            .iter()
+            .position(|(existing, _)| existing == name)
+            .ok_or_else(|| format!("Interceptor {:?} not found", name))?;
+        _ = self.interceptors.remove(index);
+        Ok(())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.interceptors.is_empty()
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.interceptors.iter().map(|(name, _)| name.as_str())
+    }
+    /// Run interceptors until one of them does not return `Continue`
+    pub fn run(&self, item: &T, context: &InterceptContext, message: &mut GeneralOmgppMessage) -> Intercept {
+        for (_, interceptor) in &self.interceptors {
+            match interceptor(item, context, message) {
+                Intercept::Continue => continue,
+                stop => return stop,
+            }
+        }
+        Intercept::Continue
+    }
+}
+impl<T> Default for InterceptorChain<T> {
+    fn default() -> Self {
+        InterceptorChain::new()
+    }
+}
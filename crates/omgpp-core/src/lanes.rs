@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use crate::messages::general_message::{general_omgpp_message::Data, GeneralOmgppMessage};
+
+/// Lane an outgoing message is sent on, chosen by its message type, rpc method or command.
+/// Everything else, e.g. snapshots and time sync, goes on lane 0
+#[derive(Debug, Clone, Default)]
+pub struct LaneMap {
+    messages: HashMap<i64, u16>,
+    rpcs: HashMap<i64, u16>,
+    cmds: HashMap<String, u16>,
+}
+impl LaneMap {
+    /// Lane 0 restores the default
+    pub fn set_message_lane(&mut self, msg_type: i64, lane: u16) {
+        set_lane(&mut self.messages, msg_type, lane);
+    }
+    /// Calls of `method_id` and their replies
+    pub fn set_rpc_lane(&mut self, method_id: i64, lane: u16) {
+        set_lane(&mut self.rpcs, method_id, lane);
+    }
+    /// Requests of `cmd` and their replies
+    pub fn set_cmd_lane(&mut self, cmd: &str, lane: u16) {
+        set_lane(&mut self.cmds, cmd.to_string(), lane);
+    }
+    pub fn lane_of(&self, message: &GeneralOmgppMessage) -> u16 {
+        let lane = match &message.data {
+            Some(Data::Message(message)) => self.messages.get(&message.type_),
+            Some(Data::Rpc(rpc)) => self.rpcs.get(&rpc.method_id),
+            Some(Data::Cmd(cmd)) => self.cmds.get(&cmd.cmd),
+            _ => None,
+        };
+        lane.cloned().unwrap_or_default()
+    }
+}
+
+fn set_lane<K: std::hash::Hash + Eq>(lanes: &mut HashMap<K, u16>, key: K, lane: u16) {
+    match lane {
+        0 => lanes.remove(&key),
+        _ => lanes.insert(key, lane),
+    };
+}
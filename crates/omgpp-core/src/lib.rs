@@ -6,10 +6,22 @@
 
 pub mod ffi;
 pub  mod cmd_handler;
-
-use std::{net::IpAddr, sync::LazyLock};
-
+pub mod cmd_args;
+pub mod interceptor;
+pub mod lanes;
+pub mod typed_message;
+pub mod clock;
+pub mod connection_config;
+pub mod network_simulation;
+pub mod transport;
+
+use std::net::IpAddr;
+#[cfg(feature = "gns")]
+use std::sync::LazyLock;
+
+#[cfg(feature = "gns")]
 use either::Either;
+#[cfg(feature = "gns")]
 use gns::{GnsGlobal, GnsUtils, GnsDroppable, IsReady, GnsConnection, GnsSocket, GnsConnectionInfo};
 
 pub mod messages{
@@ -42,15 +54,41 @@ impl OmgppPredefinedCmd {
     pub const AUTH: &str = "omgpp_auth";
     // returns where server resources are located. Usually it's a HTTP server URL
     pub const RESOURCES: &str = "omgpp_resources";
+    // lists commands available to the caller or describes the one passed as argument
+    pub const HELP: &str = "omgpp_help";
+    // admin commands; they require `ADMIN_ROLE`
+    pub const PLAYERS: &str = "omgpp_players";
+    pub const KICK: &str = "omgpp_kick";
+    pub const BAN: &str = "omgpp_ban";
+    pub const UNBAN: &str = "omgpp_unban";
+    pub const ANNOUNCE: &str = "omgpp_announce";
+    pub const SET: &str = "omgpp_set";
+    pub const STATS: &str = "omgpp_stats";
+    // sent by the server to every client, carries the announced text
+    pub const ANNOUNCEMENT: &str = "omgpp_announcement";
+    // granted to clients which authenticate with `ADMIN_AUTH` and the admin password
+    pub const ADMIN_ROLE: &str = "omgpp_admin";
+    // first `omgpp_auth` argument of admin clients, followed by the password
+    pub const ADMIN_AUTH: &str = "omgpp_admin";
+    // user commands cannot start with this prefix
+    pub const RESERVED_PREFIX: &str = "omgpp_";
+
+    pub fn is_reserved(cmd: &str) -> bool {
+        cmd.starts_with(Self::RESERVED_PREFIX)
+    }
 }
 
+#[cfg(feature = "gns")]
 pub struct GnsWrapper {
     pub global: GnsGlobal,
     pub utils: GnsUtils,
 }
+#[cfg(feature = "gns")]
 unsafe impl Send for GnsWrapper {}
+#[cfg(feature = "gns")]
 unsafe impl Sync for GnsWrapper {}
 
+#[cfg(feature = "gns")]
 pub static GNS: LazyLock<Result<GnsWrapper,String>> = LazyLock::new(|| {
     Ok(GnsWrapper {
         global: GnsGlobal::get()?,
@@ -61,6 +99,7 @@ pub static GNS: LazyLock<Result<GnsWrapper,String>> = LazyLock::new(|| {
 pub trait ToEndpoint {
     fn to_endpoint(&self) -> Endpoint;
 }
+#[cfg(feature = "gns")]
 impl ToEndpoint for GnsConnectionInfo {
     fn to_endpoint(&self) -> Endpoint {
         Endpoint {
@@ -72,8 +111,10 @@ impl ToEndpoint for GnsConnectionInfo {
 
 
 #[allow(dead_code)]
+#[cfg(feature = "gns")]
 pub struct TransmitterHelper {}
 
+#[cfg(feature = "gns")]
 impl TransmitterHelper {
     pub fn send<T: GnsDroppable + IsReady>(
         socket: &GnsSocket<'_, '_, T>,
@@ -93,12 +134,23 @@ impl TransmitterHelper {
         connections: impl Iterator<Item = GnsConnection>,
         flags: i32,
         data: &[u8],
+    ) -> Vec<Either<u64, gns_sys::EResult>> {
+        TransmitterHelper::send_on_lane(socket, connections, flags, 0, data)
+    }
+    /// `lane` has to be configured on every connection, see `ConnectionConfig::lanes`
+    pub fn send_on_lane<T: GnsDroppable + IsReady>(
+        socket: &GnsSocket<'_, '_, T>,
+        connections: impl Iterator<Item = GnsConnection>,
+        flags: i32,
+        lane: u16,
+        data: &[u8],
     ) -> Vec<Either<u64, gns_sys::EResult>> {
         let messages = connections
             .map(|connection| {
                 socket
                     .utils()
                     .allocate_message(connection.clone(), flags, data)
+                    .set_lane(lane)
             })
             .collect::<Vec<_>>();
 
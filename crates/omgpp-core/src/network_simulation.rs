@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+#[cfg(feature = "gns")]
+use gns_sys::{
+    ESteamNetworkingConfigValue, SteamAPI_ISteamNetworkingUtils_SetGlobalConfigValueFloat,
+    SteamAPI_ISteamNetworkingUtils_SetGlobalConfigValueInt32,
+    SteamAPI_SteamNetworkingUtils_SteamAPI_v004,
+};
+
+/// Fake network conditions applied to one direction of the traffic
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimulatedConditions {
+    /// Percentage of dropped packets, 0..100
+    pub loss_percent: f32,
+    /// Constant delay added to every packet
+    pub lag: Duration,
+    /// Average random delay added on top of `lag`
+    pub jitter_avg: Duration,
+    /// Maximum random delay added on top of `lag`
+    pub jitter_max: Duration,
+    /// Percentage of packets affected by jitter, 0..100
+    pub jitter_percent: f32,
+    /// Percentage of packets delivered out of order, 0..100
+    pub reorder_percent: f32,
+    /// Percentage of duplicated packets, 0..100
+    pub duplicate_percent: f32,
+}
+
+/// GNS fake packet loss, lag, jitter, reorder and duplication settings.
+/// GNS keeps these values process wide, so every socket in the process is affected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkSimulation {
+    pub send: SimulatedConditions,
+    pub recv: SimulatedConditions,
+    /// Extra delay of reordered packets
+    pub reorder_time: Duration,
+    /// Maximum delay of duplicated packets
+    pub duplicate_time_max: Duration,
+}
+
+impl NetworkSimulation {
+    /// Same conditions for sent and received packets
+    pub fn symmetric(conditions: SimulatedConditions) -> NetworkSimulation {
+        NetworkSimulation {
+            send: conditions.clone(),
+            recv: conditions,
+            ..Default::default()
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        *self != NetworkSimulation::default()
+    }
+    #[cfg(not(feature = "gns"))]
+    pub fn apply(&self) -> Result<(), String> {
+        Err("Network simulation requires the `gns` feature".to_string())
+    }
+    #[cfg(feature = "gns")]
+    pub fn apply(&self) -> Result<(), String> {
+        let send = &self.send;
+        let recv = &self.recv;
+        let float_values = [
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketLoss_Send, send.loss_percent),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketLoss_Recv, recv.loss_percent),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketReorder_Send, send.reorder_percent),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketReorder_Recv, recv.reorder_percent),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketDup_Send, send.duplicate_percent),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketDup_Recv, recv.duplicate_percent),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketJitter_Send_Pct, send.jitter_percent),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketJitter_Recv_Pct, recv.jitter_percent),
+        ];
+        let millis_values = [
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketLag_Send, send.lag),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketLag_Recv, recv.lag),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketJitter_Send_Avg, send.jitter_avg),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketJitter_Send_Max, send.jitter_max),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketJitter_Recv_Avg, recv.jitter_avg),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketJitter_Recv_Max, recv.jitter_max),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketReorder_Time, self.reorder_time),
+            (ESteamNetworkingConfigValue::k_ESteamNetworkingConfig_FakePacketDup_TimeMax, self.duplicate_time_max),
+        ];
+        unsafe {
+            let utils = SteamAPI_SteamNetworkingUtils_SteamAPI_v004();
+            for (value, data) in float_values {
+                if !SteamAPI_ISteamNetworkingUtils_SetGlobalConfigValueFloat(utils, value, data) {
+                    return Err(format!("Cannot set {:?} to {:?}", value, data));
+                }
+            }
+            for (value, data) in millis_values {
+                let millis = i32::try_from(data.as_millis()).unwrap_or(i32::MAX);
+                if !SteamAPI_ISteamNetworkingUtils_SetGlobalConfigValueInt32(utils, value, millis) {
+                    return Err(format!("Cannot set {:?} to {:?}", value, millis));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::{NetworkSimulationFFI, SimulatedConditionsFFI};
+
+    fn lossy() -> SimulatedConditions {
+        SimulatedConditions {
+            loss_percent: 5.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn defaults_are_off() {
+        let simulation = NetworkSimulation::default();
+        assert!(!simulation.is_enabled());
+        assert_eq!(simulation.send, SimulatedConditions::default());
+        assert_eq!(simulation.recv, SimulatedConditions::default());
+        assert_eq!(simulation.reorder_time, Duration::ZERO);
+        assert_eq!(simulation.duplicate_time_max, Duration::ZERO);
+    }
+
+    #[test]
+    fn any_setting_enables_it() {
+        let recv_only = NetworkSimulation {
+            recv: lossy(),
+            ..Default::default()
+        };
+        assert!(recv_only.is_enabled());
+        let reorder_time_only = NetworkSimulation {
+            reorder_time: Duration::from_millis(10),
+            ..Default::default()
+        };
+        assert!(reorder_time_only.is_enabled());
+        let symmetric = NetworkSimulation::symmetric(lossy());
+        assert!(symmetric.is_enabled());
+        assert_eq!(symmetric.send, symmetric.recv);
+    }
+
+    #[cfg(not(feature = "gns"))]
+    #[test]
+    fn apply_needs_gns() {
+        assert!(NetworkSimulation::default().apply().is_err());
+        assert!(NetworkSimulation::symmetric(lossy()).apply().is_err());
+    }
+
+    #[test]
+    fn ffi_settings_cover_both_directions() {
+        let ffi = NetworkSimulationFFI {
+            send: SimulatedConditionsFFI {
+                loss_percent: 1.0,
+                lag_ms: 50,
+                ..Default::default()
+            },
+            recv: SimulatedConditionsFFI {
+                jitter_ms: 20,
+                reorder_percent: 2.0,
+                duplicate_percent: 3.0,
+                ..Default::default()
+            },
+            reorder_time_ms: 10,
+            duplicate_time_max_ms: 30,
+        };
+        let simulation = NetworkSimulation::from(&ffi);
+        assert_eq!(simulation.send.loss_percent, 1.0);
+        assert_eq!(simulation.send.lag, Duration::from_millis(50));
+        assert_eq!(simulation.send.jitter_percent, 0.0);
+        assert_eq!(
+            simulation.recv,
+            SimulatedConditions {
+                jitter_avg: Duration::from_millis(10),
+                jitter_max: Duration::from_millis(20),
+                jitter_percent: 100.0,
+                reorder_percent: 2.0,
+                duplicate_percent: 3.0,
+                ..Default::default()
+            }
+        );
+        assert_eq!(simulation.reorder_time, Duration::from_millis(10));
+        assert_eq!(simulation.duplicate_time_max, Duration::from_millis(30));
+        assert!(!NetworkSimulation::from(&NetworkSimulationFFI::default()).is_enabled());
+    }
+}
@@ -0,0 +1,116 @@
+#[cfg(feature = "gns")]
+pub mod gns;
+pub mod loopback;
+pub mod multi;
+#[cfg(feature = "quic")]
+pub mod quic;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+use std::time::Duration;
+
+use crate::{connection_config::ConnectionConfig, Endpoint};
+
+pub type TransportResult<T> = Result<T, String>;
+
+/// Transport specific identifier of a connection
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConnectionHandle(pub u64);
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum SendMode {
+    Unreliable,
+    Reliable,
+}
+impl SendMode {
+    pub fn from_reliable(reliable: bool) -> SendMode {
+        match reliable {
+            true => SendMode::Reliable,
+            false => SendMode::Unreliable,
+        }
+    }
+}
+
+/// Real time state of a connection as far as the transport knows it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectionStatus {
+    pub ping: Option<Duration>,
+    /// Bytes per second the connection is estimated to carry
+    pub send_rate: Option<u32>,
+    /// Bytes handed to the transport and not sent yet
+    pub queued_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEvent {
+    /// Server side: a peer asks to connect and has to be accepted or closed.
+    /// Client side: the connection attempt has started
+    Connecting(ConnectionHandle, Endpoint),
+    Connected(ConnectionHandle, Endpoint),
+    /// Closed by the peer or by a problem detected locally. Closing a connection
+    /// through `Transport::close` does not produce this event
+    Disconnected(ConnectionHandle, Endpoint),
+}
+
+/// Moves `GeneralOmgppMessage` payloads between peers. `Server` and `Client`
+/// only talk to the network through this trait
+pub trait Transport {
+    /// Run transport internals, called once per `process`
+    fn poll(&self);
+    /// Handle at most about `max` connection events. Returns the number of handled events
+    fn poll_events(&self, max: usize, handler: &mut dyn FnMut(TransportEvent)) -> usize;
+    /// Handle at most about `max` received messages. Returns the number of handled messages
+    fn poll_messages(&self, max: usize, handler: &mut dyn FnMut(ConnectionHandle, &[u8])) -> usize;
+    fn send(&self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()>;
+    /// Send on one of the lanes of `ConnectionConfig::lanes`. Messages on different lanes are
+    /// ordered independently; transports without lanes send everything through `send`
+    fn send_on_lane(
+        &self,
+        connections: &[ConnectionHandle],
+        mode: SendMode,
+        _lane: u16,
+        data: &[u8],
+    ) -> TransportResult<()> {
+        self.send(connections, mode, data)
+    }
+    fn accept(&self, connection: ConnectionHandle) -> TransportResult<()>;
+    fn close(&self, connection: ConnectionHandle, reason: &str);
+    /// Server side: options of connections that ask to connect from now on, applied before
+    /// they are reported as `Connecting`
+    fn set_accept_config(&self, _config: &ConnectionConfig) -> TransportResult<()> {
+        Ok(())
+    }
+    /// Change options of an existing connection
+    fn configure_connection(
+        &self,
+        _connection: ConnectionHandle,
+        _config: &ConnectionConfig,
+    ) -> TransportResult<()> {
+        Ok(())
+    }
+    /// Index of the inner transport `connection` belongs to, 0 unless several transports are
+    /// combined. Connections of different transports can come from the same endpoint
+    fn transport_index(&self, _connection: ConnectionHandle) -> usize {
+        0
+    }
+    /// `None` if the transport does not track it or the connection is unknown
+    fn connection_status(&self, _connection: ConnectionHandle) -> Option<ConnectionStatus> {
+        None
+    }
+    /// Listen socket of a GNS server transport, for code that still talks to GNS directly
+    #[cfg(feature = "gns")]
+    fn gns_server_socket(&self) -> Option<&gns::GnsServerSocket> {
+        None
+    }
+}
+
+/// Creates client side transports
+pub trait Connector {
+    /// Start connecting to `endpoint` with `config` applied from the start.
+    /// Returns the transport and the handle of the connection
+    fn connect(
+        &self,
+        endpoint: &Endpoint,
+        config: &ConnectionConfig,
+    ) -> TransportResult<(Box<dyn Transport>, ConnectionHandle)>;
+}
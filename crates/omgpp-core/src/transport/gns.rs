@@ -0,0 +1,392 @@
+use std::{cell::RefCell, net::IpAddr, time::Duration};
+
+use bimap::BiHashMap;
+use gns::{
+    GnsConnection, GnsConnectionEvent, GnsDroppable, GnsSocket, IsClient, IsCreated, IsReady,
+    IsServer,
+};
+use gns_sys::{
+    k_nSteamNetworkingSend_Reliable, k_nSteamNetworkingSend_Unreliable,
+    ESteamNetworkingConnectionState,
+};
+
+use super::{
+    ConnectionHandle, ConnectionStatus, Connector, SendMode, Transport, TransportEvent, TransportResult,
+};
+use crate::{connection_config::ConnectionConfig, Endpoint, ToEndpoint, TransmitterHelper, GNS};
+
+/// Listen socket of `GnsServerTransport`
+pub type GnsServerSocket = GnsSocket<'static, 'static, IsServer>;
+
+// gns-rs polls with a compile time batch size
+const POLL_BATCH: usize = 64;
+
+#[derive(Default)]
+struct GnsConnections {
+    handles: BiHashMap<ConnectionHandle, GnsConnection>,
+    next_handle: u64,
+}
+impl GnsConnections {
+    fn handle(&mut self, connection: GnsConnection) -> ConnectionHandle {
+        if let Some(handle) = self.handles.get_by_right(&connection) {
+            return *handle;
+        }
+        self.next_handle += 1;
+        let handle = ConnectionHandle(self.next_handle);
+        self.handles.insert(handle, connection);
+        handle
+    }
+    fn connection(&self, handle: &ConnectionHandle) -> Option<GnsConnection> {
+        self.handles.get_by_left(handle).cloned()
+    }
+    fn known_handle(&self, connection: &GnsConnection) -> Option<ConnectionHandle> {
+        self.handles.get_by_right(connection).cloned()
+    }
+    fn forget(&mut self, handle: &ConnectionHandle) {
+        self.handles.remove_by_left(handle);
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+fn send_flags(mode: SendMode) -> i32 {
+    match mode {
+        SendMode::Reliable => k_nSteamNetworkingSend_Reliable,
+        SendMode::Unreliable => k_nSteamNetworkingSend_Unreliable,
+    }
+}
+
+fn map_event(
+    event: GnsConnectionEvent,
+    connections: &RefCell<GnsConnections>,
+) -> Option<TransportEvent> {
+    let endpoint = event.info().to_endpoint();
+    let handle = connections.borrow_mut().handle(event.connection());
+    match (event.old_state(), event.info().state()) {
+        (
+            ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_None,
+            ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting,
+        ) => Some(TransportEvent::Connecting(handle, endpoint)),
+        (
+            ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting
+            | ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connected,
+            ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_None
+            | ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_ClosedByPeer
+            | ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_ProblemDetectedLocally,
+        ) => {
+            connections.borrow_mut().forget(&handle);
+            Some(TransportEvent::Disconnected(handle, endpoint))
+        }
+        (
+            ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connecting,
+            ESteamNetworkingConnectionState::k_ESteamNetworkingConnectionState_Connected,
+        ) => Some(TransportEvent::Connected(handle, endpoint)),
+        (_, _) => None,
+    }
+}
+
+fn poll_events<S: GnsDroppable + IsReady>(
+    socket: &GnsSocket<'static, 'static, S>,
+    connections: &RefCell<GnsConnections>,
+    max: usize,
+    handler: &mut dyn FnMut(TransportEvent),
+) -> usize {
+    let mut processed = 0;
+    while processed < max {
+        let count = socket.poll_event::<POLL_BATCH>(|event| {
+            if let Some(transport_event) = map_event(event, connections) {
+                handler(transport_event);
+            }
+        });
+        processed += count;
+        if count < POLL_BATCH {
+            break;
+        }
+    }
+    processed
+}
+
+fn poll_messages<S: GnsDroppable + IsReady>(
+    socket: &GnsSocket<'static, 'static, S>,
+    connections: &RefCell<GnsConnections>,
+    max: usize,
+    handler: &mut dyn FnMut(ConnectionHandle, &[u8]),
+) -> usize {
+    let mut processed = 0;
+    while processed < max {
+        let count = socket
+            .poll_messages::<POLL_BATCH>(|msg| {
+                let handle = connections.borrow().known_handle(&msg.connection());
+                if let Some(handle) = handle {
+                    handler(handle, msg.payload());
+                }
+            })
+            .unwrap_or(0);
+        processed += count;
+        if count < POLL_BATCH {
+            break;
+        }
+    }
+    processed
+}
+
+fn send<S: GnsDroppable + IsReady>(
+    socket: &GnsSocket<'static, 'static, S>,
+    connections: &RefCell<GnsConnections>,
+    targets: &[ConnectionHandle],
+    mode: SendMode,
+    lane: u16,
+    data: &[u8],
+) -> TransportResult<()> {
+    let gns_connections = {
+        let connections = connections.borrow();
+        targets
+            .iter()
+            .filter_map(|handle| connections.connection(handle))
+            .collect::<Vec<_>>()
+    };
+    let results = TransmitterHelper::send_on_lane(
+        socket,
+        gns_connections.into_iter(),
+        send_flags(mode),
+        lane,
+        data,
+    );
+    match results.iter().find(|result| result.is_right()) {
+        Some(failed) => Err(format!("Cannot send message: {:?}", failed.right())),
+        None => Ok(()),
+    }
+}
+
+fn close<S: GnsDroppable + IsReady>(
+    socket: &GnsSocket<'static, 'static, S>,
+    connections: &RefCell<GnsConnections>,
+    handle: ConnectionHandle,
+    reason: &str,
+) {
+    let connection = connections.borrow().connection(&handle);
+    if let Some(connection) = connection {
+        socket.close_connection(connection, 0, reason, false);
+        connections.borrow_mut().forget(&handle);
+    }
+}
+
+fn connection_status<S: GnsDroppable + IsReady>(
+    socket: &GnsSocket<'static, 'static, S>,
+    connections: &RefCell<GnsConnections>,
+    handle: ConnectionHandle,
+) -> Option<ConnectionStatus> {
+    let connection = connections.borrow().connection(&handle)?;
+    let (status, _lanes) = socket.get_connection_real_time_status(connection, 0).ok()?;
+    Some(ConnectionStatus {
+        ping: (status.ping() >= 0).then(|| Duration::from_millis(status.ping() as u64)),
+        send_rate: (status.send_rate_bytes_per_sec() > 0).then(|| status.send_rate_bytes_per_sec() as u32),
+        queued_bytes: status.queued_send_bytes().max(0) as u64,
+    })
+}
+
+fn configure<S: GnsDroppable + IsReady>(
+    socket: &GnsSocket<'static, 'static, S>,
+    connections: &RefCell<GnsConnections>,
+    handle: ConnectionHandle,
+    config: &ConnectionConfig,
+) -> TransportResult<()> {
+    let connection = connections
+        .borrow()
+        .connection(&handle)
+        .ok_or("Unknown connection")?;
+    config.apply_to_connection(connection)?;
+    if config.lanes.is_empty() {
+        return Ok(());
+    }
+    let lanes = config
+        .lanes
+        .iter()
+        .map(|lane| (lane.priority, lane.weight))
+        .collect::<Vec<_>>();
+    socket
+        .configure_connection_lanes(connection, &lanes)
+        .map_err(|result| format!("Cannot configure {} lanes: {:?}", lanes.len(), result))
+}
+
+/// GameNetworkingSockets listen socket
+pub struct GnsServerTransport {
+    socket: GnsSocket<'static, 'static, IsServer>,
+    connections: RefCell<GnsConnections>,
+    accept_config: RefCell<ConnectionConfig>,
+}
+impl GnsServerTransport {
+    /// gns-rs creates the listen socket without options, `config` is set on every connection
+    /// as soon as it asks to connect, before it is accepted
+    pub fn listen(ip: IpAddr, port: u16, config: &ConnectionConfig) -> TransportResult<GnsServerTransport> {
+        let gns = GNS.as_ref()?;
+        let gns_socket = GnsSocket::<IsCreated>::new(&gns.global, &gns.utils)
+            .ok_or("Cannot create GNS socket")?;
+        let socket = gns_socket
+            .listen(to_ipv6(ip), port)
+            .or(Err("Cannot create server socket".to_string()))?;
+        Ok(GnsServerTransport {
+            socket,
+            connections: Default::default(),
+            accept_config: RefCell::new(config.clone()),
+        })
+    }
+    pub fn socket(&self) -> &GnsServerSocket {
+        &self.socket
+    }
+    pub fn gns_connection(&self, handle: &ConnectionHandle) -> Option<GnsConnection> {
+        self.connections.borrow().connection(handle)
+    }
+}
+impl Transport for GnsServerTransport {
+    fn poll(&self) {
+        self.socket.poll_callbacks();
+    }
+    fn poll_events(&self, max: usize, handler: &mut dyn FnMut(TransportEvent)) -> usize {
+        let config = self.accept_config.borrow().clone();
+        poll_events(&self.socket, &self.connections, max, &mut |event| {
+            if let TransportEvent::Connecting(handle, _) = event {
+                // a connection without the requested options is not offered to the server
+                if configure(&self.socket, &self.connections, handle, &config).is_err() {
+                    close(&self.socket, &self.connections, handle, "Cannot configure the connection");
+                    return;
+                }
+            }
+            handler(event)
+        })
+    }
+    fn poll_messages(&self, max: usize, handler: &mut dyn FnMut(ConnectionHandle, &[u8])) -> usize {
+        poll_messages(&self.socket, &self.connections, max, handler)
+    }
+    fn send(&self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()> {
+        send(&self.socket, &self.connections, connections, mode, 0, data)
+    }
+    fn send_on_lane(
+        &self,
+        connections: &[ConnectionHandle],
+        mode: SendMode,
+        lane: u16,
+        data: &[u8],
+    ) -> TransportResult<()> {
+        send(&self.socket, &self.connections, connections, mode, lane, data)
+    }
+    fn accept(&self, connection: ConnectionHandle) -> TransportResult<()> {
+        let gns_connection = self
+            .connections
+            .borrow()
+            .connection(&connection)
+            .ok_or("Unknown connection")?;
+        self.socket
+            .accept(gns_connection)
+            .or(Err("Cannot accept the connection".to_string()))
+    }
+    fn close(&self, connection: ConnectionHandle, reason: &str) {
+        close(&self.socket, &self.connections, connection, reason)
+    }
+    fn set_accept_config(&self, config: &ConnectionConfig) -> TransportResult<()> {
+        *self.accept_config.borrow_mut() = config.clone();
+        Ok(())
+    }
+    fn configure_connection(
+        &self,
+        connection: ConnectionHandle,
+        config: &ConnectionConfig,
+    ) -> TransportResult<()> {
+        configure(&self.socket, &self.connections, connection, config)
+    }
+    fn connection_status(&self, connection: ConnectionHandle) -> Option<ConnectionStatus> {
+        connection_status(&self.socket, &self.connections, connection)
+    }
+    fn gns_server_socket(&self) -> Option<&GnsServerSocket> {
+        Some(&self.socket)
+    }
+}
+
+/// GameNetworkingSockets connection to a server
+pub struct GnsClientTransport {
+    socket: GnsSocket<'static, 'static, IsClient>,
+    connections: RefCell<GnsConnections>,
+}
+impl GnsClientTransport {
+    /// gns-rs connects without options, `config` is set on the connection right after it is
+    /// created, while the handshake is still running
+    pub fn connect(
+        endpoint: &Endpoint,
+        config: &ConnectionConfig,
+    ) -> TransportResult<(GnsClientTransport, ConnectionHandle)> {
+        let gns = GNS.as_ref()?;
+        let gns_socket = GnsSocket::<IsCreated>::new(&gns.global, &gns.utils)
+            .ok_or("Cannot create GNS socket")?;
+        let socket = gns_socket
+            .connect(to_ipv6(endpoint.ip), endpoint.port)
+            .or(Err("Cannot create socket to connect to server".to_string()))?;
+        let mut connections = GnsConnections::default();
+        let handle = connections.handle(socket.connection());
+        let transport = GnsClientTransport {
+            socket,
+            connections: RefCell::new(connections),
+        };
+        configure(&transport.socket, &transport.connections, handle, config)?;
+        Ok((transport, handle))
+    }
+    pub fn socket(&self) -> &GnsSocket<'static, 'static, IsClient> {
+        &self.socket
+    }
+}
+impl Transport for GnsClientTransport {
+    fn poll(&self) {
+        self.socket.poll_callbacks();
+    }
+    fn poll_events(&self, max: usize, handler: &mut dyn FnMut(TransportEvent)) -> usize {
+        poll_events(&self.socket, &self.connections, max, handler)
+    }
+    fn poll_messages(&self, max: usize, handler: &mut dyn FnMut(ConnectionHandle, &[u8])) -> usize {
+        poll_messages(&self.socket, &self.connections, max, handler)
+    }
+    fn send(&self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()> {
+        send(&self.socket, &self.connections, connections, mode, 0, data)
+    }
+    fn send_on_lane(
+        &self,
+        connections: &[ConnectionHandle],
+        mode: SendMode,
+        lane: u16,
+        data: &[u8],
+    ) -> TransportResult<()> {
+        send(&self.socket, &self.connections, connections, mode, lane, data)
+    }
+    fn accept(&self, _connection: ConnectionHandle) -> TransportResult<()> {
+        Err("Client transport does not accept connections".to_string())
+    }
+    fn close(&self, connection: ConnectionHandle, reason: &str) {
+        close(&self.socket, &self.connections, connection, reason)
+    }
+    fn configure_connection(
+        &self,
+        connection: ConnectionHandle,
+        config: &ConnectionConfig,
+    ) -> TransportResult<()> {
+        configure(&self.socket, &self.connections, connection, config)
+    }
+    fn connection_status(&self, connection: ConnectionHandle) -> Option<ConnectionStatus> {
+        connection_status(&self.socket, &self.connections, connection)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GnsConnector;
+impl Connector for GnsConnector {
+    fn connect(
+        &self,
+        endpoint: &Endpoint,
+        config: &ConnectionConfig,
+    ) -> TransportResult<(Box<dyn Transport>, ConnectionHandle)> {
+        let (transport, handle) = GnsClientTransport::connect(endpoint, config)?;
+        Ok((Box::new(transport), handle))
+    }
+}
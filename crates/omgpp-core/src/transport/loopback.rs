@@ -0,0 +1,395 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    net::{IpAddr, Ipv4Addr},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use super::{
+    ConnectionHandle, ConnectionStatus, Connector, SendMode, Transport, TransportEvent, TransportResult,
+};
+use crate::{
+    clock::{Clock, ManualClock},
+    connection_config::ConnectionConfig,
+    Endpoint,
+};
+
+type PeerId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+    Connecting,
+    Connected,
+    Closed,
+}
+
+// both peers refer to a link by the same `ConnectionHandle`
+#[derive(Debug)]
+struct Link {
+    client: PeerId,
+    client_endpoint: Endpoint,
+    server: PeerId,
+    server_endpoint: Endpoint,
+    state: LinkState,
+}
+impl Link {
+    // the other peer and the endpoint it knows `peer` by
+    fn counterpart(&self, peer: PeerId) -> (PeerId, Endpoint) {
+        if peer == self.client {
+            (self.server, self.client_endpoint)
+        } else {
+            (self.client, self.server_endpoint)
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Delivery {
+    Event(TransportEvent),
+    Message(ConnectionHandle, Vec<u8>),
+}
+
+#[derive(Debug)]
+struct InFlight {
+    deliver_at: Instant,
+    to: PeerId,
+    delivery: Delivery,
+}
+
+#[derive(Debug, Default)]
+struct LoopbackState {
+    listeners: HashMap<Endpoint, PeerId>,
+    links: HashMap<ConnectionHandle, Link>,
+    in_flight: VecDeque<InFlight>,
+    next_peer: PeerId,
+    next_link: u64,
+    next_port: u16,
+    latency: Duration,
+    unreliable_loss_percent: u32,
+    loss_accumulator: u32,
+    dropped_unreliable: u64,
+    send_rate: Option<u32>,
+    // lanes configured by each side of a link, one if not configured
+    lane_counts: HashMap<(ConnectionHandle, PeerId), usize>,
+    // lanes of the links a listener accepts
+    accept_lane_counts: HashMap<PeerId, usize>,
+    sent_on_lane: HashMap<u16, u64>,
+}
+impl LoopbackState {
+    fn schedule(&mut self, now: Instant, to: PeerId, delivery: Delivery) {
+        // never deliver before something scheduled earlier, so changing latency keeps the order
+        let last = self.in_flight.back().map(|item| item.deliver_at);
+        let deliver_at = last.map_or(now + self.latency, |last| last.max(now + self.latency));
+        self.in_flight.push_back(InFlight {
+            deliver_at,
+            to,
+            delivery,
+        });
+    }
+    // deterministic loss: every `100 / loss_percent`-th unreliable message is dropped
+    fn should_drop_unreliable(&mut self) -> bool {
+        if self.unreliable_loss_percent == 0 {
+            return false;
+        }
+        self.loss_accumulator += self.unreliable_loss_percent;
+        if self.loss_accumulator >= 100 {
+            self.loss_accumulator -= 100;
+            self.dropped_unreliable += 1;
+            return true;
+        }
+        false
+    }
+}
+
+/// In-memory network driven by a `ManualClock`. Every message and connection event
+/// is delivered after the configured latency once the clock has been advanced far enough
+#[derive(Clone)]
+pub struct LoopbackNetwork {
+    state: Rc<RefCell<LoopbackState>>,
+    clock: ManualClock,
+}
+impl LoopbackNetwork {
+    pub fn new(clock: ManualClock) -> LoopbackNetwork {
+        LoopbackNetwork {
+            state: Rc::new(RefCell::new(LoopbackState {
+                next_port: 40000,
+                ..Default::default()
+            })),
+            clock,
+        }
+    }
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.borrow_mut().latency = latency;
+    }
+    /// Percentage of unreliable messages to drop. Reliable messages are always delivered in order
+    pub fn set_unreliable_loss(&self, percent: u32) {
+        let mut state = self.state.borrow_mut();
+        state.unreliable_loss_percent = percent.min(100);
+        state.loss_accumulator = 0;
+    }
+    pub fn dropped_unreliable(&self) -> u64 {
+        self.state.borrow().dropped_unreliable
+    }
+    /// Send rate reported by `Transport::connection_status`, nothing is throttled
+    pub fn set_send_rate(&self, bytes_per_second: Option<u32>) {
+        self.state.borrow_mut().send_rate = bytes_per_second;
+    }
+    /// Messages sent on `lane` by any peer, lanes do not change the delivery order
+    pub fn sent_on_lane(&self, lane: u16) -> u64 {
+        self.state.borrow().sent_on_lane.get(&lane).cloned().unwrap_or_default()
+    }
+    pub fn listen(&self, endpoint: Endpoint) -> TransportResult<LoopbackTransport> {
+        let mut state = self.state.borrow_mut();
+        if state.listeners.contains_key(&endpoint) {
+            return Err(format!("Endpoint {:?} is already in use", endpoint));
+        }
+        state.next_peer += 1;
+        let peer = state.next_peer;
+        state.listeners.insert(endpoint, peer);
+        Ok(LoopbackTransport {
+            network: self.clone(),
+            peer,
+        })
+    }
+    pub fn connector(&self) -> LoopbackConnector {
+        LoopbackConnector {
+            network: self.clone(),
+        }
+    }
+    fn connect(&self, endpoint: &Endpoint, config: &ConnectionConfig) -> (LoopbackTransport, ConnectionHandle) {
+        let now = self.clock.now();
+        let mut state = self.state.borrow_mut();
+        state.next_peer += 1;
+        let peer = state.next_peer;
+        state.next_link += 1;
+        let handle = ConnectionHandle(state.next_link);
+        state.next_port = state.next_port.wrapping_add(1);
+        let client_endpoint = Endpoint {
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: state.next_port,
+        };
+        let transport = LoopbackTransport {
+            network: self.clone(),
+            peer,
+        };
+        state.lane_counts.insert((handle, peer), config.lane_count());
+        // the connection attempt is visible to the client immediately
+        state.in_flight.push_front(InFlight {
+            deliver_at: now,
+            to: peer,
+            delivery: Delivery::Event(TransportEvent::Connecting(handle, *endpoint)),
+        });
+        match state.listeners.get(endpoint).cloned() {
+            Some(server) => {
+                let lane_count = state.accept_lane_counts.get(&server).cloned().unwrap_or(1);
+                state.lane_counts.insert((handle, server), lane_count);
+                state.links.insert(
+                    handle,
+                    Link {
+                        client: peer,
+                        client_endpoint,
+                        server,
+                        server_endpoint: *endpoint,
+                        state: LinkState::Connecting,
+                    },
+                );
+                let event = TransportEvent::Connecting(handle, client_endpoint);
+                state.schedule(now, server, Delivery::Event(event));
+            }
+            None => {
+                let event = TransportEvent::Disconnected(handle, *endpoint);
+                state.schedule(now, peer, Delivery::Event(event));
+            }
+        }
+        (transport, handle)
+    }
+}
+
+pub struct LoopbackConnector {
+    network: LoopbackNetwork,
+}
+impl Connector for LoopbackConnector {
+    fn connect(
+        &self,
+        endpoint: &Endpoint,
+        config: &ConnectionConfig,
+    ) -> TransportResult<(Box<dyn Transport>, ConnectionHandle)> {
+        let (transport, handle) = self.network.connect(endpoint, config);
+        Ok((Box::new(transport), handle))
+    }
+}
+
+fn event_connection(event: &TransportEvent) -> ConnectionHandle {
+    match event {
+        TransportEvent::Connecting(handle, _)
+        | TransportEvent::Connected(handle, _)
+        | TransportEvent::Disconnected(handle, _) => *handle,
+    }
+}
+
+/// One side (listening server or connected client) of a `LoopbackNetwork`
+pub struct LoopbackTransport {
+    network: LoopbackNetwork,
+    peer: PeerId,
+}
+impl LoopbackTransport {
+    // removes deliverable items for this peer, keeping everything else in order
+    fn take_due(&self, max: usize, events: bool) -> Vec<Delivery> {
+        let now = self.network.clock.now();
+        let mut state = self.network.state.borrow_mut();
+        let mut due = Vec::new();
+        let mut kept = VecDeque::with_capacity(state.in_flight.len());
+        // events of a connection wait for the messages sent on it before, e.g. a peer's last
+        // messages are delivered before its disconnect
+        let mut undelivered_messages = HashSet::new();
+        while let Some(item) = state.in_flight.pop_front() {
+            let is_event = matches!(item.delivery, Delivery::Event(_));
+            let is_waiting = match &item.delivery {
+                Delivery::Event(event) => undelivered_messages.contains(&event_connection(event)),
+                Delivery::Message(..) => false,
+            };
+            if due.len() < max && item.to == self.peer && item.deliver_at <= now && is_event == events && !is_waiting {
+                due.push(item.delivery);
+            } else {
+                if let (true, Delivery::Message(handle, _)) = (item.to == self.peer, &item.delivery) {
+                    undelivered_messages.insert(*handle);
+                }
+                kept.push_back(item);
+            }
+        }
+        state.in_flight = kept;
+        due
+    }
+}
+impl Transport for LoopbackTransport {
+    fn poll(&self) {}
+    fn poll_events(&self, max: usize, handler: &mut dyn FnMut(TransportEvent)) -> usize {
+        let due = self.take_due(max, true);
+        let count = due.len();
+        for delivery in due {
+            if let Delivery::Event(event) = delivery {
+                if let TransportEvent::Connected(handle, _) = &event {
+                    if let Some(link) = self.network.state.borrow_mut().links.get_mut(handle) {
+                        if link.state == LinkState::Connecting {
+                            link.state = LinkState::Connected;
+                        }
+                    }
+                }
+                handler(event);
+            }
+        }
+        count
+    }
+    fn poll_messages(&self, max: usize, handler: &mut dyn FnMut(ConnectionHandle, &[u8])) -> usize {
+        let due = self.take_due(max, false);
+        let count = due.len();
+        for delivery in due {
+            if let Delivery::Message(handle, data) = delivery {
+                handler(handle, &data);
+            }
+        }
+        count
+    }
+    fn send(&self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()> {
+        self.send_on_lane(connections, mode, 0, data)
+    }
+    fn send_on_lane(
+        &self,
+        connections: &[ConnectionHandle],
+        mode: SendMode,
+        lane: u16,
+        data: &[u8],
+    ) -> TransportResult<()> {
+        let now = self.network.clock.now();
+        let mut state = self.network.state.borrow_mut();
+        // nothing is sent unless every target can be sent to
+        let mut targets = Vec::with_capacity(connections.len());
+        for handle in connections {
+            let Some(link) = state.links.get(handle) else {
+                return Err(format!("Unknown connection {:?}", handle));
+            };
+            if link.state == LinkState::Closed {
+                return Err(format!("Connection {:?} is closed", handle));
+            }
+            let lane_count = state.lane_counts.get(&(*handle, self.peer)).cloned().unwrap_or(1);
+            if lane as usize >= lane_count {
+                return Err(format!("Lane {} is not configured on {:?}", lane, handle));
+            }
+            targets.push((*handle, link.counterpart(self.peer).0));
+        }
+        for (handle, remote) in targets {
+            *state.sent_on_lane.entry(lane).or_default() += 1;
+            if mode == SendMode::Unreliable && state.should_drop_unreliable() {
+                continue;
+            }
+            state.schedule(now, remote, Delivery::Message(handle, Vec::from(data)));
+        }
+        Ok(())
+    }
+    fn accept(&self, connection: ConnectionHandle) -> TransportResult<()> {
+        let now = self.network.clock.now();
+        let mut state = self.network.state.borrow_mut();
+        let link = state
+            .links
+            .get(&connection)
+            .ok_or_else(|| format!("Unknown connection {:?}", connection))?;
+        if link.server != self.peer || link.state != LinkState::Connecting {
+            return Err("Cannot accept the connection".to_string());
+        }
+        let (client, client_endpoint, server_endpoint) =
+            (link.client, link.client_endpoint, link.server_endpoint);
+        // server side completes immediately, the client learns about it after the latency
+        state.in_flight.push_front(InFlight {
+            deliver_at: now,
+            to: self.peer,
+            delivery: Delivery::Event(TransportEvent::Connected(connection, client_endpoint)),
+        });
+        let event = TransportEvent::Connected(connection, server_endpoint);
+        state.schedule(now, client, Delivery::Event(event));
+        Ok(())
+    }
+    fn close(&self, connection: ConnectionHandle, _reason: &str) {
+        let now = self.network.clock.now();
+        let mut state = self.network.state.borrow_mut();
+        let Some(link) = state.links.get_mut(&connection) else {
+            return;
+        };
+        if link.state == LinkState::Closed {
+            return;
+        }
+        link.state = LinkState::Closed;
+        let (remote, own_endpoint) = link.counterpart(self.peer);
+        // messages already sent by this peer are still delivered before the disconnect
+        let event = TransportEvent::Disconnected(connection, own_endpoint);
+        state.schedule(now, remote, Delivery::Event(event));
+    }
+    fn set_accept_config(&self, config: &ConnectionConfig) -> TransportResult<()> {
+        let mut state = self.network.state.borrow_mut();
+        state.accept_lane_counts.insert(self.peer, config.lane_count());
+        Ok(())
+    }
+    fn configure_connection(&self, connection: ConnectionHandle, config: &ConnectionConfig) -> TransportResult<()> {
+        let mut state = self.network.state.borrow_mut();
+        if !state.links.contains_key(&connection) {
+            return Err(format!("Unknown connection {:?}", connection));
+        }
+        state.lane_counts.insert((connection, self.peer), config.lane_count());
+        Ok(())
+    }
+    fn connection_status(&self, connection: ConnectionHandle) -> Option<ConnectionStatus> {
+        let state = self.network.state.borrow();
+        let link = state.links.get(&connection)?;
+        if link.state == LinkState::Closed {
+            return None;
+        }
+        Some(ConnectionStatus {
+            ping: Some(state.latency * 2),
+            send_rate: state.send_rate,
+            queued_bytes: 0,
+        })
+    }
+}
@@ -0,0 +1,155 @@
+use std::cell::RefCell;
+
+use bimap::BiHashMap;
+
+use super::{ConnectionHandle, ConnectionStatus, SendMode, Transport, TransportEvent, TransportResult};
+use crate::connection_config::ConnectionConfig;
+
+// (index of the inner transport, handle inside of it)
+type InnerHandle = (usize, ConnectionHandle);
+
+/// Several server transports behind one `Transport`, e.g. GNS for native clients
+/// and WebSocket for browsers. Handles are remapped so they never collide
+pub struct MultiTransport {
+    transports: Vec<Box<dyn Transport>>,
+    handles: RefCell<BiHashMap<ConnectionHandle, InnerHandle>>,
+    next_handle: RefCell<u64>,
+    // reported as disconnected, forgotten on the next poll so the event can still be mapped
+    disconnected: RefCell<Vec<ConnectionHandle>>,
+}
+impl MultiTransport {
+    pub fn new(transports: Vec<Box<dyn Transport>>) -> MultiTransport {
+        MultiTransport {
+            transports,
+            handles: Default::default(),
+            next_handle: RefCell::new(0),
+            disconnected: Default::default(),
+        }
+    }
+    pub fn transports(&self) -> &[Box<dyn Transport>] {
+        &self.transports
+    }
+    fn outer(&self, inner: InnerHandle) -> ConnectionHandle {
+        if let Some(handle) = self.handles.borrow().get_by_right(&inner) {
+            return *handle;
+        }
+        let mut next_handle = self.next_handle.borrow_mut();
+        *next_handle += 1;
+        let handle = ConnectionHandle(*next_handle);
+        self.handles.borrow_mut().insert(handle, inner);
+        handle
+    }
+    fn inner(&self, handle: &ConnectionHandle) -> TransportResult<InnerHandle> {
+        self.handles
+            .borrow()
+            .get_by_left(handle)
+            .cloned()
+            .ok_or_else(|| format!("Unknown connection {:?}", handle))
+    }
+    fn map_event(&self, index: usize, event: TransportEvent) -> TransportEvent {
+        match event {
+            TransportEvent::Connecting(handle, endpoint) => {
+                TransportEvent::Connecting(self.outer((index, handle)), endpoint)
+            }
+            TransportEvent::Connected(handle, endpoint) => {
+                TransportEvent::Connected(self.outer((index, handle)), endpoint)
+            }
+            TransportEvent::Disconnected(handle, endpoint) => {
+                let outer = self.outer((index, handle));
+                self.disconnected.borrow_mut().push(outer);
+                TransportEvent::Disconnected(outer, endpoint)
+            }
+        }
+    }
+}
+impl Transport for MultiTransport {
+    fn poll(&self) {
+        for handle in self.disconnected.take() {
+            self.handles.borrow_mut().remove_by_left(&handle);
+        }
+        self.transports.iter().for_each(|transport| transport.poll());
+    }
+    fn poll_events(&self, max: usize, handler: &mut dyn FnMut(TransportEvent)) -> usize {
+        let mut processed = 0;
+        for (index, transport) in self.transports.iter().enumerate() {
+            if processed >= max {
+                break;
+            }
+            processed += transport.poll_events(max - processed, &mut |event| {
+                handler(self.map_event(index, event))
+            });
+        }
+        processed
+    }
+    fn poll_messages(&self, max: usize, handler: &mut dyn FnMut(ConnectionHandle, &[u8])) -> usize {
+        let mut processed = 0;
+        for (index, transport) in self.transports.iter().enumerate() {
+            if processed >= max {
+                break;
+            }
+            processed += transport.poll_messages(max - processed, &mut |handle, data| {
+                let outer = self.handles.borrow().get_by_right(&(index, handle)).cloned();
+                if let Some(outer) = outer {
+                    handler(outer, data);
+                }
+            });
+        }
+        processed
+    }
+    fn send(&self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()> {
+        self.send_on_lane(connections, mode, 0, data)
+    }
+    fn send_on_lane(
+        &self,
+        connections: &[ConnectionHandle],
+        mode: SendMode,
+        lane: u16,
+        data: &[u8],
+    ) -> TransportResult<()> {
+        let mut per_transport = vec![Vec::new(); self.transports.len()];
+        for handle in connections {
+            let (index, inner) = self.inner(handle)?;
+            per_transport[index].push(inner);
+        }
+        for (transport, targets) in self.transports.iter().zip(per_transport) {
+            if !targets.is_empty() {
+                transport.send_on_lane(&targets, mode, lane, data)?;
+            }
+        }
+        Ok(())
+    }
+    fn accept(&self, connection: ConnectionHandle) -> TransportResult<()> {
+        let (index, inner) = self.inner(&connection)?;
+        self.transports[index].accept(inner)
+    }
+    fn close(&self, connection: ConnectionHandle, reason: &str) {
+        if let Ok((index, inner)) = self.inner(&connection) {
+            self.transports[index].close(inner, reason);
+            self.handles.borrow_mut().remove_by_left(&connection);
+        }
+    }
+    fn set_accept_config(&self, config: &ConnectionConfig) -> TransportResult<()> {
+        self.transports
+            .iter()
+            .try_for_each(|transport| transport.set_accept_config(config))
+    }
+    fn configure_connection(
+        &self,
+        connection: ConnectionHandle,
+        config: &ConnectionConfig,
+    ) -> TransportResult<()> {
+        let (index, inner) = self.inner(&connection)?;
+        self.transports[index].configure_connection(inner, config)
+    }
+    fn transport_index(&self, connection: ConnectionHandle) -> usize {
+        self.inner(&connection).map_or(0, |(index, _)| index)
+    }
+    fn connection_status(&self, connection: ConnectionHandle) -> Option<ConnectionStatus> {
+        let (index, inner) = self.inner(&connection).ok()?;
+        self.transports[index].connection_status(inner)
+    }
+    #[cfg(feature = "gns")]
+    fn gns_server_socket(&self) -> Option<&super::gns::GnsServerSocket> {
+        self.transports.iter().find_map(|transport| transport.gns_server_socket())
+    }
+}
@@ -0,0 +1,362 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+};
+
+use tungstenite::{
+    handshake::{client::ClientHandshake, server::NoCallback, server::ServerHandshake, MidHandshake},
+    Error, HandshakeError, Message, WebSocket,
+};
+
+use super::{ConnectionHandle, Connector, SendMode, Transport, TransportEvent, TransportResult};
+use crate::{connection_config::ConnectionConfig, Endpoint};
+
+type ServerMidHandshake = MidHandshake<ServerHandshake<TcpStream, NoCallback>>;
+type ClientMidHandshake = MidHandshake<ClientHandshake<TcpStream>>;
+
+/// Options of the `ws://` listener that runs next to the GNS socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebSocketSettings {
+    pub port: u16,
+    /// Drop unreliable messages to a peer while previous data is still waiting in its
+    /// TCP send buffer. Reliable messages are always queued
+    pub drop_unreliable_on_congestion: bool,
+}
+impl WebSocketSettings {
+    pub fn new(port: u16) -> WebSocketSettings {
+        WebSocketSettings {
+            port,
+            drop_unreliable_on_congestion: true,
+        }
+    }
+}
+
+struct Peer {
+    socket: WebSocket<TcpStream>,
+    endpoint: Endpoint,
+    // server side peers exchange messages only after `accept`
+    open: bool,
+    // last flush could not write everything
+    congested: bool,
+}
+
+// connections after the WebSocket handshake, shared by the server and client transports
+#[derive(Default)]
+struct Peers {
+    peers: HashMap<ConnectionHandle, Peer>,
+    events: VecDeque<TransportEvent>,
+    messages: VecDeque<(ConnectionHandle, Vec<u8>)>,
+    next_handle: u64,
+    drop_unreliable_on_congestion: bool,
+    dropped_unreliable: u64,
+}
+impl Peers {
+    fn next_handle(&mut self) -> ConnectionHandle {
+        self.next_handle += 1;
+        ConnectionHandle(self.next_handle)
+    }
+    fn read(&mut self) {
+        let mut broken = Vec::new();
+        for (handle, peer) in self.peers.iter_mut().filter(|(_, peer)| peer.open) {
+            if peer.congested {
+                peer.congested = is_would_block(peer.socket.flush());
+            }
+            loop {
+                match peer.socket.read() {
+                    Ok(Message::Binary(data)) => self.messages.push_back((*handle, data)),
+                    // text, ping, pong and close frames carry no omgpp messages
+                    Ok(_) => (),
+                    Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => {
+                        broken.push(*handle);
+                        break;
+                    }
+                }
+            }
+        }
+        for handle in broken {
+            if let Some(peer) = self.peers.remove(&handle) {
+                self.events
+                    .push_back(TransportEvent::Disconnected(handle, peer.endpoint));
+            }
+        }
+    }
+    fn take_events(&mut self, max: usize) -> Vec<TransportEvent> {
+        let count = max.min(self.events.len());
+        self.events.drain(..count).collect()
+    }
+    fn take_messages(&mut self, max: usize) -> Vec<(ConnectionHandle, Vec<u8>)> {
+        let count = max.min(self.messages.len());
+        self.messages.drain(..count).collect()
+    }
+    fn send(&mut self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()> {
+        for handle in connections {
+            let peer = self
+                .peers
+                .get_mut(handle)
+                .filter(|peer| peer.open)
+                .ok_or_else(|| format!("Unknown connection {:?}", handle))?;
+            // the stream is ordered and reliable anyway, unreliable only means "may be dropped"
+            if mode == SendMode::Unreliable && self.drop_unreliable_on_congestion && peer.congested {
+                self.dropped_unreliable += 1;
+                continue;
+            }
+            match peer.socket.write(Message::Binary(Vec::from(data))) {
+                Ok(()) => (),
+                Err(Error::WriteBufferFull(_)) if mode == SendMode::Unreliable => {
+                    self.dropped_unreliable += 1;
+                    continue;
+                }
+                Err(err) => return Err(format!("Cannot send message: {}", err)),
+            }
+            // a broken peer is detected and reported by the next `read`
+            peer.congested = is_would_block(peer.socket.flush());
+        }
+        Ok(())
+    }
+    fn close(&mut self, connection: ConnectionHandle) {
+        if let Some(mut peer) = self.peers.remove(&connection) {
+            _ = peer.socket.close(None);
+            _ = peer.socket.flush();
+        }
+    }
+}
+
+fn is_would_block(result: tungstenite::Result<()>) -> bool {
+    matches!(result, Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock)
+}
+
+fn to_endpoint(address: SocketAddr) -> Endpoint {
+    Endpoint {
+        ip: address.ip(),
+        port: address.port(),
+    }
+}
+
+/// Plain `ws://` listener. Every binary frame carries one `GeneralOmgppMessage`
+pub struct WebSocketServerTransport {
+    listener: TcpListener,
+    handshakes: RefCell<Vec<(ConnectionHandle, Endpoint, ServerMidHandshake)>>,
+    peers: RefCell<Peers>,
+}
+impl WebSocketServerTransport {
+    pub fn listen(ip: IpAddr, settings: WebSocketSettings) -> TransportResult<WebSocketServerTransport> {
+        let listener = TcpListener::bind((ip, settings.port)).map_err(|err| err.to_string())?;
+        listener.set_nonblocking(true).map_err(|err| err.to_string())?;
+        Ok(WebSocketServerTransport {
+            listener,
+            handshakes: Default::default(),
+            peers: RefCell::new(Peers {
+                drop_unreliable_on_congestion: settings.drop_unreliable_on_congestion,
+                ..Default::default()
+            }),
+        })
+    }
+    pub fn local_endpoint(&self) -> TransportResult<Endpoint> {
+        let address = self.listener.local_addr().map_err(|err| err.to_string())?;
+        Ok(to_endpoint(address))
+    }
+    /// Number of unreliable messages dropped because of congestion
+    pub fn dropped_unreliable(&self) -> u64 {
+        self.peers.borrow().dropped_unreliable
+    }
+    fn accept_streams(&self) {
+        while let Ok((stream, address)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_err() {
+                continue;
+            }
+            let endpoint = to_endpoint(address);
+            match tungstenite::accept(stream) {
+                Ok(socket) => self.handshake_completed(socket, endpoint),
+                Err(HandshakeError::Interrupted(mid)) => {
+                    let handle = self.peers.borrow_mut().next_handle();
+                    self.handshakes.borrow_mut().push((handle, endpoint, mid));
+                }
+                Err(HandshakeError::Failure(_)) => (),
+            }
+        }
+    }
+    fn continue_handshakes(&self) {
+        let handshakes = std::mem::take(&mut *self.handshakes.borrow_mut());
+        for (handle, endpoint, mid) in handshakes {
+            match mid.handshake() {
+                Ok(socket) => self.insert_peer(handle, socket, endpoint),
+                Err(HandshakeError::Interrupted(mid)) => {
+                    self.handshakes.borrow_mut().push((handle, endpoint, mid))
+                }
+                Err(HandshakeError::Failure(_)) => (),
+            }
+        }
+    }
+    fn handshake_completed(&self, socket: WebSocket<TcpStream>, endpoint: Endpoint) {
+        let handle = self.peers.borrow_mut().next_handle();
+        self.insert_peer(handle, socket, endpoint);
+    }
+    fn insert_peer(&self, handle: ConnectionHandle, socket: WebSocket<TcpStream>, endpoint: Endpoint) {
+        let mut peers = self.peers.borrow_mut();
+        peers.peers.insert(
+            handle,
+            Peer {
+                socket,
+                endpoint,
+                open: false,
+                congested: false,
+            },
+        );
+        peers
+            .events
+            .push_back(TransportEvent::Connecting(handle, endpoint));
+    }
+}
+impl Transport for WebSocketServerTransport {
+    fn poll(&self) {
+        self.accept_streams();
+        self.continue_handshakes();
+        self.peers.borrow_mut().read();
+    }
+    fn poll_events(&self, max: usize, handler: &mut dyn FnMut(TransportEvent)) -> usize {
+        // handlers call back into the transport, so nothing may stay borrowed
+        let events = self.peers.borrow_mut().take_events(max);
+        let count = events.len();
+        events.into_iter().for_each(handler);
+        count
+    }
+    fn poll_messages(&self, max: usize, handler: &mut dyn FnMut(ConnectionHandle, &[u8])) -> usize {
+        let messages = self.peers.borrow_mut().take_messages(max);
+        let count = messages.len();
+        for (handle, data) in messages {
+            handler(handle, &data);
+        }
+        count
+    }
+    fn send(&self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()> {
+        self.peers.borrow_mut().send(connections, mode, data)
+    }
+    fn accept(&self, connection: ConnectionHandle) -> TransportResult<()> {
+        let mut peers = self.peers.borrow_mut();
+        let peer = peers
+            .peers
+            .get_mut(&connection)
+            .filter(|peer| !peer.open)
+            .ok_or("Cannot accept the connection")?;
+        peer.open = true;
+        let endpoint = peer.endpoint;
+        peers
+            .events
+            .push_back(TransportEvent::Connected(connection, endpoint));
+        Ok(())
+    }
+    fn close(&self, connection: ConnectionHandle, _reason: &str) {
+        self.peers.borrow_mut().close(connection)
+    }
+}
+
+/// `ws://` connection to a server, mostly useful to test the WebSocket listener natively
+pub struct WebSocketClientTransport {
+    handshake: RefCell<Option<(ConnectionHandle, Endpoint, ClientMidHandshake)>>,
+    peers: RefCell<Peers>,
+}
+impl WebSocketClientTransport {
+    pub fn connect(endpoint: &Endpoint) -> TransportResult<(WebSocketClientTransport, ConnectionHandle)> {
+        let address = SocketAddr::new(endpoint.ip, endpoint.port);
+        let stream = TcpStream::connect(address).map_err(|err| err.to_string())?;
+        stream.set_nonblocking(true).map_err(|err| err.to_string())?;
+        let _ = stream.set_nodelay(true);
+        let url = format!("ws://{}/", address);
+
+        let mut peers = Peers::default();
+        let handle = peers.next_handle();
+        peers
+            .events
+            .push_back(TransportEvent::Connecting(handle, *endpoint));
+        let transport = WebSocketClientTransport {
+            handshake: RefCell::new(None),
+            peers: RefCell::new(peers),
+        };
+        match tungstenite::client(url, stream) {
+            Ok((socket, _response)) => transport.handshake_completed(handle, socket, *endpoint),
+            Err(HandshakeError::Interrupted(mid)) => {
+                *transport.handshake.borrow_mut() = Some((handle, *endpoint, mid))
+            }
+            Err(HandshakeError::Failure(err)) => return Err(err.to_string()),
+        }
+        Ok((transport, handle))
+    }
+    fn continue_handshake(&self) {
+        let handshake = self.handshake.borrow_mut().take();
+        if let Some((handle, endpoint, mid)) = handshake {
+            match mid.handshake() {
+                Ok((socket, _response)) => self.handshake_completed(handle, socket, endpoint),
+                Err(HandshakeError::Interrupted(mid)) => {
+                    *self.handshake.borrow_mut() = Some((handle, endpoint, mid))
+                }
+                Err(HandshakeError::Failure(_)) => self
+                    .peers
+                    .borrow_mut()
+                    .events
+                    .push_back(TransportEvent::Disconnected(handle, endpoint)),
+            }
+        }
+    }
+    fn handshake_completed(&self, handle: ConnectionHandle, socket: WebSocket<TcpStream>, endpoint: Endpoint) {
+        let mut peers = self.peers.borrow_mut();
+        peers.peers.insert(
+            handle,
+            Peer {
+                socket,
+                endpoint,
+                open: true,
+                congested: false,
+            },
+        );
+        peers
+            .events
+            .push_back(TransportEvent::Connected(handle, endpoint));
+    }
+}
+impl Transport for WebSocketClientTransport {
+    fn poll(&self) {
+        self.continue_handshake();
+        self.peers.borrow_mut().read();
+    }
+    fn poll_events(&self, max: usize, handler: &mut dyn FnMut(TransportEvent)) -> usize {
+        let events = self.peers.borrow_mut().take_events(max);
+        let count = events.len();
+        events.into_iter().for_each(handler);
+        count
+    }
+    fn poll_messages(&self, max: usize, handler: &mut dyn FnMut(ConnectionHandle, &[u8])) -> usize {
+        let messages = self.peers.borrow_mut().take_messages(max);
+        let count = messages.len();
+        for (handle, data) in messages {
+            handler(handle, &data);
+        }
+        count
+    }
+    fn send(&self, connections: &[ConnectionHandle], mode: SendMode, data: &[u8]) -> TransportResult<()> {
+        self.peers.borrow_mut().send(connections, mode, data)
+    }
+    fn accept(&self, _connection: ConnectionHandle) -> TransportResult<()> {
+        Err("Client transport does not accept connections".to_string())
+    }
+    fn close(&self, connection: ConnectionHandle, _reason: &str) {
+        self.handshake.borrow_mut().take();
+        self.peers.borrow_mut().close(connection)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WebSocketConnector;
+impl Connector for WebSocketConnector {
+    // GNS options do not apply to WebSockets
+    fn connect(
+        &self,
+        endpoint: &Endpoint,
+        _config: &ConnectionConfig,
+    ) -> TransportResult<(Box<dyn Transport>, ConnectionHandle)> {
+        let (transport, handle) = WebSocketClientTransport::connect(endpoint)?;
+        Ok((Box::new(transport), handle))
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use protobuf::MessageFull;
+use uuid::Uuid;
+
+use crate::Endpoint;
+
+/// `arg_type` of an rpc reply carrying a utf8 error reason instead of the reply message
+pub const RPC_ERROR_ARG_TYPE: i64 = -1;
+
+/// Type id derived from the protobuf full name, e.g. `game.PlayerMove`. It is the same in every build
+/// and language, as long as the message keeps its name and package
+pub fn stable_type_id(full_name: &str) -> i64 {
+    let digest = md5::compute(full_name.trim_start_matches('.'));
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    // non negative to stay apart from hand picked negative ids
+    i64::from_le_bytes(bytes) & i64::MAX
+}
+
+/// Maps protobuf message types to the `msg_type` they are sent with.
+/// Types that are not registered use their `stable_type_id`
+#[derive(Debug, Default)]
+pub struct MessageRegistry {
+    by_name: HashMap<String, i64>,
+    by_id: HashMap<i64, String>,
+}
+impl MessageRegistry {
+    pub fn new() -> MessageRegistry {
+        Default::default()
+    }
+    /// Register `M` with its `stable_type_id`
+    pub fn register<M: MessageFull>(&mut self) -> Result<i64, String> {
+        let name = M::descriptor().full_name().to_string();
+        let id = stable_type_id(&name);
+        self.insert(name, id)
+    }
+    /// Register `M` with a hand picked id, e.g. to keep the ids of an existing protocol
+    pub fn register_with_id<M: MessageFull>(&mut self, id: i64) -> Result<i64, String> {
+        self.insert(M::descriptor().full_name().to_string(), id)
+    }
+    fn insert(&mut self, name: String, id: i64) -> Result<i64, String> {
+        match (self.by_name.get(&name), self.by_id.get(&id)) {
+            (Some(existing), _) if *existing == id => return Ok(id),
+            (Some(existing), _) => return Err(format!("{} is already registered with id {}", name, existing)),
+            (None, Some(other)) => return Err(format!("Id {} is already used by {}", id, other)),
+            (None, None) => (),
+        }
+        self.by_id.insert(id, name.clone());
+        self.by_name.insert(name, id);
+        Ok(id)
+    }
+    pub fn type_id<M: MessageFull>(&self) -> i64 {
+        let descriptor = M::descriptor();
+        let name = descriptor.full_name();
+        self.by_name.get(name).copied().unwrap_or_else(|| stable_type_id(name))
+    }
+    /// Full name of the registered message sent with `id`
+    pub fn type_name(&self, id: i64) -> Option<&str> {
+        self.by_id.get(&id).map(String::as_str)
+    }
+    /// Serialize `message` and return it with its type id
+    pub fn encode<M: MessageFull>(&self, message: &M) -> Result<(i64, Vec<u8>), String> {
+        let data = message
+            .write_to_bytes()
+            .map_err(|err| format!("Cannot encode {}: {}", M::descriptor().full_name(), err))?;
+        Ok((self.type_id::<M>(), data))
+    }
+}
+
+type TypedMessageCallback<T> = Box<dyn Fn(&T, &Uuid, &Endpoint, &[u8]) -> Result<(), String> + 'static>;
+
+/// Per type message handlers. Payloads are decoded before the handler runs
+pub struct TypedMessageHandlers<T> {
+    registry: MessageRegistry,
+    handlers: HashMap<i64, TypedMessageCallback<T>>,
+}
+impl<T> TypedMessageHandlers<T> {
+    pub fn new() -> TypedMessageHandlers<T> {
+        TypedMessageHandlers {
+            registry: MessageRegistry::new(),
+            handlers: HashMap::new(),
+        }
+    }
+    pub fn registry(&self) -> &MessageRegistry {
+        &self.registry
+    }
+    pub fn registry_mut(&mut self) -> &mut MessageRegistry {
+        &mut self.registry
+    }
+    /// Handle messages of type `M`, replacing the previous handler of `M`. Registers `M` if needed
+    pub fn set_handler<M: MessageFull>(
+        &mut self,
+        handler: impl Fn(&T, &Uuid, &Endpoint, M) + 'static,
+    ) -> Result<(), String> {
+        let id = match self.registry.by_name.get(M::descriptor().full_name()) {
+            Some(id) => *id,
+            None => self.registry.register::<M>()?,
+        };
+        let decode_and_handle = move |item: &T, peer: &Uuid, endpoint: &Endpoint, data: &[u8]| {
+            let message = M::parse_from_bytes(data)
+                .map_err(|err| format!("Cannot decode {}: {}", M::descriptor().full_name(), err))?;
+            handler(item, peer, endpoint, message);
+            Ok(())
+        };
+        self.handlers.insert(id, Box::new(decode_and_handle));
+        Ok(())
+    }
+    pub fn remove_handler<M: MessageFull>(&mut self) -> bool {
+        let id = self.registry.type_id::<M>();
+        self.handlers.remove(&id).is_some()
+    }
+    pub fn contains(&self, msg_type: i64) -> bool {
+        self.handlers.contains_key(&msg_type)
+    }
+    /// Decode and handle `data`. `None` if no handler is set for `msg_type`, an error if decoding failed
+    pub fn dispatch(
+        &self,
+        item: &T,
+        peer: &Uuid,
+        endpoint: &Endpoint,
+        msg_type: i64,
+        data: &[u8],
+    ) -> Option<Result<(), String>> {
+        self.handlers
+            .get(&msg_type)
+            .map(|handler| handler(item, peer, endpoint, data))
+    }
+}
+impl<T> Default for TypedMessageHandlers<T> {
+    fn default() -> Self {
+        TypedMessageHandlers::new()
+    }
+}
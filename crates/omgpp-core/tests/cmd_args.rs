@@ -0,0 +1,58 @@
+use omgpp_core::cmd_args::{ArgSpec, ArgType, ArgValue, CmdSchema};
+use uuid::Uuid;
+
+fn args(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn teleport() -> CmdSchema {
+    CmdSchema::new("Move a player")
+        .arg(ArgSpec::new("player", ArgType::Uuid).with_description("Who to move"))
+        .arg(ArgSpec::new("x", ArgType::Float))
+        .arg(ArgSpec::new("mode", ArgType::Enum(args(&["walk", "fly"]))).with_default(ArgValue::Enum("walk".to_string())))
+        .arg(ArgSpec::new("silent", ArgType::Bool).optional())
+}
+
+#[test]
+fn arguments_are_converted() {
+    let player = Uuid::from_u128(7);
+    let parsed = teleport()
+        .parse(&args(&[&player.to_string(), "1.5", "fly", "yes"]))
+        .unwrap();
+    assert_eq!(parsed.uuid("player"), Some(player));
+    assert_eq!(parsed.float("x"), Some(1.5));
+    assert_eq!(parsed.str("mode"), Some("fly"));
+    assert_eq!(parsed.bool("silent"), Some(true));
+}
+
+#[test]
+fn omitted_optional_arguments_use_defaults() {
+    let parsed = teleport().parse(&args(&[&Uuid::nil().to_string(), "3"])).unwrap();
+    assert_eq!(parsed.float("x"), Some(3.0));
+    assert_eq!(parsed.str("mode"), Some("walk"));
+    assert_eq!(parsed.get("silent"), None);
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    let schema = teleport();
+    let player = Uuid::nil().to_string();
+    assert_eq!(schema.parse(&args(&[&player])), Err("Missing argument \"x\"".to_string()));
+    assert_eq!(
+        schema.parse(&args(&["not-a-uuid", "1"])),
+        Err("Argument \"player\": expected uuid, got \"not-a-uuid\"".to_string())
+    );
+    assert!(schema.parse(&args(&[&player, "1", "swim"])).is_err());
+    assert!(schema.parse(&args(&[&player, "1", "fly", "maybe"])).is_err());
+    assert!(schema.parse(&args(&[&player, "1", "fly", "no", "extra"])).is_err());
+    let count = CmdSchema::new("").arg(ArgSpec::new("count", ArgType::Int));
+    assert!(count.parse(&args(&["1.5"])).is_err());
+}
+
+#[test]
+fn usage_describes_arguments() {
+    assert_eq!(
+        teleport().usage("tp"),
+        "tp <player:uuid> <x:float> [mode:walk|fly=walk] [silent:bool]"
+    );
+}
@@ -0,0 +1,139 @@
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use omgpp_core::{
+    cmd_args::{ArgSpec, ArgType, CmdSchema},
+    cmd_handler::{CmdError, CmdHandler, CmdHandlerContainer, CmdPermissions},
+    messages::general_message::general_omgpp_message::CmdRequest,
+    Endpoint,
+};
+use uuid::Uuid;
+
+#[derive(Default)]
+struct Calls(Cell<usize>);
+
+fn container() -> CmdHandlerContainer<Calls> {
+    let mut container = CmdHandlerContainer::new();
+    let count = |calls: &Calls, _: &Uuid, _: &Endpoint, _: &CmdHandler<Calls>, _: &CmdRequest| {
+        calls.0.set(calls.0.get() + 1)
+    };
+    container
+        .register_handler(CmdHandler::new("public", false, Box::new(count)))
+        .unwrap();
+    container
+        .register_handler(CmdHandler::new("private", true, Box::new(count)))
+        .unwrap();
+    container
+        .register_handler(CmdHandler::new("kick", false, Box::new(count)).with_roles(&["admin", "moderator"]))
+        .unwrap();
+    let add_schema = CmdSchema::new("Add to the counter").arg(ArgSpec::new("amount", ArgType::Int));
+    container
+        .register_handler(CmdHandler::typed(
+            "add",
+            false,
+            add_schema,
+            Box::new(|calls: &Calls, _, _, _, args| {
+                calls.0.set(calls.0.get() + args.int("amount").unwrap() as usize)
+            }),
+        ))
+        .unwrap();
+    container
+}
+
+fn run(container: &CmdHandlerContainer<Calls>, calls: &Calls, cmd: &str, authenticated: bool, roles: &[&str]) -> Result<(), CmdError> {
+    run_with_args(container, calls, cmd, &[], authenticated, roles)
+}
+
+fn run_with_args(
+    container: &CmdHandlerContainer<Calls>,
+    calls: &Calls,
+    cmd: &str,
+    args: &[&str],
+    authenticated: bool,
+    roles: &[&str],
+) -> Result<(), CmdError> {
+    let roles = roles.iter().map(|role| role.to_string()).collect::<HashSet<_>>();
+    let permissions = CmdPermissions {
+        authenticated,
+        roles: &roles,
+    };
+    let request = CmdRequest {
+        cmd: cmd.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        ..Default::default()
+    };
+    let endpoint = Endpoint {
+        ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        port: 1,
+    };
+    container.handle(calls, &Uuid::nil(), &endpoint, &request, &permissions)
+}
+
+#[test]
+fn auth_required_is_enforced() {
+    let container = container();
+    let calls = Calls::default();
+    assert_eq!(run(&container, &calls, "public", false, &[]), Ok(()));
+    assert_eq!(run(&container, &calls, "private", false, &[]), Err(CmdError::NotAuthenticated));
+    assert_eq!(calls.0.get(), 1);
+    assert_eq!(run(&container, &calls, "private", true, &[]), Ok(()));
+    assert_eq!(calls.0.get(), 2);
+}
+
+#[test]
+fn any_required_role_grants_access() {
+    let container = container();
+    let calls = Calls::default();
+    assert_eq!(run(&container, &calls, "kick", false, &["admin"]), Err(CmdError::NotAuthenticated));
+    assert_eq!(
+        run(&container, &calls, "kick", true, &["player"]),
+        Err(CmdError::MissingRole(vec!["admin".to_string(), "moderator".to_string()]))
+    );
+    assert_eq!(calls.0.get(), 0);
+    assert_eq!(run(&container, &calls, "kick", true, &["player", "moderator"]), Ok(()));
+    assert_eq!(calls.0.get(), 1);
+}
+
+#[test]
+fn unknown_command_is_reported() {
+    let container = container();
+    let calls = Calls::default();
+    assert_eq!(run(&container, &calls, "missing", true, &["admin"]), Err(CmdError::NotFound));
+}
+
+#[test]
+fn typed_handler_gets_converted_arguments() {
+    let container = container();
+    let calls = Calls::default();
+    assert_eq!(run_with_args(&container, &calls, "add", &["5"], false, &[]), Ok(()));
+    assert_eq!(calls.0.get(), 5);
+    let error = run_with_args(&container, &calls, "add", &["five"], false, &[]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Argument \"amount\": expected int, got \"five\". Usage: add <amount:int>"
+    );
+    assert_eq!(calls.0.get(), 5);
+}
+
+#[test]
+fn help_lists_only_permitted_commands() {
+    let container = container();
+    let roles = HashSet::new();
+    let guest = CmdPermissions {
+        authenticated: false,
+        roles: &roles,
+    };
+    assert_eq!(container.help(&guest), vec!["add <amount:int> - Add to the counter", "public"]);
+    assert_eq!(container.help_for("private", &guest), None);
+
+    let roles = HashSet::from(["admin".to_string()]);
+    let admin = CmdPermissions {
+        authenticated: true,
+        roles: &roles,
+    };
+    assert_eq!(container.help(&admin).len(), 4);
+    assert_eq!(container.help_for("add", &admin), Some(vec!["add <amount:int>".to_string(), "Add to the counter".to_string()]));
+}
@@ -0,0 +1,70 @@
+use std::{
+    cell::RefCell,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use omgpp_core::{
+    messages::general_message::{
+        general_omgpp_message::{CmdRequest, RpcCall},
+        GeneralOmgppMessage,
+    },
+    typed_message::{stable_type_id, MessageRegistry, TypedMessageHandlers},
+    Endpoint,
+};
+use protobuf::MessageFull;
+use uuid::Uuid;
+
+const ENDPOINT: Endpoint = Endpoint {
+    ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+    port: 1,
+};
+
+#[test]
+fn stable_ids_depend_only_on_the_full_name() {
+    let id = stable_type_id("GeneralOmgppMessage.CmdRequest");
+    assert!(id >= 0);
+    assert_eq!(id, stable_type_id(".GeneralOmgppMessage.CmdRequest"));
+    assert_ne!(id, stable_type_id("GeneralOmgppMessage.RpcCall"));
+    assert_eq!(MessageRegistry::new().type_id::<CmdRequest>(), id);
+}
+
+#[test]
+fn registry_rejects_conflicting_ids() {
+    let mut registry = MessageRegistry::new();
+    assert_eq!(registry.register_with_id::<CmdRequest>(7), Ok(7));
+    // registering again with the same id is fine
+    assert_eq!(registry.register_with_id::<CmdRequest>(7), Ok(7));
+    assert!(registry.register::<CmdRequest>().is_err());
+    assert!(registry.register_with_id::<RpcCall>(7).is_err());
+    assert_eq!(registry.type_id::<CmdRequest>(), 7);
+    assert_eq!(registry.type_name(7), Some("GeneralOmgppMessage.CmdRequest"));
+
+    let id = registry.register::<GeneralOmgppMessage>().unwrap();
+    assert_eq!(id, stable_type_id(GeneralOmgppMessage::descriptor().full_name()));
+}
+
+#[test]
+fn handlers_decode_before_running() {
+    let received = RefCell::new(Vec::new());
+    let mut handlers = TypedMessageHandlers::<RefCell<Vec<String>>>::new();
+    handlers
+        .set_handler(|received: &RefCell<Vec<String>>, _, _, request: CmdRequest| {
+            received.borrow_mut().push(request.cmd)
+        })
+        .unwrap();
+    let id = handlers.registry().type_id::<CmdRequest>();
+
+    let mut request = CmdRequest::new();
+    request.cmd = "jump".to_string();
+    let (encoded_id, data) = handlers.registry().encode(&request).unwrap();
+    assert_eq!(encoded_id, id);
+    assert_eq!(handlers.dispatch(&received, &Uuid::nil(), &ENDPOINT, id, &data), Some(Ok(())));
+    assert_eq!(*received.borrow(), vec!["jump".to_string()]);
+
+    assert!(matches!(handlers.dispatch(&received, &Uuid::nil(), &ENDPOINT, id, &[0xff, 0xff]), Some(Err(_))));
+    assert_eq!(handlers.dispatch(&received, &Uuid::nil(), &ENDPOINT, id + 1, &data), None);
+    assert_eq!(received.borrow().len(), 1);
+
+    assert!(handlers.remove_handler::<CmdRequest>());
+    assert_eq!(handlers.dispatch(&received, &Uuid::nil(), &ENDPOINT, id, &data), None);
+}
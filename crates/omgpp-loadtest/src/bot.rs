@@ -0,0 +1,220 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+
+use client_server::{backend, client::Client};
+use omgpp_core::ConnectionState;
+use rand::Rng;
+
+use crate::{
+    payload,
+    scenario::Scenario,
+    stats::{Progress, Stats},
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+enum Phase {
+    Idle { until: Instant },
+    Connecting { since: Instant },
+    Connected { next_message: Instant, next_rpc: Instant, next_disconnect_roll: Instant },
+}
+
+/// Everything a bot needs from its thread
+#[derive(Clone)]
+pub struct BotContext {
+    pub scenario: Arc<Scenario>,
+    pub started: Instant,
+    pub stats: Rc<RefCell<Stats>>,
+    pub progress: Arc<Progress>,
+}
+impl BotContext {
+    fn now_us(&self) -> u64 {
+        self.started.elapsed().as_micros() as u64
+    }
+}
+
+/// One `Client` following the scenario
+pub struct Bot {
+    id: u32,
+    client: Client,
+    state: Rc<RefCell<ConnectionState>>,
+    phase: Phase,
+    next_request_id: u64,
+    context: BotContext,
+}
+impl Bot {
+    pub fn new(id: u32, connect_at: Instant, context: BotContext) -> Bot {
+        let scenario = &context.scenario;
+        let connector = match (&scenario.certificate, scenario.insecure || scenario.local_server) {
+            (Some(certificate), _) => backend::connector_with_certificate(certificate.clone()),
+            (None, true) => backend::dangerous_insecure_connector(),
+            (None, false) => backend::connector(),
+        };
+        let client = Client::with_connector(scenario.ip, scenario.port, connector);
+        let state = Rc::new(RefCell::new(ConnectionState::None));
+
+        let auth = scenario.auth.clone();
+        client.register_on_auth(move |_client, _endpoint| auth.clone());
+        let state_cloned = state.clone();
+        client.register_on_connection_state_changed(move |_client, _endpoint, new_state| {
+            *state_cloned.borrow_mut() = new_state;
+        });
+        let on_message_context = context.clone();
+        client.register_on_message(move |_client, _endpoint, _msg_type, data| {
+            on_message_context.received(id, &data);
+        });
+        let on_rpc_context = context.clone();
+        client.register_on_rpc(move |_client, _endpoint, _reliable, _method_id, _request_id, _arg_type, data| {
+            on_rpc_context.received(id, &data);
+        });
+        Bot {
+            id,
+            client,
+            state,
+            phase: Phase::Idle { until: connect_at },
+            next_request_id: 0,
+            context,
+        }
+    }
+    pub fn tick(&mut self, now: Instant) {
+        if self.client.transport().is_some() {
+            if let Err(reason) = self.client.process::<64>() {
+                self.error(&reason);
+            }
+        }
+        let state = self.state.borrow().clone();
+        match self.phase {
+            Phase::Idle { until } if now >= until => self.connect(now),
+            Phase::Idle { .. } => (),
+            Phase::Connecting { since } => match state {
+                ConnectionState::Connected => {
+                    let mut stats = self.context.stats.borrow_mut();
+                    stats.connect_succeeded += 1;
+                    stats.handshake_us.push((now - since).as_micros() as u64);
+                    self.context.progress.connected.fetch_add(1, Ordering::Relaxed);
+                    self.phase = Phase::Connected {
+                        next_message: now,
+                        next_rpc: now,
+                        next_disconnect_roll: now + Duration::from_secs(1),
+                    };
+                }
+                ConnectionState::Disconnected => self.connect_failed(now, "Connection refused or closed during handshake"),
+                _ if now - since > self.context.scenario.connect_timeout => {
+                    self.client.disconnect();
+                    self.connect_failed(now, "Handshake timed out");
+                }
+                _ => (),
+            },
+            Phase::Connected { .. } if state == ConnectionState::Disconnected => {
+                self.context.stats.borrow_mut().dropped += 1;
+                self.context.progress.connected.fetch_sub(1, Ordering::Relaxed);
+                self.phase = Phase::Idle { until: now + RECONNECT_DELAY };
+            }
+            Phase::Connected { .. } => self.run_scenario(now),
+        }
+    }
+    pub fn disconnect(&self) {
+        if matches!(self.phase, Phase::Connected { .. }) {
+            self.context.progress.connected.fetch_sub(1, Ordering::Relaxed);
+        }
+        self.client.disconnect();
+    }
+    fn connect(&mut self, now: Instant) {
+        *self.state.borrow_mut() = ConnectionState::None;
+        self.context.stats.borrow_mut().connect_attempts += 1;
+        match self.client.connect() {
+            Ok(()) => self.phase = Phase::Connecting { since: now },
+            Err(reason) => self.connect_failed(now, &reason),
+        }
+    }
+    fn connect_failed(&mut self, now: Instant, reason: &str) {
+        self.context.stats.borrow_mut().connect_failed += 1;
+        self.error(reason);
+        self.phase = Phase::Idle { until: now + RECONNECT_DELAY };
+    }
+    fn run_scenario(&mut self, now: Instant) {
+        let Phase::Connected { mut next_message, mut next_rpc, mut next_disconnect_roll } = self.phase else {
+            return;
+        };
+        let scenario = self.context.scenario.clone();
+        while scenario.message_rate > 0.0 && next_message <= now {
+            self.send_message();
+            next_message = catch_up(next_message, now, scenario.message_rate);
+        }
+        while scenario.rpc_rate > 0.0 && next_rpc <= now {
+            self.call_rpc();
+            next_rpc = catch_up(next_rpc, now, scenario.rpc_rate);
+        }
+        let mut disconnect = false;
+        if scenario.disconnect_percent > 0.0 && next_disconnect_roll <= now {
+            next_disconnect_roll = now + Duration::from_secs(1);
+            disconnect = rand::thread_rng().gen_bool((scenario.disconnect_percent / 100.0).clamp(0.0, 1.0));
+        }
+        if disconnect {
+            self.disconnect();
+            self.context.stats.borrow_mut().random_disconnects += 1;
+            self.phase = Phase::Idle { until: now + RECONNECT_DELAY };
+        } else {
+            self.phase = Phase::Connected { next_message, next_rpc, next_disconnect_roll };
+        }
+    }
+    fn send_message(&mut self) {
+        let scenario = &self.context.scenario;
+        let data = payload::encode(self.id, self.context.now_us(), scenario.payload_size);
+        let result = match scenario.reliable {
+            true => self.client.send_reliable(scenario.message_type, &data),
+            false => self.client.send(scenario.message_type, &data),
+        };
+        self.sent(result, data.len());
+    }
+    fn call_rpc(&mut self) {
+        let scenario = &self.context.scenario;
+        let data = payload::encode(self.id, self.context.now_us(), scenario.payload_size);
+        self.next_request_id += 1;
+        let result = self
+            .client
+            .call_rpc(scenario.reliable, scenario.rpc_method, self.next_request_id, 0, Some(&data));
+        self.sent(result, data.len());
+    }
+    fn sent(&self, result: Result<(), String>, size: usize) {
+        match result {
+            Ok(()) => {
+                let mut stats = self.context.stats.borrow_mut();
+                stats.messages_sent += 1;
+                stats.bytes_sent += size as u64;
+                self.context.progress.messages_sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(reason) => self.error(&reason),
+        }
+    }
+    fn error(&self, reason: &str) {
+        self.context.stats.borrow_mut().error(reason, &self.context.progress);
+    }
+}
+impl BotContext {
+    // payloads of other bots are counted, but only our own give a round trip time
+    fn received(&self, bot: u32, data: &[u8]) {
+        let mut stats = self.stats.borrow_mut();
+        stats.messages_received += 1;
+        stats.bytes_received += data.len() as u64;
+        self.progress.messages_received.fetch_add(1, Ordering::Relaxed);
+        if let Some((sender, sent_at_us)) = payload::decode(data) {
+            if sender == bot {
+                stats.round_trip_us.push(self.now_us().saturating_sub(sent_at_us));
+            }
+        }
+    }
+}
+
+// Next send time for `rate` per second. A bot that fell behind more than a second skips the backlog
+fn catch_up(next: Instant, now: Instant, rate: f64) -> Instant {
+    let next = next + Duration::from_secs_f64(1.0 / rate);
+    match now.checked_duration_since(next) {
+        Some(behind) if behind > Duration::from_secs(1) => now,
+        _ => next,
+    }
+}
@@ -0,0 +1,161 @@
+mod bot;
+mod payload;
+mod scenario;
+mod stats;
+
+use std::{
+    cell::RefCell,
+    env, process,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bot::{Bot, BotContext};
+use client_server::server::Server;
+use scenario::{Scenario, USAGE};
+use stats::{Progress, Stats};
+
+const TICK_INTERVAL: Duration = Duration::from_millis(1);
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let scenario = Scenario::from_args(&args).unwrap_or_else(|reason| {
+        eprintln!("{}\n\n{}", reason, USAGE);
+        process::exit(2);
+    });
+    let scenario = Arc::new(scenario);
+    let stop_server = Arc::new(AtomicBool::new(false));
+    let server_thread = match scenario.local_server {
+        true => Some(start_echo_server(&scenario, stop_server.clone()).unwrap_or_else(|reason| {
+            eprintln!("Cannot start local server: {}", reason);
+            process::exit(1);
+        })),
+        false => None,
+    };
+
+    println!(
+        "{} bots against {}:{} on {} threads",
+        scenario.clients, scenario.ip, scenario.port, scenario.threads
+    );
+    let started = Instant::now();
+    let progress = Arc::new(Progress::default());
+    let bot_threads = (0..scenario.threads)
+        .map(|thread_index| {
+            let scenario = scenario.clone();
+            let progress = progress.clone();
+            thread::Builder::new()
+                .name(format!("bots-{}", thread_index))
+                .spawn(move || run_bots(thread_index, scenario, started, progress))
+                .expect("Cannot spawn bot thread")
+        })
+        .collect::<Vec<_>>();
+
+    let end = started + scenario.ramp_up + scenario.duration;
+    let mut last_sent = 0;
+    let mut last_received = 0;
+    while Instant::now() < end {
+        thread::sleep(Duration::from_secs(1).min(end.saturating_duration_since(Instant::now())));
+        let sent = progress.messages_sent.load(Ordering::Relaxed);
+        let received = progress.messages_received.load(Ordering::Relaxed);
+        println!(
+            "[{:>5.1}s] connected {:>6}  sent {:>8}/s  received {:>8}/s  errors {}",
+            started.elapsed().as_secs_f64(),
+            progress.connected.load(Ordering::Relaxed),
+            sent - last_sent,
+            received - last_received,
+            progress.errors.load(Ordering::Relaxed)
+        );
+        last_sent = sent;
+        last_received = received;
+    }
+
+    let mut total = Stats::default();
+    for bot_thread in bot_threads {
+        match bot_thread.join() {
+            Ok(stats) => total.merge(stats),
+            Err(_) => total.error("Bot thread panicked", &progress),
+        }
+    }
+    let elapsed = started.elapsed();
+    stop_server.store(true, Ordering::Relaxed);
+    if let Some(server_thread) = server_thread {
+        _ = server_thread.join();
+    }
+    total.print_report(&scenario, elapsed);
+}
+
+// `Client` is not `Send`, so each thread creates and drives its own share of the bots
+fn run_bots(thread_index: usize, scenario: Arc<Scenario>, started: Instant, progress: Arc<Progress>) -> Stats {
+    let stats = Rc::new(RefCell::new(Stats::default()));
+    let context = BotContext {
+        scenario: scenario.clone(),
+        started,
+        stats: stats.clone(),
+        progress,
+    };
+    let mut bots = (thread_index..scenario.clients)
+        .step_by(scenario.threads)
+        .map(|id| {
+            let connect_at = started + scenario.ramp_up.mul_f64(id as f64 / scenario.clients as f64);
+            Bot::new(id as u32, connect_at, context.clone())
+        })
+        .collect::<Vec<_>>();
+    let end = started + scenario.ramp_up + scenario.duration;
+    while Instant::now() < end {
+        let now = Instant::now();
+        for bot in &mut bots {
+            bot.tick(now);
+        }
+        thread::sleep(TICK_INTERVAL);
+    }
+    for bot in &bots {
+        bot.disconnect();
+    }
+    drop(bots);
+    drop(context);
+    Rc::try_unwrap(stats).map(RefCell::into_inner).unwrap_or_default()
+}
+
+// Sends every message and rpc back to its sender, so the report has round trip times
+fn start_echo_server(scenario: &Scenario, stop: Arc<AtomicBool>) -> Result<thread::JoinHandle<()>, String> {
+    let (ip, port, reliable) = (scenario.ip, scenario.port, scenario.reliable);
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let server_thread = thread::Builder::new()
+        .name("echo-server".to_string())
+        .spawn(move || {
+            let mut server = match Server::new(ip, port) {
+                Ok(server) => server,
+                Err(reason) => {
+                    _ = ready_tx.send(Err(reason));
+                    return;
+                }
+            };
+            server.register_on_connect_requested(|_server, _id, _endpoint| true);
+            server.register_on_message(move |server, id, _endpoint, msg_type, data| {
+                _ = match reliable {
+                    true => server.send_reliable(id, msg_type, &data),
+                    false => server.send(id, msg_type, &data),
+                };
+            });
+            server.register_on_rpc(|server, id, _endpoint, reliable, method_id, request_id, arg_type, data| {
+                _ = server.call_rpc(id, reliable, method_id, request_id, arg_type, Some(&data));
+            });
+            _ = ready_tx.send(Ok(()));
+            while !stop.load(Ordering::Relaxed) {
+                _ = server.process::<1024>();
+                thread::sleep(TICK_INTERVAL);
+            }
+        })
+        .map_err(|err| err.to_string())?;
+    ready_rx.recv().map_err(|_| "Server thread exited".to_string())??;
+    Ok(server_thread)
+}
@@ -0,0 +1,23 @@
+// Every payload starts with a header identifying the sender, so echoed payloads give round trip times:
+// magic (4 bytes), bot id (u32), send time in microseconds since the test started (u64)
+const MAGIC: [u8; 4] = *b"OMLT";
+pub const HEADER_LEN: usize = 16;
+
+pub fn encode(bot: u32, sent_at_us: u64, size: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(size.max(HEADER_LEN));
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&bot.to_le_bytes());
+    data.extend_from_slice(&sent_at_us.to_le_bytes());
+    data.resize(size.max(HEADER_LEN), 0);
+    data
+}
+
+/// Bot id and send time, `None` for payloads not created by `encode`
+pub fn decode(data: &[u8]) -> Option<(u32, u64)> {
+    if data.len() < HEADER_LEN || data[..4] != MAGIC {
+        return None;
+    }
+    let bot = u32::from_le_bytes(data[4..8].try_into().ok()?);
+    let sent_at_us = u64::from_le_bytes(data[8..16].try_into().ok()?);
+    Some((bot, sent_at_us))
+}
@@ -0,0 +1,145 @@
+use std::{fs, net::IpAddr, time::Duration};
+
+/// What every bot does during the test
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub clients: usize,
+    pub threads: usize,
+    /// Connections are spread evenly over this period
+    pub ramp_up: Duration,
+    /// How long bots keep sending after the ramp-up
+    pub duration: Duration,
+    pub auth: Vec<String>,
+    pub connect_timeout: Duration,
+    /// Messages per second of each bot
+    pub message_rate: f64,
+    pub message_type: i64,
+    /// RPC calls per second of each bot
+    pub rpc_rate: f64,
+    pub rpc_method: i64,
+    /// Payload size in bytes, at least `payload::HEADER_LEN`
+    pub payload_size: usize,
+    pub reliable: bool,
+    /// Chance in percent that a connected bot disconnects during a second; it reconnects a second later
+    pub disconnect_percent: f64,
+    /// Run an echo server on `ip:port` in this process
+    pub local_server: bool,
+    /// DER certificate a QUIC server has to present
+    pub certificate: Option<Vec<u8>>,
+    /// Accept any QUIC server certificate, implied by `local_server`
+    pub insecure: bool,
+}
+
+pub const USAGE: &str = "Usage: omgpp-loadtest <ip> <port> [options]
+Options:
+  --clients <n>               number of bots, default 100
+  --threads <n>               threads the bots are spread over, default 4
+  --ramp-up <s>               period over which bots connect, default 1
+  --duration <s>              test length after the ramp-up, default 10
+  --auth <arg>                argument of the auth handshake, repeatable
+  --connect-timeout <s>       default 5
+  --message-rate <n>          messages per second per bot, default 10
+  --message-type <type>       default 1
+  --rpc-rate <n>              rpc calls per second per bot, default 0
+  --rpc-method <id>           default 1
+  --size <bytes>              payload size, default 64
+  --reliable                  send reliable messages and rpcs
+  --disconnect-percent <p>    chance per second that a bot disconnects and reconnects, default 0
+  --local-server              run an echo server on <ip> <port> in this process
+  --trust-cert <file.der>     QUIC builds: only accept the server presenting this certificate
+  --insecure                  QUIC builds: accept any server certificate, implied by --local-server
+  --scenario <file>           read options from a file, one `name value` per line
+Round trip latency is measured on messages and rpcs the server echoes back to the sender";
+
+impl Scenario {
+    pub fn from_args(args: &[String]) -> Result<Scenario, String> {
+        if args.len() < 2 {
+            return Err("Missing server address".to_string());
+        }
+        let mut scenario = Scenario {
+            ip: args[0].parse().map_err(|_| format!("Invalid ip {:?}", args[0]))?,
+            port: args[1].parse().map_err(|_| format!("Invalid port {:?}", args[1]))?,
+            clients: 100,
+            threads: 4,
+            ramp_up: Duration::from_secs(1),
+            duration: Duration::from_secs(10),
+            auth: Vec::new(),
+            connect_timeout: Duration::from_secs(5),
+            message_rate: 10.0,
+            message_type: 1,
+            rpc_rate: 0.0,
+            rpc_method: 1,
+            payload_size: 64,
+            reliable: false,
+            disconnect_percent: 0.0,
+            local_server: false,
+            certificate: None,
+            insecure: false,
+        };
+        scenario.apply(&args[2..])?;
+        if scenario.clients == 0 || scenario.threads == 0 {
+            return Err("--clients and --threads must be positive".to_string());
+        }
+        scenario.threads = scenario.threads.min(scenario.clients);
+        scenario.payload_size = scenario.payload_size.max(crate::payload::HEADER_LEN);
+        Ok(scenario)
+    }
+    fn apply(&mut self, args: &[String]) -> Result<(), String> {
+        let mut args_iter = args.iter();
+        while let Some(arg) = args_iter.next() {
+            let mut value = || args_iter.next().cloned().ok_or_else(|| format!("Missing value of {}", arg));
+            match &arg[..] {
+                "--clients" => self.clients = parse(arg, &value()?)?,
+                "--threads" => self.threads = parse(arg, &value()?)?,
+                "--ramp-up" => self.ramp_up = Duration::from_secs_f64(parse(arg, &value()?)?),
+                "--duration" => self.duration = Duration::from_secs_f64(parse(arg, &value()?)?),
+                "--auth" => self.auth.push(value()?),
+                "--connect-timeout" => self.connect_timeout = Duration::from_secs_f64(parse(arg, &value()?)?),
+                "--message-rate" => self.message_rate = parse(arg, &value()?)?,
+                "--message-type" => self.message_type = parse(arg, &value()?)?,
+                "--rpc-rate" => self.rpc_rate = parse(arg, &value()?)?,
+                "--rpc-method" => self.rpc_method = parse(arg, &value()?)?,
+                "--size" => self.payload_size = parse(arg, &value()?)?,
+                "--reliable" => self.reliable = true,
+                "--disconnect-percent" => self.disconnect_percent = parse(arg, &value()?)?,
+                "--local-server" => self.local_server = true,
+                "--trust-cert" => {
+                    let path = value()?;
+                    let certificate = fs::read(&path).map_err(|err| format!("Cannot read certificate {:?}: {}", path, err))?;
+                    self.certificate = Some(certificate);
+                }
+                "--insecure" => self.insecure = true,
+                "--scenario" => {
+                    let path = value()?;
+                    let content =
+                        fs::read_to_string(&path).map_err(|err| format!("Cannot read {:?}: {}", path, err))?;
+                    self.apply(&scenario_args(&content))?;
+                }
+                _ => return Err(format!("Unknown option {:?}", arg)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {:?} of {}", value, option))
+}
+
+// `clients 1000` lines become `--clients 1000`; blank lines and `#` comments are skipped
+fn scenario_args(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .flat_map(|line| {
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let option = format!("--{}", name.trim_start_matches("--"));
+            std::iter::once(option).chain(Some(value.trim().to_string()).filter(|value| !value.is_empty()))
+        })
+        .collect()
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::scenario::Scenario;
+
+/// Counters shared by all threads, printed every second while the test runs
+#[derive(Default)]
+pub struct Progress {
+    pub connected: AtomicI64,
+    pub messages_sent: AtomicU64,
+    pub messages_received: AtomicU64,
+    pub errors: AtomicU64,
+}
+
+/// Results of one thread, merged into the final report
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub connect_attempts: u64,
+    pub connect_succeeded: u64,
+    pub connect_failed: u64,
+    /// Connections closed by the server or the network
+    pub dropped: u64,
+    /// Connections closed on purpose by the scenario
+    pub random_disconnects: u64,
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    pub handshake_us: Vec<u64>,
+    pub round_trip_us: Vec<u64>,
+    pub errors: HashMap<String, u64>,
+}
+impl Stats {
+    pub fn error(&mut self, reason: &str, progress: &Progress) {
+        *self.errors.entry(reason.to_string()).or_default() += 1;
+        progress.errors.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn merge(&mut self, other: Stats) {
+        self.connect_attempts += other.connect_attempts;
+        self.connect_succeeded += other.connect_succeeded;
+        self.connect_failed += other.connect_failed;
+        self.dropped += other.dropped;
+        self.random_disconnects += other.random_disconnects;
+        self.messages_sent += other.messages_sent;
+        self.bytes_sent += other.bytes_sent;
+        self.messages_received += other.messages_received;
+        self.bytes_received += other.bytes_received;
+        self.handshake_us.extend(other.handshake_us);
+        self.round_trip_us.extend(other.round_trip_us);
+        for (reason, count) in other.errors {
+            *self.errors.entry(reason).or_default() += count;
+        }
+    }
+    pub fn print_report(&mut self, scenario: &Scenario, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let success_rate = match self.connect_attempts {
+            0 => 0.0,
+            attempts => self.connect_succeeded as f64 * 100.0 / attempts as f64,
+        };
+        println!();
+        println!(
+            "Clients      {} on {} threads, {:.1}s including {:.1}s ramp-up",
+            scenario.clients,
+            scenario.threads,
+            seconds,
+            scenario.ramp_up.as_secs_f64()
+        );
+        println!(
+            "Connections  {} attempts, {} succeeded ({:.1}%), {} failed, {} dropped, {} random disconnects",
+            self.connect_attempts,
+            self.connect_succeeded,
+            success_rate,
+            self.connect_failed,
+            self.dropped,
+            self.random_disconnects
+        );
+        println!("Handshake    {}", percentiles(&mut self.handshake_us));
+        println!("Round trip   {}", percentiles(&mut self.round_trip_us));
+        println!(
+            "Sent         {} messages ({:.0}/s), {} ({}/s)",
+            self.messages_sent,
+            self.messages_sent as f64 / seconds,
+            bytes(self.bytes_sent as f64),
+            bytes(self.bytes_sent as f64 / seconds)
+        );
+        println!(
+            "Received     {} messages ({:.0}/s), {} ({}/s)",
+            self.messages_received,
+            self.messages_received as f64 / seconds,
+            bytes(self.bytes_received as f64),
+            bytes(self.bytes_received as f64 / seconds)
+        );
+        if self.errors.is_empty() {
+            println!("Errors       none");
+        } else {
+            let mut errors = self.errors.iter().collect::<Vec<_>>();
+            errors.sort_by(|a, b| b.1.cmp(a.1));
+            println!("Errors");
+            for (reason, count) in errors {
+                println!("  {:>8}  {}", count, reason);
+            }
+        }
+    }
+}
+
+fn percentiles(samples_us: &mut [u64]) -> String {
+    if samples_us.is_empty() {
+        return "no samples".to_string();
+    }
+    samples_us.sort_unstable();
+    let at = |quantile: f64| {
+        let index = ((samples_us.len() - 1) as f64 * quantile).round() as usize;
+        samples_us[index] as f64 / 1000.0
+    };
+    format!(
+        "p50 {:.2}ms  p90 {:.2}ms  p99 {:.2}ms  max {:.2}ms  ({} samples)",
+        at(0.5),
+        at(0.9),
+        at(0.99),
+        at(1.0),
+        samples_us.len()
+    )
+}
+
+fn bytes(value: f64) -> String {
+    match value {
+        v if v >= 1_000_000.0 => format!("{:.2} MB", v / 1_000_000.0),
+        v if v >= 1_000.0 => format!("{:.2} kB", v / 1_000.0),
+        v => format!("{:.0} B", v),
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    cell::RefCell,
+    env, fs,
+    net::IpAddr,
+    process,
+    rc::Rc,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use client_server::{backend, client::Client};
+use omgpp_core::{
+    cmd_handler::CmdHandler, messages::general_message::general_omgpp_message::CmdRequest, transport::Connector,
+    ConnectionState, OmgppPredefinedCmd,
+};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter, history::DefaultHistory,
+    validate::Validator, Context, Editor, Helper,
+};
+
+const PASSWORD_ENV: &str = "OMGPP_RCON_PASSWORD";
+// DER certificate a QUIC server has to present, the admin password is never sent unverified
+const CERTIFICATE_ENV: &str = "OMGPP_RCON_CERT";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+type Replies = Rc<RefCell<Vec<CmdRequest>>>;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!(
+            "Usage: omgpp-rcon <ip> <port> [password]. The password can also be set with {}, \
+             QUIC builds read the server certificate from the file in {}",
+            PASSWORD_ENV, CERTIFICATE_ENV
+        );
+        process::exit(2);
+    }
+    let ip: IpAddr = args[1].parse().unwrap_or_else(|_| fail(&format!("Invalid ip {:?}", args[1])));
+    let port: u16 = args[2].parse().unwrap_or_else(|_| fail(&format!("Invalid port {:?}", args[2])));
+    let password = args
+        .get(3)
+        .cloned()
+        .or_else(|| env::var(PASSWORD_ENV).ok())
+        .unwrap_or_else(|| fail(&format!("Provide the admin password as argument or with {}", PASSWORD_ENV)));
+
+    let connector = match env::var(CERTIFICATE_ENV) {
+        Ok(path) => backend::connector_with_certificate(
+            fs::read(&path).unwrap_or_else(|err| fail(&format!("Cannot read certificate {:?}: {}", path, err))),
+        ),
+        Err(_) => backend::connector(),
+    };
+    let mut rcon = Rcon::new(ip, port, password, connector);
+    if let Err(reason) = rcon.connect() {
+        fail(&reason);
+    }
+    println!("Connected to {}:{}. Type `help` to list commands, `quit` to exit", ip, port);
+    let commands = Arc::new(Mutex::new(rcon.fetch_commands().unwrap_or_default()));
+    let (ready_tx, lines_rx) = spawn_prompt(commands.clone());
+    loop {
+        let line = match lines_rx.try_recv() {
+            Ok(Some(line)) => line,
+            Ok(None) | Err(TryRecvError::Disconnected) => break,
+            Err(TryRecvError::Empty) => {
+                if let Err(reason) = rcon.pump() {
+                    fail(&reason);
+                }
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let words = split_args(&line);
+        if let Some((cmd, cmd_args)) = words.split_first() {
+            match &cmd[..] {
+                "quit" | "exit" => break,
+                _ => {
+                    let cmd = resolve(cmd, &commands.lock().unwrap());
+                    match rcon.execute(&cmd, cmd_args.to_vec()) {
+                        Ok(lines) => print_reply(&lines),
+                        Err(reason) => fail(&reason),
+                    }
+                    // the command list depends on the roles, refresh it with every full help
+                    if cmd == OmgppPredefinedCmd::HELP && cmd_args.is_empty() {
+                        if let Ok(fetched) = rcon.fetch_commands() {
+                            *commands.lock().unwrap() = fetched;
+                        }
+                    }
+                }
+            }
+        }
+        if ready_tx.send(()).is_err() {
+            break;
+        }
+    }
+    rcon.client.disconnect();
+}
+
+fn fail(reason: &str) -> ! {
+    eprintln!("{}", reason);
+    process::exit(1);
+}
+
+struct Rcon {
+    client: Client,
+    state: Rc<RefCell<ConnectionState>>,
+    replies: Replies,
+    next_request_id: u64,
+}
+impl Rcon {
+    fn new(ip: IpAddr, port: u16, password: String, connector: Box<dyn Connector>) -> Rcon {
+        let client = Client::with_connector(ip, port, connector);
+        let state = Rc::new(RefCell::new(ConnectionState::None));
+        let replies: Replies = Default::default();
+
+        client.register_on_auth(move |_client, _endpoint| {
+            vec![OmgppPredefinedCmd::ADMIN_AUTH.to_string(), password.clone()]
+        });
+        let state_cloned = state.clone();
+        client.register_on_connection_state_changed(move |_client, _endpoint, new_state| {
+            *state_cloned.borrow_mut() = new_state;
+        });
+        client.register_on_announcement(|_client, _endpoint, text| {
+            println!("[announcement] {}", text);
+        });
+        let replies_cloned = replies.clone();
+        let fallback = CmdHandler::new(
+            "",
+            false,
+            Box::new(move |_client: &Client, _uuid, _endpoint, _handler, request: &CmdRequest| {
+                replies_cloned.borrow_mut().push(request.clone());
+            }),
+        );
+        _ = client.register_cmd_fallback(Some(fallback));
+        Rcon {
+            client,
+            state,
+            replies,
+            next_request_id: 1,
+        }
+    }
+    // The server authenticates as soon as the connection is accepted, a wrong password closes it
+    fn connect(&mut self) -> Result<(), String> {
+        self.client.connect()?;
+        let started = Instant::now();
+        loop {
+            self.client.process::<128>()?;
+            match *self.state.borrow() {
+                ConnectionState::Connected => return Ok(()),
+                ConnectionState::Disconnected => {
+                    return Err("Connection closed by the server. Is the admin password right?".to_string())
+                }
+                _ => (),
+            }
+            if started.elapsed() > CONNECT_TIMEOUT {
+                return Err("Timed out waiting for the server".to_string());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    fn pump(&self) -> Result<(), String> {
+        self.client.process::<128>()?;
+        if *self.state.borrow() == ConnectionState::Disconnected {
+            return Err("Disconnected from the server".to_string());
+        }
+        // replies to commands which timed out
+        for late in self.replies.borrow_mut().drain(..) {
+            println!("Late reply to {} #{}", late.cmd, late.request_id);
+            print_reply(&late.args);
+        }
+        Ok(())
+    }
+    /// Send `cmd` and wait for its reply. A timeout is reported as a reply, not as an error
+    fn execute(&mut self, cmd: &str, args: Vec<String>) -> Result<Vec<String>, String> {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        self.client.send_cmd(cmd, request_id, Some(args))?;
+        let started = Instant::now();
+        loop {
+            self.client.process::<128>()?;
+            if *self.state.borrow() == ConnectionState::Disconnected {
+                return Err("Disconnected from the server".to_string());
+            }
+            let mut replies = self.replies.borrow_mut();
+            if let Some(index) = replies.iter().position(|reply| reply.request_id == request_id) {
+                return Ok(replies.remove(index).args);
+            }
+            drop(replies);
+            if started.elapsed() > REPLY_TIMEOUT {
+                return Ok(vec!["error".to_string(), "No reply from the server".to_string()]);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    /// Names of the commands the server allows us to run, from the first word of each `omgpp_help` line
+    fn fetch_commands(&mut self) -> Result<Vec<String>, String> {
+        let lines = self.execute(OmgppPredefinedCmd::HELP, Vec::new())?;
+        if lines.first().is_some_and(|first| first == "error") {
+            return Err(lines[1..].join(" "));
+        }
+        Ok(lines
+            .iter()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(String::from)
+            .collect())
+    }
+}
+
+fn print_reply(lines: &[String]) {
+    match lines.split_first() {
+        Some((first, rest)) if first == "error" => println!("error: {}", rest.join(" ")),
+        Some(_) => lines.iter().for_each(|line| println!("{}", line)),
+        None => println!("(empty reply)"),
+    }
+}
+
+// Built-in commands can be typed without the `omgpp_` prefix
+fn resolve(cmd: &str, commands: &[String]) -> String {
+    let prefixed = format!("{}{}", OmgppPredefinedCmd::RESERVED_PREFIX, cmd);
+    if !commands.iter().any(|known| known == cmd) && commands.contains(&prefixed) {
+        return prefixed;
+    }
+    cmd.to_string()
+}
+
+// Whitespace separated words, single or double quotes keep spaces in a word
+fn split_args(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+// `Client` cannot leave the main thread, so the blocking prompt runs on its own thread.
+// It reads the next line only after the main thread signals that the previous one was handled
+fn spawn_prompt(commands: Arc<Mutex<Vec<String>>>) -> (Sender<()>, Receiver<Option<String>>) {
+    let (ready_tx, ready_rx) = mpsc::channel::<()>();
+    let (lines_tx, lines_rx) = mpsc::channel::<Option<String>>();
+    thread::spawn(move || {
+        let mut editor = match Editor::<CommandCompleter, DefaultHistory>::new() {
+            Ok(editor) => editor,
+            Err(err) => {
+                eprintln!("Cannot open the prompt: {}", err);
+                _ = lines_tx.send(None);
+                return;
+            }
+        };
+        editor.set_helper(Some(CommandCompleter { commands }));
+        loop {
+            match editor.readline("rcon> ") {
+                Ok(line) => {
+                    _ = editor.add_history_entry(line.as_str());
+                    if lines_tx.send(Some(line)).is_err() || ready_rx.recv().is_err() {
+                        return;
+                    }
+                }
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
+                    _ = lines_tx.send(None);
+                    return;
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    _ = lines_tx.send(None);
+                    return;
+                }
+            }
+        }
+    });
+    (ready_tx, lines_rx)
+}
+
+// Completes the command name, with or without the `omgpp_` prefix
+struct CommandCompleter {
+    commands: Arc<Mutex<Vec<String>>>,
+}
+impl Completer for CommandCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let typed = &line[..pos];
+        if typed.contains(char::is_whitespace) {
+            return Ok((pos, Vec::new()));
+        }
+        let commands = self.commands.lock().unwrap();
+        let mut candidates = commands
+            .iter()
+            .filter_map(|cmd| {
+                let short = cmd.strip_prefix(OmgppPredefinedCmd::RESERVED_PREFIX);
+                match short {
+                    Some(short) if short.starts_with(typed) && !cmd.starts_with(typed) => Some(short.to_string()),
+                    _ if cmd.starts_with(typed) => Some(cmd.clone()),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup();
+        Ok((0, candidates))
+    }
+}
+impl Hinter for CommandCompleter {
+    type Hint = String;
+}
+impl Highlighter for CommandCompleter {}
+impl Validator for CommandCompleter {}
+impl Helper for CommandCompleter {}
@@ -0,0 +1,100 @@
+use std::fmt::Write;
+
+use crate::{Method, Service};
+
+/// A static class per service with the ids and helpers over the csbindgen generated
+/// `OmgppClientNative.client_call_rpc` and `OmgppServerNative.server_call_rpc`.
+/// Messages are passed serialized, e.g. by Google.Protobuf
+pub fn generate_file(namespace: &str, services: &[Service]) -> String {
+    let mut out = String::new();
+    emit!(out, "// <auto-generated>");
+    emit!(out, "// This code is generated by omgpp-rpc-codegen, do not edit");
+    emit!(out, "// </auto-generated>");
+    emit!(out, "using OmgppNative;");
+    emit!(out);
+    emit!(out, "namespace {}", namespace);
+    emit!(out, "{{");
+    for (index, service) in services.iter().enumerate() {
+        if index > 0 {
+            emit!(out);
+        }
+        generate_service(&mut out, service);
+    }
+    emit!(out, "}}");
+    out
+}
+
+fn generate_service(out: &mut String, service: &Service) {
+    emit!(out, "    /// <summary>Rpc stubs of <c>{}</c> from {}</summary>", service.full_name, service.proto_file);
+    emit!(out, "    public static unsafe class {}Rpc", service.name);
+    emit!(out, "    {{");
+    emit!(out, "        /// <summary>arg_type of a reply carrying a UTF-8 error reason</summary>");
+    emit!(out, "        public const long ErrorArgType = -1;");
+    for method in &service.methods {
+        emit!(out);
+        emit!(out, "        public const long {}MethodId = {};", method.name, method.id);
+        emit!(out, "        public const long {}RequestType = {};", method.name, method.request.type_id);
+        emit!(out, "        public const long {}ReplyType = {};", method.name, method.reply.type_id);
+    }
+    for method in &service.methods {
+        emit!(out);
+        generate_call(out, method);
+        emit!(out);
+        generate_reply(out, method);
+    }
+    emit!(out);
+    emit!(out, "        /// <summary>Answer a call with an error instead of its reply</summary>");
+    emit!(
+        out,
+        "        public static void ReplyError(void* server, UuidFFI* client, long methodId, ulong requestId, string reason, bool reliable = true)"
+    );
+    emit!(out, "        {{");
+    emit!(out, "            var reasonBytes = System.Text.Encoding.UTF8.GetBytes(reason);");
+    emit!(out, "            fixed (byte* data = reasonBytes)");
+    emit!(out, "            {{");
+    emit!(
+        out,
+        "                OmgppServerNative.server_call_rpc(server, client, reliable, methodId, requestId, ErrorArgType, data, 0, (nuint)reasonBytes.Length);"
+    );
+    emit!(out, "            }}");
+    emit!(out, "        }}");
+    emit!(out, "    }}");
+}
+
+fn generate_call(out: &mut String, method: &Method) {
+    emit!(out, "        /// <summary>Call <c>{}</c> with a serialized <c>{}</c></summary>", method.full_name, method.request.full_name);
+    emit!(
+        out,
+        "        public static void Call{}(void* client, ulong requestId, byte[] request, bool reliable = true)",
+        method.name
+    );
+    emit!(out, "        {{");
+    emit!(out, "            fixed (byte* data = request)");
+    emit!(out, "            {{");
+    emit!(
+        out,
+        "                OmgppClientNative.client_call_rpc(client, reliable, {0}MethodId, requestId, {0}RequestType, data, 0, (nuint)request.Length);",
+        method.name
+    );
+    emit!(out, "            }}");
+    emit!(out, "        }}");
+}
+
+fn generate_reply(out: &mut String, method: &Method) {
+    emit!(out, "        /// <summary>Answer <c>{}</c> with a serialized <c>{}</c></summary>", method.full_name, method.reply.full_name);
+    emit!(
+        out,
+        "        public static void Reply{}(void* server, UuidFFI* client, ulong requestId, byte[] reply, bool reliable = true)",
+        method.name
+    );
+    emit!(out, "        {{");
+    emit!(out, "            fixed (byte* data = reply)");
+    emit!(out, "            {{");
+    emit!(
+        out,
+        "                OmgppServerNative.server_call_rpc(server, client, reliable, {0}MethodId, requestId, {0}ReplyType, data, 0, (nuint)reply.Length);",
+        method.name
+    );
+    emit!(out, "            }}");
+    emit!(out, "        }}");
+}
@@ -0,0 +1,315 @@
+//! Generates rpc stubs from the `service` blocks of `.proto` files, for use in `build.rs`
+//! next to `protobuf_codegen`:
+//!
+//! ```no_run
+//! omgpp_rpc_codegen::RpcCodegen::new()
+//!     .includes(&["proto"])
+//!     .input("proto/chat.proto")
+//!     .messages_path("crate::messages")
+//!     .cargo_out_dir("rpc")
+//!     .csharp_file("../../generated/csharp/ChatRpc.g.cs")
+//!     .run_from_script();
+//! ```
+// `writeln!` into a `String` cannot fail
+macro_rules! emit {
+    ($out:expr) => {
+        _ = writeln!($out)
+    };
+    ($out:expr, $($arg:tt)*) => {
+        _ = writeln!($out, $($arg)*)
+    };
+}
+
+mod csharp;
+mod rust;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use omgpp_core::typed_message::stable_type_id;
+use protobuf::descriptor::{DescriptorProto, FileDescriptorProto};
+
+/// Request or reply message of a method
+#[derive(Debug, Clone)]
+pub struct MessageType {
+    /// e.g. `game.SayRequest`
+    pub full_name: String,
+    /// Path of the type generated by `protobuf_codegen`, e.g. `crate::messages::chat::SayRequest`
+    pub rust_path: String,
+    /// `arg_type` the message is sent with
+    pub type_id: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: String,
+    /// e.g. `game.Chat.Say`
+    pub full_name: String,
+    pub id: i64,
+    pub request: MessageType,
+    pub reply: MessageType,
+}
+
+#[derive(Debug, Clone)]
+pub struct Service {
+    pub name: String,
+    /// e.g. `game.Chat`
+    pub full_name: String,
+    /// Relative path of the `.proto` file declaring the service
+    pub proto_file: String,
+    pub methods: Vec<Method>,
+}
+
+/// Generated sources, keyed by the module name of their `.proto` file
+#[derive(Debug, Default)]
+pub struct Generated {
+    pub rust: Vec<(String, String)>,
+    pub csharp: String,
+}
+
+pub struct RpcCodegen {
+    includes: Vec<PathBuf>,
+    inputs: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    messages_path: String,
+    csharp_file: Option<PathBuf>,
+    csharp_namespace: String,
+}
+impl RpcCodegen {
+    pub fn new() -> RpcCodegen {
+        RpcCodegen {
+            includes: Vec::new(),
+            inputs: Vec::new(),
+            out_dir: None,
+            messages_path: "crate::messages".to_string(),
+            csharp_file: None,
+            csharp_namespace: "OmgppRpc".to_string(),
+        }
+    }
+    pub fn include(&mut self, include: impl AsRef<Path>) -> &mut Self {
+        self.includes.push(include.as_ref().to_path_buf());
+        self
+    }
+    pub fn includes(&mut self, includes: impl IntoIterator<Item = impl AsRef<Path>>) -> &mut Self {
+        for include in includes {
+            self.include(include);
+        }
+        self
+    }
+    /// `.proto` file to generate stubs for. It must reside in one of the includes
+    pub fn input(&mut self, input: impl AsRef<Path>) -> &mut Self {
+        self.inputs.push(input.as_ref().to_path_buf());
+        self
+    }
+    pub fn inputs(&mut self, inputs: impl IntoIterator<Item = impl AsRef<Path>>) -> &mut Self {
+        for input in inputs {
+            self.input(input);
+        }
+        self
+    }
+    pub fn out_dir(&mut self, out_dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(out_dir.as_ref().to_path_buf());
+        self
+    }
+    /// Output directory relative to `OUT_DIR`, only usable from `build.rs`
+    pub fn cargo_out_dir(&mut self, rel: &str) -> &mut Self {
+        let out_dir = std::env::var_os("OUT_DIR").expect("OUT_DIR is not set, cargo_out_dir only works in build.rs");
+        self.out_dir(Path::new(&out_dir).join(rel))
+    }
+    /// Module the messages were generated into by `protobuf_codegen`, `crate::messages` by default
+    pub fn messages_path(&mut self, path: &str) -> &mut Self {
+        self.messages_path = path.trim_end_matches("::").to_string();
+        self
+    }
+    /// Also write C# stubs over `OmgppClientNative`/`OmgppServerNative` to `path`
+    pub fn csharp_file(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.csharp_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+    /// Namespace of the C# stubs, `OmgppRpc` by default
+    pub fn csharp_namespace(&mut self, namespace: &str) -> &mut Self {
+        self.csharp_namespace = namespace.to_string();
+        self
+    }
+
+    /// Parse the inputs and collect their services
+    pub fn services(&self) -> Result<Vec<Service>, String> {
+        let parsed = protobuf_parse::Parser::new()
+            .pure()
+            .includes(&self.includes)
+            .inputs(&self.inputs)
+            .parse_and_typecheck()
+            .map_err(|err| format!("Cannot parse proto files: {:#}", err))?;
+        let messages = MessageIndex::new(&parsed.file_descriptors, &self.messages_path);
+        let inputs = parsed
+            .relative_paths
+            .iter()
+            .map(|path| path.to_str().to_string())
+            .collect::<Vec<_>>();
+        let mut services = Vec::new();
+        for file in parsed.file_descriptors.iter().filter(|file| inputs.contains(&file.name().to_string())) {
+            for service in &file.service {
+                let full_name = qualify(file.package(), service.name());
+                let mut methods = Vec::new();
+                for method in &service.method {
+                    if method.client_streaming() || method.server_streaming() {
+                        return Err(format!("{}.{}: streaming methods are not supported", full_name, method.name()));
+                    }
+                    let method_name = format!("{}.{}", full_name, method.name());
+                    methods.push(Method {
+                        name: method.name().to_string(),
+                        id: stable_type_id(&method_name),
+                        full_name: method_name,
+                        request: messages.get(method.input_type())?,
+                        reply: messages.get(method.output_type())?,
+                    });
+                }
+                services.push(Service {
+                    name: service.name().to_string(),
+                    full_name,
+                    proto_file: file.name().to_string(),
+                    methods,
+                });
+            }
+        }
+        check_unique_ids(&services)?;
+        Ok(services)
+    }
+    /// Generate the sources without writing them
+    pub fn generate(&self) -> Result<Generated, String> {
+        let services = self.services()?;
+        let mut by_file: Vec<(String, Vec<&Service>)> = Vec::new();
+        for service in &services {
+            match by_file.iter_mut().find(|(file, _)| *file == service.proto_file) {
+                Some((_, file_services)) => file_services.push(service),
+                None => by_file.push((service.proto_file.clone(), vec![service])),
+            }
+        }
+        Ok(Generated {
+            rust: by_file
+                .iter()
+                .map(|(file, file_services)| (proto_path_to_mod(file), rust::generate_file(file, file_services)))
+                .collect(),
+            csharp: csharp::generate_file(&self.csharp_namespace, &services),
+        })
+    }
+    /// Write one `<proto module>.rs` per input with services plus a `mod.rs` declaring them
+    pub fn run(&self) -> Result<(), String> {
+        let generated = self.generate()?;
+        if let Some(out_dir) = &self.out_dir {
+            fs::create_dir_all(out_dir).map_err(|err| format!("Cannot create {}: {}", out_dir.display(), err))?;
+            let mut mod_rs = String::from("// @generated by omgpp-rpc-codegen\n\n");
+            for (module, code) in &generated.rust {
+                write(&out_dir.join(format!("{}.rs", module)), code)?;
+                mod_rs.push_str(&format!("pub mod {};\n", module));
+            }
+            write(&out_dir.join("mod.rs"), &mod_rs)?;
+        }
+        if let Some(csharp_file) = &self.csharp_file {
+            if let Some(parent) = csharp_file.parent() {
+                fs::create_dir_all(parent).map_err(|err| format!("Cannot create {}: {}", parent.display(), err))?;
+            }
+            write(csharp_file, &generated.csharp)?;
+        }
+        Ok(())
+    }
+    /// `run` for `build.rs`: reruns when the inputs change and panics on errors
+    pub fn run_from_script(&self) {
+        for input in &self.inputs {
+            println!("cargo::rerun-if-changed={}", input.display());
+        }
+        if let Err(reason) = self.run() {
+            panic!("Failed to generate rpc stubs: {}", reason);
+        }
+    }
+}
+impl Default for RpcCodegen {
+    fn default() -> Self {
+        RpcCodegen::new()
+    }
+}
+
+// Rust paths and ids of every message in the parsed files, by full name with a leading `.`
+struct MessageIndex {
+    messages: HashMap<String, MessageType>,
+}
+impl MessageIndex {
+    fn new(files: &[FileDescriptorProto], messages_path: &str) -> MessageIndex {
+        let mut index = MessageIndex {
+            messages: HashMap::new(),
+        };
+        for file in files {
+            let module = format!("{}::{}", messages_path, proto_path_to_mod(file.name()));
+            let prefix = match file.package() {
+                "" => String::new(),
+                package => format!(".{}", package),
+            };
+            for message in &file.message_type {
+                index.add(message, &prefix, &module);
+            }
+        }
+        index
+    }
+    fn add(&mut self, message: &DescriptorProto, prefix: &str, module: &str) {
+        let full_name = format!("{}.{}", prefix, message.name());
+        // nested types live in a module named after their parent, like `protobuf_codegen` does it
+        let nested_module = format!("{}::{}", module, protobuf_parse::snake_case(message.name()));
+        for nested in &message.nested_type {
+            self.add(nested, &full_name, &nested_module);
+        }
+        self.messages.insert(
+            full_name.clone(),
+            MessageType {
+                rust_path: format!("{}::{}", module, message.name()),
+                type_id: stable_type_id(&full_name),
+                full_name: full_name.trim_start_matches('.').to_string(),
+            },
+        );
+    }
+    fn get(&self, full_name: &str) -> Result<MessageType, String> {
+        self.messages
+            .get(full_name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown message type {}", full_name))
+    }
+}
+
+fn check_unique_ids(services: &[Service]) -> Result<(), String> {
+    let mut names = HashMap::new();
+    for method in services.iter().flat_map(|service| &service.methods) {
+        if let Some(other) = names.insert(method.id, &method.full_name) {
+            if *other != method.full_name {
+                return Err(format!("{} and {} have the same method id {}", other, method.full_name, method.id));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn qualify(package: &str, name: &str) -> String {
+    match package {
+        "" => name.to_string(),
+        package => format!("{}.{}", package, name),
+    }
+}
+
+// Module name `protobuf_codegen` gives the messages of a `.proto` file, e.g. `general_message`
+pub(crate) fn proto_path_to_mod(path: &str) -> String {
+    let file_name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    file_name
+        .trim_end_matches(".proto")
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c.is_ascii_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit()) {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+fn write(path: &Path, content: &str) -> Result<(), String> {
+    fs::write(path, content).map_err(|err| format!("Cannot write {}: {}", path.display(), err))
+}
@@ -0,0 +1,162 @@
+use std::fmt::Write;
+
+use protobuf_parse::snake_case;
+
+use crate::{Method, Service};
+
+const SERVER: &str = "::client_server::server::Server";
+const CLIENT: &str = "::client_server::client::Client";
+const UUID: &str = "::uuid::Uuid";
+const ERROR_ARG_TYPE: &str = "::omgpp_core::typed_message::RPC_ERROR_ARG_TYPE";
+
+/// One module per service: method ids, a server trait with its `dispatch`, a client stub and a reply enum
+pub fn generate_file(proto_file: &str, services: &[&Service]) -> String {
+    let mut out = String::new();
+    emit!(out, "// @generated by omgpp-rpc-codegen from {}, do not edit", proto_file);
+    for service in services {
+        emit!(out);
+        generate_service(&mut out, service);
+    }
+    out
+}
+
+fn generate_service(out: &mut String, service: &Service) {
+    let name = &service.name;
+    emit!(out, "/// Rpc stubs of `{}`", service.full_name);
+    emit!(out, "pub mod {} {{", snake_case(name));
+    emit!(out, "    use ::protobuf::Message;");
+    emit!(out);
+    for method in &service.methods {
+        let constant = snake_case(&method.name).to_uppercase();
+        emit!(out, "    /// `{}`", method.full_name);
+        emit!(out, "    pub const {}_METHOD_ID: i64 = {};", constant, method.id);
+        emit!(out, "    /// `{}`", method.request.full_name);
+        emit!(out, "    pub const {}_REQUEST_TYPE: i64 = {};", constant, method.request.type_id);
+        emit!(out, "    /// `{}`", method.reply.full_name);
+        emit!(out, "    pub const {}_REPLY_TYPE: i64 = {};", constant, method.reply.type_id);
+    }
+    emit!(out);
+
+    emit!(out, "    /// Server side of `{}`, called through `dispatch`", service.full_name);
+    emit!(out, "    pub trait {}Service {{", name);
+    for method in &service.methods {
+        emit!(
+            out,
+            "        fn {}(&self, server: &{}, client: &{}, request: {}) -> Result<{}, String>;",
+            snake_case(&method.name),
+            SERVER,
+            UUID,
+            method.request.rust_path,
+            method.reply.rust_path
+        );
+    }
+    emit!(out, "    }}");
+    emit!(out);
+
+    emit!(out, "    /// Decode a call from `Server::register_on_rpc`, run it on `service` and send the reply,");
+    emit!(out, "    /// or the error reason, back to `client`. `None` if `method_id` is not a method of `{}`", name);
+    emit!(out, "    #[allow(clippy::too_many_arguments)]");
+    emit!(out, "    pub fn dispatch(");
+    emit!(out, "        service: &impl {}Service,", name);
+    emit!(out, "        server: &{},", SERVER);
+    emit!(out, "        client: &{},", UUID);
+    emit!(out, "        reliable: bool,");
+    emit!(out, "        method_id: i64,");
+    emit!(out, "        request_id: u64,");
+    emit!(out, "        arg_type: i64,");
+    emit!(out, "        arg_data: &[u8],");
+    emit!(out, "    ) -> Option<Result<(), String>> {{");
+    emit!(out, "        let reply = match method_id {{");
+    for method in &service.methods {
+        let constant = snake_case(&method.name).to_uppercase();
+        emit!(out, "            {}_METHOD_ID => decode({}_REQUEST_TYPE, arg_type, arg_data)", constant, constant);
+        emit!(out, "                .and_then(|request| service.{}(server, client, request))", snake_case(&method.name));
+        emit!(out, "                .and_then(|reply| encode(&reply))");
+        emit!(out, "                .map(|data| ({}_REPLY_TYPE, data)),", constant);
+    }
+    emit!(out, "            _ => return None,");
+    emit!(out, "        }};");
+    emit!(out, "        let (arg_type, data) = reply.unwrap_or_else(|reason| ({}, reason.into_bytes()));", ERROR_ARG_TYPE);
+    emit!(out, "        Some(server.call_rpc(client, reliable, method_id, request_id, arg_type, Some(&data)))");
+    emit!(out, "    }}");
+    emit!(out);
+
+    emit!(out, "    /// Client side of `{}`. Replies arrive through `Client::register_on_rpc`,", service.full_name);
+    emit!(out, "    /// see `{}Reply::decode`", name);
+    emit!(out, "    pub struct {}Client<'c> {{", name);
+    emit!(out, "        client: &'c {},", CLIENT);
+    emit!(out, "        reliable: bool,");
+    emit!(out, "    }}");
+    emit!(out, "    impl<'c> {}Client<'c> {{", name);
+    emit!(out, "        pub fn new(client: &'c {}) -> {}Client<'c> {{", CLIENT, name);
+    emit!(out, "            {}Client {{ client, reliable: true }}", name);
+    emit!(out, "        }}");
+    emit!(out, "        /// Send the calls unreliably");
+    emit!(out, "        pub fn unreliable(self) -> {}Client<'c> {{", name);
+    emit!(out, "            {}Client {{ reliable: false, ..self }}", name);
+    emit!(out, "        }}");
+    for method in &service.methods {
+        generate_client_method(out, method);
+    }
+    emit!(out, "    }}");
+    emit!(out);
+
+    emit!(out, "    /// Reply to a call of `{}`, or the reason it failed", service.full_name);
+    emit!(out, "    #[derive(Debug)]");
+    emit!(out, "    pub enum {}Reply {{", name);
+    for method in &service.methods {
+        emit!(out, "        {}(Result<{}, String>),", method.name, method.reply.rust_path);
+    }
+    emit!(out, "    }}");
+    emit!(out, "    impl {}Reply {{", name);
+    emit!(out, "        /// Decode a reply from `Client::register_on_rpc`. `None` if `method_id` is not a method of `{}`", name);
+    emit!(out, "        pub fn decode(method_id: i64, arg_type: i64, arg_data: &[u8]) -> Option<{}Reply> {{", name);
+    emit!(out, "            match method_id {{");
+    for method in &service.methods {
+        let constant = snake_case(&method.name).to_uppercase();
+        emit!(
+            out,
+            "                {}_METHOD_ID => Some({}Reply::{}(decode({}_REPLY_TYPE, arg_type, arg_data))),",
+            constant,
+            name,
+            method.name,
+            constant
+        );
+    }
+    emit!(out, "                _ => None,");
+    emit!(out, "            }}");
+    emit!(out, "        }}");
+    emit!(out, "    }}");
+    emit!(out);
+
+    emit!(out, "    fn decode<M: Message>(expected: i64, arg_type: i64, arg_data: &[u8]) -> Result<M, String> {{");
+    emit!(out, "        match arg_type {{");
+    emit!(out, "            {} => Err(String::from_utf8_lossy(arg_data).into_owned()),", ERROR_ARG_TYPE);
+    emit!(out, "            arg_type if arg_type != expected => Err(format!(\"Unexpected argument type {{}}\", arg_type)),");
+    emit!(out, "            _ => M::parse_from_bytes(arg_data).map_err(|err| err.to_string()),");
+    emit!(out, "        }}");
+    emit!(out, "    }}");
+    emit!(out, "    fn encode<M: Message>(message: &M) -> Result<Vec<u8>, String> {{");
+    emit!(out, "        message.write_to_bytes().map_err(|err| err.to_string())");
+    emit!(out, "    }}");
+    emit!(out, "}}");
+}
+
+fn generate_client_method(out: &mut String, method: &Method) {
+    let constant = snake_case(&method.name).to_uppercase();
+    emit!(out, "        /// Call `{}`, the reply is matched by `request_id`", method.full_name);
+    emit!(
+        out,
+        "        pub fn {}(&self, request_id: u64, request: &{}) -> Result<(), String> {{",
+        snake_case(&method.name),
+        method.request.rust_path
+    );
+    emit!(out, "            let data = encode(request)?;");
+    emit!(
+        out,
+        "            self.client.call_rpc(self.reliable, {}_METHOD_ID, request_id, {}_REQUEST_TYPE, Some(&data))",
+        constant,
+        constant
+    );
+    emit!(out, "        }}");
+}
@@ -0,0 +1,94 @@
+use std::{fs, path::PathBuf};
+
+use omgpp_core::typed_message::stable_type_id;
+use omgpp_rpc_codegen::RpcCodegen;
+
+const GAME_PROTO: &str = r#"
+syntax = "proto3";
+package game;
+
+message Lobby {
+    message JoinRequest { string name = 1; }
+    message JoinReply { uint32 slot = 1; }
+}
+message Empty {}
+
+service Matchmaking {
+    rpc Join(Lobby.JoinRequest) returns (Lobby.JoinReply);
+    rpc Leave(Empty) returns (Empty);
+}
+"#;
+
+// Writes `files` to a fresh directory and returns it
+fn proto_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("omgpp-rpc-codegen-{}-{}", test, std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (name, content) in files {
+        fs::write(dir.join(name), content).unwrap();
+    }
+    dir
+}
+
+fn codegen(dir: &PathBuf, input: &str) -> RpcCodegen {
+    let mut codegen = RpcCodegen::new();
+    codegen.include(dir).input(dir.join(input)).messages_path("crate::proto::");
+    codegen
+}
+
+#[test]
+fn ids_and_paths_follow_the_proto_names() {
+    let dir = proto_dir("ids", &[("game-lobby.proto", GAME_PROTO)]);
+    let services = codegen(&dir, "game-lobby.proto").services().unwrap();
+    assert_eq!(services.len(), 1);
+    let service = &services[0];
+    assert_eq!(service.full_name, "game.Matchmaking");
+    let join = &service.methods[0];
+    assert_eq!(join.full_name, "game.Matchmaking.Join");
+    assert_eq!(join.id, stable_type_id("game.Matchmaking.Join"));
+    // argument types are the ids `MessageRegistry` sends the messages with
+    assert_eq!(join.request.type_id, stable_type_id("game.Lobby.JoinRequest"));
+    assert_eq!(join.request.rust_path, "crate::proto::game_lobby::lobby::JoinRequest");
+    assert_eq!(service.methods[1].reply.rust_path, "crate::proto::game_lobby::Empty");
+}
+
+#[test]
+fn generates_rust_and_csharp_stubs() {
+    let dir = proto_dir("generate", &[("game.proto", GAME_PROTO)]);
+    let out_dir = dir.join("out");
+    let csharp_file = dir.join("csharp/GameRpc.g.cs");
+    codegen(&dir, "game.proto")
+        .out_dir(&out_dir)
+        .csharp_file(&csharp_file)
+        .csharp_namespace("Game.Rpc")
+        .run()
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(out_dir.join("mod.rs")).unwrap().lines().last(), Some("pub mod game;"));
+    let rust = fs::read_to_string(out_dir.join("game.rs")).unwrap();
+    assert!(rust.contains("pub mod matchmaking {"));
+    assert!(rust.contains("pub trait MatchmakingService {"));
+    assert!(rust.contains(&format!("pub const JOIN_METHOD_ID: i64 = {};", stable_type_id("game.Matchmaking.Join"))));
+    assert!(rust.contains("fn leave(&self, server: &::client_server::server::Server"));
+    assert!(rust.contains("pub struct MatchmakingClient<'c>"));
+    let csharp = fs::read_to_string(csharp_file).unwrap();
+    assert!(csharp.contains("namespace Game.Rpc"));
+    assert!(csharp.contains("public static unsafe class MatchmakingRpc"));
+    assert!(csharp.contains(&format!("public const long LeaveMethodId = {};", stable_type_id("game.Matchmaking.Leave"))));
+    assert!(csharp.contains("OmgppClientNative.client_call_rpc(client, reliable, JoinMethodId"));
+}
+
+#[test]
+fn files_without_services_generate_nothing() {
+    let dir = proto_dir("empty", &[("types.proto", "syntax = \"proto3\";\nmessage Ping {}\n")]);
+    let generated = codegen(&dir, "types.proto").generate().unwrap();
+    assert!(generated.rust.is_empty());
+}
+
+#[test]
+fn streaming_methods_are_rejected() {
+    let proto = "syntax = \"proto3\";\nmessage Tick {}\nservice Clock { rpc Ticks(Tick) returns (stream Tick); }\n";
+    let dir = proto_dir("streaming", &[("clock.proto", proto)]);
+    let reason = codegen(&dir, "clock.proto").services().unwrap_err();
+    assert!(reason.contains("Clock.Ticks"), "{}", reason);
+}
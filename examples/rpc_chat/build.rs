@@ -0,0 +1,18 @@
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+
+    protobuf_codegen::Codegen::new()
+        .pure()
+        .includes(["proto"])
+        .input("proto/chat_room.proto")
+        .cargo_out_dir("messages")
+        .run_from_script();
+
+    omgpp_rpc_codegen::RpcCodegen::new()
+        .includes(["proto"])
+        .input("proto/chat_room.proto")
+        .messages_path("crate::messages")
+        .cargo_out_dir("rpc")
+        .csharp_file("../../generated/csharp/ChatRpc.g.cs")
+        .run_from_script();
+}
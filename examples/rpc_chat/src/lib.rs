@@ -0,0 +1,57 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use client_server::server::Server;
+use uuid::Uuid;
+
+pub mod messages {
+    include!(concat!(env!("OUT_DIR"), "/messages/mod.rs"));
+}
+pub mod rpc {
+    include!(concat!(env!("OUT_DIR"), "/rpc/mod.rs"));
+}
+
+use messages::chat_room::{RenameReply, RenameRequest, SayReply, SayRequest};
+use rpc::chat_room::chat::ChatService;
+
+const MAX_NAME_LEN: usize = 16;
+
+/// Chat history and player names, served through the generated `ChatService`
+#[derive(Default)]
+pub struct ChatRoom {
+    history: RefCell<Vec<String>>,
+    names: RefCell<HashMap<Uuid, String>>,
+}
+impl ChatRoom {
+    pub fn history(&self) -> Vec<String> {
+        self.history.borrow().clone()
+    }
+    fn name(&self, client: &Uuid) -> String {
+        self.names
+            .borrow()
+            .get(client)
+            .cloned()
+            .unwrap_or_else(|| client.to_string())
+    }
+}
+impl ChatService for ChatRoom {
+    fn say(&self, _server: &Server, client: &Uuid, request: SayRequest) -> Result<SayReply, String> {
+        if request.text.trim().is_empty() {
+            return Err("Empty message".to_string());
+        }
+        let mut history = self.history.borrow_mut();
+        history.push(format!("{}: {}", self.name(client), request.text));
+        let mut reply = SayReply::new();
+        reply.index = history.len() as u32 - 1;
+        Ok(reply)
+    }
+    fn rename(&self, _server: &Server, client: &Uuid, request: RenameRequest) -> Result<RenameReply, String> {
+        if request.name.is_empty() || request.name.len() > MAX_NAME_LEN {
+            return Err(format!("Names have 1 to {} characters", MAX_NAME_LEN));
+        }
+        let previous_name = self.name(client);
+        self.names.borrow_mut().insert(*client, request.name);
+        let mut reply = RenameReply::new();
+        reply.previous_name = previous_name;
+        Ok(reply)
+    }
+}
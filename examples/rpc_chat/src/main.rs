@@ -0,0 +1,89 @@
+use std::{
+    cell::RefCell,
+    net::{IpAddr, Ipv4Addr},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use client_server::{backend, client::Client, server::Server};
+use omgpp_core::ConnectionState;
+use rpc_chat::{
+    messages::chat_room::{RenameRequest, SayRequest},
+    rpc::chat_room::chat::{self, ChatClient, ChatReply},
+    ChatRoom,
+};
+
+const PORT: u16 = 55100;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Server and client in one process, talking through the stubs generated from proto/chat_room.proto
+fn main() -> Result<(), String> {
+    let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let mut server = Server::new(ip, PORT)?;
+    let room = Rc::new(ChatRoom::default());
+    let served_room = room.clone();
+    server.register_on_connect_requested(|_server, _id, _endpoint| true);
+    server.register_on_rpc(
+        move |server, client, _endpoint, reliable, method_id, request_id, arg_type, data| {
+            match chat::dispatch(served_room.as_ref(), server, client, reliable, method_id, request_id, arg_type, &data) {
+                Some(Err(reason)) => eprintln!("Cannot reply to {}: {}", request_id, reason),
+                None => eprintln!("Unknown method {}", method_id),
+                Some(Ok(())) => (),
+            }
+        },
+    );
+
+    // the server runs in this process, a QUIC build has no certificate to check it against
+    let mut client = Client::with_connector(ip, PORT, backend::dangerous_insecure_connector());
+    let state = Rc::new(RefCell::new(ConnectionState::None));
+    let state_cloned = state.clone();
+    client.register_on_connection_state_changed(move |_client, _endpoint, new_state| {
+        *state_cloned.borrow_mut() = new_state;
+    });
+    let replies = Rc::new(RefCell::new(0));
+    let replies_cloned = replies.clone();
+    client.register_on_rpc(move |_client, _endpoint, _reliable, method_id, request_id, arg_type, data| {
+        match ChatReply::decode(method_id, arg_type, &data) {
+            Some(ChatReply::Say(Ok(reply))) => println!("#{} said, line {}", request_id, reply.index),
+            Some(ChatReply::Rename(Ok(reply))) => println!("#{} renamed, was {}", request_id, reply.previous_name),
+            Some(ChatReply::Say(Err(reason)) | ChatReply::Rename(Err(reason))) => {
+                println!("#{} failed: {}", request_id, reason)
+            }
+            None => println!("#{} unknown method {}", request_id, method_id),
+        }
+        *replies_cloned.borrow_mut() += 1;
+    });
+    client.connect()?;
+
+    let started = Instant::now();
+    let pump = |until: &dyn Fn() -> bool| -> Result<(), String> {
+        while !until() {
+            if started.elapsed() > TIMEOUT {
+                return Err("Timed out".to_string());
+            }
+            server.process::<64>()?;
+            client.process::<64>()?;
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    };
+    pump(&|| *state.borrow() == ConnectionState::Connected)?;
+
+    let chat = ChatClient::new(&client);
+    let mut rename = RenameRequest::new();
+    rename.name = "alice".to_string();
+    chat.rename(1, &rename)?;
+    for (request_id, text) in [(2, "hello"), (3, " "), (4, "bye")] {
+        let mut say = SayRequest::new();
+        say.text = text.to_string();
+        chat.say(request_id, &say)?;
+    }
+    pump(&|| *replies.borrow() == 4)?;
+
+    for line in room.history() {
+        println!("{}", line);
+    }
+    client.disconnect();
+    Ok(())
+}
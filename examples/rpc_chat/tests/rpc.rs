@@ -0,0 +1,75 @@
+use std::{cell::RefCell, rc::Rc};
+
+use client_server::testing::TestHarness;
+use rpc_chat::{
+    messages::chat_room::{RenameRequest, SayRequest},
+    rpc::chat_room::chat::{self, ChatClient, ChatReply},
+    ChatRoom,
+};
+
+type Replies = Rc<RefCell<Vec<(u64, ChatReply)>>>;
+
+// Serves a `ChatRoom` and collects the decoded replies of the first client
+fn chat_harness() -> (TestHarness, Rc<ChatRoom>, Replies) {
+    let mut harness = TestHarness::start_loopback(1).unwrap();
+    assert!(harness.wait_all_connected());
+    let room = Rc::new(ChatRoom::default());
+    let served_room = room.clone();
+    harness.server.server.register_on_rpc(
+        move |server, client, _endpoint, reliable, method_id, request_id, arg_type, data| {
+            let result = chat::dispatch(served_room.as_ref(), server, client, reliable, method_id, request_id, arg_type, &data);
+            assert!(matches!(result, Some(Ok(()))));
+        },
+    );
+    let replies = Rc::new(RefCell::new(Vec::new()));
+    let log = replies.clone();
+    harness
+        .client(0)
+        .client
+        .register_on_rpc(move |_client, _endpoint, _reliable, method_id, request_id, arg_type, data| {
+            log.borrow_mut().push((request_id, ChatReply::decode(method_id, arg_type, &data).unwrap()));
+        });
+    (harness, room, replies)
+}
+
+fn say(text: &str) -> SayRequest {
+    let mut request = SayRequest::new();
+    request.text = text.to_string();
+    request
+}
+
+#[test]
+fn generated_stubs_call_and_reply() {
+    let (harness, room, replies) = chat_harness();
+    let chat = ChatClient::new(&harness.client(0).client);
+    let mut rename = RenameRequest::new();
+    rename.name = "alice".to_string();
+    chat.rename(1, &rename).unwrap();
+    chat.say(2, &say("hello")).unwrap();
+
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| replies.borrow().len() == 2));
+    let replies = replies.borrow();
+    assert!(matches!(&replies[0], (1, ChatReply::Rename(Ok(_)))));
+    assert!(matches!(&replies[1], (2, ChatReply::Say(Ok(reply))) if reply.index == 0));
+    assert_eq!(room.history(), vec!["alice: hello".to_string()]);
+}
+
+#[test]
+fn service_errors_reach_the_caller() {
+    let (harness, room, replies) = chat_harness();
+    let chat = ChatClient::new(&harness.client(0).client).unreliable();
+    chat.say(7, &say("  ")).unwrap();
+
+    assert!(harness.pump_until(TestHarness::DEFAULT_TIMEOUT, |_| replies.borrow().len() == 1));
+    assert!(matches!(&replies.borrow()[0], (7, ChatReply::Say(Err(reason))) if reason == "Empty message"));
+    assert!(room.history().is_empty());
+}
+
+#[test]
+fn other_methods_are_not_dispatched() {
+    assert!(ChatReply::decode(42, 0, &[]).is_none());
+    assert_ne!(chat::SAY_METHOD_ID, chat::RENAME_METHOD_ID);
+    // a reply with the wrong message type is reported instead of misparsed
+    let reply = ChatReply::decode(chat::SAY_METHOD_ID, chat::RENAME_REPLY_TYPE, &[]).unwrap();
+    assert!(matches!(reply, ChatReply::Say(Err(_))));
+}
@@ -1,24 +1,66 @@
 use std::{
-    cell::Cell, net::{IpAddr, Ipv4Addr}, rc::Rc, sync::mpsc, thread, time::Instant
+    cell::Cell, net::{IpAddr, Ipv4Addr}, rc::Rc, sync::mpsc, thread, time::{Duration, Instant}
 };
 
 use client_server::client::Client;
 use client_server::server::Server;
+use omgpp_core::network_simulation::{NetworkSimulation, SimulatedConditions};
 use omgpp_core::ConnectionState;
 use std::env;
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() == 1 {
-        panic!("Provide command line arguments. 1 - to start server, 2 - to start client")
+        panic!("Provide command line arguments. 1 - to start server, 2 - to start client. Optional: --loss <percent> --lag <ms> --jitter <ms> --reorder <percent> --dup <percent>")
     }
     let start_type = &args[1];
+    let simulation = parse_network_simulation(&args[2..]);
     match &start_type[..] {
-        "1" => start_server(),
-        "2" => start_client(),
+        "1" => start_server(simulation),
+        "2" => start_client(simulation),
         _ => panic!("error: invalid command"),
     }
 }
 
+// Accepts both `--loss 10` (command line) and `loss=10` (`/sim` chat command) forms
+fn parse_network_simulation(args: &[String]) -> NetworkSimulation {
+    let mut conditions = SimulatedConditions::default();
+    let mut pairs = Vec::new();
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        if let Some((key, value)) = arg.split_once('=') {
+            pairs.push((key.trim_start_matches("--").to_string(), value.to_string()));
+        } else if let Some(key) = arg.strip_prefix("--") {
+            let value = args_iter.next().cloned().unwrap_or_default();
+            pairs.push((key.to_string(), value));
+        }
+    }
+    for (key, value) in pairs {
+        let Ok(number) = value.trim().parse::<f32>() else {
+            println!("Invalid value {:?} for {:?}", value, key);
+            continue;
+        };
+        match &key[..] {
+            "loss" => conditions.loss_percent = number,
+            "lag" => conditions.lag = Duration::from_millis(number as u64),
+            "jitter" => {
+                conditions.jitter_avg = Duration::from_millis(number as u64 / 2);
+                conditions.jitter_max = Duration::from_millis(number as u64);
+                conditions.jitter_percent = 100.0;
+            }
+            "reorder" => conditions.reorder_percent = number,
+            "dup" => conditions.duplicate_percent = number,
+            _ => println!("Unknown network simulation option {:?}", key),
+        }
+    }
+    let reorder_time = conditions.jitter_max.max(Duration::from_millis(10));
+    NetworkSimulation {
+        send: conditions,
+        reorder_time,
+        duplicate_time_max: reorder_time,
+        ..Default::default()
+    }
+}
+
 /*
     sequenceDiagram
     engine->>+client: RunFrame()
@@ -31,9 +73,13 @@ fn main() {
 
     client->>-engine:
 */
-fn start_server() {
+fn start_server(simulation: NetworkSimulation) {
     println!("Hello! Im Server");
-    let server = Server::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 55655).unwrap();
+    let mut server = Server::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 55655).unwrap();
+    if simulation.is_enabled() {
+        println!("Simulating network: {:?}", simulation);
+        server.set_network_simulation(simulation).unwrap();
+    }
     server.register_on_connect_requested(|_server,_id, _endpoint| true);
     server.register_on_connection_state_changed(|server,id, endpoint, state| {
         let msg= format!("Client {:?} {:?}",endpoint,state);
@@ -63,15 +109,19 @@ fn start_server() {
         // send data to clients with fixed FPS
     }
 }
-fn start_client() {
-    println!("Hello! Im a client");
-    let (tx_channel, rx_channel) = mpsc::channel();
+fn start_client(simulation: NetworkSimulation) {
+    println!("Hello! Im a client. Type `/sim loss=10 lag=100 jitter=20 reorder=5 dup=1` to change network conditions");
+    let (tx_channel, rx_channel) = mpsc::channel::<String>();
     let _client_connection_thread = thread::spawn(move || {
         let port: u16 = 55655;
         let should_reconnected = Rc::from(Cell::from(false));
         let _should_reconnected_cloned = should_reconnected.clone(); // Don't know how to pass it inside a closure without cloning
 
         let mut client = Client::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+        if simulation.is_enabled() {
+            println!("Simulating network: {:?}", simulation);
+            client.set_network_simulation(simulation).unwrap();
+        }
 
         client.register_on_connection_state_changed(move |client,endpoint, state| {
             println!("{:?} {:?}", endpoint, state);
@@ -145,6 +195,13 @@ fn start_client() {
             }
             loop {
                 if let Ok(received) = rx_channel.try_recv() {
+                    if let Some(options) = received.trim().strip_prefix("/sim") {
+                        let options = options.split_whitespace().map(String::from).collect::<Vec<_>>();
+                        let simulation = parse_network_simulation(&options);
+                        println!("Simulating network: {:?}", simulation);
+                        _ = client.set_network_simulation(simulation);
+                        continue;
+                    }
                     msg_buf.push(received);
                 } else {
                     break;